@@ -0,0 +1,267 @@
+//! `--check` mode: verify that files highlight cleanly without producing output.
+//!
+//! Intended as a CI guard for repositories full of code samples: every file
+//! must have a detectable language, a compiled-in grammar, and parse without
+//! (too many) syntax errors.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use arborium::Highlighter;
+use arborium::tree_sitter::{Language, Node, Parser};
+
+/// What to do with files whose language can't be detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Undetected {
+    /// Report the file as skipped (doesn't fail the check).
+    #[default]
+    Skip,
+    /// Report the file as failed.
+    Fail,
+}
+
+impl std::str::FromStr for Undetected {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Undetected::Skip),
+            "fail" => Ok(Undetected::Fail),
+            other => Err(format!(
+                "Invalid value for --check-undetected: {} (expected skip or fail)",
+                other
+            )),
+        }
+    }
+}
+
+/// Options for [`check_paths`] and [`check_file`].
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Language to use for every file instead of auto-detection.
+    pub language: Option<String>,
+    /// What to do with files whose language can't be detected.
+    pub undetected: Undetected,
+    /// Maximum fraction (0.0 to 1.0) of the source covered by ERROR or
+    /// MISSING nodes before the file fails. The default of 0 fails on any
+    /// parse error.
+    pub max_error_ratio: f64,
+}
+
+impl CheckOptions {
+    /// Reject options that can't be checked against, such as a
+    /// `max_error_ratio` outside 0.0 to 1.0.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.max_error_ratio) {
+            return Err(format!(
+                "Invalid value for --max-error-ratio: {} (expected 0.0 to 1.0)",
+                self.max_error_ratio
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Result of checking a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckOutcome {
+    /// The file highlighted cleanly.
+    Passed {
+        /// The language the file was highlighted as.
+        language: String,
+    },
+    /// The file was not checked (e.g. undetected language with [`Undetected::Skip`]).
+    Skipped {
+        /// Why the file was skipped.
+        reason: String,
+    },
+    /// The file failed the check.
+    Failed {
+        /// Why the file failed.
+        reason: String,
+    },
+}
+
+impl CheckOutcome {
+    /// Returns `true` if this outcome should fail the overall check.
+    pub fn is_failure(&self) -> bool {
+        matches!(self, CheckOutcome::Failed { .. })
+    }
+}
+
+impl fmt::Display for CheckOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckOutcome::Passed { language } => write!(f, "ok ({})", language),
+            CheckOutcome::Skipped { reason } => write!(f, "skipped: {}", reason),
+            CheckOutcome::Failed { reason } => write!(f, "FAILED: {}", reason),
+        }
+    }
+}
+
+/// A checked file together with its outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileReport {
+    /// Path of the checked file.
+    pub path: PathBuf,
+    /// What happened.
+    pub outcome: CheckOutcome,
+}
+
+/// Check every file under `paths`, recursing into directories.
+///
+/// Files are reported in a stable (sorted) order. Hidden files and
+/// directories (names starting with `.`) found while recursing are ignored.
+pub fn check_paths(paths: &[PathBuf], options: &CheckOptions) -> io::Result<Vec<FileReport>> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+
+    let highlighter = Highlighter::new();
+    let mut parser = Parser::new();
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let outcome = check_file_with(&highlighter, &mut parser, &path, options);
+            FileReport { path, outcome }
+        })
+        .collect())
+}
+
+/// Check a single file.
+pub fn check_file(path: &Path, options: &CheckOptions) -> CheckOutcome {
+    check_file_with(&Highlighter::new(), &mut Parser::new(), path, options)
+}
+
+fn check_file_with(
+    highlighter: &Highlighter,
+    parser: &mut Parser,
+    path: &Path,
+    options: &CheckOptions,
+) -> CheckOutcome {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            return CheckOutcome::Failed {
+                reason: format!("failed to read file: {}", e),
+            };
+        }
+    };
+
+    let language = match &options.language {
+        Some(lang) => lang.as_str(),
+        None => {
+            let detected = arborium::detect_language(&path.to_string_lossy())
+                .or_else(|| crate::detect_from_content(&content));
+            match (detected, options.undetected) {
+                (Some(lang), _) => lang,
+                (None, Undetected::Skip) => {
+                    return CheckOutcome::Skipped {
+                        reason: "could not detect language".to_string(),
+                    };
+                }
+                (None, Undetected::Fail) => {
+                    return CheckOutcome::Failed {
+                        reason: "could not detect language".to_string(),
+                    };
+                }
+            }
+        }
+    };
+
+    let language = match crate::resolve_language(language) {
+        Ok(language) => language,
+        Err(reason) => return CheckOutcome::Failed { reason },
+    };
+
+    // Compiles the grammar's queries, so a broken grammar fails here
+    if let Err(e) = highlighter.warm(&[language]) {
+        return CheckOutcome::Failed {
+            reason: format!("highlighting as {} failed: {}", language, e),
+        };
+    }
+
+    if let Some(ts_language) = arborium::get_language(language) {
+        let ratio = error_ratio_with(parser, &ts_language, &content);
+        if ratio > options.max_error_ratio {
+            return CheckOutcome::Failed {
+                reason: format!(
+                    "{:.1}% of the source is covered by {} parse errors (max {:.1}%)",
+                    ratio * 100.0,
+                    language,
+                    options.max_error_ratio * 100.0
+                ),
+            };
+        }
+    }
+
+    CheckOutcome::Passed {
+        language: language.to_string(),
+    }
+}
+
+/// Fraction of `source` covered by ERROR or MISSING nodes when parsed as `language`.
+///
+/// MISSING nodes have no width, so each one counts as a single byte.
+/// Returns 1.0 if the source can't be parsed at all.
+pub fn error_ratio(language: &Language, source: &str) -> f64 {
+    error_ratio_with(&mut Parser::new(), language, source)
+}
+
+fn error_ratio_with(parser: &mut Parser, language: &Language, source: &str) -> f64 {
+    if parser.set_language(language).is_err() {
+        return 1.0;
+    }
+    let Some(tree) = parser.parse(source, None) else {
+        return 1.0;
+    };
+
+    let root = tree.root_node();
+    if !root.has_error() {
+        return 0.0;
+    }
+
+    let covered = error_bytes(root);
+    (covered as f64 / source.len().max(1) as f64).min(1.0)
+}
+
+fn error_bytes(node: Node<'_>) -> usize {
+    if node.is_error() {
+        return node.byte_range().len();
+    }
+    if node.is_missing() {
+        return 1;
+    }
+    if !node.has_error() {
+        return 0;
+    }
+
+    let mut cursor = node.walk();
+    node.children(&mut cursor).map(error_bytes).sum()
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            !p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with('.'))
+        })
+        .collect();
+    entries.sort();
+
+    for entry in entries {
+        collect_files(&entry, files)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Terminal setup for ANSI output.
+
+/// Prepare stdout for ANSI escape sequences.
+///
+/// Windows consoles print escape sequences literally unless virtual terminal
+/// processing is turned on, which this does. Returns `false` if it couldn't
+/// be (consoles older than Windows 10), in which case output should be plain
+/// text. When stdout isn't a console (a pipe or file) there's nothing to set
+/// up, and like on other platforms the escapes are written as-is.
+pub fn enable_ansi() -> bool {
+    #[cfg(windows)]
+    {
+        windows::enable_virtual_terminal()
+    }
+    #[cfg(not(windows))]
+    {
+        true
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    pub(super) fn enable_virtual_terminal() -> bool {
+        let handle = io::stdout().as_raw_handle();
+        let mut mode = 0;
+        // SAFETY: `handle` is stdout's handle, or null if there is none, in
+        // which case the call fails; `mode` is a valid place to write to.
+        if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+            // Not a console
+            return true;
+        }
+        if mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 {
+            return true;
+        }
+        // SAFETY: as above, and `handle` is known to be a console.
+        unsafe { SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0 }
+    }
+}
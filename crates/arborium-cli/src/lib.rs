@@ -0,0 +1,66 @@
+//! Library side of the `arborium` command-line tool.
+//!
+//! The binary is a thin wrapper around these modules; they live here so
+//! that integration tests can drive them directly.
+
+pub mod check;
+pub mod console;
+
+use arborium::LanguageSupport;
+
+/// Resolve a language name or alias to the canonical name of a grammar
+/// compiled into this build.
+///
+/// The error says whether the language is unknown altogether or just
+/// wasn't compiled in, and in the latter case which feature to enable.
+pub fn resolve_language(language: &str) -> Result<&'static str, String> {
+    resolve_language_support(language, arborium::language_support(language))
+}
+
+/// [`resolve_language`], given the [`LanguageSupport`] already looked up
+/// for `language`.
+pub fn resolve_language_support(
+    language: &str,
+    support: LanguageSupport,
+) -> Result<&'static str, String> {
+    match support {
+        LanguageSupport::Supported(name) => Ok(name),
+        LanguageSupport::NotCompiled(name) => Err(format!(
+            "language '{}' is recognized but not compiled into this build; \
+             rebuild with feature 'lang-{}'",
+            language, name
+        )),
+        LanguageSupport::Unknown => Err(format!("Unknown language: {}", language)),
+    }
+}
+
+/// Detect language from content (e.g., shebang lines)
+pub fn detect_from_content(content: &str) -> Option<&'static str> {
+    let first_line = content.lines().next()?;
+
+    // Check for shebang
+    if let Some(shebang) = first_line.strip_prefix("#!") {
+        let shebang = shebang.trim();
+
+        // Common interpreters
+        if shebang.contains("python") {
+            return Some("python");
+        } else if shebang.contains("node") || shebang.contains("nodejs") {
+            return Some("javascript");
+        } else if shebang.contains("ruby") {
+            return Some("ruby");
+        } else if shebang.contains("perl") {
+            return Some("perl");
+        } else if shebang.contains("bash") || shebang.contains("/sh") {
+            return Some("bash");
+        } else if shebang.contains("zsh") {
+            return Some("zsh");
+        } else if shebang.contains("fish") {
+            return Some("fish");
+        } else if shebang.contains("php") {
+            return Some("php");
+        }
+    }
+
+    None
+}
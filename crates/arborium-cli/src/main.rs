@@ -1,9 +1,12 @@
 use arborium::theme::builtin;
-use arborium::{AnsiHighlighter, Highlighter};
+use arborium::{AnsiHighlighter, Highlighter, Timings};
+use arborium_cli::check::{self, CheckOptions};
+use arborium_cli::console;
+use arborium_cli::{detect_from_content, resolve_language};
 use facet::Facet;
 use facet_args as args;
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Arborium syntax highlighter - terminal-friendly code highlighting
 #[derive(Debug, Facet)]
@@ -23,12 +26,41 @@ struct Args {
     /// If a file path is provided, reads from that file.
     /// If '-' is provided, reads from stdin.
     /// Otherwise, treats the argument as raw code to highlight.
+    ///
+    /// With --check, any number of files or directories to check.
     #[facet(args::positional, default)]
-    input: Option<String>,
+    inputs: Vec<String>,
 
     /// Theme for ANSI output (ignored with --html)
     #[facet(args::named, default)]
     theme: Option<String>,
+
+    /// List the built-in themes and exit
+    #[facet(args::named, default)]
+    list_themes: bool,
+
+    /// Check that files highlight without errors instead of printing them
+    ///
+    /// Directories are checked recursively. Exits with a non-zero status
+    /// if any file fails.
+    #[facet(args::named, default)]
+    check: bool,
+
+    /// With --check, what to do with files of unknown language: skip (default) or fail
+    #[facet(args::named, default)]
+    check_undetected: Option<String>,
+
+    /// With --check, maximum fraction of a file covered by parse errors (default 0)
+    #[facet(args::named, default)]
+    max_error_ratio: Option<f64>,
+
+    /// With --check, only print failing files
+    #[facet(args::named, args::short = 'q', default)]
+    quiet: bool,
+
+    /// Print the time spent loading grammars, parsing, querying and rendering to stderr
+    #[facet(args::named, default)]
+    timings: bool,
 }
 
 fn main() {
@@ -41,15 +73,34 @@ fn main() {
         std::process::exit(1);
     });
 
-    if let Err(e) = run(args) {
+    if args.list_themes {
+        for name in builtin::names() {
+            println!("{name}");
+        }
+        return;
+    }
+
+    let result = if args.check {
+        run_check(args)
+    } else {
+        run(args)
+    };
+
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
 }
 
 fn run(args: Args) -> Result<(), String> {
+    if args.inputs.len() > 1 {
+        return Err(
+            "Expected a single input (multiple paths are only supported with --check)".into(),
+        );
+    }
+
     // Determine input source and read content
-    let (content, filename) = match args.input.as_deref() {
+    let (content, filename) = match args.inputs.first().map(String::as_str) {
         None | Some("-") => {
             // Read from stdin
             let mut buffer = String::new();
@@ -83,9 +134,7 @@ fn run(args: Args) -> Result<(), String> {
     };
 
     let lang = detected_lang.ok_or_else(|| {
-        if args.lang.is_some() {
-            format!("Unknown language: {}", args.lang.as_ref().unwrap())
-        } else if let Some(filename) = &filename {
+        if let Some(filename) = &filename {
             format!(
                 "Could not detect language from filename: {}. Use --lang to specify.",
                 filename
@@ -94,72 +143,88 @@ fn run(args: Args) -> Result<(), String> {
             "Could not detect language. Use --lang to specify.".to_string()
         }
     })?;
+    let lang = resolve_language(lang)?;
 
     // Highlight based on output format
-    if args.html {
+    let (output, timings) = if args.html {
         let mut highlighter = Highlighter::new();
-        let html = highlighter
-            .highlight(lang, &content)
-            .map_err(|e| format!("Highlighting failed: {}", e))?;
-        println!("{}", html);
+        highlighter.highlight_timed(lang, &content)
+    } else if !console::enable_ansi() {
+        // The console would print escape sequences literally
+        Ok((content, Timings::default()))
     } else {
         // Determine theme
-        let theme = match args.theme.as_deref() {
-            Some("mocha") | Some("catppuccin-mocha") => builtin::catppuccin_mocha(),
-            Some("latte") | Some("catppuccin-latte") => builtin::catppuccin_latte(),
-            Some("macchiato") | Some("catppuccin-macchiato") => builtin::catppuccin_macchiato(),
-            Some("frappe") | Some("catppuccin-frappe") => builtin::catppuccin_frappe(),
-            Some("dracula") => builtin::dracula(),
-            Some("tokyo-night") => builtin::tokyo_night(),
-            Some("nord") => builtin::nord(),
-            Some("one-dark") => builtin::one_dark(),
-            Some("github-dark") => builtin::github_dark(),
-            Some("github-light") => builtin::github_light(),
-            Some("gruvbox-dark") => builtin::gruvbox_dark(),
-            Some("gruvbox-light") => builtin::gruvbox_light(),
-            Some(other) => {
-                return Err(format!("Unknown theme: {}", other));
-            }
-            None => builtin::catppuccin_mocha(), // Default theme
-        };
+        let theme = resolve_theme(args.theme.as_deref().unwrap_or("catppuccin-mocha"))?;
 
         let mut highlighter = AnsiHighlighter::new(theme.clone());
-        let ansi = highlighter
-            .highlight(lang, &content)
-            .map_err(|e| format!("Highlighting failed: {}", e))?;
-        println!("{}", ansi);
+        highlighter.highlight_timed(lang, &content)
+    }
+    .map_err(|e| format!("Highlighting failed: {}", e))?;
+    println!("{}", output);
+
+    if args.timings {
+        print_timings(&timings);
     }
 
     Ok(())
 }
 
-/// Detect language from content (e.g., shebang lines)
-fn detect_from_content(content: &str) -> Option<&'static str> {
-    let first_line = content.lines().next()?;
-
-    // Check for shebang
-    if let Some(shebang) = first_line.strip_prefix("#!") {
-        let shebang = shebang.trim();
-
-        // Common interpreters
-        if shebang.contains("python") {
-            return Some("python");
-        } else if shebang.contains("node") || shebang.contains("nodejs") {
-            return Some("javascript");
-        } else if shebang.contains("ruby") {
-            return Some("ruby");
-        } else if shebang.contains("perl") {
-            return Some("perl");
-        } else if shebang.contains("bash") || shebang.contains("/sh") {
-            return Some("bash");
-        } else if shebang.contains("zsh") {
-            return Some("zsh");
-        } else if shebang.contains("fish") {
-            return Some("fish");
-        } else if shebang.contains("php") {
-            return Some("php");
+/// Print a breakdown of where highlighting spent its time to stderr.
+fn print_timings(timings: &Timings) {
+    let total = timings.load + timings.parse + timings.query + timings.render;
+    eprintln!("load:   {:?}", timings.load);
+    eprintln!("parse:  {:?}", timings.parse);
+    eprintln!("query:  {:?}", timings.query);
+    eprintln!("render: {:?}", timings.render);
+    eprintln!("total:  {:?}", total);
+}
+
+/// Resolve a theme name, accepting the short catppuccin flavor names
+/// ("mocha", "latte", ...) as well as the built-in names.
+fn resolve_theme(name: &str) -> Result<&'static arborium::theme::Theme, String> {
+    builtin::by_name(name)
+        .or_else(|| builtin::by_name(&format!("catppuccin-{name}")))
+        .ok_or_else(|| format!("Unknown theme: {name} (see --list-themes)"))
+}
+
+fn run_check(args: Args) -> Result<(), String> {
+    if args.inputs.is_empty() {
+        return Err("--check requires at least one file or directory".into());
+    }
+
+    let options = CheckOptions {
+        language: args.lang,
+        undetected: args
+            .check_undetected
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        max_error_ratio: args.max_error_ratio.unwrap_or(0.0),
+    };
+    options.validate()?;
+
+    let paths: Vec<PathBuf> = args.inputs.iter().map(PathBuf::from).collect();
+    let reports = check::check_paths(&paths, &options)
+        .map_err(|e| format!("Failed to read input paths: {}", e))?;
+
+    let mut failures = 0;
+    for report in &reports {
+        if report.outcome.is_failure() {
+            failures += 1;
+            println!("{}: {}", report.path.display(), report.outcome);
+        } else if !args.quiet {
+            println!("{}: {}", report.path.display(), report.outcome);
         }
     }
 
-    None
+    if failures > 0 {
+        return Err(format!(
+            "{} of {} files failed the check",
+            failures,
+            reports.len()
+        ));
+    }
+
+    Ok(())
 }
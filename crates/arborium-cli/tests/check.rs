@@ -0,0 +1,90 @@
+//! Tests for `--check` mode.
+
+use std::path::PathBuf;
+
+use arborium_cli::check::{self, CheckOptions, CheckOutcome, Undetected};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/check")
+        .join(name)
+}
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn valid_rust_passes() {
+    let outcome = check::check_file(&fixture("ok.rs"), &CheckOptions::default());
+    assert_eq!(
+        outcome,
+        CheckOutcome::Passed {
+            language: "rust".to_string()
+        }
+    );
+}
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn broken_rust_fails() {
+    let outcome = check::check_file(&fixture("broken.rs"), &CheckOptions::default());
+    assert!(outcome.is_failure(), "expected failure, got {outcome:?}");
+}
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn broken_rust_passes_with_generous_error_ratio() {
+    let options = CheckOptions {
+        max_error_ratio: 1.0,
+        ..Default::default()
+    };
+    let outcome = check::check_file(&fixture("broken.rs"), &options);
+    assert!(!outcome.is_failure(), "expected pass, got {outcome:?}");
+}
+
+#[test]
+fn unknown_extension_is_skipped_by_default() {
+    let outcome = check::check_file(&fixture("notes.unknownext"), &CheckOptions::default());
+    assert!(matches!(outcome, CheckOutcome::Skipped { .. }));
+}
+
+#[test]
+fn unknown_extension_can_fail() {
+    let options = CheckOptions {
+        undetected: Undetected::Fail,
+        ..Default::default()
+    };
+    let outcome = check::check_file(&fixture("notes.unknownext"), &options);
+    assert!(outcome.is_failure());
+}
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn directory_is_walked_in_order() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/check");
+    let reports = check::check_paths(&[dir], &CheckOptions::default()).unwrap();
+
+    let names: Vec<_> = reports
+        .iter()
+        .map(|r| r.path.file_name().unwrap().to_str().unwrap())
+        .collect();
+    assert_eq!(names, ["broken.rs", "notes.unknownext", "ok.rs"]);
+
+    let failures: Vec<_> = reports.iter().filter(|r| r.outcome.is_failure()).collect();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].path.ends_with("broken.rs"));
+}
+
+#[test]
+fn error_ratio_outside_unit_range_is_rejected() {
+    for max_error_ratio in [-0.1, 1.5, f64::NAN] {
+        let options = CheckOptions {
+            max_error_ratio,
+            ..Default::default()
+        };
+        assert!(options.validate().is_err(), "{max_error_ratio} accepted");
+    }
+    let options = CheckOptions {
+        max_error_ratio: 1.0,
+        ..Default::default()
+    };
+    assert_eq!(options.validate(), Ok(()));
+}
@@ -0,0 +1,12 @@
+//! Tests for setting up the console for ANSI output.
+
+#![cfg(windows)]
+
+use arborium_cli::console;
+
+#[test]
+fn enabling_ansi_succeeds() {
+    assert!(console::enable_ansi());
+    // Enabling it again is harmless
+    assert!(console::enable_ansi());
+}
@@ -0,0 +1,26 @@
+//! Tests for resolving `--lang` against the compiled-in grammars.
+
+use arborium::LanguageSupport;
+use arborium_cli::{resolve_language, resolve_language_support};
+
+#[test]
+fn unknown_language_is_reported_as_unknown() {
+    let err = resolve_language("no-such-language").unwrap_err();
+    assert_eq!(err, "Unknown language: no-such-language");
+}
+
+#[test]
+fn known_but_not_compiled_language_names_its_feature() {
+    let err = resolve_language_support("py", LanguageSupport::NotCompiled("python")).unwrap_err();
+    assert_eq!(
+        err,
+        "language 'py' is recognized but not compiled into this build; \
+         rebuild with feature 'lang-python'"
+    );
+}
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn compiled_language_resolves() {
+    assert_eq!(resolve_language("rust"), Ok("rust"));
+}
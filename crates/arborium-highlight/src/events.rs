@@ -0,0 +1,161 @@
+//! Highlight events in the `tree-sitter-highlight` format.
+//!
+//! Tools built around `tree_sitter_highlight::Highlighter` consume a stream
+//! of [`HighlightEvent`]s rather than spans. [`to_highlight_events`] produces
+//! the same stream from arborium's spans, so those tools can switch to
+//! arborium's grammars and queries without changing their rendering code.
+//!
+//! [`Highlight`] indices follow arborium's slot ordering: index `i` is
+//! [`HIGHLIGHTS[i]`](arborium_theme::HIGHLIGHTS), as returned by
+//! [`Slot::highlight_index`].
+
+use arborium_theme::Slot;
+
+use crate::Span;
+
+/// An index into [`arborium_theme::HIGHLIGHTS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Highlight(pub usize);
+
+/// An event in a highlighted document, mirroring
+/// `tree_sitter_highlight::HighlightEvent`.
+///
+/// `Source` events cover the document in order and without gaps;
+/// `HighlightStart` and `HighlightEnd` are properly nested around them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightEvent {
+    /// A run of source text, as byte offsets.
+    Source { start: usize, end: usize },
+    /// The following source is highlighted, until the matching
+    /// [`HighlightEnd`](Self::HighlightEnd).
+    HighlightStart(Highlight),
+    /// Closes the innermost open highlight.
+    HighlightEnd,
+}
+
+/// Convert highlight spans for `source` into a stream of highlight events.
+///
+/// Spans with no theme slot are dropped, as are spans out of bounds or not on
+/// character boundaries. Among spans with the same range, the highest
+/// `pattern_index` wins. A span that starts inside another one but ends after
+/// it is cut at the outer span's end, so that events always nest.
+pub fn to_highlight_events(source: &str, spans: &[Span]) -> Vec<HighlightEvent> {
+    let mut highlighted: Vec<(usize, usize, u32, Highlight)> = spans
+        .iter()
+        .filter_map(|span| {
+            let (start, end) = (span.start as usize, span.end as usize);
+            if start >= end
+                || end > source.len()
+                || !source.is_char_boundary(start)
+                || !source.is_char_boundary(end)
+            {
+                return None;
+            }
+            let slot = Slot::from_capture(&span.capture)?;
+            Some((
+                start,
+                end,
+                span.pattern_index,
+                Highlight(slot.highlight_index()),
+            ))
+        })
+        .collect();
+
+    // Outer spans first; for identical ranges, keep only the one with the
+    // highest pattern_index
+    highlighted.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| b.1.cmp(&a.1))
+            .then_with(|| b.2.cmp(&a.2))
+    });
+    highlighted.dedup_by_key(|(start, end, _, _)| (*start, *end));
+
+    let mut events = Vec::with_capacity(highlighted.len() * 3 + 1);
+    let mut pos = 0;
+    // Ends of the open highlights, innermost last
+    let mut open: Vec<usize> = Vec::new();
+
+    fn source_until(events: &mut Vec<HighlightEvent>, pos: &mut usize, end: usize) {
+        if *pos < end {
+            events.push(HighlightEvent::Source { start: *pos, end });
+            *pos = end;
+        }
+    }
+
+    for (start, end, _, highlight) in highlighted {
+        while let Some(&outer_end) = open.last().filter(|&&outer_end| outer_end <= start) {
+            source_until(&mut events, &mut pos, outer_end);
+            events.push(HighlightEvent::HighlightEnd);
+            open.pop();
+        }
+
+        let end = open.last().map_or(end, |&outer_end| end.min(outer_end));
+        if start >= end {
+            continue;
+        }
+        source_until(&mut events, &mut pos, start);
+        events.push(HighlightEvent::HighlightStart(highlight));
+        open.push(end);
+    }
+
+    while let Some(outer_end) = open.pop() {
+        source_until(&mut events, &mut pos, outer_end);
+        events.push(HighlightEvent::HighlightEnd);
+    }
+    source_until(&mut events, &mut pos, source.len());
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32, capture: &str) -> Span {
+        Span::new(start, end, capture)
+    }
+
+    #[test]
+    fn test_events_reconstruct_source() {
+        let source = "let s = \"a\\n\"; // done";
+        let spans = vec![
+            span(0, 3, "keyword"),
+            span(8, 13, "string"),
+            span(10, 12, "string.escape"),
+            span(15, 22, "comment"),
+            // Starts inside the string and ends after it
+            span(12, 14, "punctuation.delimiter"),
+        ];
+
+        let events = to_highlight_events(source, &spans);
+
+        let mut text = String::new();
+        let mut depth = 0i32;
+        for event in &events {
+            match *event {
+                HighlightEvent::Source { start, end } => text.push_str(&source[start..end]),
+                HighlightEvent::HighlightStart(_) => depth += 1,
+                HighlightEvent::HighlightEnd => {
+                    depth -= 1;
+                    assert!(depth >= 0, "unbalanced events: {events:?}");
+                }
+            }
+        }
+        assert_eq!(text, source);
+        assert_eq!(depth, 0);
+
+        let keyword = Highlight(Slot::Keyword.highlight_index());
+        let string = Highlight(Slot::String.highlight_index());
+        assert_eq!(
+            &events[..6],
+            [
+                HighlightEvent::HighlightStart(keyword),
+                HighlightEvent::Source { start: 0, end: 3 },
+                HighlightEvent::HighlightEnd,
+                HighlightEvent::Source { start: 3, end: 8 },
+                HighlightEvent::HighlightStart(string),
+                HighlightEvent::Source { start: 8, end: 10 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Which injections of a document get highlighted.
+//!
+//! [`InjectionFilter`] holds the rules every highlighter applies before
+//! parsing an injection, so that [`HighlighterCore`](crate::HighlighterCore)
+//! and the highlighters built on this crate can't drift apart.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::{HighlightConfig, Injection, trim_blank_lines};
+
+/// Decides which injections of a document are highlighted, and over which
+/// bytes.
+///
+/// Applies [`HighlightConfig::injection_allowlist`],
+/// [`HighlightConfig::max_injected_languages`] and
+/// [`HighlightConfig::trim_injection_blank_lines`], and keeps injections
+/// within the region they were found in. An injection with the language and
+/// byte range of a region enclosing it is skipped too, so a grammar that
+/// injects itself over its own text doesn't use up the injection depth.
+///
+/// Regions are the documents added with [`document`](Self::document) and
+/// the injections added with [`enter`](Self::enter), each identified by
+/// the index these return.
+#[derive(Debug, Clone)]
+pub struct InjectionFilter {
+    allowlist: Option<HashSet<String>>,
+    max_languages: Option<usize>,
+    trim_blank_lines: bool,
+    /// Distinct languages injected so far.
+    languages: HashSet<String>,
+    /// Each region's language, byte range in its document, and the region
+    /// enclosing it.
+    regions: Vec<(String, Range<usize>, Option<usize>)>,
+}
+
+impl InjectionFilter {
+    /// A filter applying the injection settings of `config`.
+    pub fn new(config: &HighlightConfig) -> Self {
+        Self::with_options(
+            config.injection_allowlist.clone(),
+            config.max_injected_languages,
+            config.trim_injection_blank_lines,
+        )
+    }
+
+    /// A filter with the given settings, for highlighters with a
+    /// configuration of their own. See [`HighlightConfig`] for what each
+    /// one means.
+    pub fn with_options(
+        allowlist: Option<HashSet<String>>,
+        max_languages: Option<usize>,
+        trim_blank_lines: bool,
+    ) -> Self {
+        Self {
+            allowlist,
+            max_languages,
+            trim_blank_lines,
+            languages: HashSet::new(),
+            regions: Vec::new(),
+        }
+    }
+
+    /// Add a document of `len` bytes in `language`, returning its region.
+    ///
+    /// Documents added to the same filter share the language limit, such as
+    /// the two parts of a document with front-matter.
+    pub fn document(&mut self, language: &str, len: usize) -> usize {
+        self.regions.push((language.to_string(), 0..len, None));
+        self.regions.len() - 1
+    }
+
+    /// Language of `region`.
+    pub fn language(&self, region: usize) -> &str {
+        &self.regions[region].0
+    }
+
+    /// Where in `source` to highlight `injection`, found in `region` of it,
+    /// or `None` to leave it plain.
+    ///
+    /// `source` is the whole document `region` belongs to, and the offsets
+    /// of `injection` are relative to the start of `region`.
+    pub fn accept(
+        &self,
+        source: &str,
+        region: usize,
+        injection: &Injection,
+    ) -> Option<Range<usize>> {
+        let (_, parent, _) = &self.regions[region];
+        let mut start = parent.start + injection.start as usize;
+        let mut end = parent.start + injection.end as usize;
+
+        if self
+            .allowlist
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(&injection.language))
+        {
+            return None;
+        }
+        if !self.languages.contains(&injection.language)
+            && self
+                .max_languages
+                .is_some_and(|max| self.languages.len() >= max)
+        {
+            return None;
+        }
+
+        if self.trim_blank_lines && end <= parent.end && start < end {
+            let trimmed = trim_blank_lines(&source[start..end]);
+            end = start + trimmed.end;
+            start += trimmed.start;
+        }
+        if end > parent.end || start >= end {
+            return None;
+        }
+
+        let repeats_ancestor =
+            std::iter::successors(Some(region), |&i| self.regions[i].2).any(|i| {
+                let (language, range, _) = &self.regions[i];
+                *language == injection.language && *range == (start..end)
+            });
+        (!repeats_ancestor).then_some(start..end)
+    }
+
+    /// Record that `language` is highlighted over `range`, a range returned
+    /// by [`accept`](Self::accept) for an injection found in `parent`,
+    /// returning the new region.
+    ///
+    /// Call this once the language's grammar is known to be available, so
+    /// that languages that can't be highlighted don't count towards the
+    /// limit.
+    pub fn enter(&mut self, language: &str, range: Range<usize>, parent: usize) -> usize {
+        if !self.languages.contains(language) {
+            self.languages.insert(language.to_string());
+        }
+        self.regions
+            .push((language.to_string(), range, Some(parent)));
+        self.regions.len() - 1
+    }
+}
@@ -96,30 +96,53 @@
 //! - **`CustomElementsWithPrefix(prefix)`**: Custom elements with your prefix, e.g., `<code-k>`
 //! - **`ClassNames`**: Traditional `<span class="keyword">` for compatibility
 //! - **`ClassNamesWithPrefix(prefix)`**: Namespaced classes like `<span class="arb-keyword">`
+//! - **`FullCaptureNames`**: The whole capture as classes, like `<span class="keyword control import">`
 //!
 //! See [`HtmlFormat`] for examples and use cases.
 
+pub mod events;
+mod injections;
+pub mod lsp;
+mod preview;
 mod render;
+pub mod rtf;
+mod scopes;
 mod types;
+mod unescape;
 
 #[cfg(feature = "tree-sitter")]
 pub mod tree_sitter;
 
+pub use events::{Highlight, HighlightEvent, to_highlight_events};
+pub use injections::InjectionFilter;
+pub use preview::preview_spans;
 pub use render::{
-    AnsiOptions, ThemedSpan, html_escape, spans_to_ansi, spans_to_ansi_with_options, spans_to_html,
-    spans_to_themed, write_spans_as_ansi, write_spans_as_html,
+    AnsiOptions, ControlCharMode, DocumentOptions, LineNumberConfig, RenderedHtml, ThemedSpan,
+    estimate_output_size, html_escape, html_tags_for_slot, line_ranges, normalize_html_output,
+    spans_to_ansi, spans_to_ansi_dimmed, spans_to_ansi_multi_theme, spans_to_ansi_with_colors,
+    spans_to_ansi_with_options, spans_to_html, spans_to_html_document, spans_to_html_exact,
+    spans_to_html_figure, spans_to_html_lines, spans_to_html_with_data_capture,
+    spans_to_html_with_limit, spans_to_html_with_line_numbers, spans_to_html_with_scopes,
+    spans_to_html_with_theme, spans_to_lines, spans_to_themed, used_slots, write_html_escaped_into,
+    write_spans_as_ansi, write_spans_as_html,
 };
-pub use types::{HighlightError, Injection, ParseResult, Span};
+pub use rtf::spans_to_rtf;
+pub use scopes::Scope;
+pub use types::{HighlightError, Injection, InjectionRegion, ParseResult, Span};
+pub use unescape::{CStringUnescaper, StringUnescaper, Unescaped};
 
 #[cfg(feature = "tree-sitter")]
-pub use tree_sitter::{CompiledGrammar, GrammarConfig, GrammarError, ParseContext};
+pub use tree_sitter::{CompiledGrammar, GrammarConfig, GrammarError, ParseContext, ParseTimings};
 
 // Backward compatibility aliases
 #[cfg(feature = "tree-sitter")]
 #[doc(hidden)]
 pub use tree_sitter::{TreeSitterGrammarConfig, TreeSitterGrammarError};
 
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::ops::Range;
+use std::sync::Arc;
 use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
 /// A grammar that can parse text and produce highlight spans.
@@ -138,9 +161,133 @@ pub trait Grammar {
     ///
     /// This is always synchronous - the async part is *getting* the grammar,
     /// not using it.
+    ///
+    /// If this panics, the highlighter reports
+    /// [`HighlightError::GrammarPanic`] (or skips the injection) instead of
+    /// unwinding through the caller.
     fn parse(&mut self, text: &str) -> ParseResult;
 }
 
+/// Byte range of `text` with leading and trailing blank lines removed.
+///
+/// A blank line is one containing only whitespace. Leading blank lines are
+/// removed along with their newlines; trailing ones from the newline ending
+/// the last non-blank line. Returns an empty range if `text` is entirely
+/// blank.
+///
+/// ```
+/// use arborium_highlight::trim_blank_lines;
+///
+/// let text = "\n  \nlet x = 1;\n\n";
+/// assert_eq!(&text[trim_blank_lines(text)], "let x = 1;");
+/// ```
+pub fn trim_blank_lines(text: &str) -> Range<usize> {
+    let Some(first) = text.find(|c: char| !c.is_whitespace()) else {
+        return 0..0;
+    };
+    let last = text.rfind(|c: char| !c.is_whitespace()).unwrap_or(first);
+
+    let start = text[..first].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[last..].find('\n').map_or(text.len(), |i| last + i);
+    start..end
+}
+
+/// Text with the indentation common to all its lines removed, see [`dedent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dedented {
+    /// The dedented text.
+    pub text: String,
+    /// Start of each line in `text`, and the number of bytes removed from
+    /// the original up to and including that line's indentation.
+    lines: Vec<(usize, usize)>,
+}
+
+impl Dedented {
+    /// Map a byte range in [`text`](Self::text) back to the original text.
+    ///
+    /// A range starting at the beginning of a line starts after that line's
+    /// indentation; one ending at the beginning of a line ends before it.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        let removed_before = |offset: usize, include_line_start: bool| {
+            let line = self.lines.partition_point(|&(start, _)| {
+                start < offset || (include_line_start && start == offset)
+            });
+            line.checked_sub(1).map_or(0, |line| self.lines[line].1)
+        };
+        range.start + removed_before(range.start, true)
+            ..range.end + removed_before(range.end, false)
+    }
+}
+
+/// Remove the leading indentation common to all lines of `text`.
+///
+/// Indented blocks (such as a fenced code block inside a Markdown list)
+/// would otherwise reach the injected grammar with indentation it doesn't
+/// expect. Blank lines don't count towards the common indentation. Returns
+/// `None` if there is no common indentation to remove.
+///
+/// ```
+/// use arborium_highlight::dedent;
+///
+/// let text = "    fn main() {\n        run();\n    }";
+/// let dedented = dedent(text).unwrap();
+/// assert_eq!(dedented.text, "fn main() {\n    run();\n}");
+/// assert_eq!(&text[dedented.original_range(16..21)], "run()");
+/// ```
+pub fn dedent(text: &str) -> Option<Dedented> {
+    fn indentation(line: &str) -> &str {
+        let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        &line[..len]
+    }
+
+    let mut common: Option<&str> = None;
+    for line in text.split('\n') {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = indentation(line);
+        let shared = common.map_or(indent.len(), |common| {
+            common
+                .bytes()
+                .zip(indent.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        common = Some(&indent[..shared]);
+    }
+    let common = common.filter(|common| !common.is_empty())?;
+
+    let mut dedented = Dedented {
+        text: String::with_capacity(text.len()),
+        lines: Vec::new(),
+    };
+    let mut removed = 0;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            dedented.text.push('\n');
+        }
+        // Blank lines may be shorter than the common indentation
+        let strip = indentation(line).len().min(common.len());
+        removed += strip;
+        dedented.lines.push((dedented.text.len(), removed));
+        dedented.text.push_str(&line[strip..]);
+    }
+    Some(dedented)
+}
+
+/// Run `grammar.parse`, turning a panic into [`HighlightError::GrammarPanic`].
+fn parse_catching_panics<G: Grammar>(
+    grammar: &mut G,
+    language: &str,
+    text: &str,
+) -> Result<ParseResult, HighlightError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| grammar.parse(text))).map_err(|_| {
+        HighlightError::GrammarPanic {
+            language: language.into(),
+        }
+    })
+}
+
 /// Provides grammars for languages.
 ///
 /// This trait abstracts over how grammars are obtained:
@@ -185,7 +332,7 @@ pub trait GrammarProvider {
 }
 
 /// HTML output format for syntax highlighting.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum HtmlFormat {
     /// Custom elements with default prefix: `<a-k>`, `<a-f>`, etc. (default)
     ///
@@ -228,6 +375,23 @@ pub enum HtmlFormat {
     /// <span class="arb-keyword">fn</span> <span class="arb-function">main</span>()
     /// ```
     ClassNamesWithPrefix(String),
+
+    /// Class-based spans named after the full capture, with its dots turned
+    /// into spaces: `keyword.control.import` becomes
+    /// `<span class="keyword control import">`.
+    ///
+    /// For stylesheets written against highlight.js or the tree-sitter CLI
+    /// that target detailed capture names. Captures aren't mapped to theme
+    /// slots, so captures Arborium has no slot for are kept, and adjacent
+    /// spans are only merged when their captures are the same. Where there
+    /// is only a slot to go by (e.g. [`html_tags_for_slot`]), its name is
+    /// used, as with [`ClassNames`](Self::ClassNames).
+    ///
+    /// # Example
+    /// ```html
+    /// <span class="keyword function">fn</span> <span class="function">main</span>()
+    /// ```
+    FullCaptureNames,
 }
 
 impl Default for HtmlFormat {
@@ -237,6 +401,12 @@ impl Default for HtmlFormat {
 }
 
 /// Configuration for highlighting.
+///
+/// There is no counterpart to the umbrella crate's `suppress_error_regions`:
+/// dropping spans inside ERROR nodes needs the syntax tree, and a
+/// [`Grammar`] only returns spans. When parsing with tree-sitter directly
+/// (the `tree-sitter` feature), use
+/// `ParseContext::set_suppress_error_regions`.
 #[derive(Debug, Clone)]
 pub struct HighlightConfig {
     /// Maximum depth for processing language injections.
@@ -248,6 +418,101 @@ pub struct HighlightConfig {
 
     /// HTML output format (custom elements vs class-based spans).
     pub html_format: HtmlFormat,
+
+    /// Languages that injections may be processed as.
+    ///
+    /// - `None`: Default, any injected language the provider knows about
+    /// - `Some(set)`: Only languages in the set; other injections are skipped
+    ///   without asking the provider for a grammar. An empty set disables
+    ///   injections entirely.
+    ///
+    /// Useful when highlighting untrusted input, so a document can't trigger
+    /// loading arbitrary grammars.
+    pub injection_allowlist: Option<HashSet<String>>,
+
+    /// Maximum number of distinct languages injections may be processed as
+    /// in one document.
+    ///
+    /// Once that many languages have been injected, injections of other
+    /// languages are skipped without asking the provider for a grammar;
+    /// those already injected are still processed. `None` (the default)
+    /// means no limit. Unlike [`injection_allowlist`](Self::injection_allowlist),
+    /// this bounds how many grammars untrusted input can load without fixing
+    /// which ones.
+    pub max_injected_languages: Option<usize>,
+
+    /// Trim leading and trailing blank lines from injected regions before
+    /// parsing them.
+    ///
+    /// Fenced code blocks often start or end with empty lines that would
+    /// otherwise be handed to the injected grammar. Off by default so output
+    /// matches the source exactly.
+    pub trim_injection_blank_lines: bool,
+
+    /// Remove the indentation common to all lines of an injected region
+    /// before parsing it (see [`dedent`]).
+    ///
+    /// The grammar sees un-indented code, and the resulting spans are mapped
+    /// back, so the output keeps the original indentation. Off by default.
+    pub dedent_injections: bool,
+
+    /// Maximum size of the rendered HTML, in bytes.
+    ///
+    /// Highlighting fails with [`HighlightError::OutputTooLarge`] instead of
+    /// producing more. `None` (the default) means no limit. Use
+    /// [`spans_to_html_with_limit`] directly to get the truncated output.
+    pub max_output_bytes: Option<usize>,
+
+    /// How control characters in the source are rendered. Defaults to
+    /// [`ControlCharMode::Picture`].
+    pub control_char_handling: ControlCharMode,
+
+    /// Language to highlight documents as when their own language isn't
+    /// supported, e.g. a generic grammar that picks out strings, numbers and
+    /// comments.
+    ///
+    /// `None` (the default) fails with
+    /// [`HighlightError::UnsupportedLanguage`] instead. Injections are not
+    /// affected: unsupported injected languages are always left plain.
+    pub fallback_language: Option<String>,
+
+    /// Render one HTML element per span, nested, instead of deduplicating
+    /// and merging spans. See [`spans_to_html_exact`]. Defaults to `false`.
+    pub exact_spans: bool,
+
+    /// Wrap each line of the HTML output in an element carrying its line
+    /// number, `<span class="line" data-line="N">` by default, for CSS to
+    /// number. See [`spans_to_html_with_line_numbers`]. Not applied with
+    /// [`exact_spans`](Self::exact_spans), whose nested elements can't be
+    /// split at line breaks. `None` (the default) leaves lines unwrapped.
+    pub line_numbers: Option<LineNumberConfig>,
+
+    /// String unescapers for injections, by host language.
+    ///
+    /// Text injected into a language in this map is unescaped with its
+    /// unescaper before being parsed, so code embedded in a string literal
+    /// (such as SQL in a C string) reaches the injected grammar as written
+    /// rather than escaped; its spans are mapped back to the escaped source.
+    /// Empty by default.
+    pub string_unescapers: HashMap<String, Arc<dyn StringUnescaper>>,
+
+    /// Drop a leading byte order mark from the HTML output. Span offsets
+    /// are unaffected. Defaults to `false`.
+    pub strip_bom: bool,
+
+    /// Write `\r\n` and lone `\r` line endings as `\n` in the HTML output
+    /// (see [`normalize_html_output`]). Span offsets are unaffected.
+    /// Defaults to `false`.
+    pub normalize_line_endings: bool,
+}
+
+impl HighlightConfig {
+    /// Returns `true` if injections of `language` should be processed.
+    pub fn allows_injection(&self, language: &str) -> bool {
+        self.injection_allowlist
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(language))
+    }
 }
 
 impl Default for HighlightConfig {
@@ -255,6 +520,18 @@ impl Default for HighlightConfig {
         Self {
             max_injection_depth: 3,
             html_format: HtmlFormat::default(),
+            injection_allowlist: None,
+            max_injected_languages: None,
+            trim_injection_blank_lines: false,
+            dedent_injections: false,
+            max_output_bytes: None,
+            control_char_handling: ControlCharMode::default(),
+            fallback_language: None,
+            exact_spans: false,
+            line_numbers: None,
+            string_unescapers: HashMap::new(),
+            strip_bom: false,
+            normalize_line_endings: false,
         }
     }
 }
@@ -288,23 +565,33 @@ impl<P: GrammarProvider> HighlighterCore<P> {
         language: &str,
         source: &str,
     ) -> Result<Vec<Span>, HighlightError> {
-        // 1. Get the primary grammar
+        // 1. Get the primary grammar, or the fallback one
+        let mut grammar_language = language;
+        if self.provider.get(language).await.is_none() {
+            grammar_language = self
+                .config
+                .fallback_language
+                .as_deref()
+                .ok_or_else(|| HighlightError::UnsupportedLanguage(language.into()))?;
+        }
         let grammar = self
             .provider
-            .get(language)
+            .get(grammar_language)
             .await
             .ok_or_else(|| HighlightError::UnsupportedLanguage(language.into()))?;
 
         // 2. Parse the primary language
-        let result = grammar.parse(source);
+        let result = parse_catching_panics(grammar, grammar_language, source)?;
 
         // 3. Collect all spans (including from injections)
         let mut all_spans = result.spans;
 
-        // 4. Process injections recursively
+        // 4. Process injections, including nested ones
         if self.config.max_injection_depth > 0 {
+            let grammar_language = grammar_language.to_string();
             self.process_injections(
                 source,
+                &grammar_language,
                 result.injections,
                 0,
                 self.config.max_injection_depth,
@@ -319,59 +606,161 @@ impl<P: GrammarProvider> HighlighterCore<P> {
     /// The main highlight function - written once, used by both wrappers.
     async fn highlight(&mut self, language: &str, source: &str) -> Result<String, HighlightError> {
         let spans = self.highlight_spans(language, source).await?;
-        Ok(spans_to_html(source, spans, &self.config.html_format))
+        let rendered = if self.config.exact_spans {
+            spans_to_html_exact(
+                source,
+                spans,
+                &self.config.html_format,
+                self.config.max_output_bytes,
+                self.config.control_char_handling,
+                false,
+            )
+        } else if let Some(line_numbers) = &self.config.line_numbers {
+            spans_to_html_with_line_numbers(
+                source,
+                spans,
+                &self.config.html_format,
+                line_numbers,
+                self.config.max_output_bytes,
+                self.config.control_char_handling,
+            )
+        } else {
+            spans_to_html_with_limit(
+                source,
+                spans,
+                &self.config.html_format,
+                self.config.max_output_bytes,
+                self.config.control_char_handling,
+            )
+        };
+        if rendered.truncated {
+            return Err(HighlightError::OutputTooLarge {
+                limit: self.config.max_output_bytes.unwrap_or_default(),
+            });
+        }
+        let (strip_bom, normalize_line_endings) =
+            (self.config.strip_bom, self.config.normalize_line_endings);
+        if strip_bom || normalize_line_endings {
+            return Ok(normalize_html_output(
+                &rendered.html,
+                strip_bom,
+                normalize_line_endings,
+            ));
+        }
+        Ok(rendered.html)
     }
 
-    /// Process injections recursively.
+    /// Process injections, and the injections inside them, down to
+    /// `max_depth` levels.
+    ///
+    /// Every injection is parsed as a separate document, so nothing from the
+    /// host parse (trees, query state, or local scopes) carries into it;
+    /// only the resulting spans are offset and merged.
+    ///
+    /// Nested injections are handled with an explicit stack rather than
+    /// recursion, so deep nesting doesn't allocate a boxed future per level.
+    /// They are processed depth-first, in the order the recursive version
+    /// would, so spans come out in the same order.
+    ///
+    /// An injection of the same language over the same text as one of the
+    /// regions it's nested in would only repeat that region's spans, so it
+    /// is skipped rather than let use up the depth budget.
     async fn process_injections(
         &mut self,
         source: &str,
+        language: &str,
         injections: Vec<Injection>,
         base_offset: u32,
-        remaining_depth: u32,
+        max_depth: u32,
         all_spans: &mut Vec<Span>,
     ) {
-        if remaining_depth == 0 {
-            return;
-        }
-
-        for injection in injections {
-            let start = injection.start as usize;
-            let end = injection.end as usize;
-
-            if end <= source.len() && start < end {
-                // Try to get grammar for injected language
-                if let Some(inj_grammar) = self.provider.get(&injection.language).await {
-                    let injected_text = &source[start..end];
-                    let result = inj_grammar.parse(injected_text);
-
-                    // Adjust offsets and add spans
-                    let adjusted_spans: Vec<Span> = result
-                        .spans
+        let mut filter = InjectionFilter::new(&self.config);
+        let document = filter.document(language, source.len());
+        // Injections left to process, next last: the injection, the index
+        // of the region it's relative to, and how many levels of injections
+        // may still be processed
+        let mut pending: Vec<(Injection, usize, u32)> = Vec::new();
+        let push_all = |pending: &mut Vec<_>, injections: Vec<Injection>, region, depth| {
+            if depth > 0 {
+                pending.extend(
+                    injections
                         .into_iter()
-                        .map(|mut s| {
-                            s.start += base_offset + injection.start;
-                            s.end += base_offset + injection.start;
-                            s
-                        })
-                        .collect();
-                    all_spans.extend(adjusted_spans);
-
-                    // Recurse into nested injections
-                    if !result.injections.is_empty() {
-                        // Box the recursive call to avoid infinite type size
-                        Box::pin(self.process_injections(
-                            injected_text,
-                            result.injections,
-                            base_offset + injection.start,
-                            remaining_depth - 1,
-                            all_spans,
-                        ))
-                        .await;
-                    }
+                        .rev()
+                        .map(|injection| (injection, region, depth)),
+                );
+            }
+        };
+        push_all(&mut pending, injections, document, max_depth);
+
+        while let Some((injection, region, depth)) = pending.pop() {
+            let Some(range) = filter.accept(source, region, &injection) else {
+                continue;
+            };
+            // Try to get grammar for injected language; if it's not
+            // available, skip this injection silently
+            let Some(inj_grammar) = self.provider.get(&injection.language).await else {
+                continue;
+            };
+            let (start, end) = (range.start, range.end);
+            let injected = filter.enter(&injection.language, range, region);
+            let injected_text = &source[start..end];
+            let unescaped = self
+                .config
+                .string_unescapers
+                .get(filter.language(region))
+                .and_then(|unescaper| unescaper.unescape(injected_text));
+            let unescaped_text = unescaped.as_ref().map_or(injected_text, |u| &u.text);
+            let dedented = if self.config.dedent_injections {
+                dedent(unescaped_text)
+            } else {
+                None
+            };
+            let parsed_text = dedented.as_ref().map_or(unescaped_text, |d| &d.text);
+            // A panicking injected grammar only loses its own spans
+            let Ok(mut result) =
+                parse_catching_panics(inj_grammar, &injection.language, parsed_text)
+            else {
+                continue;
+            };
+
+            // Back to offsets in the injected text
+            if dedented.is_some() || unescaped.is_some() {
+                let original_range = |range: Range<usize>| {
+                    let range = dedented
+                        .as_ref()
+                        .map_or(range.clone(), |d| d.original_range(range));
+                    unescaped
+                        .as_ref()
+                        .map_or(range.clone(), |u| u.original_range(range))
+                };
+                for s in &mut result.spans {
+                    let range = original_range(s.start as usize..s.end as usize);
+                    (s.start, s.end) = (range.start as u32, range.end as u32);
+                }
+                for i in &mut result.injections {
+                    let range = original_range(i.start as usize..i.end as usize);
+                    (i.start, i.end) = (range.start as u32, range.end as u32);
                 }
-                // If grammar not available, skip this injection silently
             }
+
+            // A buggy grammar may return spans past the end of the text it
+            // was given; keep them within the injection
+            let len = (end - start) as u32;
+            result.spans.retain_mut(|s| {
+                s.end = s.end.min(len);
+                s.start < s.end
+            });
+
+            // Adjust offsets and add spans
+            let offset = base_offset + start as u32;
+            all_spans.extend(result.spans.into_iter().map(|mut s| {
+                s.start += offset;
+                s.end += offset;
+                s
+            }));
+
+            // Nested injections come next, before this one's siblings
+            push_all(&mut pending, result.injections, injected, depth - 1);
         }
     }
 }
@@ -479,6 +868,33 @@ impl<P: GrammarProvider> SyncHighlighter<P> {
             }
         }
     }
+
+    /// Highlight source code synchronously and return its spans grouped by
+    /// line, with offsets relative to each line.
+    ///
+    /// See [`spans_to_lines`] for how lines and multi-line spans are
+    /// handled.
+    pub fn highlight_lines(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<Vec<Vec<Span>>, HighlightError> {
+        let future = self.core.highlight_spans(language, source);
+
+        let mut future = std::pin::pin!(future);
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(spans)) => Ok(spans_to_lines(source, &spans)),
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => {
+                panic!(
+                    "SyncHighlighter: provider yielded. Use AsyncHighlighter for async providers."
+                )
+            }
+        }
+    }
 }
 
 /// Asynchronous highlighter for WASM/browser contexts.
@@ -525,6 +941,20 @@ impl<P: GrammarProvider> AsyncHighlighter<P> {
     ) -> Result<String, HighlightError> {
         self.core.highlight(language, source).await
     }
+
+    /// Highlight source code asynchronously and return its spans grouped by
+    /// line, with offsets relative to each line.
+    ///
+    /// See [`spans_to_lines`] for how lines and multi-line spans are
+    /// handled.
+    pub async fn highlight_lines(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<Vec<Vec<Span>>, HighlightError> {
+        let spans = self.core.highlight_spans(language, source).await?;
+        Ok(spans_to_lines(source, &spans))
+    }
 }
 
 /// Create a no-op waker for sync polling.
@@ -581,13 +1011,9 @@ mod tests {
                 "test",
                 MockGrammar {
                     result: ParseResult {
-                        spans: vec![Span {
-                            start: 0,
-                            end: 2,
-                            capture: "keyword".into(),
-                            pattern_index: 0,
-                        }],
+                        spans: vec![Span::new(0, 2, "keyword")],
                         injections: vec![],
+                        truncated: false,
                     },
                 },
             )]
@@ -614,6 +1040,7 @@ mod tests {
                                 language: "inner".into(),
                                 include_children: false,
                             }],
+                            truncated: false,
                         },
                     },
                 ),
@@ -621,13 +1048,128 @@ mod tests {
                     "inner",
                     MockGrammar {
                         result: ParseResult {
-                            spans: vec![Span {
+                            spans: vec![Span::new(0, 5, "string")],
+                            injections: vec![],
+                            truncated: false,
+                        },
+                    },
+                ),
+            ]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let html = highlighter.highlight("outer", "hello").unwrap();
+        assert_eq!(html, "<a-s>hello</a-s>");
+    }
+
+    #[test]
+    fn test_nested_injections() {
+        // Each grammar highlights its first character and injects the next
+        // one into the rest of its text
+        let grammar = |capture: &str, injected: &str, len| MockGrammar {
+            result: ParseResult {
+                spans: vec![Span::new(0, 1, capture)],
+                injections: vec![Injection {
+                    start: 1,
+                    end: len,
+                    language: injected.into(),
+                    include_children: false,
+                }],
+                truncated: false,
+            },
+        };
+        let highlight = |max_injection_depth| {
+            let provider = MockProvider {
+                grammars: [
+                    ("l0", grammar("keyword", "l1", 8)),
+                    ("l1", grammar("string", "l2", 7)),
+                    ("l2", grammar("comment", "l3", 6)),
+                    ("l3", grammar("number", "l0", 5)),
+                ]
+                .into(),
+            };
+            let config = HighlightConfig {
+                max_injection_depth,
+                ..Default::default()
+            };
+            let mut highlighter = SyncHighlighter::with_config(provider, config);
+            highlighter.highlight("l0", "abcdefgh").unwrap()
+        };
+
+        assert_eq!(
+            highlight(3),
+            "<a-k>a</a-k><a-s>b</a-s><a-c>c</a-c><a-n>d</a-n>efgh"
+        );
+        assert_eq!(highlight(2), "<a-k>a</a-k><a-s>b</a-s><a-c>c</a-c>defgh");
+    }
+
+    #[test]
+    fn test_self_injection_is_skipped() {
+        // Each grammar highlights all of its text and injects the other one
+        // over all of it
+        let grammar = |injected: &str| MockGrammar {
+            result: ParseResult {
+                spans: vec![Span::new(0, 5, "string")],
+                injections: vec![Injection {
+                    start: 0,
+                    end: 5,
+                    language: injected.into(),
+                    include_children: false,
+                }],
+                truncated: false,
+            },
+        };
+        let parses = |grammars: Vec<(&'static str, MockGrammar)>| {
+            let provider = MockProvider {
+                grammars: grammars.into_iter().collect(),
+            };
+            let config = HighlightConfig {
+                max_injection_depth: 3,
+                ..Default::default()
+            };
+            let mut highlighter = SyncHighlighter::with_config(provider, config);
+            // Spans aren't deduplicated yet: one per parse
+            highlighter.highlight_lines("outer", "hello").unwrap()[0].len()
+        };
+
+        assert_eq!(parses(vec![("outer", grammar("outer"))]), 1);
+        // Repeating an ancestor further up is caught too
+        assert_eq!(
+            parses(vec![
+                ("outer", grammar("inner")),
+                ("inner", grammar("outer"))
+            ]),
+            2
+        );
+    }
+
+    #[test]
+    fn test_injection_allowlist() {
+        let provider = MockProvider {
+            grammars: [
+                (
+                    "outer",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![],
+                            injections: vec![Injection {
                                 start: 0,
                                 end: 5,
-                                capture: "string".into(),
-                                pattern_index: 0,
+                                language: "inner".into(),
+                                include_children: false,
                             }],
+                            truncated: false,
+                        },
+                    },
+                ),
+                (
+                    "inner",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![Span::new(0, 5, "string")],
                             injections: vec![],
+                            truncated: false,
                         },
                     },
                 ),
@@ -635,11 +1177,265 @@ mod tests {
             .into(),
         };
 
-        let mut highlighter = SyncHighlighter::new(provider);
+        let config = HighlightConfig {
+            injection_allowlist: Some(["css".to_string()].into()),
+            ..Default::default()
+        };
+        let mut highlighter = SyncHighlighter::with_config(provider, config);
+        let html = highlighter.highlight("outer", "hello").unwrap();
+        assert_eq!(html, "hello");
+
+        highlighter.core.config.injection_allowlist = Some(["inner".to_string()].into());
         let html = highlighter.highlight("outer", "hello").unwrap();
         assert_eq!(html, "<a-s>hello</a-s>");
     }
 
+    #[test]
+    fn test_max_injected_languages() {
+        let injection = |start, language: &str| Injection {
+            start,
+            end: start + 1,
+            language: language.into(),
+            include_children: false,
+        };
+        let inner = || MockGrammar {
+            result: ParseResult {
+                spans: vec![Span::new(0, 1, "string")],
+                injections: vec![],
+                truncated: false,
+            },
+        };
+        let provider = MockProvider {
+            grammars: [
+                (
+                    "outer",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![],
+                            // A third language, then the first one again
+                            injections: vec![
+                                injection(0, "a"),
+                                injection(1, "b"),
+                                injection(2, "c"),
+                                injection(3, "a"),
+                            ],
+                            truncated: false,
+                        },
+                    },
+                ),
+                ("a", inner()),
+                ("b", inner()),
+                ("c", inner()),
+            ]
+            .into(),
+        };
+
+        let config = HighlightConfig {
+            max_injected_languages: Some(2),
+            ..Default::default()
+        };
+        let mut highlighter = SyncHighlighter::with_config(provider, config);
+        let html = highlighter.highlight("outer", "abcd").unwrap();
+        assert_eq!(html, "<a-s>ab</a-s>c<a-s>d</a-s>");
+
+        highlighter.core.config.max_injected_languages = None;
+        let html = highlighter.highlight("outer", "abcd").unwrap();
+        assert_eq!(html, "<a-s>abcd</a-s>");
+    }
+
+    #[test]
+    fn test_trim_injection_blank_lines() {
+        // The inner grammar highlights its first two bytes; with trimming those
+        // are "ab" rather than the leading blank lines.
+        let source = "\n\nab\n\n";
+        let provider = MockProvider {
+            grammars: [
+                (
+                    "outer",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![],
+                            injections: vec![Injection {
+                                start: 0,
+                                end: source.len() as u32,
+                                language: "inner".into(),
+                                include_children: false,
+                            }],
+                            truncated: false,
+                        },
+                    },
+                ),
+                (
+                    "inner",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![Span::new(0, 2, "string")],
+                            injections: vec![],
+                            truncated: false,
+                        },
+                    },
+                ),
+            ]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let html = highlighter.highlight("outer", source).unwrap();
+        assert_eq!(html, "<a-s>\n\n</a-s>ab");
+
+        highlighter.core.config.trim_injection_blank_lines = true;
+        let html = highlighter.highlight("outer", source).unwrap();
+        assert_eq!(html, "\n\n<a-s>ab</a-s>");
+    }
+
+    #[test]
+    fn test_trim_blank_lines() {
+        assert_eq!(trim_blank_lines(""), 0..0);
+        assert_eq!(trim_blank_lines(" \n\t\n"), 0..0);
+        assert_eq!(trim_blank_lines("ab"), 0..2);
+        assert_eq!(trim_blank_lines("  ab  "), 0..6);
+        assert_eq!(trim_blank_lines("\n  ab\n \n"), 1..5);
+    }
+
+    #[test]
+    fn test_dedent() {
+        assert_eq!(dedent("ab\n  cd"), None);
+        assert_eq!(dedent(""), None);
+
+        // Blank lines don't count, and may be shorter than the indentation
+        let text = "    a\n\n      b\n  \n    c";
+        let dedented = dedent(text).unwrap();
+        assert_eq!(dedented.text, "a\n\n  b\n\nc");
+        for (word, range) in [("a", 0..1), ("b", 5..6), ("c", 8..9)] {
+            assert_eq!(&text[dedented.original_range(range)], word);
+        }
+        // Ranges ending at a line start don't take its indentation
+        assert_eq!(&text[dedented.original_range(0..3)], "a\n\n");
+
+        // Only indentation shared byte for byte is removed
+        assert_eq!(dedent("\t  a\n\t b").unwrap().text, " a\nb");
+    }
+
+    #[test]
+    fn test_string_unescapers() {
+        // SQL in a C string literal; the SQL grammar's spans are relative to
+        // the unescaped text `SELECT "name" FROM t`
+        let source = r#"q("SELECT \"name\" FROM t");"#;
+        let provider = MockProvider {
+            grammars: [
+                (
+                    "c",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![],
+                            injections: vec![Injection {
+                                start: 3,
+                                end: 24,
+                                language: "sql".into(),
+                                include_children: false,
+                            }],
+                            truncated: false,
+                        },
+                    },
+                ),
+                (
+                    "sql",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: [(0, 6, "keyword"), (7, 13, "variable"), (14, 18, "keyword")]
+                                .into_iter()
+                                .map(|(start, end, capture)| Span::new(start, end, capture))
+                                .collect(),
+                            injections: vec![],
+                            truncated: false,
+                        },
+                    },
+                ),
+            ]
+            .into(),
+        };
+
+        let config = HighlightConfig {
+            string_unescapers: [(
+                "c".to_string(),
+                Arc::new(CStringUnescaper) as Arc<dyn StringUnescaper>,
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let mut highlighter = SyncHighlighter::with_config(provider, config);
+        let html = highlighter.highlight("c", source).unwrap();
+        assert_eq!(
+            html,
+            r#"q(&quot;<a-k>SELECT</a-k> <a-v>\&quot;name\&quot;</a-v> <a-k>FROM</a-k> t&quot;);"#
+        );
+    }
+
+    #[test]
+    fn test_output_normalization() {
+        let source = "\u{feff}fn a\r\nfn b\r\n";
+        let provider = MockProvider {
+            grammars: [(
+                "test",
+                MockGrammar {
+                    result: ParseResult {
+                        // The comment ends between the `\r` and the `\n`
+                        spans: [(3, 5, "keyword"), (6, 8, "comment"), (9, 11, "keyword")]
+                            .into_iter()
+                            .map(|(start, end, capture)| Span::new(start, end, capture))
+                            .collect(),
+                        injections: vec![],
+                        truncated: false,
+                    },
+                },
+            )]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let html = highlighter.highlight("test", source).unwrap();
+        assert_eq!(
+            html,
+            "\u{feff}<a-k>fn</a-k> <a-c>a\r</a-c>\n<a-k>fn</a-k> b\r"
+        );
+
+        highlighter.core.config.strip_bom = true;
+        highlighter.core.config.normalize_line_endings = true;
+        let html = highlighter.highlight("test", source).unwrap();
+        assert_eq!(html, "<a-k>fn</a-k> <a-c>a\n</a-c><a-k>fn</a-k> b");
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let provider = MockProvider {
+            grammars: [(
+                "test",
+                MockGrammar {
+                    result: ParseResult {
+                        spans: vec![Span::new(0, 2, "keyword")],
+                        injections: vec![],
+                        truncated: false,
+                    },
+                },
+            )]
+            .into(),
+        };
+
+        let config = HighlightConfig {
+            max_output_bytes: Some(8),
+            ..Default::default()
+        };
+        let mut highlighter = SyncHighlighter::with_config(provider, config);
+        let result = highlighter.highlight("test", "fn");
+        assert_eq!(result, Err(HighlightError::OutputTooLarge { limit: 8 }));
+
+        highlighter.core.config.max_output_bytes = Some(13);
+        assert_eq!(
+            highlighter.highlight("test", "fn").unwrap(),
+            "<a-k>fn</a-k>"
+        );
+    }
+
     #[test]
     fn test_unsupported_language() {
         let provider = MockProvider {
@@ -654,6 +1450,161 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_fallback_language() {
+        let provider = MockProvider {
+            grammars: [(
+                "generic",
+                MockGrammar {
+                    result: ParseResult {
+                        spans: vec![Span::new(0, 3, "string")],
+                        injections: vec![],
+                        truncated: false,
+                    },
+                },
+            )]
+            .into(),
+        };
+
+        let config = HighlightConfig {
+            fallback_language: Some("generic".into()),
+            ..Default::default()
+        };
+        let mut highlighter = SyncHighlighter::with_config(provider, config);
+        let html = highlighter.highlight("unknown", "\"a\" x").unwrap();
+        assert_eq!(html, "<a-s>&quot;a&quot;</a-s> x");
+    }
+
+    /// Grammar whose parse panics, standing in for a buggy external scanner
+    struct PanickingGrammar;
+
+    impl Grammar for PanickingGrammar {
+        fn parse(&mut self, _text: &str) -> ParseResult {
+            panic!("external scanner blew up");
+        }
+    }
+
+    enum MaybePanickingGrammar {
+        Mock(MockGrammar),
+        Panicking(PanickingGrammar),
+    }
+
+    impl Grammar for MaybePanickingGrammar {
+        fn parse(&mut self, text: &str) -> ParseResult {
+            match self {
+                MaybePanickingGrammar::Mock(g) => g.parse(text),
+                MaybePanickingGrammar::Panicking(g) => g.parse(text),
+            }
+        }
+    }
+
+    struct MaybePanickingProvider {
+        grammars: HashMap<&'static str, MaybePanickingGrammar>,
+    }
+
+    impl GrammarProvider for MaybePanickingProvider {
+        type Grammar = MaybePanickingGrammar;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        async fn get(&mut self, language: &str) -> Option<&mut Self::Grammar> {
+            self.grammars.get_mut(language)
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        async fn get(&mut self, language: &str) -> Option<&mut Self::Grammar> {
+            self.grammars.get_mut(language)
+        }
+    }
+
+    #[test]
+    fn test_grammar_panic() {
+        let provider = MaybePanickingProvider {
+            grammars: [("broken", MaybePanickingGrammar::Panicking(PanickingGrammar))].into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let result = highlighter.highlight("broken", "code");
+        assert_eq!(
+            result,
+            Err(HighlightError::GrammarPanic {
+                language: "broken".into()
+            })
+        );
+
+        // The highlighter is still usable afterwards
+        let result = highlighter.highlight("broken", "code");
+        assert!(matches!(result, Err(HighlightError::GrammarPanic { .. })));
+    }
+
+    #[test]
+    fn test_injected_grammar_panic_is_skipped() {
+        let provider = MaybePanickingProvider {
+            grammars: [
+                (
+                    "outer",
+                    MaybePanickingGrammar::Mock(MockGrammar {
+                        result: ParseResult {
+                            spans: vec![Span::new(0, 2, "keyword")],
+                            injections: vec![Injection {
+                                start: 3,
+                                end: 8,
+                                language: "broken".into(),
+                                include_children: false,
+                            }],
+                            truncated: false,
+                        },
+                    }),
+                ),
+                ("broken", MaybePanickingGrammar::Panicking(PanickingGrammar)),
+            ]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let html = highlighter.highlight("outer", "fn hello").unwrap();
+        assert_eq!(html, "<a-k>fn</a-k> hello");
+    }
+
+    #[test]
+    fn test_oversized_injected_spans_are_clamped() {
+        let provider = MockProvider {
+            grammars: [
+                (
+                    "outer",
+                    MockGrammar {
+                        result: ParseResult {
+                            spans: vec![],
+                            injections: vec![Injection {
+                                start: 1,
+                                end: 3,
+                                language: "inner".into(),
+                                include_children: false,
+                            }],
+                            truncated: false,
+                        },
+                    },
+                ),
+                (
+                    "inner",
+                    MockGrammar {
+                        result: ParseResult {
+                            // Far past the end of both the injection and the
+                            // source, and entirely outside the injection
+                            spans: vec![Span::new(0, 100, "string"), Span::new(2, 3, "keyword")],
+                            injections: vec![],
+                            truncated: false,
+                        },
+                    },
+                ),
+            ]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let html = highlighter.highlight("outer", "abcdef").unwrap();
+        assert_eq!(html, "a<a-s>bc</a-s>def");
+    }
+
     #[test]
     fn test_reuse_with_shorter_text() {
         // Regression test: reusing a highlighter with a shorter string
@@ -664,13 +1615,9 @@ mod tests {
                 "test",
                 MockGrammar {
                     result: ParseResult {
-                        spans: vec![Span {
-                            start: 0,
-                            end: 2,
-                            capture: "keyword".into(),
-                            pattern_index: 0,
-                        }],
+                        spans: vec![Span::new(0, 2, "keyword")],
                         injections: vec![],
+                        truncated: false,
                     },
                 },
             )]
@@ -686,21 +1633,42 @@ mod tests {
         let _ = highlighter.highlight("test", "short");
     }
 
+    #[test]
+    fn test_highlight_lines() {
+        let provider = MockProvider {
+            grammars: [(
+                "test",
+                MockGrammar {
+                    result: ParseResult {
+                        // A keyword, then a block comment over three lines
+                        spans: vec![Span::new(6, 14, "comment"), Span::new(0, 2, "keyword")],
+                        injections: vec![],
+                        truncated: false,
+                    },
+                },
+            )]
+            .into(),
+        };
+
+        let mut highlighter = SyncHighlighter::new(provider);
+        let lines = highlighter
+            .highlight_lines("test", "fn x; /*\na\nb*/ y\n\n")
+            .unwrap();
+        assert_eq!(
+            lines,
+            [
+                vec![Span::new(0, 2, "keyword"), Span::new(6, 8, "comment")],
+                vec![Span::new(0, 1, "comment")],
+                vec![Span::new(0, 3, "comment")],
+            ]
+        );
+    }
+
     #[test]
     fn test_span_coalescing() {
         let spans = vec![
-            Span {
-                start: 0,
-                end: 3,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "keyword.function".into(),
-                pattern_index: 0,
-            },
+            Span::new(0, 3, "keyword"),
+            Span::new(3, 7, "keyword.function"),
         ];
         let html = spans_to_html("keyword", spans, &HtmlFormat::default());
         assert_eq!(html, "<a-k>keyword</a-k>");
@@ -0,0 +1,386 @@
+//! LSP semantic tokens from highlight spans.
+//!
+//! Language servers can use arborium to answer `textDocument/semanticTokens`
+//! requests. [`to_semantic_tokens`] turns the spans of a document into the
+//! delta-encoded token stream the protocol expects, using the legend in
+//! [`TOKEN_TYPES`] and [`TOKEN_MODIFIERS`].
+//!
+//! Positions are in UTF-16 code units (the protocol's default position
+//! encoding). Tokens never overlap and never span lines: nested spans are
+//! resolved innermost-wins, and multi-line spans (block comments, raw
+//! strings) are split into one token per line.
+
+use arborium_theme::Slot;
+
+use crate::Span;
+
+/// A semantic token in the LSP wire format.
+///
+/// `delta_line` is relative to the previous token's line; `delta_start` is
+/// relative to the previous token's start if both are on the same line, and
+/// to the start of the line otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    /// Line offset from the previous token.
+    pub delta_line: u32,
+    /// Start column offset (UTF-16) from the previous token, or from the line start.
+    pub delta_start: u32,
+    /// Token length in UTF-16 code units.
+    pub length: u32,
+    /// Index into [`TOKEN_TYPES`].
+    pub token_type: u32,
+    /// Bit set of indices into [`TOKEN_MODIFIERS`].
+    pub token_modifiers: u32,
+}
+
+/// Token type legend, as advertised in the server's
+/// `SemanticTokensLegend.tokenTypes`.
+///
+/// These are standard LSP token types; [`SemanticToken::token_type`] indexes
+/// into this list.
+pub const TOKEN_TYPES: &[&str] = &[
+    "namespace",
+    "type",
+    "class",
+    "parameter",
+    "variable",
+    "property",
+    "function",
+    "method",
+    "macro",
+    "keyword",
+    "comment",
+    "string",
+    "number",
+    "regexp",
+    "operator",
+    "decorator",
+    "label",
+];
+
+/// Token modifier legend, as advertised in the server's
+/// `SemanticTokensLegend.tokenModifiers`.
+///
+/// Bit `i` of [`SemanticToken::token_modifiers`] stands for `TOKEN_MODIFIERS[i]`.
+pub const TOKEN_MODIFIERS: &[&str] = &["readonly", "defaultLibrary", "documentation"];
+
+const READONLY: u32 = 1 << 0;
+const DEFAULT_LIBRARY: u32 = 1 << 1;
+const DOCUMENTATION: u32 = 1 << 2;
+
+/// LSP token type and modifiers for a capture name.
+///
+/// Returns `None` for captures with no LSP counterpart (punctuation, markup,
+/// diff lines, ...); those spans produce no tokens.
+pub fn token_type_for_capture(capture: &str) -> Option<(u32, u32)> {
+    let slot = Slot::from_capture(capture)?;
+
+    let has = |part: &str| capture.split('.').any(|p| p == part);
+    let name = if has("parameter") {
+        "parameter"
+    } else if has("method") {
+        "method"
+    } else if has("regex") || has("regexp") {
+        "regexp"
+    } else {
+        match slot {
+            Slot::Keyword => "keyword",
+            Slot::Function => "function",
+            Slot::String => "string",
+            Slot::Comment => "comment",
+            Slot::Type => "type",
+            Slot::Variable | Slot::Constant => "variable",
+            Slot::Number => "number",
+            Slot::Operator => "operator",
+            Slot::Property => "property",
+            Slot::Attribute => "decorator",
+            Slot::Macro => "macro",
+            Slot::Label => "label",
+            Slot::Namespace => "namespace",
+            Slot::Constructor => "class",
+            _ => return None,
+        }
+    };
+    let token_type = TOKEN_TYPES.iter().position(|t| *t == name)? as u32;
+
+    let mut modifiers = 0;
+    if slot == Slot::Constant {
+        modifiers |= READONLY;
+    }
+    if has("builtin") {
+        modifiers |= DEFAULT_LIBRARY;
+    }
+    if has("documentation") || has("doc") {
+        modifiers |= DOCUMENTATION;
+    }
+
+    Some((token_type, modifiers))
+}
+
+/// Convert highlight spans for `source` into delta-encoded LSP semantic tokens.
+///
+/// Spans may come straight from a highlighter: they can overlap, nest, or
+/// cover the same range more than once. Where spans nest, the innermost one
+/// wins; among spans with the same range, the highest `pattern_index` wins.
+/// Spans out of bounds or not on character boundaries are ignored.
+pub fn to_semantic_tokens(spans: &[Span], source: &str) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let mut encoder = Encoder::new(source);
+    for (start, end, token_type, modifiers) in flatten(spans, source) {
+        encoder.push(start, end, token_type, modifiers, &mut tokens);
+    }
+    tokens
+}
+
+/// Resolve spans into sorted, non-overlapping `(start, end, type, modifiers)`
+/// byte ranges.
+fn flatten(spans: &[Span], source: &str) -> Vec<(usize, usize, u32, u32)> {
+    struct Styled {
+        start: usize,
+        end: usize,
+        pattern_index: u32,
+        token: (u32, u32),
+    }
+
+    let mut styled: Vec<Styled> = spans
+        .iter()
+        .filter_map(|span| {
+            let (start, end) = (span.start as usize, span.end as usize);
+            if start >= end
+                || end > source.len()
+                || !source.is_char_boundary(start)
+                || !source.is_char_boundary(end)
+            {
+                return None;
+            }
+            Some(Styled {
+                start,
+                end,
+                pattern_index: span.pattern_index,
+                token: token_type_for_capture(&span.capture)?,
+            })
+        })
+        .collect();
+
+    // Outer spans first; for identical ranges the highest pattern_index ends
+    // up last, i.e. on top of the stack
+    styled.sort_by(|a, b| {
+        a.start
+            .cmp(&b.start)
+            .then_with(|| b.end.cmp(&a.end))
+            .then_with(|| a.pattern_index.cmp(&b.pattern_index))
+    });
+
+    // Ends sort before starts at the same position
+    let mut events: Vec<(usize, bool, usize)> = Vec::with_capacity(styled.len() * 2);
+    for (i, span) in styled.iter().enumerate() {
+        events.push((span.start, true, i));
+        events.push((span.end, false, i));
+    }
+    events.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+    let mut flat: Vec<(usize, usize, u32, u32)> = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    let mut last_pos = 0;
+    for (pos, is_start, idx) in events {
+        if pos > last_pos {
+            if let Some(&top) = stack.last() {
+                let (token_type, modifiers) = styled[top].token;
+                match flat.last_mut() {
+                    Some(last) if last.1 == last_pos && (last.2, last.3) == styled[top].token => {
+                        last.1 = pos;
+                    }
+                    _ => flat.push((last_pos, pos, token_type, modifiers)),
+                }
+            }
+            last_pos = pos;
+        }
+
+        if is_start {
+            stack.push(idx);
+        } else if let Some(i) = stack.iter().rposition(|&x| x == idx) {
+            stack.remove(i);
+        }
+    }
+
+    flat
+}
+
+/// Tracks line/column positions while emitting tokens in document order.
+struct Encoder<'a> {
+    source: &'a str,
+    /// Byte offset and line number of the start of the current line.
+    line_start: usize,
+    line: u32,
+    /// Line and UTF-16 column of the previously emitted token.
+    prev_line: u32,
+    prev_col: u32,
+}
+
+impl<'a> Encoder<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            line_start: 0,
+            line: 0,
+            prev_line: 0,
+            prev_col: 0,
+        }
+    }
+
+    /// Move the current line forward to the one containing `pos`.
+    fn seek(&mut self, pos: usize) {
+        while let Some(nl) = self.source[self.line_start..pos].find('\n') {
+            self.line_start += nl + 1;
+            self.line += 1;
+        }
+    }
+
+    fn push(
+        &mut self,
+        mut start: usize,
+        end: usize,
+        token_type: u32,
+        token_modifiers: u32,
+        tokens: &mut Vec<SemanticToken>,
+    ) {
+        // One token per line the range touches
+        while start < end {
+            self.seek(start);
+            let line_end = self.source[start..end]
+                .find('\n')
+                .map_or(end, |nl| start + nl);
+            let text = self.source[start..line_end].trim_end_matches('\r');
+
+            if !text.is_empty() {
+                let col = utf16_len(&self.source[self.line_start..start]);
+                let delta_line = self.line - self.prev_line;
+                let delta_start = if delta_line == 0 {
+                    col - self.prev_col
+                } else {
+                    col
+                };
+                tokens.push(SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length: utf16_len(text),
+                    token_type,
+                    token_modifiers,
+                });
+                self.prev_line = self.line;
+                self.prev_col = col;
+            }
+
+            start = line_end + 1;
+        }
+    }
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.chars().map(char::len_utf16).sum::<usize>() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u32, end: u32, capture: &str) -> Span {
+        Span::new(start, end, capture)
+    }
+
+    fn token_type(name: &str) -> u32 {
+        TOKEN_TYPES.iter().position(|t| *t == name).unwrap() as u32
+    }
+
+    #[test]
+    fn test_delta_encoding() {
+        //            0         1
+        //            0123456789012345
+        let source = "fn main() {\n    let s = \"hi\";\n}\n";
+        let let_start = source.find("let").unwrap() as u32;
+        let str_start = source.find('"').unwrap() as u32;
+        let spans = vec![
+            span(0, 2, "keyword.function"),
+            span(3, 7, "function"),
+            span(let_start, let_start + 3, "keyword"),
+            span(str_start, str_start + 4, "string"),
+            // Unstyled and punctuation spans produce no tokens
+            span(8, 9, "punctuation.bracket"),
+            span(0, 7, "spell"),
+        ];
+
+        let tokens = to_semantic_tokens(&spans, source);
+        let encoded: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect();
+        assert_eq!(
+            encoded,
+            [
+                (0, 0, 2, token_type("keyword")),
+                (0, 3, 4, token_type("function")),
+                (1, 4, 3, token_type("keyword")),
+                (0, 8, 4, token_type("string")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiline_span_is_split() {
+        let source = "/* a\nbc\n*/ x";
+        let spans = vec![span(0, 10, "comment")];
+
+        let tokens = to_semantic_tokens(&spans, source);
+        let encoded: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.delta_line, t.delta_start, t.length))
+            .collect();
+        assert_eq!(encoded, [(0, 0, 4), (1, 0, 2), (1, 0, 2)]);
+    }
+
+    #[test]
+    fn test_nested_spans_innermost_wins() {
+        let source = "\"a {x} b\"";
+        let spans = vec![span(0, 9, "string"), span(4, 5, "variable")];
+
+        let tokens = to_semantic_tokens(&spans, source);
+        let encoded: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.delta_start, t.length, t.token_type))
+            .collect();
+        assert_eq!(
+            encoded,
+            [
+                (0, 4, token_type("string")),
+                (4, 1, token_type("variable")),
+                (1, 4, token_type("string")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        // '€' is 3 bytes in UTF-8 but 1 UTF-16 code unit; '𝄞' is 4 bytes / 2 units
+        let source = "€𝄞 x";
+        let x = source.find('x').unwrap() as u32;
+        let tokens = to_semantic_tokens(&[span(x, x + 1, "variable")], source);
+        assert_eq!(tokens[0].delta_start, 4);
+        assert_eq!(tokens[0].length, 1);
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let (ty, modifiers) = token_type_for_capture("function.builtin").unwrap();
+        assert_eq!(ty, token_type("function"));
+        assert_eq!(modifiers, DEFAULT_LIBRARY);
+
+        let (ty, modifiers) = token_type_for_capture("constant").unwrap();
+        assert_eq!(ty, token_type("variable"));
+        assert_eq!(modifiers, READONLY);
+
+        let (ty, _) = token_type_for_capture("variable.parameter").unwrap();
+        assert_eq!(ty, token_type("parameter"));
+
+        assert_eq!(token_type_for_capture("punctuation.delimiter"), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! A synthetic snippet for previewing themes.
+//!
+//! [`preview_spans`] returns a short piece of pseudo-code whose spans use
+//! every theme [`Slot`](arborium_theme::Slot), so rendering it with
+//! [`spans_to_ansi`](crate::spans_to_ansi) or
+//! [`spans_to_html`](crate::spans_to_html) shows a theme's whole palette
+//! without having to highlight real code.
+
+use crate::Span;
+
+/// The preview snippet, as pieces of text and their captures (empty for
+/// unhighlighted text).
+const PREVIEW: &[(&str, &str)] = &[
+    ("// Theme preview", "comment"),
+    ("\n", ""),
+    ("use", "keyword"),
+    (" ", ""),
+    ("geometry", "namespace"),
+    ("::", "punctuation.delimiter"),
+    ("Point", "type"),
+    (";", "punctuation.delimiter"),
+    ("\n\n", ""),
+    ("#[inline]", "attribute"),
+    ("\n", ""),
+    ("fn", "keyword"),
+    (" ", ""),
+    ("area", "function"),
+    ("(", "punctuation.bracket"),
+    ("size", "variable"),
+    (": ", ""),
+    ("u32", "type"),
+    (")", "punctuation.bracket"),
+    (" ", ""),
+    ("->", "operator"),
+    (" ", ""),
+    ("Point", "type"),
+    (" {\n    ", ""),
+    ("'outer", "label"),
+    (": ", ""),
+    ("println!", "function.macro"),
+    ("(", "punctuation.bracket"),
+    ("\"{}\"", "string"),
+    (", ", ""),
+    ("size", "variable"),
+    (" ", ""),
+    ("*", "operator"),
+    (" ", ""),
+    ("2", "number"),
+    (");\n    ", ""),
+    ("Point", "constructor"),
+    (" { ", ""),
+    ("x", "property"),
+    (": ", ""),
+    ("ORIGIN", "constant"),
+    (" }\n}\n\n", ""),
+    ("<div>", "tag"),
+    (" ", ""),
+    ("${embedded}", "embedded"),
+    (" ", ""),
+    ("%%%", "error"),
+    ("\n", ""),
+    ("# Title", "markup.heading"),
+    (" ", ""),
+    ("**strong**", "markup.bold"),
+    (" ", ""),
+    ("*emphasis*", "markup.italic"),
+    (" ", ""),
+    ("`literal`", "markup.raw"),
+    (" ", ""),
+    ("~~struck~~", "markup.strikethrough"),
+    (" ", ""),
+    ("https://example.com", "markup.link.url"),
+    ("\n", ""),
+    ("+ added", "diff.plus"),
+    ("\n", ""),
+    ("- deleted", "diff.minus"),
+    ("\n", ""),
+];
+
+/// A short synthetic source and its spans, covering every theme slot.
+///
+/// Meant for theme pickers and galleries: render the result to show what
+/// each of a theme's colors looks like.
+///
+/// ```
+/// use arborium_highlight::{HtmlFormat, preview_spans, spans_to_html};
+///
+/// let (source, spans) = preview_spans();
+/// let html = spans_to_html(&source, spans, &HtmlFormat::CustomElements);
+/// assert!(html.contains("<a-k>fn</a-k>"));
+/// ```
+pub fn preview_spans() -> (String, Vec<Span>) {
+    let mut source = String::new();
+    let mut spans = Vec::new();
+    for &(text, capture) in PREVIEW {
+        let start = source.len() as u32;
+        source.push_str(text);
+        if !capture.is_empty() {
+            spans.push(Span::new(start, source.len() as u32, capture));
+        }
+    }
+    (source, spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arborium_theme::Slot;
+
+    #[test]
+    fn test_preview_covers_every_slot() {
+        let (source, spans) = preview_spans();
+        for slot in Slot::ALL {
+            assert!(
+                spans
+                    .iter()
+                    .any(|span| Slot::from_capture(&span.capture) == Some(*slot)),
+                "no preview span for {slot:?}"
+            );
+        }
+        // Every span has a slot, and covers the text it claims to
+        for span in &spans {
+            assert!(Slot::from_capture(&span.capture).is_some(), "{span:?}");
+            assert!(source.is_char_boundary(span.end as usize));
+        }
+    }
+}
@@ -12,31 +12,35 @@
 //!
 //! Both map to the "keyword" slot (`k` tag), so they become a single `<a-k>` element.
 
+use crate::scopes::{Scope, resolve_variables, with_variable_color};
 use crate::{HtmlFormat, Span};
-use arborium_theme::{
-    Theme, capture_to_slot, slot_to_highlight_index, tag_for_capture, tag_to_name,
-};
-use std::collections::HashMap;
+use arborium_theme::{COUNT, Color, ColorDepth, Slot, Style, Theme, extended_highlight_index};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{self, Write};
+use std::ops::Range;
 
-/// A span with a theme style index for rendering.
+/// A span resolved to a theme slot for rendering.
 ///
 /// This is the output of processing raw `Span` objects through the theme system.
-/// The `theme_index` can be used with `Theme::style()` to get colors and modifiers.
+/// The `slot` can be used with `Theme::style_for()` to get colors and modifiers.
 #[derive(Debug, Clone)]
 pub struct ThemedSpan {
     /// Byte offset where the span starts (inclusive).
     pub start: u32,
     /// Byte offset where the span ends (exclusive).
     pub end: u32,
+    /// The theme slot this span is styled with.
+    pub slot: Slot,
     /// Index into the theme's style array.
+    #[deprecated(note = "use `slot` with `Theme::style_for` instead")]
     pub theme_index: usize,
 }
 
-/// Convert raw spans to themed spans by resolving capture names to theme indices.
+/// Convert raw spans to themed spans by resolving capture names to theme slots.
 ///
-/// This performs deduplication and returns spans with theme style indices that can
-/// be used with `Theme::style()` to get colors and modifiers.
+/// This performs deduplication and returns spans with theme slots that can
+/// be used with `Theme::style_for()` to get colors and modifiers.
 pub fn spans_to_themed(spans: Vec<Span>) -> Vec<ThemedSpan> {
     if spans.is_empty() {
         return Vec::new();
@@ -51,11 +55,10 @@ pub fn spans_to_themed(spans: Vec<Span>) -> Vec<ThemedSpan> {
     let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
     for span in spans {
         let key = (span.start, span.end);
-        let new_has_slot = slot_to_highlight_index(capture_to_slot(&span.capture)).is_some();
+        let new_has_slot = Slot::from_capture(&span.capture).is_some();
 
         if let Some(existing) = deduped.get(&key) {
-            let existing_has_slot =
-                slot_to_highlight_index(capture_to_slot(&existing.capture)).is_some();
+            let existing_has_slot = Slot::from_capture(&existing.capture).is_some();
             // Prefer spans with styling over unstyled spans
             // Among equally-styled spans, prefer higher pattern_index (later in query)
             let should_replace = match (new_has_slot, existing_has_slot) {
@@ -75,12 +78,13 @@ pub fn spans_to_themed(spans: Vec<Span>) -> Vec<ThemedSpan> {
     let mut themed: Vec<ThemedSpan> = deduped
         .into_values()
         .filter_map(|span| {
-            let slot = capture_to_slot(&span.capture);
-            let theme_index = slot_to_highlight_index(slot)?;
+            let slot = Slot::from_capture(&span.capture)?;
+            #[allow(deprecated)]
             Some(ThemedSpan {
                 start: span.start,
                 end: span.end,
-                theme_index,
+                slot,
+                theme_index: slot.highlight_index(),
             })
         })
         .collect();
@@ -109,8 +113,8 @@ fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
             let close = format!("</{prefix}-{short_tag}>");
             (open, close)
         }
-        HtmlFormat::ClassNames => {
-            if let Some(name) = tag_to_name(short_tag) {
+        HtmlFormat::ClassNames | HtmlFormat::FullCaptureNames => {
+            if let Some(name) = Slot::from_tag(short_tag).map(Slot::css_name) {
                 let open = format!("<span class=\"{name}\">");
                 let close = "</span>".to_string();
                 (open, close)
@@ -120,7 +124,7 @@ fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
             }
         }
         HtmlFormat::ClassNamesWithPrefix(prefix) => {
-            if let Some(name) = tag_to_name(short_tag) {
+            if let Some(name) = Slot::from_tag(short_tag).map(Slot::css_name) {
                 let open = format!("<span class=\"{prefix}-{name}\">");
                 let close = "</span>".to_string();
                 (open, close)
@@ -132,31 +136,120 @@ fn make_html_tags(short_tag: &str, format: &HtmlFormat) -> (String, String) {
     }
 }
 
+/// Opening and closing HTML tags for `slot` in the given format.
+///
+/// These are the tags [`spans_to_html`] uses, for callers that wrap extra
+/// markup (e.g. whole diff lines) around rendered output.
+pub fn html_tags_for_slot(slot: Slot, format: &HtmlFormat) -> (String, String) {
+    make_html_tags(slot.tag(), format)
+}
+
 /// A normalized span with theme slot tag.
 #[derive(Debug, Clone)]
 struct NormalizedSpan {
     start: u32,
     end: u32,
     tag: &'static str,
+    /// The original capture, kept only for `data-capture` output.
+    capture: Option<String>,
+    /// The variable this span refers to, for variables colored by scope.
+    variable: Option<usize>,
+    /// Inline CSS for the span's theme style, when rendering with a theme.
+    style: Option<String>,
+    /// The (escaped) classes for [`HtmlFormat::FullCaptureNames`].
+    classes: Option<String>,
 }
 
-/// Normalize spans: map captures to theme slots and merge adjacent spans with same tag.
-fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
-    if spans.is_empty() {
-        return vec![];
+impl NormalizedSpan {
+    fn html_tags(&self, format: &HtmlFormat) -> (String, String) {
+        let (mut open, close) = match (&self.style, &self.classes) {
+            (Some(style), _) => (format!("<span style=\"{style}\">"), "</span>".to_string()),
+            (None, Some(classes)) => (format!("<span class=\"{classes}\">"), "</span>".to_string()),
+            (None, None) => make_html_tags(self.tag, format),
+        };
+        if let Some(capture) = &self.capture {
+            open = with_data_capture(&open, capture);
+        }
+        if let Some(variable) = self.variable {
+            open = with_variable_color(&open, variable);
+        }
+        (open, close)
     }
+}
 
-    // First, normalize all spans to their theme slot tags
-    let mut normalized: Vec<NormalizedSpan> = spans
+/// Classes for `capture` in [`HtmlFormat::FullCaptureNames`]: its dot
+/// separated parts, space separated.
+fn capture_classes(capture: &str) -> String {
+    let capture = capture.strip_prefix('@').unwrap_or(capture);
+    html_escape(&capture.replace('.', " "))
+}
+
+/// `open` with a `data-capture` attribute added.
+fn with_data_capture(open: &str, capture: &str) -> String {
+    let open = open.strip_suffix('>').unwrap_or(open);
+    format!("{open} data-capture=\"{}\">", html_escape(capture))
+}
+
+/// Map captures to theme slot tags, dropping spans without a slot.
+///
+/// With `keep_captures`, spans keep their capture. With `full_names`, spans
+/// record their capture's classes (see [`HtmlFormat::FullCaptureNames`]),
+/// and spans without a slot are kept, with an empty tag. Variable spans
+/// found in `variables` (see [`resolve_variables`]) record the variable.
+/// With a `theme`, spans record their style as inline CSS, and spans it
+/// doesn't style are dropped too.
+fn normalize_spans(
+    spans: Vec<Span>,
+    keep_captures: bool,
+    full_names: bool,
+    variables: &HashMap<(u32, u32), usize>,
+    theme: Option<&Theme>,
+) -> Vec<NormalizedSpan> {
+    spans
         .into_iter()
         .filter_map(|span| {
-            tag_for_capture(&span.capture).map(|tag| NormalizedSpan {
+            let slot = Slot::from_capture(&span.capture);
+            if slot.is_none() && (!full_names || theme.is_some()) {
+                return None;
+            }
+            let style = match (theme, slot) {
+                (Some(theme), Some(slot)) => Some(inline_css(theme, slot)?),
+                _ => None,
+            };
+            Some(NormalizedSpan {
                 start: span.start,
                 end: span.end,
-                tag,
+                tag: slot.map_or("", Slot::tag),
+                capture: keep_captures.then(|| span.capture.clone()),
+                variable: (slot == Some(Slot::Variable))
+                    .then(|| variables.get(&(span.start, span.end)).copied())
+                    .flatten(),
+                style,
+                classes: full_names.then(|| capture_classes(&span.capture)),
             })
         })
-        .collect();
+        .collect()
+}
+
+/// Normalize spans: map captures to theme slots and merge adjacent spans with same tag.
+///
+/// With `keep_captures`, spans keep their capture and are not merged.
+/// Spans referring to different `variables` are never merged either. With a
+/// `theme`, spans are merged when their styles are the same, whatever their
+/// tags. With `full_names`, they are merged only when their captures are.
+fn normalize_and_coalesce(
+    spans: Vec<Span>,
+    keep_captures: bool,
+    full_names: bool,
+    variables: &HashMap<(u32, u32), usize>,
+    theme: Option<&Theme>,
+) -> Vec<NormalizedSpan> {
+    if spans.is_empty() {
+        return vec![];
+    }
+
+    // First, normalize all spans to their theme slot tags
+    let mut normalized = normalize_spans(spans, keep_captures, full_names, variables, theme);
 
     if normalized.is_empty() {
         return vec![];
@@ -164,14 +257,22 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
 
     // Sort by start position
     normalized.sort_by_key(|s| (s.start, s.end));
+    if keep_captures {
+        return normalized;
+    }
 
     // Coalesce adjacent spans with the same tag
     let mut coalesced: Vec<NormalizedSpan> = Vec::with_capacity(normalized.len());
 
     for span in normalized {
         if let Some(last) = coalesced.last_mut() {
-            // If this span is adjacent (or overlapping) and has the same tag, merge
-            if span.tag == last.tag && span.start <= last.end {
+            let same_look = match (&span.style, &last.style) {
+                (Some(style), Some(last_style)) => style == last_style,
+                _ if full_names => span.classes == last.classes,
+                _ => span.tag == last.tag,
+            };
+            // If this span is adjacent (or overlapping) and looks the same, merge
+            if same_look && span.variable == last.variable && span.start <= last.end {
                 // Extend the last span to cover this one
                 last.end = last.end.max(span.end);
                 continue;
@@ -195,50 +296,638 @@ fn normalize_and_coalesce(spans: Vec<Span>) -> Vec<NormalizedSpan> {
 /// Note: Trailing newlines are trimmed from the source to avoid extra whitespace
 /// when the output is embedded in `<pre><code>` tags.
 pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> String {
+    spans_to_html_with_limit(source, spans, format, None, ControlCharMode::default()).html
+}
+
+/// How renderers treat control characters in the source.
+///
+/// Raw control characters (NUL, BEL, form feed, ...) can corrupt terminal
+/// output or produce invalid HTML. Tabs and line breaks (`\t`, `\n`, `\r`)
+/// are never affected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlCharMode {
+    /// Emit control characters unchanged.
+    Passthrough,
+    /// Replace them with a visible representation (default): the Unicode
+    /// control picture (`␀`, `␇`, ...) in HTML, dimmed caret notation
+    /// (`^@`, `^G`, ...) in ANSI output. C1 controls, which have neither,
+    /// become `�`.
+    #[default]
+    Picture,
+    /// Remove them.
+    Strip,
+}
+
+/// Returns `true` for the control characters [`ControlCharMode`] applies to.
+fn is_replaced_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// Unicode control picture for `c` (`U+2400`..`U+2421`), or `�` for C1 controls.
+fn control_picture(c: char) -> char {
+    match c {
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32).unwrap_or('\u{fffd}'),
+        '\x7f' => '\u{2421}',
+        _ => '\u{fffd}',
+    }
+}
+
+/// Caret notation for `c` (`^@`, `^G`, `^?`), or `�` for C1 controls.
+fn control_caret(c: char) -> String {
+    match c {
+        '\0'..='\x1f' => format!("^{}", (c as u8 + b'@') as char),
+        '\x7f' => "^?".to_string(),
+        _ => "\u{fffd}".to_string(),
+    }
+}
+
+/// HTML produced by [`spans_to_html_with_limit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedHtml {
+    /// The rendered HTML, possibly cut short.
+    pub html: String,
+    /// Whether rendering stopped early because of the output limit.
+    pub truncated: bool,
+}
+
+/// Like [`spans_to_html`], but stops once the output would exceed
+/// `max_output_bytes`.
+///
+/// Heavily highlighted source can render to many times its own size, so
+/// public highlighting services should cap the output. Truncation happens
+/// between elements: the result ends after the last complete element (or
+/// run of plain text) that fit, never inside a tag or an escape sequence.
+pub fn spans_to_html_with_limit(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+) -> RenderedHtml {
+    render_html(
+        source,
+        spans,
+        format,
+        max_output_bytes,
+        control_chars,
+        false,
+        false,
+    )
+}
+
+/// Like [`spans_to_html_with_limit`], but every element records the capture
+/// it was rendered from, e.g. `<a-k data-capture="keyword.function">`.
+///
+/// This is meant for interactive tools (showing the capture name on hover,
+/// for instance). Adjacent tokens styled the same are not merged into one
+/// element, since their captures may differ, and each element carries the
+/// extra attribute, so expect output noticeably larger than
+/// [`spans_to_html`]'s.
+pub fn spans_to_html_with_data_capture(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+) -> RenderedHtml {
+    render_html(
+        source,
+        spans,
+        format,
+        max_output_bytes,
+        control_chars,
+        true,
+        false,
+    )
+}
+
+/// Like [`spans_to_html_with_limit`], but with one element per span, exactly
+/// as given: spans are neither deduplicated nor merged, and nested spans
+/// become nested elements (`<a-s>"a<a-s>\n</a-s>"</a-s>`).
+///
+/// This is meant for consumers that map elements back to captures one to
+/// one, at the cost of larger output. Spans with no theme slot are still
+/// dropped, and a span that starts inside another one but ends after it is
+/// cut at the outer span's end so that elements nest. With `data_capture`,
+/// elements also carry their capture, as in
+/// [`spans_to_html_with_data_capture`].
+pub fn spans_to_html_exact(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+    data_capture: bool,
+) -> RenderedHtml {
+    render_html(
+        source,
+        spans,
+        format,
+        max_output_bytes,
+        control_chars,
+        data_capture,
+        true,
+    )
+}
+
+fn render_html(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+    data_capture: bool,
+    exact_spans: bool,
+) -> RenderedHtml {
     // Trim trailing newlines from source to avoid extra whitespace in code blocks
     let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(source.len(), max_output_bytes, control_chars);
+    out.data_capture = data_capture;
+    write_html(&mut out, source, spans, format, exact_spans);
 
-    if spans.is_empty() {
-        return html_escape(source);
+    RenderedHtml {
+        html: out.html,
+        truncated: out.truncated,
     }
+}
 
-    // Sort spans by (start, -end) so longer spans come first at same start
-    let mut spans = spans;
-    spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
+/// Clean up rendered HTML for embedding: drop a leading byte order mark
+/// (`strip_bom`) and turn `\r\n` and lone `\r` line endings into `\n`
+/// (`normalize_line_endings`).
+///
+/// Only the text between tags changes, so `html` may come from any of the
+/// `spans_to_html*` functions; span offsets stay relative to the original
+/// source. A `\r\n` split by a tag (a comment span ending at the `\r`, say)
+/// still becomes a single `\n`, and trailing line breaks are trimmed as the
+/// renderers trim trailing `\n`s.
+///
+/// ```
+/// use arborium_highlight::normalize_html_output;
+///
+/// let html = "\u{feff}<a-k>fn</a-k> f() {}\r\n";
+/// assert_eq!(normalize_html_output(html, true, true), "<a-k>fn</a-k> f() {}");
+/// ```
+pub fn normalize_html_output(html: &str, strip_bom: bool, normalize_line_endings: bool) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    // Whether any text has been seen, so later BOMs are kept
+    let mut seen_text = !strip_bom;
+    // Whether the last text character was a `\r`, written as `\n`
+    let mut after_cr = false;
+    for c in html.chars() {
+        if in_tag {
+            in_tag = c != '>';
+            out.push(c);
+            continue;
+        }
+        match c {
+            '<' => {
+                in_tag = true;
+                out.push(c);
+                continue;
+            }
+            '\u{feff}' if !seen_text => {}
+            '\r' if normalize_line_endings => out.push('\n'),
+            '\n' if after_cr => {}
+            _ => out.push(c),
+        }
+        seen_text = true;
+        after_cr = c == '\r' && normalize_line_endings;
+    }
+
+    if normalize_line_endings {
+        // Trailing line breaks, possibly followed by closing tags
+        let mut end = out.len();
+        while out[..end].ends_with('>') {
+            match out[..end].rfind('<') {
+                Some(open) => end = open,
+                None => break,
+            }
+        }
+        while out[..end].ends_with('\n') {
+            out.remove(end - 1);
+            end -= 1;
+        }
+    }
+    out
+}
+
+/// Like [`spans_to_html_with_limit`], but variables are colored by what they
+/// refer to, according to `scopes`.
+///
+/// Every variable span whose text is defined in an enclosing scope gets an
+/// inline color of its own, the same for all references to the same
+/// definition; other spans render as usual. See [`Scope`] for how
+/// references are resolved.
+pub fn spans_to_html_with_scopes(
+    source: &str,
+    spans: Vec<Span>,
+    scopes: &[Scope],
+    format: &HtmlFormat,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+) -> RenderedHtml {
+    let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(source.len(), max_output_bytes, control_chars);
+    out.variables = resolve_variables(source, &spans, scopes);
+    write_html(&mut out, source, spans, format, false);
+
+    RenderedHtml {
+        html: out.html,
+        truncated: out.truncated,
+    }
+}
+
+/// Like [`spans_to_html`], but styled inline with `theme`'s colors, e.g.
+/// `<span style="color: #cba6f7">`, for places a stylesheet can't be added
+/// to (HTML email, RSS feeds).
+///
+/// Adjacent spans styled the same are merged even if their captures map to
+/// different slots, and text the theme doesn't style is left unwrapped.
+pub fn spans_to_html_with_theme(source: &str, spans: Vec<Span>, theme: &Theme) -> String {
+    let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(source.len(), None, ControlCharMode::default());
+    out.theme = Some(theme);
+    write_html(&mut out, source, spans, &HtmlFormat::default(), false);
+    out.html
+}
+
+/// The inline CSS for `slot` in `theme`, or `None` if it isn't styled.
+///
+/// Uses the same properties as [`Theme::to_css`].
+fn inline_css(theme: &Theme, slot: Slot) -> Option<String> {
+    let style = theme.style(theme.resolve_index(slot.highlight_index()))?;
+    let mut css = Vec::new();
+    if let Some(fg) = &style.fg {
+        css.push(format!("color: {}", fg.to_hex()));
+    }
+    if let Some(bg) = &style.bg {
+        css.push(format!("background: {}", bg.to_hex()));
+    }
+    let decorations: Vec<&str> = [
+        (style.modifiers.underline, "underline"),
+        (style.modifiers.strikethrough, "line-through"),
+    ]
+    .into_iter()
+    .filter_map(|(on, decoration)| on.then_some(decoration))
+    .collect();
+    if !decorations.is_empty() {
+        css.push(format!("text-decoration: {}", decorations.join(" ")));
+    }
+    if style.modifiers.bold {
+        css.push("font-weight: bold".to_string());
+    }
+    if style.modifiers.italic {
+        css.push("font-style: italic".to_string());
+    }
+    (!css.is_empty()).then(|| css.join("; "))
+}
+
+/// Highlight spans as HTML, one string per line of `source`.
+///
+/// Elements spanning several lines (block comments, multi-line strings) are
+/// closed at the end of each line and reopened on the next, so every line
+/// is well-formed on its own and can be rendered without the others, as
+/// virtualized views that only draw visible lines need. Joined with `\n`,
+/// the lines are the same as [`spans_to_html`]'s output apart from those
+/// extra tags.
+pub fn spans_to_html_lines(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Vec<String> {
+    let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(source.len(), None, ControlCharMode::default());
+    out.split_lines = true;
+    write_html(&mut out, source, spans, format, false);
+
+    // Line breaks are only ever written between elements
+    out.html.split('\n').map(str::to_string).collect()
+}
+
+/// Line numbering for [`spans_to_html_with_line_numbers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineNumberConfig {
+    /// Number of the first line. Defaults to 1.
+    pub start: usize,
+    /// Class of the element wrapping each line. Defaults to `"line"`.
+    pub class: String,
+}
+
+impl Default for LineNumberConfig {
+    fn default() -> Self {
+        Self {
+            start: 1,
+            class: "line".to_string(),
+        }
+    }
+}
+
+/// Closing tag of the element wrapping a numbered line.
+const LINE_CLOSE: &str = "</span>";
+
+/// Like [`spans_to_html_with_limit`], but with every line wrapped in
+/// `<span class="line" data-line="N">`, numbered from
+/// [`LineNumberConfig::start`], for CSS to number.
+///
+/// Lines are separated by `\n` between the wrappers, as in
+/// `<span class="line" data-line="1">a</span>\n<span class="line" data-line="2">b</span>`.
+/// Like [`spans_to_html_lines`], elements spanning several lines are closed
+/// at the end of each line and reopened on the next, so the wrappers nest
+/// properly; a CSS counter or `attr(data-line)` can then render the gutter.
+pub fn spans_to_html_with_line_numbers(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    line_numbers: &LineNumberConfig,
+    max_output_bytes: Option<usize>,
+    control_chars: ControlCharMode,
+) -> RenderedHtml {
+    let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(source.len(), max_output_bytes, control_chars);
+    out.split_lines = true;
+    out.line_class = Some(html_escape(&line_numbers.class));
+    out.next_line = line_numbers.start;
+
+    // Keep room for closing the last line
+    out.reserved += LINE_CLOSE.len();
+    out.open_line();
+    if out.html.len() + out.reserved > out.limit {
+        return RenderedHtml {
+            html: String::new(),
+            truncated: true,
+        };
+    }
+    write_html(&mut out, source, spans, format, false);
+    out.reserved -= LINE_CLOSE.len();
+    out.html.push_str(LINE_CLOSE);
+
+    RenderedHtml {
+        html: out.html,
+        truncated: out.truncated,
+    }
+}
+
+/// Spans grouped by line of `source`, with offsets relative to the start
+/// of their line.
+///
+/// Spans crossing line breaks (block comments, multi-line strings) are
+/// split into one piece per line, without the `\n`; pieces left empty are
+/// dropped. Lines are the same as [`spans_to_html_lines`]': trailing line
+/// breaks are trimmed first, and an empty source is a single empty line.
+/// Spans are kept raw (not deduplicated or mapped to theme slots), sorted
+/// by start within each line.
+pub fn spans_to_lines(source: &str, spans: &[Span]) -> Vec<Vec<Span>> {
+    let source = source.trim_end_matches('\n');
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut lines = vec![Vec::new(); line_starts.len()];
 
-    // Deduplicate: for spans with the exact same (start, end), prefer spans with higher pattern_index
-    // This matches tree-sitter convention: later patterns in highlights.scm override earlier ones.
-    // We also prefer styled spans over unstyled (e.g., @comment over @spell).
-    let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
     for span in spans {
-        let key = (span.start, span.end);
-        let new_has_styling = tag_for_capture(&span.capture).is_some();
+        let start = (span.start as usize).min(source.len());
+        let end = (span.end as usize).min(source.len());
+        if start >= end {
+            continue;
+        }
+        let first = line_starts.partition_point(|&line_start| line_start <= start) - 1;
+        for (line, &line_start) in line_starts.iter().enumerate().skip(first) {
+            if line_start >= end {
+                break;
+            }
+            let line_end = line_starts
+                .get(line + 1)
+                .map_or(source.len(), |&next| next - 1);
+            let piece_start = start.max(line_start);
+            let piece_end = end.min(line_end);
+            if piece_start < piece_end {
+                lines[line].push(Span {
+                    start: (piece_start - line_start) as u32,
+                    end: (piece_end - line_start) as u32,
+                    ..span.clone()
+                });
+            }
+        }
+    }
 
-        if let Some(existing) = deduped.get(&key) {
-            let existing_has_styling = tag_for_capture(&existing.capture).is_some();
-            // Prefer spans with styling over unstyled spans
-            // Among equally-styled spans, prefer higher pattern_index (later in query)
-            let should_replace = match (new_has_styling, existing_has_styling) {
-                (true, false) => true,  // New has styling, existing doesn't
-                (false, true) => false, // Existing has styling, new doesn't
-                _ => span.pattern_index >= existing.pattern_index, // Both same styling status: higher pattern_index wins
-            };
-            if should_replace {
-                deduped.insert(key, span);
+    for line in &mut lines {
+        line.sort_by_key(|span| span.start);
+    }
+    lines
+}
+
+fn write_html(
+    out: &mut HtmlOutput,
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    exact_spans: bool,
+) {
+    // Fast paths for trivial documents (inline code, short doc examples)
+    match spans.as_slice() {
+        [] => {
+            out.text(source);
+        }
+        _ if exact_spans => spans_to_html_nested(out, source, spans, format),
+        [span]
+            if out.variables.is_empty()
+                && out.theme.is_none()
+                && *format != HtmlFormat::FullCaptureNames =>
+        {
+            single_span_to_html(out, source, span, format)
+        }
+        _ => spans_to_html_general(out, source, spans, format),
+    }
+}
+
+/// How much output the writers buffer before writing it out.
+const STREAM_CHUNK_BYTES: usize = 8 * 1024;
+
+/// HTML output buffer that refuses writes past a size limit.
+struct HtmlOutput<'w> {
+    html: String,
+    limit: usize,
+    /// Room kept free below `limit`, for closing tags still to be written.
+    reserved: usize,
+    truncated: bool,
+    control_chars: ControlCharMode,
+    /// Add a `data-capture` attribute to elements, and don't coalesce them.
+    data_capture: bool,
+    /// Close elements before line breaks and reopen them after.
+    split_lines: bool,
+    /// Variables colored by scope, by span range.
+    variables: HashMap<(u32, u32), usize>,
+    /// Where `html` is written out as it grows, rather than kept whole.
+    /// Only used without a limit, which counts what's still buffered.
+    sink: Option<&'w mut dyn Write>,
+    /// The first error writing to `sink`; rendering stops there.
+    error: Option<io::Error>,
+    /// Style elements inline with this theme's colors rather than by tag.
+    theme: Option<&'w Theme>,
+    /// With `split_lines`, the (escaped) class of the element wrapping each
+    /// line, for line numbers.
+    line_class: Option<String>,
+    /// Number of the next line to open.
+    next_line: usize,
+}
+
+impl HtmlOutput<'_> {
+    fn new(source_len: usize, limit: Option<usize>, control_chars: ControlCharMode) -> Self {
+        let limit = limit.unwrap_or(usize::MAX);
+        Self {
+            html: String::with_capacity((source_len * 2).min(limit)),
+            limit,
+            reserved: 0,
+            truncated: false,
+            control_chars,
+            data_capture: false,
+            split_lines: false,
+            variables: HashMap::new(),
+            sink: None,
+            error: None,
+            theme: None,
+            line_class: None,
+            next_line: 0,
+        }
+    }
+
+    /// Open the element wrapping the next line, if lines are numbered.
+    fn open_line(&mut self) {
+        if let Some(class) = &self.line_class {
+            let open = format!("<span class=\"{class}\" data-line=\"{}\">", self.next_line);
+            self.html.push_str(&open);
+            self.next_line += 1;
+        }
+    }
+
+    /// Write the buffered HTML out to the sink, if there is one, once
+    /// enough has built up (or regardless, with `force`).
+    fn flush(&mut self, force: bool) {
+        let Some(sink) = self.sink.as_mut() else {
+            return;
+        };
+        if self.error.is_some() || (!force && self.html.len() < STREAM_CHUNK_BYTES) {
+            return;
+        }
+        if let Err(error) = sink.write_all(self.html.as_bytes()) {
+            self.error = Some(error);
+            self.truncated = true;
+        }
+        self.html.clear();
+    }
+
+    /// Append escaped `text`. Returns `false` (and writes nothing) if it
+    /// doesn't fit.
+    fn text(&mut self, text: &str) -> bool {
+        self.element("", text, "")
+    }
+
+    /// Append escaped `text` wrapped in `open`/`close`, all or nothing.
+    fn element(&mut self, open: &str, text: &str, close: &str) -> bool {
+        if self.truncated {
+            return false;
+        }
+        // Escape straight into the output, and take it back if it overflows
+        let mark = self.html.len();
+        let numbered = self.line_class.is_some();
+        if self.split_lines && (numbered || !open.is_empty()) && text.contains('\n') {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    if numbered {
+                        self.html.push_str(LINE_CLOSE);
+                    }
+                    self.html.push('\n');
+                    self.open_line();
+                }
+                if !line.is_empty() {
+                    self.push_element(open, line, close);
+                }
             }
         } else {
-            deduped.insert(key, span);
+            self.push_element(open, text, close);
+        }
+        if self.html.len() + self.reserved > self.limit {
+            self.html.truncate(mark);
+            self.truncated = true;
+            return false;
         }
+        self.flush(false);
+        !self.truncated
     }
 
-    // Convert back to vec
-    let spans: Vec<Span> = deduped.into_values().collect();
+    /// Append escaped `text` wrapped in `open`/`close`, without checking
+    /// the limit.
+    fn push_element(&mut self, open: &str, text: &str, close: &str) {
+        self.html.push_str(open);
+        match self.control_chars {
+            ControlCharMode::Passthrough => write_html_escaped_into(&mut self.html, text),
+            mode if text.contains(is_replaced_control) => {
+                for c in text.chars() {
+                    match (is_replaced_control(c), mode) {
+                        (false, _) => push_html_escaped(&mut self.html, c),
+                        (true, ControlCharMode::Strip) => {}
+                        (true, _) => self.html.push(control_picture(c)),
+                    }
+                }
+            }
+            _ => write_html_escaped_into(&mut self.html, text),
+        }
+        self.html.push_str(close);
+    }
+}
+
+/// Byte range of `source` covered by a lone span, or `None` if the span
+/// leaves the whole source unstyled.
+///
+/// Mirrors the general event loop exactly: a span ending past the source is
+/// dropped, while an empty (or inverted) span never gets closed and so
+/// styles everything from its start to the end of the source.
+fn single_span_range(source: &str, span: &Span) -> Option<Range<usize>> {
+    let (start, end) = (span.start as usize, span.end as usize);
+    if start < end {
+        (end <= source.len()).then_some(start..end)
+    } else {
+        (start < source.len()).then_some(start..source.len())
+    }
+}
+
+fn single_span_to_html(out: &mut HtmlOutput, source: &str, span: &Span, format: &HtmlFormat) {
+    let tag = Slot::from_capture(&span.capture).map(Slot::tag);
+    let (Some(tag), Some(range)) = (tag, single_span_range(source, span)) else {
+        out.text(source);
+        return;
+    };
+
+    let (mut open_tag, close_tag) = make_html_tags(tag, format);
+    if out.data_capture {
+        open_tag = with_data_capture(&open_tag, &span.capture);
+    }
+    let _ = out.text(&source[..range.start])
+        && out.element(&open_tag, &source[range.clone()], &close_tag)
+        && out.text(&source[range.end..]);
+}
+
+/// The full sort/dedup/coalesce/event-loop renderer behind [`spans_to_html`].
+fn spans_to_html_general(
+    out: &mut HtmlOutput,
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+) {
+    if spans.is_empty() {
+        out.text(source);
+        return;
+    }
+    let spans = dedup_spans(spans);
 
     // Normalize to theme slots and coalesce adjacent same-tag spans
-    let spans = normalize_and_coalesce(spans);
+    let spans = normalize_and_coalesce(
+        spans,
+        out.data_capture,
+        *format == HtmlFormat::FullCaptureNames,
+        &out.variables,
+        out.theme,
+    );
 
     if spans.is_empty() {
-        return html_escape(source);
+        out.text(source);
+        return;
     }
 
     // Re-sort after coalescing
@@ -258,7 +947,6 @@ pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Str
     });
 
     // Process events with a stack
-    let mut html = String::with_capacity(source.len() * 2);
     let mut last_pos: usize = 0;
     let mut stack: Vec<usize> = Vec::new(); // indices into spans
 
@@ -268,14 +956,14 @@ pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Str
         // Emit any source text before this position
         if pos > last_pos && pos <= source.len() {
             let text = &source[last_pos..pos];
-            if let Some(&top_idx) = stack.last() {
-                let tag = spans[top_idx].tag;
-                let (open_tag, close_tag) = make_html_tags(tag, format);
-                html.push_str(&open_tag);
-                html.push_str(&html_escape(text));
-                html.push_str(&close_tag);
+            let written = if let Some(&top_idx) = stack.last() {
+                let (open_tag, close_tag) = spans[top_idx].html_tags(format);
+                out.element(&open_tag, text, &close_tag)
             } else {
-                html.push_str(&html_escape(text));
+                out.text(text)
+            };
+            if !written {
+                return;
             }
             last_pos = pos;
         }
@@ -295,55 +983,417 @@ pub fn spans_to_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> Str
     if last_pos < source.len() {
         let text = &source[last_pos..];
         if let Some(&top_idx) = stack.last() {
-            let tag = spans[top_idx].tag;
-            let (open_tag, close_tag) = make_html_tags(tag, format);
-            html.push_str(&open_tag);
-            html.push_str(&html_escape(text));
-            html.push_str(&close_tag);
+            let (open_tag, close_tag) = spans[top_idx].html_tags(format);
+            out.element(&open_tag, text, &close_tag);
         } else {
-            html.push_str(&html_escape(text));
+            out.text(text);
         }
     }
-
-    html
 }
 
-/// Write spans as HTML to a writer.
-///
-/// This is more efficient than `spans_to_html` for streaming output.
-pub fn write_spans_as_html<W: Write>(
-    w: &mut W,
-    source: &str,
-    spans: Vec<Span>,
-    format: &HtmlFormat,
-) -> io::Result<()> {
-    let html = spans_to_html(source, spans, format);
-    w.write_all(html.as_bytes())
-}
+/// Renderer behind [`spans_to_html_exact`]: one element per span, nested.
+fn spans_to_html_nested(out: &mut HtmlOutput, source: &str, spans: Vec<Span>, format: &HtmlFormat) {
+    let mut spans = normalize_spans(
+        spans,
+        out.data_capture,
+        *format == HtmlFormat::FullCaptureNames,
+        &out.variables,
+        out.theme,
+    );
+    // Outer spans first; spans with the same range stay in their given order
+    spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
 
-/// Escape HTML special characters.
-pub fn html_escape(text: &str) -> String {
-    let mut result = String::with_capacity(text.len());
-    for c in text.chars() {
-        match c {
-            '<' => result.push_str("&lt;"),
-            '>' => result.push_str("&gt;"),
-            '&' => result.push_str("&amp;"),
-            '"' => result.push_str("&quot;"),
-            '\'' => result.push_str("&#39;"),
-            _ => result.push(c),
+    let mut pos = 0;
+    // End and closing tag of the open elements, innermost last
+    let mut open: Vec<(usize, String)> = Vec::new();
+
+    // Write the text up to `end`, then close the innermost element. Room for
+    // the closing tag was reserved when the element was opened.
+    let close = |out: &mut HtmlOutput, pos: &mut usize, open: &mut Vec<(usize, String)>| {
+        let (end, close_tag) = open.pop().expect("an element is open");
+        let written = out.text(&source[*pos..end]);
+        *pos = end;
+        out.reserved -= close_tag.len();
+        out.html.push_str(&close_tag);
+        written
+    };
+
+    for span in &spans {
+        let start = span.start as usize;
+        while open.last().is_some_and(|&(end, _)| end <= start) {
+            if !close(out, &mut pos, &mut open) {
+                break;
+            }
+        }
+        if out.truncated {
+            break;
         }
+
+        let end = open
+            .last()
+            .map_or(source.len(), |&(outer_end, _)| outer_end)
+            .min(span.end as usize);
+        if start >= end || !source.is_char_boundary(start) || !source.is_char_boundary(end) {
+            continue;
+        }
+        let (open_tag, close_tag) = span.html_tags(format);
+        if !out.text(&source[pos..start]) {
+            break;
+        }
+        pos = start;
+        out.reserved += close_tag.len();
+        if !out.element(&open_tag, "", "") {
+            out.reserved -= close_tag.len();
+            break;
+        }
+        open.push((end, close_tag));
+    }
+
+    // Close what's still open, even after truncation, so the output stays
+    // well-formed
+    while !open.is_empty() {
+        close(out, &mut pos, &mut open);
+    }
+    if !out.truncated {
+        out.text(&source[pos..]);
     }
-    result
 }
 
-/// Options controlling ANSI rendering behavior.
-#[derive(Debug, Clone)]
-pub struct AnsiOptions {
-    /// If true, apply the theme's foreground/background as a base style
-    /// for all text (including un-highlighted regions).
-    pub use_theme_base_style: bool,
-    /// Optional hard wrap width (in columns). When None, no wrapping is
+/// Sort and deduplicate spans: for spans with the exact same (start, end), prefer
+/// spans with higher pattern_index.
+///
+/// This matches tree-sitter convention: later patterns in highlights.scm override earlier ones.
+/// We also prefer styled spans over unstyled (e.g., @comment over @spell).
+fn dedup_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    // Sort spans by (start, -end) so longer spans come first at same start
+    spans.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
+
+    let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
+    for span in spans {
+        let key = (span.start, span.end);
+        let new_has_styling = Slot::from_capture(&span.capture).is_some();
+
+        if let Some(existing) = deduped.get(&key) {
+            let existing_has_styling = Slot::from_capture(&existing.capture).is_some();
+            // Prefer spans with styling over unstyled spans
+            // Among equally-styled spans, prefer higher pattern_index (later in query)
+            let should_replace = match (new_has_styling, existing_has_styling) {
+                (true, false) => true,  // New has styling, existing doesn't
+                (false, true) => false, // Existing has styling, new doesn't
+                _ => span.pattern_index >= existing.pattern_index, // Both same styling status: higher pattern_index wins
+            };
+            if should_replace {
+                deduped.insert(key, span);
+            }
+        } else {
+            deduped.insert(key, span);
+        }
+    }
+
+    // Convert back to vec
+    deduped.into_values().collect()
+}
+
+/// Rough size in bytes of the HTML [`spans_to_html`] would render, without
+/// rendering it.
+///
+/// This is the length of the source, plus the HTML escapes it needs, plus
+/// an opening and closing tag for every span with a theme slot. It skips the
+/// deduplication and merging of spans, and the splitting of overlapping
+/// ones, so it can be off either way, but usually by little: use it as a
+/// cheap check before rendering (against your own budget, or
+/// `max_output_bytes`), not as an exact size.
+pub fn estimate_output_size(source: &str, spans: &[Span], format: &HtmlFormat) -> usize {
+    let source = source.trim_end_matches('\n');
+    let escapes: usize = source
+        .bytes()
+        .filter_map(html_entity)
+        .map(|entity| entity.len() - 1)
+        .sum();
+
+    if *format == HtmlFormat::FullCaptureNames {
+        let tags: usize = spans
+            .iter()
+            .map(|span| "<span class=\"\"></span>".len() + capture_classes(&span.capture).len())
+            .sum();
+        return source.len() + escapes + tags;
+    }
+
+    // Tag lengths by slot tag, computed once per slot
+    let mut tag_lengths: HashMap<&'static str, usize> = HashMap::new();
+    let tags: usize = spans
+        .iter()
+        .filter_map(|span| Slot::from_capture(&span.capture))
+        .map(|slot| {
+            *tag_lengths.entry(slot.tag()).or_insert_with(|| {
+                let (open, close) = make_html_tags(slot.tag(), format);
+                open.len() + close.len()
+            })
+        })
+        .sum();
+
+    source.len() + escapes + tags
+}
+
+/// Short tags (e.g. `"k"`) of the slots `spans` render with, after the same
+/// deduplication and coalescing as [`spans_to_html`].
+///
+/// Useful to emit CSS rules only for the slots a document actually uses.
+pub fn used_slots(spans: &[Span]) -> BTreeSet<&'static str> {
+    normalize_and_coalesce(
+        dedup_spans(spans.to_vec()),
+        false,
+        false,
+        &HashMap::new(),
+        None,
+    )
+    .into_iter()
+    .filter(|span| span.start < span.end)
+    .map(|span| span.tag)
+    .collect()
+}
+
+/// Byte range of each line of `source`, without its `\n` or `\r\n`
+/// terminator.
+///
+/// Lines are the same as [`str::lines`]: a trailing newline doesn't start
+/// another line, and an empty source has none.
+pub fn line_ranges(source: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for line in source.split_inclusive('\n') {
+        let content = line
+            .strip_suffix('\n')
+            .map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+        ranges.push(start..start + content.len());
+        start += line.len();
+    }
+    ranges
+}
+
+/// Options for [`spans_to_html_document`].
+#[derive(Debug, Clone, Default)]
+pub struct DocumentOptions {
+    /// Mark the code block up for screen readers.
+    ///
+    /// The `<pre>` gets `role="region"`, an `aria-label` naming the language
+    /// (e.g. `"code, rust"`) and `tabindex="0"` so keyboard users can scroll
+    /// it. Off by default.
+    pub accessible: bool,
+
+    /// Add a `class="language-{language}"` attribute to the `<pre>`, as
+    /// expected by most static site generators. Off by default.
+    pub language_class: bool,
+
+    /// Render one element per token, each with a `data-capture` attribute
+    /// holding its original capture (e.g. `data-capture="keyword.function"`).
+    ///
+    /// Adjacent tokens are no longer coalesced, and every element carries the
+    /// attribute, so the output grows considerably; only enable this for
+    /// tooling that needs the captures. Off by default.
+    pub emit_data_capture: bool,
+
+    /// Add a `data-code` attribute to the `<pre>` holding the original
+    /// source, so a "copy code" button can read it (`pre.dataset.code`)
+    /// instead of stripping tags from the highlighted HTML.
+    ///
+    /// The source is HTML-escaped into the attribute, with line breaks as
+    /// character references so the browser doesn't normalize them. This
+    /// roughly doubles the size of the output. Off by default.
+    pub embed_raw_source: bool,
+
+    /// Produce a single-line `<code>` fragment for use inside prose, instead
+    /// of a `<pre><code>` block.
+    ///
+    /// The source is trimmed and its line breaks are rendered as spaces;
+    /// attributes that would go on the `<pre>` go on the `<code>`. Off by
+    /// default.
+    pub inline_compact: bool,
+}
+
+impl DocumentOptions {
+    /// Wrap already-highlighted HTML in a `<pre><code>` block (or a
+    /// `<code>` element, with [`inline_compact`](Self::inline_compact)).
+    ///
+    /// Without the source at hand this can't honor
+    /// [`embed_raw_source`](Self::embed_raw_source); use
+    /// [`wrap_with_source`](Self::wrap_with_source) for that.
+    pub fn wrap(&self, body: &str, language: &str) -> String {
+        self.wrap_inner(body, language, None)
+    }
+
+    /// Wrap `body`, the highlighted HTML for `source`, in a `<pre><code>`
+    /// block.
+    pub fn wrap_with_source(&self, body: &str, language: &str, source: &str) -> String {
+        self.wrap_inner(body, language, Some(source))
+    }
+
+    fn wrap_inner(&self, body: &str, language: &str, source: Option<&str>) -> String {
+        let language = html_escape(language);
+        let mut attrs = String::new();
+        if self.language_class {
+            attrs.push_str(&format!(" class=\"language-{language}\""));
+        }
+        if self.accessible {
+            attrs.push_str(&format!(
+                " role=\"region\" aria-label=\"code, {language}\" tabindex=\"0\""
+            ));
+        }
+        if let Some(source) = source.filter(|_| self.embed_raw_source) {
+            attrs.push_str(" data-code=\"");
+            for c in source.chars() {
+                match c {
+                    '\n' => attrs.push_str("&#10;"),
+                    '\r' => attrs.push_str("&#13;"),
+                    _ => push_html_escaped(&mut attrs, c),
+                }
+            }
+            attrs.push('"');
+        }
+        if self.inline_compact {
+            let body = body.replace("\r\n", " ").replace(['\n', '\r'], " ");
+            return format!("<code{attrs}>{}</code>", body.trim());
+        }
+        format!("<pre{attrs}><code>{body}</code></pre>")
+    }
+}
+
+/// Render spans as a complete `<pre><code>` block, or an inline `<code>`
+/// fragment with [`DocumentOptions::inline_compact`].
+///
+/// `language` is only used for the accessibility label; the highlighted
+/// contents are the same as [`spans_to_html`].
+pub fn spans_to_html_document(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    language: &str,
+    options: &DocumentOptions,
+) -> String {
+    let (source, spans) = if options.inline_compact {
+        trim_source(source, spans)
+    } else {
+        (source, spans)
+    };
+    let body = if options.emit_data_capture {
+        spans_to_html_with_data_capture(source, spans, format, None, ControlCharMode::default())
+            .html
+    } else {
+        spans_to_html(source, spans, format)
+    };
+    options.wrap_with_source(&body, language, source)
+}
+
+/// `source` without leading and trailing whitespace, and `spans` moved to
+/// match.
+fn trim_source(source: &str, spans: Vec<Span>) -> (&str, Vec<Span>) {
+    let trimmed = source.trim();
+    let start = (source.len() - source.trim_start().len()) as u32;
+    let end = start + trimmed.len() as u32;
+    let spans = spans
+        .into_iter()
+        .filter_map(|mut span| {
+            span.start = span.start.clamp(start, end) - start;
+            span.end = span.end.clamp(start, end) - start;
+            (span.start < span.end).then_some(span)
+        })
+        .collect();
+    (trimmed, spans)
+}
+
+/// Render spans as a `<figure>` holding a captioned `<pre><code>` block.
+///
+/// The `<pre>` carries a `language-{language}` class; `caption`, if any, is
+/// escaped into a `<figcaption>` placed before the code.
+pub fn spans_to_html_figure(
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+    caption: Option<&str>,
+    language: &str,
+) -> String {
+    let options = DocumentOptions {
+        language_class: true,
+        ..Default::default()
+    };
+    let document = spans_to_html_document(source, spans, format, language, &options);
+    match caption {
+        Some(caption) => format!(
+            "<figure><figcaption>{}</figcaption>{document}</figure>",
+            html_escape(caption)
+        ),
+        None => format!("<figure>{document}</figure>"),
+    }
+}
+
+/// Write spans as HTML to a writer.
+///
+/// The output is the same as [`spans_to_html`]'s, but it's written out in
+/// chunks as it's rendered instead of being built up whole, so memory use
+/// doesn't grow with the document. Rendering stops at the first write error.
+pub fn write_spans_as_html<W: Write>(
+    w: &mut W,
+    source: &str,
+    spans: Vec<Span>,
+    format: &HtmlFormat,
+) -> io::Result<()> {
+    let source = source.trim_end_matches('\n');
+    let mut out = HtmlOutput::new(0, None, ControlCharMode::default());
+    out.sink = Some(w);
+    write_html(&mut out, source, spans, format, false);
+    out.flush(true);
+    out.error.map_or(Ok(()), Err)
+}
+
+/// Escape HTML special characters.
+pub fn html_escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    write_html_escaped_into(&mut result, text);
+    result
+}
+
+/// Append `text` to `out`, escaped like [`html_escape`], without allocating
+/// an intermediate string.
+pub fn write_html_escaped_into(out: &mut String, text: &str) {
+    out.reserve(text.len());
+    // Copy the runs between special characters in one go
+    let mut run_start = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        if let Some(escaped) = html_entity(byte) {
+            out.push_str(&text[run_start..i]);
+            out.push_str(escaped);
+            run_start = i + 1;
+        }
+    }
+    out.push_str(&text[run_start..]);
+}
+
+fn push_html_escaped(out: &mut String, c: char) {
+    match u8::try_from(c).ok().and_then(html_entity) {
+        Some(escaped) => out.push_str(escaped),
+        None => out.push(c),
+    }
+}
+
+/// The entity replacing an HTML-special character.
+fn html_entity(byte: u8) -> Option<&'static str> {
+    match byte {
+        b'<' => Some("&lt;"),
+        b'>' => Some("&gt;"),
+        b'&' => Some("&amp;"),
+        b'"' => Some("&quot;"),
+        b'\'' => Some("&#39;"),
+        _ => None,
+    }
+}
+
+/// Options controlling ANSI rendering behavior.
+#[derive(Debug, Clone)]
+pub struct AnsiOptions {
+    /// If true, apply the theme's foreground/background as a base style
+    /// for all text (including un-highlighted regions).
+    pub use_theme_base_style: bool,
+    /// Optional hard wrap width (in columns). When None, no wrapping is
     /// performed and the original line structure is preserved.
     pub width: Option<usize>,
     /// If true and `width` is set, pad each visual line with spaces up
@@ -365,6 +1415,32 @@ pub struct AnsiOptions {
     pub padding_y: usize,
     /// If true, draw a border around the code block using half-block characters.
     pub border: bool,
+    /// Character drawn (dimmed) at the end of each row that `width` soft-wraps,
+    /// e.g. `'↩'`. It sits in the right padding when there is some; otherwise
+    /// a column is kept free for it so wrapped rows stay within `width`.
+    /// Rows ending at a real newline never get a marker.
+    pub wrap_marker: Option<char>,
+    /// How to render control characters in the source. Defaults to
+    /// [`ControlCharMode::Picture`] so they can't mess with the terminal.
+    pub control_char_handling: ControlCharMode,
+    /// Colors to emit. [`ColorDepth::Ansi16`] maps every theme color to the
+    /// nearest of the 16 standard terminal colors (see
+    /// [`Theme::to_ansi16_map`]), for CI logs and minimal terminals.
+    pub color_depth: ColorDepth,
+    /// Style the [`CAPTURE_NAMES_EXTENDED`](arborium_theme::CAPTURE_NAMES_EXTENDED)
+    /// subcategories (e.g. `keyword.control`) with their own theme style
+    /// rather than their slot's, where the theme defines one. Off by default.
+    pub extended_slots: bool,
+    /// Color `punctuation.bracket` spans by nesting depth, cycling through
+    /// these colors ("rainbow brackets"), instead of with the theme's
+    /// bracket style. `None` (the default) or an empty list leaves brackets
+    /// to the theme.
+    pub rainbow_brackets: Option<Vec<Color>>,
+    /// Text to show dimmed and right-aligned at the end of some lines, by
+    /// line number (0-based), e.g. to explain code alongside it. Only drawn
+    /// when `width` is set; an annotation that doesn't fit after the code
+    /// is cut short. Empty by default.
+    pub annotations: HashMap<usize, String>,
 }
 
 /// Unicode block drawing characters used to create visual borders around ANSI output.
@@ -414,6 +1490,12 @@ impl Default for AnsiOptions {
             padding_x: 0,
             padding_y: 0,
             border: false,
+            wrap_marker: None,
+            control_char_handling: ControlCharMode::default(),
+            color_depth: ColorDepth::default(),
+            extended_slots: false,
+            rainbow_brackets: None,
+            annotations: HashMap::new(),
         }
     }
 }
@@ -438,23 +1520,120 @@ fn char_display_width(c: char, col: usize, tab_width: usize) -> usize {
     }
 }
 
+/// Columns inside the border of a `width` columns wide block.
+fn content_width(width: usize, border: bool) -> usize {
+    // Minimum to handle narrow terminals
+    const MIN_CONTENT_WIDTH: usize = 10;
+    if border {
+        width.saturating_sub(2).max(MIN_CONTENT_WIDTH)
+    } else {
+        width.max(MIN_CONTENT_WIDTH)
+    }
+}
+
+/// Write `annotation` dimmed, right-aligned to end at `content_end`, after
+/// at least one space. It is cut short if the line leaves too little room,
+/// and dropped if there is none.
+fn write_annotation(
+    out: &mut String,
+    annotation: &str,
+    content_end: usize,
+    current_col: &mut usize,
+    base_ansi: &str,
+    tab_width: usize,
+) {
+    let room = content_end.saturating_sub(*current_col + 1);
+    let mut width = 0;
+    let fitting = annotation
+        .char_indices()
+        .map(|(i, c)| (i + c.len_utf8(), char_display_width(c, 0, tab_width)))
+        .take_while(|&(_, w)| {
+            width += w;
+            width <= room
+        })
+        .last()
+        .map_or(0, |(end, _)| end);
+    let annotation = &annotation[..fitting];
+    if annotation.is_empty() {
+        return;
+    }
+    let annotation_width: usize = annotation
+        .chars()
+        .map(|c| char_display_width(c, 0, tab_width))
+        .sum();
+
+    for _ in 0..content_end - *current_col - annotation_width {
+        out.push(' ');
+    }
+    out.push_str(Theme::ANSI_RESET);
+    out.push_str("\x1b[2m");
+    out.push_str(annotation);
+    out.push_str(Theme::ANSI_RESET);
+    out.push_str(base_ansi);
+    *current_col = content_end;
+}
+
 fn write_wrapped_text(
     out: &mut String,
     text: &str,
     options: &AnsiOptions,
     current_col: &mut usize,
+    line: &mut usize,
     base_ansi: &str,
     active_style: Option<usize>,
-    theme: &Theme,
+    palette: &AnsiPalette,
     use_base_bg: bool,
     border_style: &str,
 ) {
+    // Render control characters on their own, between the plain runs around them
+    if options.control_char_handling != ControlCharMode::Passthrough
+        && text.contains(is_replaced_control)
+    {
+        let mut write = |out: &mut String, text: &str, col: &mut usize| {
+            write_wrapped_text(
+                out,
+                text,
+                options,
+                col,
+                line,
+                base_ansi,
+                active_style,
+                palette,
+                use_base_bg,
+                border_style,
+            )
+        };
+        let mut rest = text;
+        while let Some(i) = rest.find(is_replaced_control) {
+            write(out, &rest[..i], current_col);
+            let c = rest[i..].chars().next().unwrap();
+            if options.control_char_handling == ControlCharMode::Picture {
+                out.push_str("\x1b[2m");
+                write(out, &control_caret(c), current_col);
+                // Back to whatever style the surrounding text had
+                out.push_str(Theme::ANSI_RESET);
+                out.push_str(base_ansi);
+                if let Some(idx) = active_style {
+                    if use_base_bg {
+                        out.push_str(&palette.ansi_style_with_base_bg(idx));
+                    } else {
+                        out.push_str(&palette.ansi_style(idx));
+                    }
+                }
+            }
+            rest = &rest[i + c.len_utf8()..];
+        }
+        write(out, rest, current_col);
+        return;
+    }
+
     // No wrapping requested: just track column and append text.
     let Some(inner_width) = options.width else {
         for ch in text.chars() {
             match ch {
                 '\n' | '\r' => {
                     *current_col = 0;
+                    *line += usize::from(ch == '\n');
                     out.push(ch);
                 }
                 other => {
@@ -476,15 +1655,14 @@ fn write_wrapped_text(
     let padding_x = options.padding_x;
     let margin_x = options.margin_x;
     let border = options.border;
-    // Inner width excludes border characters, with a minimum to handle narrow terminals
-    const MIN_CONTENT_WIDTH: usize = 10;
-    let width = if border {
-        inner_width.saturating_sub(2).max(MIN_CONTENT_WIDTH)
-    } else {
-        inner_width.max(MIN_CONTENT_WIDTH)
-    };
+    let width = content_width(inner_width, border);
     let content_end = width.saturating_sub(padding_x); // where content should stop (before right padding)
     let pad_to_width = options.pad_to_width;
+    // The wrap marker goes in the right padding, or in a column kept free for it
+    let marker_width = options
+        .wrap_marker
+        .map_or(0, |c| char_display_width(c, 0, options.tab_width));
+    let wrap_at = content_end.min(width.saturating_sub(marker_width));
 
     for ch in text.chars() {
         // At the start of a visual line, emit margin + left border + left padding
@@ -512,6 +1690,19 @@ fn write_wrapped_text(
         }
 
         if ch == '\n' || ch == '\r' {
+            if ch == '\n' {
+                if let Some(annotation) = options.annotations.get(line) {
+                    write_annotation(
+                        out,
+                        annotation,
+                        content_end,
+                        current_col,
+                        base_ansi,
+                        options.tab_width,
+                    );
+                }
+                *line += 1;
+            }
             // Pad to full width (including right padding)
             if pad_to_width && *current_col < width {
                 let pad = width - *current_col;
@@ -535,9 +1726,9 @@ fn write_wrapped_text(
             }
             if let Some(idx) = active_style {
                 let style = if use_base_bg {
-                    theme.ansi_style_with_base_bg(idx)
+                    palette.ansi_style_with_base_bg(idx)
                 } else {
-                    theme.ansi_style(idx)
+                    palette.ansi_style(idx)
                 };
                 out.push_str(&style);
             }
@@ -546,7 +1737,17 @@ fn write_wrapped_text(
 
         let w = char_display_width(ch, *current_col, options.tab_width);
         // Wrap when we would exceed the content area (before right padding)
-        if w > 0 && *current_col + w > content_end {
+        if w > 0 && *current_col + w > wrap_at {
+            if let Some(marker) = options.wrap_marker {
+                out.push_str(Theme::ANSI_RESET);
+                out.push_str("\x1b[2m");
+                out.push(marker);
+                out.push_str(Theme::ANSI_RESET);
+                if !base_ansi.is_empty() {
+                    out.push_str(base_ansi);
+                }
+                *current_col += marker_width;
+            }
             // Pad to full width (including right padding)
             if pad_to_width && *current_col < width {
                 let pad = width - *current_col;
@@ -585,9 +1786,9 @@ fn write_wrapped_text(
             // Re-apply active style after border
             if let Some(idx) = active_style {
                 let style = if use_base_bg {
-                    theme.ansi_style_with_base_bg(idx)
+                    palette.ansi_style_with_base_bg(idx)
                 } else {
-                    theme.ansi_style(idx)
+                    palette.ansi_style(idx)
                 };
                 out.push_str(&style);
             }
@@ -628,11 +1829,104 @@ pub fn spans_to_ansi_with_options(
     theme: &Theme,
     options: &AnsiOptions,
 ) -> String {
+    let mut palette = AnsiPalette::new(theme, options.color_depth);
+    if let Some(colors) = options
+        .rainbow_brackets
+        .as_deref()
+        .filter(|c| !c.is_empty())
+    {
+        palette.color_brackets(source, &spans, colors, options);
+    } else if let [span] = spans.as_slice() {
+        // Fast path for a lone span when there's no wrapping or base style to manage
+        if options.width.is_none() && !options.use_theme_base_style {
+            return single_span_to_ansi(source.trim_end_matches('\n'), span, theme, options);
+        }
+    }
+    spans_to_ansi_general(source, spans, &palette, options, None)
+}
+
+/// Source without any styling, as-is apart from control characters.
+fn unstyled_ansi(source: &str, palette: &AnsiPalette, options: &AnsiOptions) -> String {
+    if options.control_char_handling == ControlCharMode::Passthrough
+        || !source.contains(is_replaced_control)
+    {
+        return source.to_string();
+    }
+    // Unstyled output isn't wrapped either
+    let options = AnsiOptions {
+        width: None,
+        ..options.clone()
+    };
+    let mut out = String::with_capacity(source.len() + 16);
+    write_wrapped_text(
+        &mut out, source, &options, &mut 0, &mut 0, "", None, palette, false, "",
+    );
+    out
+}
+
+fn single_span_to_ansi(source: &str, span: &Span, theme: &Theme, options: &AnsiOptions) -> String {
+    let palette = AnsiPalette::new(theme, options.color_depth);
+    let Some(index) = ansi_index(&span.capture, options) else {
+        return unstyled_ansi(source, &palette, options);
+    };
+
+    let mut out = String::with_capacity(source.len() + 32);
+    let (mut current_col, mut line) = (0, 0);
+    let mut write = |out: &mut String, text: &str, style: Option<usize>| {
+        write_wrapped_text(
+            out,
+            text,
+            options,
+            &mut current_col,
+            &mut line,
+            "",
+            style,
+            &palette,
+            false,
+            "",
+        );
+    };
+
+    let Some(range) = single_span_range(source, span) else {
+        write(&mut out, source, None);
+        return out;
+    };
+
+    write(&mut out, &source[..range.start], None);
+    out.push_str(&palette.ansi_style(index));
+    write(&mut out, &source[range.clone()], Some(index));
+    out.push_str(Theme::ANSI_RESET);
+    write(&mut out, &source[range.end..], None);
+    out
+}
+
+/// Highlight index `capture` is styled with, before theme fallbacks.
+fn ansi_index(capture: &str, options: &AnsiOptions) -> Option<usize> {
+    if options.extended_slots {
+        extended_highlight_index(capture)
+    } else {
+        Slot::from_capture(capture).map(Slot::highlight_index)
+    }
+}
+
+/// The full sort/dedup/coalesce/event-loop renderer behind
+/// [`spans_to_ansi_with_options`].
+///
+/// With `flush`, it's handed the output buffer to drain whenever enough has
+/// built up, and the returned string is only what's left.
+fn spans_to_ansi_general(
+    source: &str,
+    spans: Vec<Span>,
+    palette: &AnsiPalette,
+    options: &AnsiOptions,
+    mut flush: Option<&mut dyn FnMut(&mut String)>,
+) -> String {
+    let theme = palette.theme;
     // Trim trailing newlines from source
     let source = source.trim_end_matches('\n');
 
     if spans.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, palette, options);
     }
 
     // Sort spans by (start, -end) so longer spans come first at same start
@@ -644,11 +1938,10 @@ pub fn spans_to_ansi_with_options(
     let mut deduped: HashMap<(u32, u32), Span> = HashMap::new();
     for span in spans {
         let key = (span.start, span.end);
-        let new_has_slot = slot_to_highlight_index(capture_to_slot(&span.capture)).is_some();
+        let new_has_slot = Slot::from_capture(&span.capture).is_some();
 
         if let Some(existing) = deduped.get(&key) {
-            let existing_has_slot =
-                slot_to_highlight_index(capture_to_slot(&existing.capture)).is_some();
+            let existing_has_slot = Slot::from_capture(&existing.capture).is_some();
             // Prefer spans with styling over unstyled spans
             // Among equally-styled spans, prefer higher pattern_index (later in query)
             let should_replace = match (new_has_slot, existing_has_slot) {
@@ -677,26 +1970,69 @@ pub fn spans_to_ansi_with_options(
     let mut normalized: Vec<StyledSpan> = spans
         .into_iter()
         .filter_map(|span| {
-            let slot = capture_to_slot(&span.capture);
-            let index = slot_to_highlight_index(slot)?;
-            // Filter out empty styles when using base style - they'll just use the base
-            if options.use_theme_base_style {
-                if let Some(style) = theme.style(index) {
-                    if style.is_empty() {
-                        return None;
-                    }
-                }
-            }
+            let alternate = palette.brackets.get(&(span.start, span.end));
             Some(StyledSpan {
                 start: span.start,
                 end: span.end,
-                index,
+                index: ansi_index(&span.capture, options)? + alternate.map_or(0, |n| n * COUNT),
             })
         })
         .collect();
 
+    // Move the parts of spans inside themed regions onto those themes' styles
+    if !palette.regions.is_empty() {
+        normalized = normalized
+            .into_iter()
+            .flat_map(|span| {
+                if span.start >= span.end {
+                    return vec![span];
+                }
+                let mut pieces = Vec::new();
+                let mut pos = span.start;
+                let first = palette
+                    .regions
+                    .partition_point(|&(_, end, _)| end <= span.start);
+                for &(start, end, alternate) in palette.regions[first..]
+                    .iter()
+                    .take_while(|&&(start, _, _)| start < span.end)
+                {
+                    let (start, end) = (start.max(pos), end.min(span.end));
+                    if pos < start {
+                        pieces.push(StyledSpan {
+                            start: pos,
+                            end: start,
+                            index: span.index,
+                        });
+                    }
+                    pieces.push(StyledSpan {
+                        start,
+                        end,
+                        index: span.index + alternate * COUNT,
+                    });
+                    pos = end;
+                }
+                if pos < span.end {
+                    pieces.push(StyledSpan {
+                        start: pos,
+                        end: span.end,
+                        index: span.index,
+                    });
+                }
+                pieces
+            })
+            .collect();
+    }
+
+    // Filter out empty styles when using base style - they'll just use the base
+    if options.use_theme_base_style {
+        normalized.retain(|span| {
+            let (theme, index) = palette.resolve(span.index);
+            !theme.style(index).is_some_and(Style::is_empty)
+        });
+    }
+
     if normalized.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, palette, options);
     }
 
     // Sort by start
@@ -715,7 +2051,7 @@ pub fn spans_to_ansi_with_options(
     }
 
     if coalesced.is_empty() {
-        return source.to_string();
+        return unstyled_ansi(source, palette, options);
     }
 
     // Build events from spans
@@ -732,9 +2068,11 @@ pub fn spans_to_ansi_with_options(
     let mut stack: Vec<usize> = Vec::new();
     let mut active_style: Option<usize> = None;
     let mut current_col: usize = 0;
+    // Source line being written, for annotations
+    let mut line: usize = 0;
 
     let base_ansi = if options.use_theme_base_style {
-        theme.ansi_base_style()
+        theme.ansi_base_style_at(options.color_depth)
     } else {
         String::new()
     };
@@ -748,7 +2086,7 @@ pub fn spans_to_ansi_with_options(
     let margin_y = options.margin_y;
     let border = options.border;
     let border_style = if border {
-        theme.ansi_border_style()
+        theme.ansi_border_style_at(options.color_depth)
     } else {
         String::new()
     };
@@ -842,9 +2180,10 @@ pub fn spans_to_ansi_with_options(
                         text,
                         options,
                         &mut current_col,
+                        &mut line,
                         &base_ansi,
                         Some(a),
-                        theme,
+                        palette,
                         use_base_bg,
                         &border_style,
                     );
@@ -853,9 +2192,9 @@ pub fn spans_to_ansi_with_options(
                     // Style change: reset and apply new style
                     out.push_str(Theme::ANSI_RESET);
                     let style = if use_base_bg {
-                        theme.ansi_style_with_base_bg(d)
+                        palette.ansi_style_with_base_bg(d)
                     } else {
-                        theme.ansi_style(d)
+                        palette.ansi_style(d)
                     };
                     // If using base_bg, the style already includes base colors, so don't emit base_ansi separately
                     // If the style is identical to base, just emit base once
@@ -872,9 +2211,10 @@ pub fn spans_to_ansi_with_options(
                         text,
                         options,
                         &mut current_col,
+                        &mut line,
                         &base_ansi,
                         Some(d),
-                        theme,
+                        palette,
                         use_base_bg,
                         &border_style,
                     );
@@ -883,9 +2223,9 @@ pub fn spans_to_ansi_with_options(
                 (None, Some(d)) => {
                     // First styled span or transitioning from unstyled to styled
                     let style = if use_base_bg {
-                        theme.ansi_style_with_base_bg(d)
+                        palette.ansi_style_with_base_bg(d)
                     } else {
-                        theme.ansi_style(d)
+                        palette.ansi_style(d)
                     };
 
                     // When using base_bg, if the style is identical to base_ansi, don't emit it
@@ -904,9 +2244,10 @@ pub fn spans_to_ansi_with_options(
                         text,
                         options,
                         &mut current_col,
+                        &mut line,
                         &base_ansi,
                         Some(d),
-                        theme,
+                        palette,
                         use_base_bg,
                         &border_style,
                     );
@@ -923,9 +2264,10 @@ pub fn spans_to_ansi_with_options(
                         text,
                         options,
                         &mut current_col,
+                        &mut line,
                         &base_ansi,
                         None,
-                        theme,
+                        palette,
                         use_base_bg,
                         &border_style,
                     );
@@ -942,9 +2284,10 @@ pub fn spans_to_ansi_with_options(
                         text,
                         options,
                         &mut current_col,
+                        &mut line,
                         &base_ansi,
                         None,
-                        theme,
+                        palette,
                         use_base_bg,
                         &border_style,
                     );
@@ -959,6 +2302,12 @@ pub fn spans_to_ansi_with_options(
         } else if let Some(idx) = stack.iter().rposition(|&x| x == span_idx) {
             stack.remove(idx);
         }
+
+        if let Some(flush) = flush.as_mut()
+            && out.len() >= STREAM_CHUNK_BYTES
+        {
+            flush(&mut out);
+        }
     }
 
     if last_pos < source.len() {
@@ -971,9 +2320,10 @@ pub fn spans_to_ansi_with_options(
                     text,
                     options,
                     &mut current_col,
+                    &mut line,
                     &base_ansi,
                     Some(a),
-                    theme,
+                    palette,
                     use_base_bg,
                     &border_style,
                 );
@@ -981,9 +2331,9 @@ pub fn spans_to_ansi_with_options(
             (Some(_), Some(d)) => {
                 out.push_str(Theme::ANSI_RESET);
                 let style = if use_base_bg {
-                    theme.ansi_style_with_base_bg(d)
+                    palette.ansi_style_with_base_bg(d)
                 } else {
-                    theme.ansi_style(d)
+                    palette.ansi_style(d)
                 };
                 // If using base_bg, the style already includes base colors
                 if use_base_bg {
@@ -999,9 +2349,10 @@ pub fn spans_to_ansi_with_options(
                     text,
                     options,
                     &mut current_col,
+                    &mut line,
                     &base_ansi,
                     Some(d),
-                    theme,
+                    palette,
                     use_base_bg,
                     &border_style,
                 );
@@ -1009,9 +2360,9 @@ pub fn spans_to_ansi_with_options(
             }
             (None, Some(d)) => {
                 let style = if use_base_bg {
-                    theme.ansi_style_with_base_bg(d)
+                    palette.ansi_style_with_base_bg(d)
                 } else {
-                    theme.ansi_style(d)
+                    palette.ansi_style(d)
                 };
 
                 // When using base_bg, if the style is identical to base_ansi, don't emit it
@@ -1026,9 +2377,10 @@ pub fn spans_to_ansi_with_options(
                     text,
                     options,
                     &mut current_col,
+                    &mut line,
                     &base_ansi,
                     Some(d),
-                    theme,
+                    palette,
                     use_base_bg,
                     &border_style,
                 );
@@ -1044,9 +2396,10 @@ pub fn spans_to_ansi_with_options(
                     text,
                     options,
                     &mut current_col,
+                    &mut line,
                     &base_ansi,
                     None,
-                    theme,
+                    palette,
                     use_base_bg,
                     &border_style,
                 );
@@ -1061,9 +2414,10 @@ pub fn spans_to_ansi_with_options(
                     text,
                     options,
                     &mut current_col,
+                    &mut line,
                     &base_ansi,
                     None,
-                    theme,
+                    palette,
                     use_base_bg,
                     &border_style,
                 );
@@ -1081,6 +2435,18 @@ pub fn spans_to_ansi_with_options(
             width
         };
 
+        if let Some(annotation) = options.annotations.get(&line) {
+            let content_end = content_width(width, border).saturating_sub(options.padding_x);
+            write_annotation(
+                &mut out,
+                annotation,
+                content_end,
+                &mut current_col,
+                &base_ansi,
+                options.tab_width,
+            );
+        }
+
         // Pad the final content line out to the full width.
         if pad_to_width && current_col < inner_width {
             let pad = inner_width - current_col;
@@ -1159,15 +2525,239 @@ pub fn spans_to_ansi_with_options(
     out
 }
 
+/// How far colors outside the focus range are blended toward the background.
+const DIM_FACTOR: f32 = 0.6;
+
+/// Theme lookups for the ANSI renderer.
+///
+/// Parts of the source can be rendered with other themes than the main one,
+/// e.g. the dimmed copy of the theme outside the focus range of
+/// [`spans_to_ansi_dimmed`]. Style index `n * COUNT + i`, for `n >= 1`,
+/// refers to style `i` of `alternates[n - 1]`.
+struct AnsiPalette<'a> {
+    theme: &'a Theme,
+    alternates: Vec<Cow<'a, Theme>>,
+    /// Sorted, disjoint byte ranges of the source rendered with an alternate
+    /// theme, with that theme's `n`.
+    regions: Vec<(u32, u32, usize)>,
+    /// Bracket spans, by range, rendered with an alternate theme, with that
+    /// theme's `n`.
+    brackets: HashMap<(u32, u32), usize>,
+    depth: ColorDepth,
+}
+
+impl<'a> AnsiPalette<'a> {
+    fn new(theme: &'a Theme, depth: ColorDepth) -> Self {
+        Self {
+            theme,
+            alternates: Vec::new(),
+            regions: Vec::new(),
+            brackets: HashMap::new(),
+            depth,
+        }
+    }
+
+    /// Render `punctuation.bracket` spans with one alternate theme per color,
+    /// picked by the bracket's nesting depth.
+    fn color_brackets(
+        &mut self,
+        source: &str,
+        spans: &[Span],
+        colors: &[Color],
+        options: &AnsiOptions,
+    ) {
+        let Some(index) = ansi_index("punctuation.bracket", options) else {
+            return;
+        };
+        let first = self.alternates.len() + 1;
+        for &color in colors {
+            let mut theme = self.theme.clone();
+            let style = theme.style(theme.resolve_index(index)).cloned();
+            theme.set_style(index, style.unwrap_or_default().fg(color));
+            self.alternates.push(Cow::Owned(theme));
+        }
+
+        // Brackets in order, each range once
+        let brackets: BTreeMap<(u32, u32), char> = spans
+            .iter()
+            .filter(|span| span.capture == "punctuation.bracket")
+            .filter_map(|span| {
+                let text = source.get(span.start as usize..span.end as usize)?;
+                Some(((span.start, span.end), text.chars().next()?))
+            })
+            .collect();
+        let mut depth = 0usize;
+        for (range, bracket) in brackets {
+            if matches!(bracket, ')' | ']' | '}') {
+                depth = depth.saturating_sub(1);
+            }
+            self.brackets.insert(range, first + depth % colors.len());
+            if matches!(bracket, '(' | '[' | '{') {
+                depth += 1;
+            }
+        }
+    }
+
+    fn resolve(&self, index: usize) -> (&Theme, usize) {
+        let theme = match index / COUNT {
+            0 => self.theme,
+            n => &self.alternates[n - 1],
+        };
+        let index = index % COUNT;
+        (theme, theme.resolve_index(index))
+    }
+
+    fn ansi_style(&self, index: usize) -> String {
+        let (theme, index) = self.resolve(index);
+        theme.ansi_style_at(index, self.depth)
+    }
+
+    fn ansi_style_with_base_bg(&self, index: usize) -> String {
+        let (theme, index) = self.resolve(index);
+        theme.ansi_style_with_base_bg_at(index, self.depth)
+    }
+}
+
+/// ANSI rendering that de-emphasizes everything outside `focus_range`.
+///
+/// Spans inside `focus_range` (byte offsets into `source`) render with
+/// `theme` as usual; spans outside it have their colors blended toward the
+/// theme background with [`Color::lerp`]. Spans crossing the range boundary
+/// are split. Text not covered by any span is left as is.
+pub fn spans_to_ansi_dimmed(
+    source: &str,
+    spans: Vec<Span>,
+    theme: &Theme,
+    focus_range: Range<usize>,
+    options: &AnsiOptions,
+) -> String {
+    let background = theme.background.unwrap_or(if theme.is_dark {
+        Color::new(0, 0, 0)
+    } else {
+        Color::new(255, 255, 255)
+    });
+    let mut dimmed = theme.clone();
+    for style in &mut dimmed.styles {
+        style.fg = style.fg.map(|c| c.lerp(background, DIM_FACTOR));
+        style.bg = style.bg.map(|c| c.lerp(background, DIM_FACTOR));
+    }
+
+    let (focus_start, focus_end) = (
+        focus_range.start as u32,
+        focus_range.end.max(focus_range.start) as u32,
+    );
+    let mut palette = AnsiPalette::new(theme, options.color_depth);
+    palette.alternates.push(Cow::Owned(dimmed));
+    palette.regions = [(0, focus_start, 1), (focus_end, u32::MAX, 1)]
+        .into_iter()
+        .filter(|&(start, end, _)| start < end)
+        .collect();
+    spans_to_ansi_general(source, spans, &palette, options, None)
+}
+
+/// ANSI rendering with a different theme for some lines, e.g. to color the
+/// added and removed lines of a diff.
+///
+/// `line_themes` maps line numbers (0-based) to the theme their spans are
+/// styled with; other lines use `base`. Only span colors change per line:
+/// text not covered by any span, and the base style, background and border
+/// of [`AnsiOptions`], always come from `base`.
+pub fn spans_to_ansi_multi_theme(
+    source: &str,
+    spans: Vec<Span>,
+    line_themes: &HashMap<usize, &Theme>,
+    base: &Theme,
+    options: &AnsiOptions,
+) -> String {
+    let mut palette = AnsiPalette::new(base, options.color_depth);
+    let mut offset = 0;
+    for (line_number, line) in source.split_inclusive('\n').enumerate() {
+        let (start, end) = (offset as u32, (offset + line.len()) as u32);
+        offset += line.len();
+        let Some(&theme) = line_themes
+            .get(&line_number)
+            .filter(|&&theme| !std::ptr::eq(theme, base))
+        else {
+            continue;
+        };
+
+        let n = match palette
+            .alternates
+            .iter()
+            .position(|alternate| std::ptr::eq(&**alternate, theme))
+        {
+            Some(i) => i + 1,
+            None => {
+                palette.alternates.push(Cow::Borrowed(theme));
+                palette.alternates.len()
+            }
+        };
+        // Consecutive lines with the same theme make up a single region
+        match palette.regions.last_mut() {
+            Some((_, last_end, last_n)) if *last_end == start && *last_n == n => *last_end = end,
+            _ => palette.regions.push((start, end, n)),
+        }
+    }
+    spans_to_ansi_general(source, spans, &palette, options, None)
+}
+
+/// ANSI rendering with a raw RGB color per highlight index, bypassing themes.
+///
+/// `colors` is indexed by highlight index (the ordering of
+/// [`arborium_theme::HIGHLIGHTS`], see [`Slot::highlight_index`]). Slots whose
+/// index is past the end of `colors` are left unstyled. Colors are applied as
+/// foregrounds only; `options.use_theme_base_style` has no effect since there
+/// is no base style.
+pub fn spans_to_ansi_with_colors(
+    source: &str,
+    spans: Vec<Span>,
+    colors: &[(u8, u8, u8)],
+    options: &AnsiOptions,
+) -> String {
+    let mut theme = Theme::default();
+    for (style, &(r, g, b)) in theme.styles.iter_mut().zip(colors) {
+        *style = Style::new().fg(Color::new(r, g, b));
+    }
+
+    // Drop spans without a color up front so they render as plain text
+    let spans = spans
+        .into_iter()
+        .filter(|span| {
+            Slot::from_capture(&span.capture)
+                .is_some_and(|slot| slot.highlight_index() < colors.len())
+        })
+        .collect();
+    spans_to_ansi_with_options(source, spans, &theme, options)
+}
+
 /// Write spans as ANSI-colored text to a writer.
+///
+/// The output is the same as [`spans_to_ansi`]'s, written out in chunks as
+/// it's rendered, like [`write_spans_as_html`].
 pub fn write_spans_as_ansi<W: Write>(
     w: &mut W,
     source: &str,
     spans: Vec<Span>,
     theme: &Theme,
 ) -> io::Result<()> {
-    let ansi = spans_to_ansi(source, spans, theme);
-    w.write_all(ansi.as_bytes())
+    let options = AnsiOptions::default();
+    if spans.len() == 1 {
+        // A lone span takes a fast path that renders it whole
+        let ansi = spans_to_ansi_with_options(source, spans, theme, &options);
+        return w.write_all(ansi.as_bytes());
+    }
+
+    let palette = AnsiPalette::new(theme, options.color_depth);
+    let mut result = Ok(());
+    let mut flush = |out: &mut String| {
+        if result.is_ok() {
+            result = w.write_all(out.as_bytes());
+        }
+        out.clear();
+    };
+    let rest = spans_to_ansi_general(source, spans, &palette, &options, Some(&mut flush));
+    result?;
+    w.write_all(rest.as_bytes())
 }
 
 #[cfg(test)]
@@ -1177,163 +2767,949 @@ mod tests {
     #[test]
     fn test_simple_highlight() {
         let source = "fn main";
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        assert_eq!(html, "<a-k>fn</a-k> <a-f>main</a-f>");
+    }
+
+    #[test]
+    fn test_keyword_variants_coalesce() {
+        // Different keyword captures should all map to "k" and coalesce
+        let source = "with use import";
+        let spans = vec![
+            // nvim-treesitter name
+            Span::new(0, 4, "include"),
+            Span::new(5, 8, "keyword"),
+            Span::new(9, 15, "keyword.import"),
+        ];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        // All should use "k" tag - but they're not adjacent so still separate
+        assert!(html.contains("<a-k>with</a-k>"));
+        assert!(html.contains("<a-k>use</a-k>"));
+        assert!(html.contains("<a-k>import</a-k>"));
+    }
+
+    #[test]
+    fn test_adjacent_same_tag_coalesce() {
+        // Adjacent spans with same tag should merge
+        let source = "keyword";
+        let spans = vec![
+            Span::new(0, 3, "keyword"),
+            // Maps to same slot
+            Span::new(3, 7, "keyword.function"),
+        ];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        // Should be one tag, not two
+        assert_eq!(html, "<a-k>keyword</a-k>");
+    }
+
+    #[test]
+    fn test_overlapping_spans_dedupe() {
+        let source = "apiVersion";
+        // Two spans for the same range - should keep only one
+        let spans = vec![Span::new(0, 10, "property"), Span::new(0, 10, "variable")];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        // Should only have one tag, not two
+        assert!(!html.contains("apiVersionapiVersion"));
+        assert!(html.contains("apiVersion"));
+    }
+
+    #[test]
+    fn test_registered_capture_alias() {
+        let slot = arborium_theme::register_capture_alias("@foo", "keyword");
+        assert_eq!(slot, Some(Slot::Keyword));
+
+        let source = "foo bar";
+        let html = spans_to_html(
+            source,
+            vec![Span::new(0, 3, "foo"), Span::new(4, 7, "keyword")],
+            &HtmlFormat::CustomElements,
+        );
+        assert_eq!(html, "<a-k>foo</a-k> <a-k>bar</a-k>");
+
+        let theme = arborium_theme::theme::builtin::dracula();
+        let ansi = |capture| spans_to_ansi(source, vec![Span::new(0, 3, capture)], &theme);
+        assert_eq!(ansi("foo"), ansi("keyword"));
+    }
+
+    #[test]
+    fn test_html_escape() {
+        let source = "<script>";
+        let spans = vec![];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        assert_eq!(html, "&lt;script&gt;");
+    }
+
+    #[test]
+    fn test_html_escape_many_chunks() {
+        // Every other character is its own highlighted chunk
+        let source = "if a<b && c>\"d\" { é('f'); } // ü&".repeat(20);
+        let mut spans = Vec::new();
+        let mut expected = String::new();
+        for (i, (offset, c)) in source.char_indices().enumerate() {
+            let text = c.to_string();
+            if i % 2 == 0 {
+                spans.push(Span::new(
+                    offset as u32,
+                    (offset + c.len_utf8()) as u32,
+                    if i % 4 == 0 { "keyword" } else { "string" },
+                ));
+                let tag = if i % 4 == 0 { "k" } else { "s" };
+                expected.push_str(&format!("<a-{tag}>{}</a-{tag}>", html_escape(&text)));
+            } else {
+                expected.push_str(&html_escape(&text));
+            }
+        }
+
+        let html = spans_to_html(&source, spans, &HtmlFormat::CustomElements);
+        assert_eq!(html, expected);
+
+        let mut escaped = String::from("x");
+        write_html_escaped_into(&mut escaped, "<a href='b'>&\"é\"</a>");
+        assert_eq!(
+            escaped,
+            "x&lt;a href=&#39;b&#39;&gt;&amp;&quot;é&quot;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_nospell_filtered() {
+        // Captures like "spell" and "nospell" should produce no output
+        let source = "hello world";
+        let spans = vec![Span::new(0, 5, "spell"), Span::new(6, 11, "nospell")];
+        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
+        // No tags should be emitted
+        assert_eq!(html, "hello world");
+    }
+
+    #[test]
+    fn test_spans_to_themed_slots() {
+        let spans = vec![
+            Span::new(0, 2, "keyword.function"),
+            Span::new(3, 7, "spell"),
+        ];
+
+        let themed = spans_to_themed(spans);
+        assert_eq!(themed.len(), 1);
+        assert_eq!(themed[0].slot, Slot::Keyword);
+        #[allow(deprecated)]
+        let theme_index = themed[0].theme_index;
+        assert_eq!(theme_index, Slot::Keyword.highlight_index());
+    }
+
+    #[test]
+    fn test_simple_ansi_highlight() {
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let source = "fn main";
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
+
+        let kw_idx = Slot::Keyword.highlight_index();
+        let fn_idx = Slot::Function.highlight_index();
+
+        let ansi = spans_to_ansi(source, spans, &theme);
+
+        let expected = format!(
+            "{}fn{} {}main{}",
+            theme.ansi_style(kw_idx),
+            Theme::ANSI_RESET,
+            theme.ansi_style(fn_idx),
+            Theme::ANSI_RESET
+        );
+        assert_eq!(ansi, expected);
+    }
+
+    fn general_html(source: &str, spans: Vec<Span>, format: &HtmlFormat) -> String {
+        let source = source.trim_end_matches('\n');
+        let mut out = HtmlOutput::new(source.len(), None, ControlCharMode::default());
+        spans_to_html_general(&mut out, source, spans, format);
+        out.html
+    }
+
+    #[test]
+    fn test_html_document_accessible() {
+        let spans = vec![Span::new(0, 2, "keyword")];
+        let format = HtmlFormat::CustomElements;
+
+        let html = spans_to_html_document(
+            "fn",
+            spans.clone(),
+            &format,
+            "rust",
+            &DocumentOptions::default(),
+        );
+        assert_eq!(html, "<pre><code><a-k>fn</a-k></code></pre>");
+
+        let options = DocumentOptions {
+            accessible: true,
+            ..Default::default()
+        };
+        let html = spans_to_html_document("fn", spans, &format, "rust", &options);
+        assert_eq!(
+            html,
+            "<pre role=\"region\" aria-label=\"code, rust\" tabindex=\"0\"><code><a-k>fn</a-k></code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_html_document_data_capture() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        // Both captures map to the keyword slot and would normally coalesce
+        let spans = vec![span(0, 3, "keyword"), span(3, 5, "keyword.function")];
+        let options = DocumentOptions {
+            emit_data_capture: true,
+            ..Default::default()
+        };
+
+        let html = spans_to_html_document(
+            "pubfn<",
+            spans.clone(),
+            &HtmlFormat::CustomElements,
+            "rust",
+            &options,
+        );
+        assert_eq!(
+            html,
+            "<pre><code><a-k data-capture=\"keyword\">pub</a-k><a-k data-capture=\"keyword.function\">fn</a-k>&lt;</code></pre>"
+        );
+
+        let html =
+            spans_to_html_document("pubfn<", spans, &HtmlFormat::ClassNames, "rust", &options);
+        assert!(html.contains("<span class=\"keyword\" data-capture=\"keyword\">pub</span>"));
+    }
+
+    #[test]
+    fn test_html_document_embed_raw_source() {
+        let source = "fn f() {\r\n    \"<a>\" & 'b'\n}\n";
+        let spans = vec![Span::new(0, 2, "keyword")];
+        let options = DocumentOptions {
+            embed_raw_source: true,
+            ..Default::default()
+        };
+
+        let html =
+            spans_to_html_document(source, spans, &HtmlFormat::CustomElements, "rust", &options);
+        let attr = html
+            .strip_prefix("<pre data-code=\"")
+            .and_then(|rest| rest.split_once("\"><code>"))
+            .map(|(attr, _)| attr)
+            .unwrap_or_else(|| panic!("no data-code attribute in {html:?}"));
+        // Nothing in the attribute needs raw line breaks or quotes
+        assert!(!attr.contains(['\n', '\r', '"', '<']), "{attr:?}");
+
+        let decoded = [
+            ("&#10;", "\n"),
+            ("&#13;", "\r"),
+            ("&lt;", "<"),
+            ("&gt;", ">"),
+            ("&quot;", "\""),
+            ("&#39;", "'"),
+            ("&amp;", "&"),
+        ]
+        .iter()
+        .fold(attr.to_string(), |text, (entity, c)| {
+            text.replace(entity, c)
+        });
+        assert_eq!(decoded, source);
+    }
+
+    #[test]
+    fn test_html_document_inline_compact() {
+        let source = "\n  let a = 1;\r\nlet b = 2;\n";
+        let spans = vec![Span::new(3, 6, "keyword"), Span::new(15, 18, "keyword")];
+        let options = DocumentOptions {
+            inline_compact: true,
+            language_class: true,
+            ..Default::default()
+        };
+
+        let html =
+            spans_to_html_document(source, spans, &HtmlFormat::CustomElements, "rust", &options);
+        assert_eq!(
+            html,
+            "<code class=\"language-rust\"><a-k>let</a-k> a = 1; <a-k>let</a-k> b = 2;</code>"
+        );
+    }
+
+    #[test]
+    fn test_html_exact_spans() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let format = HtmlFormat::CustomElements;
+        let source = "ifelse \"a\\n\"";
+        let spans = || {
+            vec![
+                // Adjacent spans with the same tag
+                span(0, 2, "keyword"),
+                span(2, 6, "keyword"),
+                span(7, 12, "string"),
+                span(9, 11, "string.escape"),
+            ]
+        };
+
+        let coalesced = spans_to_html(source, spans(), &format);
+        assert_eq!(coalesced, "<a-k>ifelse</a-k> <a-s>&quot;a\\n&quot;</a-s>");
+
+        let exact = spans_to_html_exact(
+            source,
+            spans(),
+            &format,
+            None,
+            ControlCharMode::default(),
+            false,
+        );
+        assert!(!exact.truncated);
+        assert_eq!(
+            exact.html,
+            "<a-k>if</a-k><a-k>else</a-k> <a-s>&quot;a<a-s>\\n</a-s>&quot;</a-s>"
+        );
+
+        // Truncated output still closes its elements
+        let limit = Some(exact.html.len() - 1);
+        let truncated = spans_to_html_exact(
+            source,
+            spans(),
+            &format,
+            limit,
+            ControlCharMode::default(),
+            false,
+        );
+        assert!(truncated.truncated);
+        assert!(truncated.html.len() < exact.html.len());
+        assert_eq!(
+            truncated.html,
+            "<a-k>if</a-k><a-k>else</a-k> <a-s>&quot;a<a-s>\\n</a-s></a-s>"
+        );
+        for limit in 0..exact.html.len() {
+            let html = spans_to_html_exact(
+                source,
+                spans(),
+                &format,
+                Some(limit),
+                ControlCharMode::default(),
+                false,
+            )
+            .html;
+            assert!(html.len() <= limit, "{limit}: {html}");
+            assert_eq!(html.matches("<a-").count(), html.matches("</a-").count());
+        }
+    }
+
+    #[test]
+    fn test_html_lines() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let source = "fn f() {\n    /* a\n\n  b<c */ x\n}\n";
+        let comment_start = source.find("/*").unwrap() as u32;
+        let comment_end = source.find("*/").unwrap() as u32 + 2;
+        let spans = vec![
+            span(0, 2, "keyword"),
+            span(3, 4, "function"),
+            span(comment_start, comment_end, "comment"),
+        ];
+        let format = HtmlFormat::CustomElements;
+
+        let lines = spans_to_html_lines(source, spans.clone(), &format);
+        assert_eq!(
+            lines,
+            [
+                "<a-k>fn</a-k> <a-f>f</a-f>() {",
+                "    <a-c>/* a</a-c>",
+                "",
+                "<a-c>  b&lt;c */</a-c> x",
+                "}",
+            ]
+        );
+
+        // Every line closes what it opens
+        for line in &lines {
+            let mut open = Vec::new();
+            for tag in line.split('<').skip(1) {
+                let name = tag.split('>').next().unwrap();
+                match name.strip_prefix('/') {
+                    Some(name) => assert_eq!(open.pop(), Some(name), "{line}"),
+                    None => open.push(name),
+                }
+            }
+            assert!(open.is_empty(), "{line}");
+        }
+
+        // Apart from the extra tags at line breaks, the same as spans_to_html
+        let joined = lines.join("\n").replace("</a-c>\n\n<a-c>", "\n\n");
+        let joined = joined.replace("</a-c>\n<a-c>", "\n");
+        assert_eq!(joined, spans_to_html(source, spans, &format));
+    }
+
+    #[test]
+    fn test_html_with_line_numbers() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        // A block comment over three lines is closed and reopened around
+        // each line's wrapper
+        let source = "a /* one\ntwo\nthree */ b\n";
+        let spans = vec![span(0, 1, "keyword"), span(2, 21, "comment")];
+        let rendered = spans_to_html_with_line_numbers(
+            source,
+            spans.clone(),
+            &HtmlFormat::CustomElements,
+            &LineNumberConfig::default(),
+            None,
+            ControlCharMode::default(),
+        );
+        assert!(!rendered.truncated);
+        assert_eq!(
+            rendered.html,
+            concat!(
+                "<span class=\"line\" data-line=\"1\"><a-k>a</a-k> <a-c>/* one</a-c></span>\n",
+                "<span class=\"line\" data-line=\"2\"><a-c>two</a-c></span>\n",
+                "<span class=\"line\" data-line=\"3\"><a-c>three */</a-c> b</span>",
+            )
+        );
+
+        // Unstyled text is split too
+        let options = LineNumberConfig {
+            start: 10,
+            class: "ln".to_string(),
+        };
+        let rendered = spans_to_html_with_line_numbers(
+            "x\n\ny",
+            vec![],
+            &HtmlFormat::CustomElements,
+            &options,
+            None,
+            ControlCharMode::default(),
+        );
+        assert_eq!(
+            rendered.html,
+            concat!(
+                "<span class=\"ln\" data-line=\"10\">x</span>\n",
+                "<span class=\"ln\" data-line=\"11\"></span>\n",
+                "<span class=\"ln\" data-line=\"12\">y</span>",
+            )
+        );
+
+        // The last line is still closed when the output is cut short
+        let rendered = spans_to_html_with_line_numbers(
+            source,
+            spans,
+            &HtmlFormat::CustomElements,
+            &LineNumberConfig::default(),
+            Some(80),
+            ControlCharMode::default(),
+        );
+        assert!(rendered.truncated);
+        assert!(rendered.html.len() <= 80);
+        assert!(rendered.html.ends_with("</span>"), "{}", rendered.html);
+    }
+
+    #[test]
+    fn test_html_with_theme() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let mocha = arborium_theme::theme::builtin::catppuccin_mocha();
+        let dracula = arborium_theme::theme::builtin::dracula();
+
+        let spans = vec![span(0, 2, "keyword"), span(3, 7, "function")];
+        assert_eq!(
+            spans_to_html_with_theme("fn main", spans.clone(), &mocha),
+            "<span style=\"color: #cba6f7\">fn</span> <span style=\"color: #89b4fa\">main</span>"
+        );
+        assert_eq!(
+            spans_to_html_with_theme("fn main", spans, &dracula),
+            "<span style=\"color: #ff79c6\">fn</span> <span style=\"color: #50fa7b\">main</span>"
+        );
+
+        // Modifiers, and escaping
+        assert_eq!(
+            spans_to_html_with_theme("# A & B", vec![span(0, 7, "markup.heading")], &dracula),
+            "<span style=\"color: #bd93f9; font-weight: bold\"># A &amp; B</span>"
+        );
+        assert_eq!(
+            spans_to_html_with_theme("x.io", vec![span(0, 4, "markup.link.url")], &mocha),
+            "<span style=\"color: #89b4fa; text-decoration: underline\">x.io</span>"
+        );
+
+        // Constants and numbers are both peach in mocha, so they merge
+        let spans = vec![span(0, 3, "constant"), span(3, 4, "number")];
+        assert_eq!(
+            spans_to_html_with_theme("MAX1", spans, &mocha),
+            "<span style=\"color: #fab387\">MAX1</span>"
+        );
+
+        // A theme without a style for the slot leaves the text unwrapped
+        let mut theme = mocha.clone();
+        theme.set_style(Slot::Keyword.highlight_index(), Style::default());
+        let spans = vec![span(0, 2, "keyword"), span(3, 7, "function")];
+        assert_eq!(
+            spans_to_html_with_theme("fn main", spans, &theme),
+            "fn <span style=\"color: #89b4fa\">main</span>"
+        );
+    }
+
+    #[test]
+    fn test_full_capture_names() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let format = HtmlFormat::FullCaptureNames;
+
+        // Same slot, different captures: not merged
+        let spans = vec![span(0, 2, "keyword.function"), span(2, 3, "keyword")];
+        assert_eq!(
+            spans_to_html("fnx", spans.clone(), &format),
+            "<span class=\"keyword function\">fn</span><span class=\"keyword\">x</span>"
+        );
+        assert_eq!(
+            spans_to_html("fnx", spans, &HtmlFormat::ClassNames),
+            "<span class=\"keyword\">fnx</span>"
+        );
+
+        // Unknown captures are kept, even alone
+        assert_eq!(
+            spans_to_html("x", vec![span(0, 1, "@my.thing")], &format),
+            "<span class=\"my thing\">x</span>"
+        );
+
+        // Identical ranges are still deduplicated by pattern index
         let spans = vec![
+            span(0, 2, "keyword"),
             Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
+                pattern_index: 1,
+                ..span(0, 2, "keyword.control.import")
             },
         ];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        assert_eq!(html, "<a-k>fn</a-k> <a-f>main</a-f>");
+        let html = spans_to_html("fn", spans.clone(), &format);
+        assert_eq!(html, "<span class=\"keyword control import\">fn</span>");
+        assert_eq!(estimate_output_size("fn", &spans[1..], &format), html.len());
+
+        // Only a slot to go by: its name
+        assert_eq!(
+            html_tags_for_slot(Slot::Keyword, &format).0,
+            "<span class=\"keyword\">"
+        );
     }
 
     #[test]
-    fn test_keyword_variants_coalesce() {
-        // Different keyword captures should all map to "k" and coalesce
-        let source = "with use import";
+    fn test_html_with_scopes() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        //            0         1         2
+        //            0123456789012345678901234567
+        let source = "let a = 1; { let b = a; b.a }";
         let spans = vec![
-            Span {
-                start: 0,
-                end: 4,
-                capture: "include".into(), // nvim-treesitter name
-                pattern_index: 0,
-            },
-            Span {
-                start: 5,
-                end: 8,
-                capture: "keyword".into(),
-                pattern_index: 0,
+            span(4, 5, "variable"),
+            span(17, 18, "variable"),
+            span(21, 22, "variable"),
+            span(24, 25, "variable"),
+            span(26, 27, "variable.member"),
+        ];
+        let scopes = [
+            Scope {
+                range: 0..source.len(),
+                definitions: vec!["a".into()],
             },
-            Span {
-                start: 9,
-                end: 15,
-                capture: "keyword.import".into(),
-                pattern_index: 0,
+            Scope {
+                range: 11..source.len(),
+                definitions: vec!["b".into()],
             },
         ];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        // All should use "k" tag - but they're not adjacent so still separate
-        assert!(html.contains("<a-k>with</a-k>"));
-        assert!(html.contains("<a-k>use</a-k>"));
-        assert!(html.contains("<a-k>import</a-k>"));
+
+        let html = spans_to_html_with_scopes(
+            source,
+            spans,
+            &scopes,
+            &HtmlFormat::CustomElements,
+            None,
+            ControlCharMode::default(),
+        )
+        .html;
+        let a = with_variable_color("<a-v>", 0);
+        let b = with_variable_color("<a-v>", 1);
+        assert_ne!(a, b);
+        assert_eq!(
+            html,
+            format!(
+                "let {a}a</a-v> = 1; {{ let {b}b</a-v> = {a}a</a-v>; {b}b</a-v>.<a-v>a</a-v> }}"
+            )
+        );
     }
 
     #[test]
-    fn test_adjacent_same_tag_coalesce() {
-        // Adjacent spans with same tag should merge
-        let source = "keyword";
+    fn test_estimate_output_size() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let source = "pub fn main() {\n    let s = \"<a>\\n\";\n    if x && y { f(s) }\n}\n";
+        let find = |needle: &str| source.find(needle).unwrap() as u32;
         let spans = vec![
-            Span {
-                start: 0,
-                end: 3,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "keyword.function".into(), // Maps to same slot
-                pattern_index: 0,
-            },
+            span(0, 3, "keyword"),
+            span(4, 6, "keyword"),
+            span(7, 11, "function"),
+            span(find("let"), find("let") + 3, "keyword"),
+            span(find("s ="), find("s =") + 1, "variable"),
+            span(find("\"<"), find("\";") + 1, "string"),
+            span(find("\\n"), find("\\n") + 2, "string.escape"),
+            span(find("if"), find("if") + 2, "keyword"),
+            span(find("&&"), find("&&") + 2, "operator"),
+            span(find("f("), find("f(") + 1, "function"),
+            span(find("f(") + 1, find("f(") + 2, "punctuation.bracket"),
+            span(find("s)"), find("s)") + 1, "variable"),
+            span(find("s)") + 1, find("s)") + 2, "punctuation.bracket"),
+            span(0, 1, "spell"),
         ];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        // Should be one tag, not two
-        assert_eq!(html, "<a-k>keyword</a-k>");
+
+        for format in [HtmlFormat::CustomElements, HtmlFormat::ClassNames] {
+            let estimate = estimate_output_size(source, &spans, &format);
+            let actual = spans_to_html(source, spans.clone(), &format).len();
+            assert!(
+                actual * 9 / 10 <= estimate && estimate <= actual * 11 / 10,
+                "{estimate} vs {actual}"
+            );
+        }
     }
 
     #[test]
-    fn test_overlapping_spans_dedupe() {
-        let source = "apiVersion";
-        // Two spans for the same range - should keep only one
+    fn test_used_slots() {
+        let span = |start, end, capture: &str, pattern_index| {
+            Span::with_pattern_index(start, end, capture, pattern_index)
+        };
         let spans = vec![
-            Span {
-                start: 0,
-                end: 10,
-                capture: "property".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 0,
-                end: 10,
-                capture: "variable".into(),
-                pattern_index: 0,
-            },
+            span(0, 3, "keyword", 0),
+            // Loses to the later string pattern on the same range
+            span(4, 9, "comment", 0),
+            span(4, 9, "string", 1),
+            span(4, 9, "spell", 2),
+            span(10, 13, "keyword.function", 0),
         ];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        // Should only have one tag, not two
-        assert!(!html.contains("apiVersionapiVersion"));
-        assert!(html.contains("apiVersion"));
+
+        assert_eq!(used_slots(&spans), BTreeSet::from(["k", "s"]));
     }
 
     #[test]
-    fn test_html_escape() {
-        let source = "<script>";
-        let spans = vec![];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        assert_eq!(html, "&lt;script&gt;");
+    fn test_line_ranges() {
+        assert_eq!(line_ranges("ab\ncd\n"), [0..2, 3..5]);
+        assert_eq!(line_ranges("ab\r\ncd\r\n"), [0..2, 4..6]);
+        assert_eq!(line_ranges("ab\n\ncd"), [0..2, 3..3, 4..6]);
+        assert_eq!(line_ranges("ab\r\n\r"), [0..2, 4..5]);
+        assert!(line_ranges("").is_empty());
+
+        let source = "a\r\nbc\nd";
+        let lines: Vec<_> = line_ranges(source)
+            .into_iter()
+            .map(|r| &source[r])
+            .collect();
+        assert_eq!(lines, source.lines().collect::<Vec<_>>());
     }
 
     #[test]
-    fn test_nospell_filtered() {
-        // Captures like "spell" and "nospell" should produce no output
-        let source = "hello world";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 5,
-                capture: "spell".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 6,
-                end: 11,
-                capture: "nospell".into(),
-                pattern_index: 0,
-            },
+    fn test_html_figure() {
+        let spans = || vec![Span::new(0, 2, "keyword")];
+        let format = HtmlFormat::CustomElements;
+
+        let html = spans_to_html_figure("fn", spans(), &format, Some("<main> & co"), "rust");
+        assert_eq!(
+            html,
+            "<figure><figcaption>&lt;main&gt; &amp; co</figcaption>\
+             <pre class=\"language-rust\"><code><a-k>fn</a-k></code></pre></figure>"
+        );
+
+        let html = spans_to_html_figure("fn", spans(), &format, None, "rust");
+        assert_eq!(
+            html,
+            "<figure><pre class=\"language-rust\"><code><a-k>fn</a-k></code></pre></figure>"
+        );
+    }
+
+    #[test]
+    fn test_html_output_limit() {
+        let source = "fn main <x>";
+        let spans = || vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
+        let format = HtmlFormat::CustomElements;
+        let full = spans_to_html(source, spans(), &format);
+        assert_eq!(full, "<a-k>fn</a-k> <a-f>main</a-f> &lt;x&gt;");
+
+        // Room for the first element and the space, but not the second element
+        let rendered = spans_to_html_with_limit(
+            source,
+            spans(),
+            &format,
+            Some(20),
+            ControlCharMode::default(),
+        );
+        assert_eq!(
+            rendered,
+            RenderedHtml {
+                html: "<a-k>fn</a-k> ".to_string(),
+                truncated: true,
+            }
+        );
+
+        // Plain text is never cut inside an escape sequence
+        let rendered = spans_to_html_with_limit(
+            source,
+            spans(),
+            &format,
+            Some(full.len() - 1),
+            ControlCharMode::default(),
+        );
+        assert!(rendered.truncated);
+        assert_eq!(rendered.html, "<a-k>fn</a-k> <a-f>main</a-f>");
+
+        // A limit that fits exactly doesn't truncate
+        let rendered = spans_to_html_with_limit(
+            source,
+            spans(),
+            &format,
+            Some(full.len()),
+            ControlCharMode::default(),
+        );
+        assert!(!rendered.truncated);
+        assert_eq!(rendered.html, full);
+
+        // Fast paths honor the limit too
+        let rendered = spans_to_html_with_limit(
+            source,
+            spans()[..1].to_vec(),
+            &format,
+            Some(5),
+            ControlCharMode::default(),
+        );
+        assert_eq!(rendered.html, "");
+        assert!(rendered.truncated);
+        let rendered = spans_to_html_with_limit(
+            source,
+            Vec::new(),
+            &format,
+            Some(5),
+            ControlCharMode::default(),
+        );
+        assert_eq!(rendered.html, "");
+        assert!(rendered.truncated);
+    }
+
+    #[test]
+    fn test_fast_paths_match_general_path() {
+        let source = "fn\tmain <x>\n\n";
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let cases = vec![
+            vec![],
+            vec![span(0, 2, "keyword")],
+            vec![span(3, 7, "function")],
+            vec![span(8, 11, "string")],
+            vec![span(3, 7, "spell")],
+            // Empty, inverted and out-of-bounds spans
+            vec![span(3, 3, "keyword")],
+            vec![span(7, 2, "keyword")],
+            vec![span(3, 100, "keyword")],
+            vec![span(50, 60, "keyword")],
+            vec![span(11, 11, "keyword")],
+            vec![span(0, 2, "keyword"), span(3, 7, "function")],
+            vec![span(0, 7, "comment"), span(3, 7, "spell")],
         ];
-        let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
-        // No tags should be emitted
-        assert_eq!(html, "hello world");
+
+        let theme = arborium_theme::theme::builtin::catppuccin_mocha();
+        let options = AnsiOptions {
+            width: None,
+            pad_to_width: false,
+            ..Default::default()
+        };
+        for spans in cases {
+            for format in [HtmlFormat::CustomElements, HtmlFormat::ClassNames] {
+                assert_eq!(
+                    spans_to_html(source, spans.clone(), &format),
+                    general_html(source, spans.clone(), &format),
+                    "{spans:?}"
+                );
+            }
+            assert_eq!(
+                spans_to_ansi_with_options(source, spans.clone(), &theme, &options),
+                spans_to_ansi_general(
+                    source,
+                    spans.clone(),
+                    &AnsiPalette::new(&theme, options.color_depth),
+                    &options,
+                    None,
+                ),
+                "{spans:?}"
+            );
+        }
     }
 
     #[test]
-    fn test_simple_ansi_highlight() {
+    fn test_writers_match_string_renderers() {
+        let span = |start, end, capture: &str| Span::new(start, end, capture);
+        let line = "fn main() { let x = \"<a & b>\"; } // done\n";
+        let source = line.repeat(1000);
+        let mut large = Vec::new();
+        for i in 0..1000 {
+            let base = i * line.len() as u32;
+            large.push(span(base, base + 2, "keyword"));
+            large.push(span(base + 3, base + 7, "function"));
+            large.push(span(base + 12, base + 15, "keyword"));
+            large.push(span(base + 20, base + 29, "string"));
+            large.push(span(base + 31, base + 32, "punctuation.bracket"));
+            large.push(span(base + 33, base + 40, "comment"));
+        }
+        let cases = vec![
+            vec![],
+            vec![span(0, 2, "keyword")],
+            vec![span(0, 7, "comment"), span(3, 7, "function")],
+            vec![span(0, 100, "string"), span(3, 7, "spell")],
+            large,
+        ];
+
         let theme = arborium_theme::theme::builtin::catppuccin_mocha();
-        let source = "fn main";
+        for spans in cases {
+            let format = HtmlFormat::CustomElements;
+            let mut html = Vec::new();
+            write_spans_as_html(&mut html, &source, spans.clone(), &format).unwrap();
+            assert_eq!(
+                String::from_utf8(html).unwrap(),
+                spans_to_html(&source, spans.clone(), &format)
+            );
+
+            let mut ansi = Vec::new();
+            write_spans_as_ansi(&mut ansi, &source, spans.clone(), &theme).unwrap();
+            assert_eq!(
+                String::from_utf8(ansi).unwrap(),
+                spans_to_ansi(&source, spans, &theme)
+            );
+        }
+    }
+
+    #[test]
+    fn test_ansi_dimmed_outside_focus() {
+        let source = "aa bb cc";
+        let spans = [(0, 2), (3, 5), (6, 8)]
+            .into_iter()
+            .map(|(start, end)| Span::new(start, end, "keyword"))
+            .collect();
+
+        let mut theme = Theme {
+            background: Some(Color::new(0, 0, 0)),
+            ..Theme::default()
+        };
+        theme.styles[Slot::Keyword.highlight_index()] = Style::new().fg(Color::new(200, 100, 50));
+
+        let options = AnsiOptions {
+            width: None,
+            pad_to_width: false,
+            ..Default::default()
+        };
+        let ansi = spans_to_ansi_dimmed(source, spans, &theme, 3..5, &options);
+
+        let focused = "\x1b[38;2;200;100;50m";
+        let dimmed = "\x1b[38;2;80;40;20m";
+        assert_eq!(
+            ansi,
+            format!(
+                "{dimmed}aa{reset} {focused}bb{reset} {dimmed}cc{reset}",
+                reset = Theme::ANSI_RESET
+            )
+        );
+    }
+
+    #[test]
+    fn test_ansi_multi_theme() {
+        let source = "fn\nfn\nfn";
+        let spans = [(0, 2), (3, 5), (6, 8)]
+            .into_iter()
+            .map(|(start, end)| Span::new(start, end, "keyword"))
+            .collect();
+
+        let mut base = Theme::default();
+        base.styles[Slot::Keyword.highlight_index()] = Style::new().fg(Color::new(200, 100, 50));
+        let mut added = Theme::default();
+        added.styles[Slot::Keyword.highlight_index()] = Style::new().fg(Color::new(50, 200, 100));
+        let line_themes = HashMap::from([(1, &added)]);
+
+        let ansi =
+            spans_to_ansi_multi_theme(source, spans, &line_themes, &base, &AnsiOptions::default());
+
+        let lines: Vec<&str> = ansi.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("\x1b[38;2;200;100;50m"));
+        assert!(lines[1].contains("\x1b[38;2;50;200;100m"));
+        assert!(!lines[1].contains("\x1b[38;2;200;100;50m"));
+        assert!(lines[2].contains("\x1b[38;2;200;100;50m"));
+    }
+
+    #[test]
+    fn test_ansi_rainbow_brackets() {
+        let source = "f((a)[b])";
+        let spans = source
+            .match_indices(['(', ')', '[', ']'])
+            .map(|(i, _)| Span::new(i as u32, i as u32 + 1, "punctuation.bracket"))
+            .collect();
+        let options = AnsiOptions {
+            width: None,
+            rainbow_brackets: Some(vec![Color::new(255, 0, 0), Color::new(0, 255, 0)]),
+            ..Default::default()
+        };
+
+        let ansi = spans_to_ansi_with_options(source, spans, &Theme::default(), &options);
+        assert_eq!(
+            ansi,
+            format!(
+                "f{red}({reset}{green}({reset}a{green})[{reset}b{green}]{reset}{red}){reset}",
+                red = "\x1b[38;2;255;0;0m",
+                green = "\x1b[38;2;0;255;0m",
+                reset = Theme::ANSI_RESET
+            )
+        );
+    }
+
+    #[test]
+    fn test_ansi_extended_slots() {
+        let source = "fn if";
         let spans = vec![
-            Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
-            },
+            Span::new(0, 2, "keyword.function"),
+            Span::new(3, 5, "keyword.conditional"),
         ];
+        let keyword = "\x1b[38;2;200;100;50m";
+        let control = "\x1b[38;2;50;100;200m";
+
+        let mut theme = Theme::default();
+        theme.styles[Slot::Keyword.highlight_index()] = Style::new().fg(Color::new(200, 100, 50));
+        let options = AnsiOptions {
+            extended_slots: true,
+            ..Default::default()
+        };
+
+        // Without a keyword.control style, control keywords use the keyword one
+        let ansi = spans_to_ansi_with_options(source, spans.clone(), &theme, &options);
+        assert!(!ansi.contains(control));
+        assert_eq!(ansi.matches(keyword).count(), 2);
 
-        let kw_idx = slot_to_highlight_index(capture_to_slot("keyword")).unwrap();
-        let fn_idx = slot_to_highlight_index(capture_to_slot("function")).unwrap();
+        let control_index = extended_highlight_index("keyword.control").unwrap();
+        theme.styles[control_index] = Style::new().fg(Color::new(50, 100, 200));
+        let ansi = spans_to_ansi_with_options(source, spans.clone(), &theme, &options);
+        assert_eq!(
+            ansi,
+            format!(
+                "{keyword}fn{reset} {control}if{reset}",
+                reset = Theme::ANSI_RESET
+            )
+        );
 
+        // The subcategory is only kept apart on request
         let ansi = spans_to_ansi(source, spans, &theme);
+        assert!(!ansi.contains(control));
+    }
+
+    #[test]
+    fn test_ansi_with_custom_colors() {
+        let source = "#[a] X fn";
+        let spans = vec![
+            Span::new(0, 4, "attribute"),
+            Span::new(5, 6, "constant"),
+            Span::new(7, 9, "keyword"),
+        ];
+        assert_eq!(Slot::Attribute.highlight_index(), 0);
+        assert_eq!(Slot::Constant.highlight_index(), 1);
+
+        let colors = [(255, 0, 0), (0, 255, 0), (0, 0, 255)];
+        let options = AnsiOptions {
+            width: None,
+            pad_to_width: false,
+            ..Default::default()
+        };
+        let ansi = spans_to_ansi_with_colors(source, spans, &colors, &options);
 
+        // Keyword's index is past the end of the map, so it stays unstyled
         let expected = format!(
-            "{}fn{} {}main{}",
-            theme.ansi_style(kw_idx),
-            Theme::ANSI_RESET,
-            theme.ansi_style(fn_idx),
-            Theme::ANSI_RESET
+            "\x1b[38;2;255;0;0m#[a]{reset} \x1b[38;2;0;255;0mX{reset} fn",
+            reset = Theme::ANSI_RESET
         );
         assert_eq!(ansi, expected);
     }
@@ -1342,12 +3718,7 @@ mod tests {
     fn test_ansi_with_base_background() {
         let theme = arborium_theme::theme::builtin::tokyo_night();
         let source = "fn";
-        let spans = vec![Span {
-            start: 0,
-            end: 2,
-            capture: "keyword".into(),
-            pattern_index: 0,
-        }];
+        let spans = vec![Span::new(0, 2, "keyword")];
 
         let mut options = AnsiOptions::default();
         options.use_theme_base_style = true;
@@ -1364,12 +3735,7 @@ mod tests {
         let theme = arborium_theme::theme::builtin::dracula();
         // Source must be longer than MIN_CONTENT_WIDTH (10) to trigger wrapping
         let source = "abcdefghijklmnop";
-        let spans = vec![Span {
-            start: 0,
-            end: source.len() as u32,
-            capture: "string".into(),
-            pattern_index: 0,
-        }];
+        let spans = vec![Span::new(0, source.len() as u32, "string")];
 
         let mut options = AnsiOptions::default();
         options.use_theme_base_style = true;
@@ -1386,26 +3752,148 @@ mod tests {
         assert!(ansi.ends_with(Theme::ANSI_RESET));
     }
 
+    #[test]
+    fn test_ansi_wrap_marker() {
+        let theme = Theme::default();
+        let source = "abcdefghijklmnop\nxy";
+        let options = AnsiOptions {
+            width: Some(12),
+            pad_to_width: false,
+            wrap_marker: Some('↩'),
+            ..Default::default()
+        };
+
+        let spans = vec![Span::new(0, 1, "keyword")];
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        let reset = Theme::ANSI_RESET;
+        let marker = format!("{reset}\x1b[2m↩{reset}");
+        let rows: Vec<&str> = ansi.split('\n').collect();
+        assert_eq!(rows.len(), 3, "{ansi:?}");
+        // Only the soft-wrapped row gets a marker, and it stays within the width
+        assert_eq!(rows[0], format!("a{reset}bcdefghijk{marker}{reset}"));
+        assert_eq!(rows[1], format!("lmnop{reset}"));
+        assert_eq!(rows[2], format!("xy{reset}"));
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-width")]
+    fn test_ansi_combining_marks() {
+        use unicode_width::UnicodeWidthStr;
+
+        // "e" followed by a combining acute accent: two chars, one column
+        let source = "e\u{301}".repeat(16);
+        let theme = arborium_theme::theme::builtin::dracula();
+        let render = |border, pad_to_width| {
+            let options = AnsiOptions {
+                width: Some(14),
+                border,
+                pad_to_width,
+                ..Default::default()
+            };
+            let spans = vec![Span::new(0, source.len() as u32, "string")];
+            spans_to_ansi_with_options(&source, spans, &theme, &options)
+        };
+        // Text of a row without its escape sequences
+        let visible = |row: &str| {
+            let mut text = String::new();
+            let mut rest = row;
+            while let Some(esc) = rest.find('\x1b') {
+                text.push_str(&rest[..esc]);
+                let end = rest[esc..].find('m').map_or(rest.len(), |m| esc + m + 1);
+                rest = &rest[end..];
+            }
+            text.push_str(rest);
+            text
+        };
+
+        // Marks stay with their base and don't count towards the width, so
+        // rows wrap after 14 accented letters, not after 7
+        let ansi = render(false, false);
+        let rows: Vec<&str> = ansi.split('\n').collect();
+        assert_eq!(rows.len(), 2, "{ansi:?}");
+        assert_eq!(visible(rows[0]), "e\u{301}".repeat(14), "{ansi:?}");
+        assert_eq!(visible(rows[1]), "e\u{301}".repeat(2), "{ansi:?}");
+
+        // Padding and the right border line up on every row
+        let ansi = render(true, true);
+        let rows: Vec<&str> = ansi.lines().collect();
+        assert!(rows.len() > 2, "{ansi:?}");
+        for row in &rows {
+            assert_eq!(visible(row).width(), 14, "{row:?} in {ansi:?}");
+        }
+    }
+
+    #[test]
+    fn test_ansi_annotations() {
+        let theme = Theme::default();
+        let source = "let a\nlet b\nlet c";
+        let options = AnsiOptions {
+            width: Some(12),
+            pad_to_width: false,
+            annotations: [(1, "note".into()), (2, "a long note".into())].into(),
+            ..Default::default()
+        };
+
+        let spans = vec![Span::new(0, 1, "keyword")];
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        let reset = Theme::ANSI_RESET;
+        let rows: Vec<&str> = ansi.split('\n').collect();
+        assert_eq!(rows.len(), 3, "{ansi:?}");
+        assert_eq!(rows[0], format!("l{reset}et a{reset}"));
+        // Right-aligned to the width
+        assert_eq!(rows[1], format!("let b   {reset}\x1b[2mnote{reset}{reset}"));
+        // Cut short to fit after the code
+        assert_eq!(rows[2], format!("let c {reset}\x1b[2ma long{reset}{reset}"));
+    }
+
+    #[test]
+    fn test_ansi_control_chars() {
+        let theme = Theme::default();
+        let source = "a\0b\x07";
+        let render = |mode| {
+            let options = AnsiOptions {
+                control_char_handling: mode,
+                ..Default::default()
+            };
+            spans_to_ansi_with_options(source, Vec::new(), &theme, &options)
+        };
+
+        let reset = Theme::ANSI_RESET;
+        assert_eq!(
+            render(ControlCharMode::Picture),
+            format!("a\x1b[2m^@{reset}b\x1b[2m^G{reset}")
+        );
+        assert_eq!(render(ControlCharMode::Strip), "ab");
+        assert_eq!(render(ControlCharMode::Passthrough), source);
+    }
+
+    #[test]
+    fn test_ansi16_color_depth() {
+        let theme = arborium_theme::theme::builtin::one_dark();
+        let source = "fn";
+        let spans = vec![Span::new(0, 2, "keyword")];
+        let options = AnsiOptions {
+            width: None,
+            color_depth: ColorDepth::Ansi16,
+            ..Default::default()
+        };
+
+        let ansi = spans_to_ansi_with_options(source, spans, &theme, &options);
+        let code = theme.to_ansi16_map()[Slot::Keyword.highlight_index()].fg_code();
+        assert_eq!(ansi, format!("\x1b[{code}mfn{}", Theme::ANSI_RESET));
+        assert!(!ansi.contains("38;2;"));
+    }
+
     #[test]
     fn test_ansi_coalesces_same_style() {
         let theme = arborium_theme::theme::builtin::catppuccin_mocha();
         let source = "keyword";
         let spans = vec![
-            Span {
-                start: 0,
-                end: 3,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "keyword.function".into(),
-                pattern_index: 0,
-            },
+            Span::new(0, 3, "keyword"),
+            Span::new(3, 7, "keyword.function"),
         ];
 
-        let kw_idx = slot_to_highlight_index(capture_to_slot("keyword")).unwrap();
+        let kw_idx = Slot::Keyword.highlight_index();
         let ansi = spans_to_ansi(source, spans, &theme);
 
         let expected = format!("{}keyword{}", theme.ansi_style(kw_idx), Theme::ANSI_RESET);
@@ -1417,20 +3905,7 @@ mod tests {
         // When a node has @comment @spell, both produce spans with the same range.
         // The @spell should NOT overwrite @comment - we should keep @comment.
         let source = "# a comment";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 11,
-                capture: "comment".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 0,
-                end: 11,
-                capture: "spell".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 11, "comment"), Span::new(0, 11, "spell")];
         let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
         // Should have comment styling, not be unstyled
         assert_eq!(html, "<a-c># a comment</a-c>");
@@ -1439,20 +3914,7 @@ mod tests {
     #[test]
     fn test_html_format_custom_elements() {
         let source = "fn main";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
         let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
         assert_eq!(html, "<a-k>fn</a-k> <a-f>main</a-f>");
     }
@@ -1460,20 +3922,7 @@ mod tests {
     #[test]
     fn test_html_format_custom_elements_with_prefix() {
         let source = "fn main";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
         let html = spans_to_html(
             source,
             spans,
@@ -1485,20 +3934,7 @@ mod tests {
     #[test]
     fn test_html_format_class_names() {
         let source = "fn main";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
         let html = spans_to_html(source, spans, &HtmlFormat::ClassNames);
         assert_eq!(
             html,
@@ -1509,20 +3945,7 @@ mod tests {
     #[test]
     fn test_html_format_class_names_with_prefix() {
         let source = "fn main";
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 2,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 3,
-                end: 7,
-                capture: "function".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(3, 7, "function")];
         let html = spans_to_html(
             source,
             spans,
@@ -1566,12 +3989,7 @@ mod tests {
 
         for (tag, capture_name, _class_name) in &tags {
             let len = tag.len() as u32;
-            spans.push(Span {
-                start: offset,
-                end: offset + len,
-                capture: capture_name.to_string(),
-                pattern_index: 0,
-            });
+            spans.push(Span::new(offset, offset + len, *capture_name));
             offset += len;
         }
 
@@ -1602,20 +4020,7 @@ mod html_tests {
         .expect("Failed to read cpp sample");
 
         // Create some fake spans that cover the whole file
-        let spans = vec![
-            Span {
-                start: 0,
-                end: 10,
-                capture: "comment".into(),
-                pattern_index: 0,
-            },
-            Span {
-                start: 100,
-                end: 110,
-                capture: "keyword".into(),
-                pattern_index: 0,
-            },
-        ];
+        let spans = vec![Span::new(0, 10, "comment"), Span::new(100, 110, "keyword")];
 
         // This should not panic
         let html = spans_to_html(&sample, spans, &HtmlFormat::default());
@@ -1702,24 +4107,9 @@ mod html_tests {
         // Simulate plugin runtime output: both @string and @property cover "name"
         // @property has higher pattern_index (11) than @string (7)
         let spans = vec![
-            Span {
-                start: 0,
-                end: 4,
-                capture: "string".into(),
-                pattern_index: 7,
-            },
-            Span {
-                start: 0,
-                end: 4,
-                capture: "property".into(),
-                pattern_index: 11,
-            },
-            Span {
-                start: 5,
-                end: 10,
-                capture: "string".into(),
-                pattern_index: 7,
-            },
+            Span::with_pattern_index(0, 4, "string", 7),
+            Span::with_pattern_index(0, 4, "property", 11),
+            Span::with_pattern_index(5, 10, "string", 7),
         ];
 
         let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
@@ -1750,18 +4140,8 @@ mod html_tests {
 
         // @string has higher pattern_index (11) than @property (7)
         let spans = vec![
-            Span {
-                start: 0,
-                end: 4,
-                capture: "property".into(),
-                pattern_index: 7,
-            },
-            Span {
-                start: 0,
-                end: 4,
-                capture: "string".into(),
-                pattern_index: 11,
-            },
+            Span::with_pattern_index(0, 4, "property", 7),
+            Span::with_pattern_index(0, 4, "string", 11),
         ];
 
         let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
@@ -1782,12 +4162,7 @@ mod html_tests {
     #[test]
     fn test_trailing_newlines_trimmed() {
         let source = "fn main() {}\n";
-        let spans = vec![Span {
-            start: 0,
-            end: 2,
-            capture: "keyword".into(),
-            pattern_index: 0,
-        }];
+        let spans = vec![Span::new(0, 2, "keyword")];
 
         let html = spans_to_html(source, spans, &HtmlFormat::CustomElements);
 
@@ -1799,6 +4174,32 @@ mod html_tests {
         assert_eq!(html, "<a-k>fn</a-k> main() {}");
     }
 
+    #[test]
+    fn test_html_control_chars() {
+        let source = "a\0<b>\x07";
+        let spans = vec![Span::new(2, 5, "keyword")];
+        let render = |mode| {
+            spans_to_html_with_limit(
+                source,
+                spans.clone(),
+                &HtmlFormat::CustomElements,
+                None,
+                mode,
+            )
+            .html
+        };
+
+        assert_eq!(
+            render(ControlCharMode::Picture),
+            "a\u{2400}<a-k>&lt;b&gt;</a-k>\u{2407}"
+        );
+        assert_eq!(render(ControlCharMode::Strip), "a<a-k>&lt;b&gt;</a-k>");
+        assert_eq!(
+            render(ControlCharMode::Passthrough),
+            "a\0<a-k>&lt;b&gt;</a-k>\x07"
+        );
+    }
+
     /// Test that multiple trailing newlines are all trimmed.
     #[test]
     fn test_multiple_trailing_newlines_trimmed() {
@@ -0,0 +1,186 @@
+//! Rich Text Format output, for pasting highlighted code into word
+//! processors and email clients.
+//!
+//! [`spans_to_rtf`] produces a minimal RTF document: a monospace font, a
+//! color table with the theme's colors, and one group per styled run.
+
+use std::fmt::Write as _;
+
+use arborium_theme::{Color, Style, Theme};
+
+use crate::{HighlightEvent, Span, to_highlight_events};
+
+/// Render highlight spans for `source` as an RTF document colored with
+/// `theme`.
+///
+/// Nested spans are resolved innermost-wins, as in HTML output: each run of
+/// text takes the style of the innermost span that the theme styles. Text
+/// outside any styled span uses the theme's foreground color, if it has one.
+pub fn spans_to_rtf(source: &str, spans: Vec<Span>, theme: &Theme) -> String {
+    // Color table entries, in order; `\cfN` and `\cbN` refer to
+    // `colors[N - 1]`
+    let mut colors: Vec<Color> = Vec::new();
+    for color in theme
+        .styles
+        .iter()
+        .flat_map(|style| [style.fg, style.bg])
+        .flatten()
+        .chain(theme.foreground)
+    {
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+    }
+    let color_number = |color: Option<Color>| {
+        color.map_or(0, |color| {
+            colors.iter().position(|&c| c == color).unwrap_or(0) + 1
+        })
+    };
+
+    let mut out = String::with_capacity(source.len() * 2 + 256);
+    out.push_str(r"{\rtf1\ansi\deff0{\fonttbl{\f0\fmodern Courier New;}}");
+    out.push_str(r"{\colortbl;");
+    for color in &colors {
+        let _ = write!(out, r"\red{}\green{}\blue{};", color.r, color.g, color.b);
+    }
+    out.push_str("}\n");
+    out.push_str(r"\f0\fs20");
+    let plain = color_number(theme.foreground);
+    if plain != 0 {
+        let _ = write!(out, r"\cf{plain}");
+    }
+    out.push(' ');
+
+    // Highlights currently open, innermost last
+    let mut open: Vec<usize> = Vec::new();
+    for event in to_highlight_events(source, &spans) {
+        match event {
+            HighlightEvent::HighlightStart(highlight) => open.push(highlight.0),
+            HighlightEvent::HighlightEnd => {
+                open.pop();
+            }
+            HighlightEvent::Source { start, end } => {
+                let style = open
+                    .iter()
+                    .rev()
+                    .filter_map(|&index| theme.style(index))
+                    .find(|style| !style.is_empty());
+                match style {
+                    Some(style) => {
+                        out.push('{');
+                        let fg = color_number(style.fg);
+                        let bg = color_number(style.bg);
+                        if push_style(&mut out, style, fg, bg) {
+                            // Delimits the last control word from the text
+                            out.push(' ');
+                        }
+                        push_rtf_escaped(&mut out, &source[start..end]);
+                        out.push('}');
+                    }
+                    None => push_rtf_escaped(&mut out, &source[start..end]),
+                }
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Control words for `style`, with `fg` and `bg` the color table numbers of
+/// its colors. Returns whether any were written.
+fn push_style(out: &mut String, style: &Style, fg: usize, bg: usize) -> bool {
+    let len = out.len();
+    if fg != 0 {
+        let _ = write!(out, r"\cf{fg}");
+    }
+    if bg != 0 {
+        // Word reads `\highlight`, most other readers `\cb`
+        let _ = write!(out, r"\cb{bg}\highlight{bg}");
+    }
+    if style.modifiers.bold {
+        out.push_str(r"\b");
+    }
+    if style.modifiers.italic {
+        out.push_str(r"\i");
+    }
+    if style.modifiers.underline {
+        out.push_str(r"\ul");
+    }
+    if style.modifiers.strikethrough {
+        out.push_str(r"\strike");
+    }
+    out.len() != len
+}
+
+/// Append `text` as RTF: special characters escaped, line breaks as `\par`,
+/// and anything outside ASCII as `\uN?` (UTF-16 code units, as signed 16-bit
+/// numbers, with `?` for readers that don't support Unicode).
+fn push_rtf_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\par\n"),
+            '\r' => {}
+            '\t' => out.push_str(r"\tab "),
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(out, r"\u{}?", *unit as i16);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arborium_theme::{Slot, Style};
+
+    #[test]
+    fn test_spans_to_rtf() {
+        let mut theme = Theme::new("test");
+        let red = Color::new(255, 0, 0);
+        let blue = Color::new(0, 0, 255);
+        theme.set_style(Slot::Keyword.highlight_index(), Style::new().fg(red).bold());
+        theme.set_style(Slot::String.highlight_index(), Style::new().fg(blue));
+
+        let source = "fn f() { \"é\" }";
+        let spans = vec![Span::new(0, 2, "keyword"), Span::new(9, 13, "string")];
+
+        let rtf = spans_to_rtf(source, spans, &theme);
+        assert!(rtf.starts_with(r"{\rtf1\ansi"), "{rtf}");
+        assert!(
+            rtf.contains(r"{\colortbl;\red255\green0\blue0;\red0\green0\blue255;}"),
+            "{rtf}"
+        );
+        assert!(rtf.contains(r"{\cf1\b fn}"), "{rtf}");
+        assert!(rtf.contains(r#"{\cf2 "\u233?"}"#), "{rtf}");
+        // Braces in the source are escaped
+        assert!(rtf.contains(r" f() \{ "), "{rtf}");
+        assert!(rtf.ends_with("\\}}\n"), "{rtf}");
+    }
+
+    #[test]
+    fn test_spans_to_rtf_background() {
+        let mut theme = Theme::new("test");
+        let yellow = Color::new(255, 255, 0);
+        let style = Style {
+            bg: Some(yellow),
+            ..Style::new()
+        };
+        theme.set_style(Slot::Comment.highlight_index(), style);
+
+        let rtf = spans_to_rtf("x // hi", vec![Span::new(2, 7, "comment")], &theme);
+        assert!(
+            rtf.contains(r"{\colortbl;\red255\green255\blue0;}"),
+            "{rtf}"
+        );
+        assert!(rtf.contains(r"{\cb1\highlight1 // hi}"), "{rtf}");
+    }
+}
@@ -0,0 +1,86 @@
+//! Variable coloring from caller-supplied scopes.
+//!
+//! Editors that already know a document's scopes (from a language server,
+//! say) can pass them as [`Scope`]s to
+//! [`spans_to_html_with_scopes`](crate::spans_to_html_with_scopes). Every
+//! variable reference that resolves to one of their definitions is then
+//! colored by the variable it refers to, "rainbow identifier" style, instead
+//! of with the theme's single variable color.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use arborium_theme::Slot;
+
+use crate::Span;
+
+/// A region of the source and the variables defined in it.
+///
+/// Scopes may nest; a reference resolves to the innermost enclosing scope
+/// that defines its name, so inner definitions shadow outer ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    /// Byte range of the scope in the source.
+    pub range: Range<usize>,
+    /// Names of the variables defined in the scope.
+    pub definitions: Vec<String>,
+}
+
+/// Resolve variable spans against `scopes`, returning the variable each
+/// resolved span refers to, keyed by the span's range.
+///
+/// Variables are numbered in the order they're defined in `scopes`, so the
+/// same scopes always give the same numbering.
+pub(crate) fn resolve_variables(
+    source: &str,
+    spans: &[Span],
+    scopes: &[Scope],
+) -> HashMap<(u32, u32), usize> {
+    // Number of the first variable defined in each scope
+    let first_variable: Vec<usize> = scopes
+        .iter()
+        .scan(0, |next, scope| {
+            let first = *next;
+            *next += scope.definitions.len();
+            Some(first)
+        })
+        .collect();
+
+    let mut variables = HashMap::new();
+    for span in spans {
+        // Field accesses are never local variables
+        if Slot::from_capture(&span.capture) != Some(Slot::Variable)
+            || span.capture.contains("member")
+            || span.capture == "field"
+        {
+            continue;
+        }
+        let range = span.start as usize..span.end as usize;
+        let Some(name) = source.get(range.clone()) else {
+            continue;
+        };
+        let innermost = scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| scope.range.start <= range.start && range.end <= scope.range.end)
+            .filter_map(|(i, scope)| {
+                let definition = scope.definitions.iter().position(|d| d == name)?;
+                Some((scope.range.len(), first_variable[i] + definition))
+            })
+            .min_by_key(|&(len, _)| len);
+        if let Some((_, variable)) = innermost {
+            variables.insert((span.start, span.end), variable);
+        }
+    }
+    variables
+}
+
+/// `open` with an inline color for `variable` added.
+///
+/// Hues are spaced by the golden angle, so consecutive variables get
+/// clearly different colors however many there are.
+pub(crate) fn with_variable_color(open: &str, variable: usize) -> String {
+    let hue = (variable as f64 * 137.508) % 360.0;
+    let open = open.strip_suffix('>').unwrap_or(open);
+    format!("{open} style=\"color: hsl({hue:.0}, 65%, 55%)\">")
+}
@@ -31,10 +31,29 @@
 //! }).collect();
 //! ```
 
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
 use crate::types::{Injection, ParseResult, Span};
-use arborium_tree_sitter::{Language, Parser, Query, QueryCursor};
+use arborium_tree_sitter::{Language, Node, Parser, Query, QueryCursor};
 use streaming_iterator::StreamingIterator;
 
+/// Size above which an ERROR node's spans are dropped when
+/// [`ParseContext::set_suppress_error_regions`] is on.
+///
+/// Smaller ERROR nodes (a missing semicolon, a half-typed token) are left
+/// alone: the code around them still highlights sensibly.
+pub const ERROR_REGION_MIN_BYTES: usize = 32;
+
+/// Time spent in [`CompiledGrammar::parse_timed`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseTimings {
+    /// Building the syntax tree.
+    pub parse: Duration,
+    /// Running the highlights and injections queries over the tree.
+    pub query: Duration,
+}
+
 /// Configuration for creating a [`CompiledGrammar`].
 pub struct GrammarConfig<'a> {
     /// The tree-sitter Language
@@ -44,6 +63,10 @@ pub struct GrammarConfig<'a> {
     /// The injections query (for embedded languages)
     pub injections_query: &'a str,
     /// The locals query (for local variable tracking, currently unused)
+    ///
+    /// When locals resolution is implemented it must stay per parse: each
+    /// injection is parsed on its own, so its scopes must not see (or leak
+    /// into) definitions from the host document.
     pub locals_query: &'a str,
 }
 
@@ -148,28 +171,140 @@ impl CompiledGrammar {
         &self.language
     }
 
+    /// Returns `true` if the grammar has any injection patterns.
+    ///
+    /// When this is `false`, [`ParseResult::injections`] is always empty and
+    /// callers can skip resolving injected languages altogether.
+    pub fn has_injections(&self) -> bool {
+        self.injection_content_idx.is_some()
+            && self
+                .injections_query
+                .as_ref()
+                .is_some_and(|query| query.pattern_count() > 0)
+    }
+
     /// Parse text and return highlight spans and injection points.
     ///
     /// Requires a [`ParseContext`] which holds the mutable parser state.
     /// Each thread should have its own context.
     pub fn parse(&self, ctx: &mut ParseContext, text: &str) -> ParseResult {
+        self.parse_inner(ctx, text, None)
+    }
+
+    /// Like [`parse`](Self::parse), but also adds the time spent building
+    /// the syntax tree and running the queries over it to `timings`.
+    ///
+    /// Timings accumulate, so one [`ParseTimings`] can cover a document and
+    /// all its injections.
+    pub fn parse_timed(
+        &self,
+        ctx: &mut ParseContext,
+        text: &str,
+        timings: &mut ParseTimings,
+    ) -> ParseResult {
+        self.parse_inner(ctx, text, Some(timings))
+    }
+
+    fn parse_inner(
+        &self,
+        ctx: &mut ParseContext,
+        text: &str,
+        timings: Option<&mut ParseTimings>,
+    ) -> ParseResult {
+        let started = timings.is_some().then(Instant::now);
+
         // Parse the text
         let tree = match ctx.parser.parse(text, None) {
             Some(tree) => tree,
             None => return ParseResult::default(),
         };
+        let parsed = timings.is_some().then(Instant::now);
 
-        let root_node = tree.root_node();
         let source = text.as_bytes();
+        let mut result = self.query_tree(
+            &mut ctx.cursor,
+            ctx.match_limit,
+            tree.root_node(),
+            &mut |node: Node<'_>| Cow::Borrowed(&source[node.byte_range()]),
+        );
+        if ctx.suppress_error_regions {
+            drop_error_region_spans(tree.root_node(), &mut result.spans);
+        }
+
+        if let (Some(timings), Some(started), Some(parsed)) = (timings, started, parsed) {
+            timings.parse += parsed - started;
+            timings.query += parsed.elapsed();
+        }
+        result
+    }
+
+    /// Parse a document supplied in chunks and return highlight spans and
+    /// injection points.
+    ///
+    /// `chunk(offset)` must return the bytes of the document starting at byte
+    /// `offset` (any non-empty prefix of the rest will do), or an empty slice
+    /// at the end of the document. This lets rope-backed editors highlight
+    /// without first copying the document into a `String`. Spans are byte
+    /// offsets into the logical document, exactly as [`parse`](Self::parse)
+    /// would return for the concatenated text.
+    ///
+    /// `chunk` is also called after parsing to read the text of nodes that
+    /// query predicates and injections look at.
+    #[cfg(feature = "chunked-input")]
+    pub fn parse_with_callback<'a, F>(&self, ctx: &mut ParseContext, mut chunk: F) -> ParseResult
+    where
+        F: FnMut(usize) -> &'a [u8],
+    {
+        let tree = match ctx
+            .parser
+            .parse_with_options(&mut |offset, _| chunk(offset), None, None)
+        {
+            Some(tree) => tree,
+            None => return ParseResult::default(),
+        };
+
+        let mut result = self.query_tree(
+            &mut ctx.cursor,
+            ctx.match_limit,
+            tree.root_node(),
+            &mut |node: Node<'_>| node_text_from_chunks(&mut chunk, node.byte_range()),
+        );
+        if ctx.suppress_error_regions {
+            drop_error_region_spans(tree.root_node(), &mut result.spans);
+        }
+        result
+    }
+
+    /// Run the highlights and injections queries over a parsed tree,
+    /// stopping after `match_limit` matches.
+    ///
+    /// `node_text` returns the source bytes covered by a node.
+    fn query_tree<'t, 's, T>(
+        &self,
+        cursor: &mut QueryCursor,
+        match_limit: usize,
+        root_node: Node<'t>,
+        node_text: &mut T,
+    ) -> ParseResult
+    where
+        T: FnMut(Node<'_>) -> Cow<'s, [u8]>,
+    {
+        let mut text_provider = |node: Node<'_>| std::iter::once(node_text(node));
 
         // Collect highlight spans
         let mut spans = Vec::new();
+        let mut remaining = match_limit;
+        let mut truncated = false;
 
-        let mut matches = ctx
-            .cursor
-            .matches(&self.highlights_query, root_node, source);
+        let mut matches = cursor.matches(&self.highlights_query, root_node, &mut text_provider);
 
         while let Some(m) = matches.next() {
+            if remaining == 0 {
+                truncated = true;
+                break;
+            }
+            remaining -= 1;
+
             for capture in m.captures {
                 let capture_name = self.highlights_query.capture_names()[capture.index as usize];
 
@@ -193,15 +328,26 @@ impl CompiledGrammar {
             }
         }
 
-        // Collect injections
-        let mut injections = Vec::new();
+        // Collect injections. Languages captured from the source are read
+        // once the matches are done, since the text provider is borrowed
+        // while iterating.
+        let mut found = Vec::new();
 
-        if let Some(ref injections_query) = self.injections_query {
-            let mut matches = ctx.cursor.matches(injections_query, root_node, source);
+        if let Some(ref injections_query) = self.injections_query
+            && !truncated
+        {
+            let mut matches = cursor.matches(injections_query, root_node, &mut text_provider);
 
             while let Some(m) = matches.next() {
+                if remaining == 0 {
+                    truncated = true;
+                    break;
+                }
+                remaining -= 1;
+
                 let mut content_node = None;
                 let mut language_name = None;
+                let mut language_node = None;
                 let mut include_children = false;
 
                 // Check for #set! injection.language property
@@ -225,27 +371,95 @@ impl CompiledGrammar {
                         content_node = Some(capture.node);
                     } else if Some(capture.index) == self.injection_language_idx {
                         // Language can come from captured text
-                        if language_name.is_none() {
-                            if let Ok(lang) = capture.node.utf8_text(source) {
-                                language_name = Some(lang.to_string());
-                            }
-                        }
+                        language_node = Some(capture.node);
                     }
                 }
 
-                if let (Some(node), Some(lang)) = (content_node, language_name) {
-                    injections.push(Injection {
-                        start: node.start_byte() as u32,
-                        end: node.end_byte() as u32,
-                        language: lang,
-                        include_children,
-                    });
+                if let Some(node) = content_node {
+                    found.push((node, language_name, language_node, include_children));
                 }
             }
         }
 
-        ParseResult { spans, injections }
+        let mut injections = Vec::new();
+        for (node, language_name, language_node, include_children) in found {
+            let language = language_name.or_else(|| {
+                let text = node_text(language_node?);
+                std::str::from_utf8(&text).ok().map(str::to_string)
+            });
+            if let Some(lang) = language {
+                injections.push(Injection {
+                    start: node.start_byte() as u32,
+                    end: node.end_byte() as u32,
+                    language: lang,
+                    include_children,
+                });
+            }
+        }
+
+        ParseResult {
+            spans,
+            injections,
+            truncated,
+        }
+    }
+}
+
+/// Remove spans lying entirely inside an ERROR node larger than
+/// [`ERROR_REGION_MIN_BYTES`].
+///
+/// While code is being typed, tree-sitter can give up on large stretches of
+/// it, and whatever the highlights query still matches in there tends to be
+/// noise that changes with every keystroke.
+fn drop_error_region_spans(root: Node<'_>, spans: &mut Vec<Span>) {
+    // Outermost large ERROR nodes; subtrees without errors are skipped
+    let mut regions: Vec<std::ops::Range<usize>> = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if !node.has_error() {
+            continue;
+        }
+        if node.is_error() && node.byte_range().len() > ERROR_REGION_MIN_BYTES {
+            regions.push(node.byte_range());
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    if regions.is_empty() {
+        return;
+    }
+    regions.sort_by_key(|region| region.start);
+
+    spans.retain(|span| {
+        let (start, end) = (span.start as usize, span.end as usize);
+        let i = regions.partition_point(|region| region.start <= start);
+        !(i > 0 && end <= regions[i - 1].end)
+    });
+}
+
+/// Read `range` of a chunked document, borrowing when it lies in one chunk.
+#[cfg(feature = "chunked-input")]
+fn node_text_from_chunks<'a, F>(chunk: &mut F, range: std::ops::Range<usize>) -> Cow<'a, [u8]>
+where
+    F: FnMut(usize) -> &'a [u8],
+{
+    let first = chunk(range.start);
+    if first.len() >= range.len() {
+        return Cow::Borrowed(&first[..range.len()]);
     }
+
+    let mut text = Vec::with_capacity(range.len());
+    text.extend_from_slice(first);
+    while text.len() < range.len() {
+        let next = chunk(range.start + text.len());
+        if next.is_empty() {
+            break;
+        }
+        let take = next.len().min(range.len() - text.len());
+        text.extend_from_slice(&next[..take]);
+    }
+    Cow::Owned(text)
 }
 
 /// Per-thread parsing context.
@@ -256,7 +470,9 @@ impl CompiledGrammar {
 /// # Usage
 ///
 /// Each thread should have its own `ParseContext`. Create it once and reuse
-/// for multiple parse calls.
+/// for multiple parse calls: the query cursor is kept across parses, so
+/// reusing a context avoids allocating a new cursor per request. Contexts
+/// are `Send`, so they can be pooled and handed between threads.
 ///
 /// ```rust,ignore
 /// let mut ctx = ParseContext::for_grammar(&grammar)?;
@@ -268,6 +484,8 @@ impl CompiledGrammar {
 pub struct ParseContext {
     parser: Parser,
     cursor: QueryCursor,
+    suppress_error_regions: bool,
+    match_limit: usize,
 }
 
 impl ParseContext {
@@ -283,6 +501,8 @@ impl ParseContext {
         Ok(Self {
             parser,
             cursor: QueryCursor::new(),
+            suppress_error_regions: false,
+            match_limit: usize::MAX,
         })
     }
 
@@ -299,6 +519,8 @@ impl ParseContext {
         Ok(Self {
             parser,
             cursor: QueryCursor::new(),
+            suppress_error_regions: false,
+            match_limit: usize::MAX,
         })
     }
 
@@ -310,6 +532,26 @@ impl ParseContext {
             .set_language(language)
             .map_err(|_| GrammarError::LanguageError)
     }
+
+    /// Drop highlight spans that fall entirely within ERROR nodes larger
+    /// than [`ERROR_REGION_MIN_BYTES`]. Off by default.
+    ///
+    /// Useful when highlighting incomplete input as it is typed, where
+    /// large unparseable regions otherwise flicker with arbitrary colors.
+    pub fn set_suppress_error_regions(&mut self, suppress: bool) {
+        self.suppress_error_regions = suppress;
+    }
+
+    /// Stop collecting after `limit` query matches per parse, marking the
+    /// result [`truncated`](ParseResult::truncated). Unlimited by default.
+    ///
+    /// Caps the work a single request can cause on a server, the way
+    /// cancellation does in the plugin runtime. Matches of the highlights
+    /// and injections queries both count. Injected languages are parsed
+    /// separately, each with the full limit.
+    pub fn set_match_limit(&mut self, limit: usize) {
+        self.match_limit = limit;
+    }
 }
 
 // Backward compatibility aliases
@@ -320,5 +562,80 @@ pub type TreeSitterGrammarError = GrammarError;
 
 #[cfg(test)]
 mod tests {
-    // Tests would go here but require actual tree-sitter grammars
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "chunked-input")]
+    fn test_parse_with_callback_matches_str() {
+        let source = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../demo/samples/cpp.cc"
+        ))
+        .expect("Failed to read cpp sample");
+
+        let grammar = CompiledGrammar::new(GrammarConfig {
+            language: arborium_cpp::language().into(),
+            highlights_query: &arborium_cpp::HIGHLIGHTS_QUERY,
+            injections_query: arborium_cpp::INJECTIONS_QUERY,
+            locals_query: "",
+        })
+        .expect("Failed to compile grammar");
+        let mut ctx = ParseContext::for_grammar(&grammar).unwrap();
+        let expected = grammar.parse(&mut ctx, &source);
+        assert!(!expected.spans.is_empty());
+
+        // Small chunks split most tokens, like a rope's leaves would
+        let bytes = source.as_bytes();
+        let chunks: Vec<&[u8]> = bytes.chunks(7).collect();
+        let result = grammar.parse_with_callback(&mut ctx, |offset| {
+            chunks
+                .get(offset / 7)
+                .map_or(&[][..], |chunk| &chunk[offset % 7..])
+        });
+
+        assert_eq!(result.spans, expected.spans);
+        assert_eq!(result.injections, expected.injections);
+    }
+
+    #[test]
+    fn test_match_limit_truncates() {
+        let grammar = CompiledGrammar::new(GrammarConfig {
+            language: arborium_cpp::language().into(),
+            highlights_query: &arborium_cpp::HIGHLIGHTS_QUERY,
+            injections_query: arborium_cpp::INJECTIONS_QUERY,
+            locals_query: "",
+        })
+        .expect("Failed to compile grammar");
+        let source = "int main() { return 0; }\n".repeat(50);
+        let mut ctx = ParseContext::for_grammar(&grammar).unwrap();
+
+        let full = grammar.parse(&mut ctx, &source);
+        assert!(!full.truncated);
+        assert!(full.spans.len() > 10);
+
+        ctx.set_match_limit(10);
+        let limited = grammar.parse(&mut ctx, &source);
+        assert!(limited.truncated);
+        assert!(limited.spans.len() < full.spans.len());
+        assert_eq!(limited.spans[..], full.spans[..limited.spans.len()]);
+    }
+
+    #[test]
+    fn test_has_injections() {
+        let compile = |injections_query| {
+            CompiledGrammar::new(GrammarConfig {
+                language: arborium_cpp::language().into(),
+                highlights_query: &arborium_cpp::HIGHLIGHTS_QUERY,
+                injections_query,
+                locals_query: "",
+            })
+            .expect("Failed to compile grammar")
+        };
+
+        assert!(!compile("").has_injections());
+        assert!(
+            compile("((raw_string_content) @injection.content (#set! injection.language \"cpp\"))")
+                .has_injections()
+        );
+    }
 }
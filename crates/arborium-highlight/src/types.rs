@@ -7,6 +7,13 @@ use std::fmt;
 /// Spans come from grammar parsers and contain the raw capture name
 /// (e.g., "keyword.function", "include", "string.special.symbol").
 /// The capture name is later mapped to a theme slot for rendering.
+///
+/// # Stability
+///
+/// The fields are public, but `Span` may gain fields in future releases.
+/// Build spans with [`Span::new`] or [`Span::with_pattern_index`] rather
+/// than struct literals, which would stop compiling when a field is added;
+/// constructors and accessors are kept stable.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Span {
     /// Byte offset where the span starts (inclusive).
@@ -29,6 +36,50 @@ pub struct Span {
     pub pattern_index: u32,
 }
 
+impl Span {
+    /// A span of `capture` over `start..end`, with pattern index 0.
+    pub fn new(start: u32, end: u32, capture: impl Into<String>) -> Self {
+        Self::with_pattern_index(start, end, capture, 0)
+    }
+
+    /// A span of `capture` over `start..end`, from the query pattern
+    /// `pattern_index`.
+    pub fn with_pattern_index(
+        start: u32,
+        end: u32,
+        capture: impl Into<String>,
+        pattern_index: u32,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            capture: capture.into(),
+            pattern_index,
+        }
+    }
+
+    /// Byte offset where the span starts (inclusive).
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Byte offset where the span ends (exclusive).
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// The capture name from the grammar's highlight query.
+    pub fn capture(&self) -> &str {
+        &self.capture
+    }
+
+    /// Pattern index from the query; among spans with the same range, the
+    /// one with the highest index wins.
+    pub fn pattern_index(&self) -> u32 {
+        self.pattern_index
+    }
+}
+
 /// An injection point for embedded languages.
 ///
 /// Injections are detected by the grammar's injection query. For example,
@@ -48,6 +99,24 @@ pub struct Injection {
     pub include_children: bool,
 }
 
+/// A region of a document that was highlighted as an injected language.
+///
+/// Returned alongside spans by highlighters that report injection
+/// boundaries, e.g. so an editor can draw a gutter marker per injected
+/// language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InjectionRegion {
+    /// The injected language.
+    pub language: String,
+
+    /// Byte range of the injected text in the document.
+    pub byte_range: std::ops::Range<usize>,
+
+    /// Nesting depth: 1 for a language injected into the document itself,
+    /// 2 for one injected into that, and so on.
+    pub depth: u32,
+}
+
 /// Result of parsing a document with a grammar.
 #[derive(Debug, Clone, Default)]
 pub struct ParseResult {
@@ -56,6 +125,55 @@ pub struct ParseResult {
 
     /// Injection points for other languages.
     pub injections: Vec<Injection>,
+
+    /// Whether the parse stopped collecting matches early, at the
+    /// [`ParseContext`](crate::tree_sitter::ParseContext)'s match limit, so
+    /// the spans and injections may be incomplete.
+    pub truncated: bool,
+}
+
+impl ParseResult {
+    /// A readable listing of the spans and injections, with the text of
+    /// `source` they cover, for debugging.
+    ///
+    /// Each span is a line like `[0-2] keyword "fn"`, followed by one line
+    /// per injection, like `injection [10-20] sql`.
+    pub fn display_with_source<'a>(&'a self, source: &'a str) -> impl fmt::Display + 'a {
+        DisplayWithSource {
+            result: self,
+            source,
+        }
+    }
+}
+
+/// Returned by [`ParseResult::display_with_source`].
+struct DisplayWithSource<'a> {
+    result: &'a ParseResult,
+    source: &'a str,
+}
+
+impl fmt::Display for DisplayWithSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for span in &self.result.spans {
+            write!(f, "[{}-{}] {}", span.start, span.end, span.capture)?;
+            // Grammars can report spans past the end of the text
+            match self.source.get(span.start as usize..span.end as usize) {
+                Some(text) => writeln!(f, " {text:?}")?,
+                None => writeln!(f)?,
+            }
+        }
+        for injection in &self.result.injections {
+            writeln!(
+                f,
+                "injection [{}-{}] {}",
+                injection.start, injection.end, injection.language
+            )?;
+        }
+        if self.result.truncated {
+            writeln!(f, "(truncated)")?;
+        }
+        Ok(())
+    }
 }
 
 /// Errors that can occur during highlighting.
@@ -66,6 +184,24 @@ pub enum HighlightError {
 
     /// An error occurred during parsing.
     ParseError(String),
+
+    /// The grammar panicked while parsing (e.g. a buggy external scanner).
+    ///
+    /// Panics are only caught when the binary is built with
+    /// `panic = "unwind"` (the default). With `panic = "abort"` the process
+    /// still aborts. On WASM a panic is a trap, which can't be caught at all;
+    /// run grammars behind the plugin boundary to keep a trap from taking
+    /// down the host.
+    GrammarPanic {
+        /// The language whose grammar panicked.
+        language: String,
+    },
+
+    /// The rendered output would exceed the configured `max_output_bytes`.
+    OutputTooLarge {
+        /// The configured limit, in bytes.
+        limit: usize,
+    },
 }
 
 impl fmt::Display for HighlightError {
@@ -77,8 +213,40 @@ impl fmt::Display for HighlightError {
             HighlightError::ParseError(msg) => {
                 write!(f, "parse error: {}", msg)
             }
+            HighlightError::GrammarPanic { language } => {
+                write!(f, "grammar for {} panicked during parse", language)
+            }
+            HighlightError::OutputTooLarge { limit } => {
+                write!(f, "highlighted output exceeds {} bytes", limit)
+            }
         }
     }
 }
 
 impl std::error::Error for HighlightError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_with_source() {
+        let source = "let q = \"select 1\";";
+        let result = ParseResult {
+            spans: vec![Span::new(0, 3, "keyword"), Span::new(8, 18, "string")],
+            injections: vec![Injection {
+                start: 9,
+                end: 17,
+                language: "sql".into(),
+                include_children: false,
+            }],
+            truncated: false,
+        };
+        assert_eq!(
+            result.display_with_source(source).to_string(),
+            "[0-3] keyword \"let\"\n\
+             [8-18] string \"\\\"select 1\\\"\"\n\
+             injection [9-17] sql\n"
+        );
+    }
+}
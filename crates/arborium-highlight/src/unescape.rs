@@ -0,0 +1,180 @@
+//! Unescaping of injected string literal contents.
+//!
+//! Code embedded in a string literal (SQL in a C string, say) is still
+//! escaped by the host language's rules, so an injected grammar would see
+//! `\"` where the embedded code has `"`. A [`StringUnescaper`] for the host
+//! language undoes that before the injected text is parsed, and the
+//! resulting spans are mapped back to the escaped source.
+
+use std::fmt::Debug;
+use std::ops::Range;
+
+/// Undoes a language's string escapes, see
+/// [`HighlightConfig::string_unescapers`](crate::HighlightConfig::string_unescapers).
+pub trait StringUnescaper: Debug + Send + Sync {
+    /// Unescape `text`, the contents of a string literal.
+    ///
+    /// Returns `None` if `text` has nothing to unescape.
+    fn unescape(&self, text: &str) -> Option<Unescaped>;
+}
+
+/// Unescaped text, and where each part of it came from in the original.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unescaped {
+    /// The unescaped text.
+    pub text: String,
+    /// Offset in the original text of each byte of `text`.
+    origins: Vec<usize>,
+    /// Length of the original text.
+    original_len: usize,
+}
+
+impl Unescaped {
+    /// Start unescaping a text of `original_len` bytes.
+    pub fn new(original_len: usize) -> Self {
+        Self {
+            text: String::with_capacity(original_len),
+            origins: Vec::with_capacity(original_len),
+            original_len,
+        }
+    }
+
+    /// Append `text`, unescaped from the `original` range of the original
+    /// text.
+    ///
+    /// If `text` is as long as `original` it is taken to be copied unchanged
+    /// and maps back byte for byte; otherwise all of it maps to the start of
+    /// `original`.
+    pub fn push(&mut self, text: &str, original: Range<usize>) {
+        if text.len() == original.len() {
+            self.origins.extend(original);
+        } else {
+            self.origins
+                .extend(std::iter::repeat_n(original.start, text.len()));
+        }
+        self.text.push_str(text);
+    }
+
+    /// Map a byte range in [`text`](Self::text) back to the original text.
+    ///
+    /// A range covering part of an escape sequence's output covers the
+    /// whole sequence.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        let origin = |offset: usize| {
+            self.origins
+                .get(offset)
+                .copied()
+                .unwrap_or(self.original_len)
+        };
+        origin(range.start)..origin(range.end)
+    }
+}
+
+/// Unescapes C-style string literals.
+///
+/// Handles the simple escapes (`\n`, `\t`, `\"`, `\\` and so on), octal and
+/// `\x` escapes of ASCII characters, `\u` and `\U` escapes, and line
+/// continuations. Anything else, including escapes of bytes that aren't
+/// valid UTF-8 on their own, is kept as written.
+///
+/// ```
+/// use arborium_highlight::{CStringUnescaper, StringUnescaper};
+///
+/// let text = r#"SELECT \"name\"\x20FROM t"#;
+/// let unescaped = CStringUnescaper.unescape(text).unwrap();
+/// assert_eq!(unescaped.text, r#"SELECT "name" FROM t"#);
+/// assert_eq!(&text[unescaped.original_range(7..13)], r#"\"name\""#);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CStringUnescaper;
+
+impl StringUnescaper for CStringUnescaper {
+    fn unescape(&self, text: &str) -> Option<Unescaped> {
+        if !text.contains('\\') {
+            return None;
+        }
+
+        let mut unescaped = Unescaped::new(text.len());
+        let mut pos = 0;
+        while let Some(backslash) = text[pos..].find('\\').map(|i| pos + i) {
+            unescaped.push(&text[pos..backslash], pos..backslash);
+            let rest = &text[backslash + 1..];
+            // Line continuations disappear entirely
+            let continuation = ["\n", "\r\n"].into_iter().find(|nl| rest.starts_with(nl));
+            if let Some(newline) = continuation {
+                pos = backslash + 1 + newline.len();
+                continue;
+            }
+            let (c, len) = c_escape(rest);
+            let end = backslash + 1 + len;
+            match c {
+                Some(c) => unescaped.push(c.encode_utf8(&mut [0; 4]), backslash..end),
+                None => unescaped.push(&text[backslash..end], backslash..end),
+            }
+            pos = end;
+        }
+        unescaped.push(&text[pos..], pos..text.len());
+        Some(unescaped)
+    }
+}
+
+/// Decode the C escape sequence at the start of `rest`, the text after a
+/// backslash.
+///
+/// Returns the escaped character, or `None` to keep the sequence as
+/// written, and the sequence's length after the backslash.
+fn c_escape(rest: &str) -> (Option<char>, usize) {
+    let Some(first) = rest.chars().next() else {
+        return (None, 0);
+    };
+    let simple = match first {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        '\\' | '\'' | '"' | '?' => Some(first),
+        _ => None,
+    };
+    if simple.is_some() {
+        return (simple, 1);
+    }
+
+    let digits = |radix: u32, max: usize| {
+        rest.char_indices()
+            .skip(usize::from(radix == 16))
+            .take(max)
+            .take_while(|(_, c)| c.is_digit(radix))
+            .last()
+            .map_or(0, |(i, c)| i + c.len_utf8())
+    };
+    let (value, len) = match first {
+        '0'..='7' => {
+            let len = digits(8, 3);
+            (u32::from_str_radix(&rest[..len], 8).ok(), len)
+        }
+        'x' => {
+            let len = digits(16, 2);
+            (
+                u32::from_str_radix(&rest[1..len.max(1)], 16).ok(),
+                len.max(1),
+            )
+        }
+        'u' | 'U' => {
+            let count = if first == 'u' { 4 } else { 8 };
+            let len = digits(16, count);
+            if len == 1 + count {
+                (u32::from_str_radix(&rest[1..len], 16).ok(), len)
+            } else {
+                (None, 1)
+            }
+        }
+        _ => (None, first.len_utf8()),
+    };
+    // Octal and hex escapes are single bytes; only ASCII ones are whole
+    // characters
+    let value = value.filter(|&v| matches!(first, 'u' | 'U') || v < 0x80);
+    (value.and_then(char::from_u32), len)
+}
@@ -25,6 +25,7 @@
 //! ```
 
 use std::collections::HashMap;
+use std::future::Future;
 
 use wasm_bindgen::prelude::*;
 
@@ -106,6 +107,7 @@ fn parse_js_result(value: JsValue) -> ParseResult {
             return ParseResult {
                 spans,
                 injections: vec![],
+                truncated: false,
             };
         }
     };
@@ -139,7 +141,11 @@ fn parse_js_result(value: JsValue) -> ParseResult {
         });
     }
 
-    ParseResult { spans, injections }
+    ParseResult {
+        spans,
+        injections,
+        truncated: false,
+    }
 }
 
 /// A grammar that wraps a JS grammar handle.
@@ -166,16 +172,56 @@ impl Grammar for JsGrammar {
 ///
 /// Implements `GrammarProvider` so we can use the shared `AsyncHighlighter`
 /// from `arborium_highlight`.
+///
+/// Each language is loaded at most once per provider, whether or not the
+/// load succeeds: a document injecting the same language many times (or an
+/// unavailable one) makes a single `loadGrammar` call for it.
 pub struct JsGrammarProvider {
-    /// Cached grammars by language name
-    grammars: HashMap<String, JsGrammar>,
+    /// Grammars by language name; `None` records a failed load
+    grammars: HashMap<String, Option<JsGrammar>>,
+    /// Loads left before further languages are left unhighlighted
+    loads_left: Option<u32>,
 }
 
 impl JsGrammarProvider {
     pub fn new() -> Self {
         Self {
             grammars: HashMap::new(),
+            loads_left: None,
+        }
+    }
+
+    /// Load at most `max_loads` grammars; languages not loaded by then are
+    /// left unhighlighted.
+    pub fn with_max_loads(max_loads: u32) -> Self {
+        Self {
+            loads_left: Some(max_loads),
+            ..Self::new()
+        }
+    }
+
+    /// The grammar for `language`, calling `load` for its handle unless it
+    /// was already loaded (or failed to load). A handle of 0 means the
+    /// grammar wasn't found.
+    async fn get_or_load<F, Fut>(&mut self, language: &str, load: F) -> Option<&mut JsGrammar>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Option<GrammarHandle>>,
+    {
+        if !self.grammars.contains_key(language) {
+            if self.loads_left == Some(0) {
+                return None;
+            }
+            if let Some(left) = &mut self.loads_left {
+                *left -= 1;
+            }
+            let grammar = load()
+                .await
+                .filter(|&handle| handle != 0)
+                .map(JsGrammar::new);
+            self.grammars.insert(language.to_string(), grammar);
         }
+        self.grammars.get_mut(language)?.as_mut()
     }
 }
 
@@ -196,26 +242,12 @@ impl GrammarProvider for JsGrammarProvider {
             return None;
         }
 
-        // Check if we already have this grammar cached
-        if self.grammars.contains_key(language) {
-            return self.grammars.get_mut(language);
-        }
-
-        // Load the grammar from JS (async)
-        let handle = match js_load_grammar(language).await {
-            Ok(val) => val.as_f64().unwrap_or(0.0) as GrammarHandle,
-            Err(_) => return None,
-        };
-
-        // 0 means not found
-        if handle == 0 {
-            return None;
-        }
-
-        // Cache and return
-        self.grammars
-            .insert(language.to_string(), JsGrammar::new(handle));
-        self.grammars.get_mut(language)
+        // Load the grammar from JS (async), unless already tried
+        self.get_or_load(language, || async {
+            let handle = js_load_grammar(language).await.ok()?;
+            Some(handle.as_f64().unwrap_or(0.0) as GrammarHandle)
+        })
+        .await
     }
 
     // Stub for non-wasm32 targets (never used, just for compilation)
@@ -230,6 +262,7 @@ impl GrammarProvider for JsGrammarProvider {
 pub struct HighlightConfig {
     max_injection_depth: u32,
     html_format: CoreHtmlFormat,
+    max_grammar_loads: Option<u32>,
 }
 
 #[wasm_bindgen]
@@ -239,6 +272,7 @@ impl HighlightConfig {
         Self {
             max_injection_depth: 3,
             html_format: CoreHtmlFormat::default(),
+            max_grammar_loads: None,
         }
     }
 
@@ -247,6 +281,13 @@ impl HighlightConfig {
         self.max_injection_depth = depth;
     }
 
+    /// Load at most `count` grammars per highlight call. Injected languages
+    /// beyond that are left unhighlighted. Unlimited by default.
+    #[wasm_bindgen(js_name = setMaxGrammarLoads)]
+    pub fn set_max_grammar_loads(&mut self, count: u32) {
+        self.max_grammar_loads = Some(count);
+    }
+
     /// Set HTML format to custom elements (default): `<a-k>`, `<a-f>`, etc.
     #[wasm_bindgen(js_name = setHtmlFormatCustomElements)]
     pub fn set_html_format_custom_elements(&mut self) {
@@ -270,6 +311,13 @@ impl HighlightConfig {
     pub fn set_html_format_class_names_with_prefix(&mut self, prefix: String) {
         self.html_format = CoreHtmlFormat::ClassNamesWithPrefix(prefix);
     }
+
+    /// Set HTML format to full capture names:
+    /// `<span class="keyword control import">`, etc.
+    #[wasm_bindgen(js_name = setHtmlFormatFullCaptureNames)]
+    pub fn set_html_format_full_capture_names(&mut self) {
+        self.html_format = CoreHtmlFormat::FullCaptureNames;
+    }
 }
 
 impl Default for HighlightConfig {
@@ -297,9 +345,13 @@ pub async fn highlight_with_config(
     let core_config = CoreConfig {
         max_injection_depth: config.max_injection_depth,
         html_format: config.html_format.clone(),
+        ..Default::default()
     };
 
-    let provider = JsGrammarProvider::new();
+    let provider = match config.max_grammar_loads {
+        Some(max_loads) => JsGrammarProvider::with_max_loads(max_loads),
+        None => JsGrammarProvider::new(),
+    };
     let mut highlighter = AsyncHighlighter::with_config(provider, core_config);
 
     highlighter
@@ -313,3 +365,56 @@ pub async fn highlight_with_config(
 pub fn is_language_available(language: &str) -> bool {
     js_is_language_available(language)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Get `language` from `provider` with a loader that counts its calls
+    fn get(
+        provider: &mut JsGrammarProvider,
+        language: &str,
+        handle: GrammarHandle,
+        loads: &Cell<u32>,
+    ) -> Option<GrammarHandle> {
+        let future = provider.get_or_load(language, || async {
+            loads.set(loads.get() + 1);
+            Some(handle)
+        });
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(grammar) => grammar.map(|grammar| grammar.handle),
+            Poll::Pending => panic!("loader never waits"),
+        }
+    }
+
+    #[test]
+    fn test_grammar_loaded_once() {
+        let loads = Cell::new(0);
+        let mut provider = JsGrammarProvider::new();
+
+        // Two injections of the same language
+        assert_eq!(get(&mut provider, "css", 7, &loads), Some(7));
+        assert_eq!(get(&mut provider, "css", 7, &loads), Some(7));
+        assert_eq!(loads.get(), 1);
+
+        // Failed loads aren't retried either
+        assert_eq!(get(&mut provider, "missing", 0, &loads), None);
+        assert_eq!(get(&mut provider, "missing", 0, &loads), None);
+        assert_eq!(loads.get(), 2);
+    }
+
+    #[test]
+    fn test_max_grammar_loads() {
+        let loads = Cell::new(0);
+        let mut provider = JsGrammarProvider::with_max_loads(1);
+
+        assert_eq!(get(&mut provider, "css", 1, &loads), Some(1));
+        assert_eq!(get(&mut provider, "javascript", 2, &loads), None);
+        // Already loaded grammars are still available
+        assert_eq!(get(&mut provider, "css", 1, &loads), Some(1));
+        assert_eq!(loads.get(), 1);
+    }
+}
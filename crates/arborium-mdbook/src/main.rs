@@ -4,7 +4,7 @@ use std::io::{self, Read};
 use std::process;
 
 use anyhow::{Context, Result};
-use arborium::Highlighter;
+use arborium::{FenceLanguageMap, Highlighter};
 use html_escape::{encode_double_quoted_attribute, encode_safe};
 use mdbook::book::{Book, BookItem};
 use mdbook::errors::Error as MdError;
@@ -44,7 +44,10 @@ fn main() -> Result<()> {
 }
 
 #[derive(Default)]
-struct ArboriumPreprocessor;
+struct ArboriumPreprocessor {
+    /// Grammars for the info strings of fenced code blocks.
+    languages: FenceLanguageMap,
+}
 
 impl ArboriumPreprocessor {
     fn apply(&self, ctx: &PreprocessorContext, book: &mut Book) -> Result<()> {
@@ -57,7 +60,7 @@ impl ArboriumPreprocessor {
                     return;
                 }
 
-                match transform_markdown(&chapter.content, &mut highlighter) {
+                match transform_markdown(&chapter.content, &mut highlighter, &self.languages) {
                     Ok(transformed) => {
                         chapter.content = transformed;
                     }
@@ -97,7 +100,11 @@ struct PreprocessorRequest {
     book: Book,
 }
 
-fn transform_markdown(content: &str, highlighter: &mut Highlighter) -> Result<String> {
+fn transform_markdown(
+    content: &str,
+    highlighter: &mut Highlighter,
+    languages: &FenceLanguageMap,
+) -> Result<String> {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_FOOTNOTES);
@@ -136,7 +143,9 @@ fn transform_markdown(content: &str, highlighter: &mut Highlighter) -> Result<St
             }
             Event::End(TagEnd::CodeBlock) => {
                 if let Some(block) = active.take() {
-                    events.push(Event::Html(CowStr::from(block.render(highlighter))));
+                    events.push(Event::Html(CowStr::from(
+                        block.render(highlighter, languages),
+                    )));
                     handled = true;
                 }
             }
@@ -149,7 +158,9 @@ fn transform_markdown(content: &str, highlighter: &mut Highlighter) -> Result<St
     }
 
     if let Some(block) = active.take() {
-        events.push(Event::Html(CowStr::from(block.render(highlighter))));
+        events.push(Event::Html(CowStr::from(
+            block.render(highlighter, languages),
+        )));
     }
 
     let mut output = String::new();
@@ -194,7 +205,7 @@ impl FencedBlock {
         self.code.push_str(text);
     }
 
-    fn render(mut self, highlighter: &mut Highlighter) -> String {
+    fn render(mut self, highlighter: &mut Highlighter, languages: &FenceLanguageMap) -> String {
         // Trim trailing newline inserted by parser to avoid double spacing.
         if self.code.ends_with('\n') {
             self.code.pop();
@@ -204,15 +215,15 @@ impl FencedBlock {
         }
 
         let lang = parse_language(&self.info);
-        let highlighted =
-            lang.as_deref()
-                .and_then(|lang| match highlighter.highlight(lang, &self.code) {
-                    Ok(html) => Some(html),
-                    Err(err) => {
-                        eprintln!("[arborium-mdbook] unsupported language '{lang}': {err}");
-                        None
-                    }
-                });
+        // Plain text and unknown tags are left unstyled
+        let grammar = lang.as_deref().and_then(|lang| languages.resolve(lang));
+        let highlighted = grammar.and_then(|lang| match highlighter.highlight(lang, &self.code) {
+            Ok(html) => Some(html),
+            Err(err) => {
+                eprintln!("[arborium-mdbook] unsupported language '{lang}': {err}");
+                None
+            }
+        });
 
         build_code_block_html(
             lang.as_deref(),
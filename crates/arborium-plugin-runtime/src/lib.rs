@@ -6,16 +6,41 @@
 //! - Session management (create/free)
 //! - Parser state and tree storage
 //! - Query execution to produce Span and Injection records
-//! - Incremental parsing via edit application
-//! - Cancellation support
+//! - Incremental parsing via edit application, and re-highlighting of just
+//!   the changed regions ([`PluginRuntime::parse_incremental`])
+//! - Cancellation support, and per-session or per-parse timeouts
+//!   ([`PluginRuntime::set_timeout`], [`PluginRuntime::parse_with_timeout`])
 //!
 //! # Offset Encoding
 //!
 //! Tree-sitter natively produces UTF-8 byte offsets. This runtime provides
-//! two parsing methods:
+//! three parsing methods:
 //!
 //! - [`PluginRuntime::parse`] returns UTF-8 byte offsets (for Rust string slicing)
 //! - [`PluginRuntime::parse_utf16`] returns UTF-16 code unit indices (for JavaScript)
+//! - [`PluginRuntime::parse_utf32`] returns code point indices (for Python, `char` indexing)
+//!
+//! # Line/Column Positions
+//!
+//! [`PluginRuntime::parse_with_points`] and
+//! [`PluginRuntime::parse_utf16_with_points`] also report each span's
+//! zero-based row/column positions, for editors and language servers that
+//! address text by line. Rows and byte columns are read from the nodes'
+//! `start_position()`/`end_position()`, not recomputed from offsets.
+//!
+//! LSP positions count UTF-16 code units unless the client negotiates
+//! `positionEncoding: "utf-8"`, so a language server picks the
+//! [`ColumnEncoding`] to match:
+//!
+//! ```ignore
+//! use arborium_plugin_runtime::ColumnEncoding;
+//!
+//! let result = runtime.parse_with_points(session, ColumnEncoding::Utf16)?;
+//! for span in result.spans {
+//!     // Semantic token at (span.start_row, span.start_col), spanning
+//!     // to (span.end_row, span.end_col)
+//! }
+//! ```
 //!
 //! # Example
 //!
@@ -48,24 +73,96 @@ use arborium_sysroot as _;
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::ops::Range;
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
 
 use arborium_tree_sitter::{
-    InputEdit, Language, Parser, Point, Query, QueryCursor, QueryError, StreamingIterator, Tree,
+    InputEdit, Language, Node, Parser, Point, Query, QueryCursor, QueryError, StreamingIterator,
+    Tree,
 };
 use arborium_wire::{
-    Edit, ParseError, Utf8Injection, Utf8ParseResult, Utf8Span, Utf16Injection, Utf16ParseResult,
-    Utf16Span,
+    Diagnostic, DiagnosticKind, Edit, ParseError, ParseStats, Utf8Injection, Utf8ParseResult,
+    Utf8PointParseResult, Utf8PointSpan, Utf8Span, Utf16Injection, Utf16ParseResult,
+    Utf16PointParseResult, Utf16PointSpan, Utf16Span, Utf32Injection, Utf32ParseResult, Utf32Span,
 };
 use tree_sitter_language::LanguageFn;
 
+pub mod raw;
+
+use raw::{RawInjection, RawSpan};
+
+/// A monotonic clock, in milliseconds from an arbitrary starting point.
+///
+/// Used for session timeouts (see [`PluginRuntime::set_timeout`]). Native
+/// builds default to [`std::time::Instant`]; WASM has no clock of its own, so
+/// plugins pass one backed by the host (such as `performance.now()`) to
+/// [`PluginRuntime::set_clock`].
+pub type Clock = fn() -> u64;
+
+/// Parse `text`, timing the parse on `clock` if there is one.
+fn timed_parse(
+    parser: &mut Parser,
+    text: &str,
+    old_tree: Option<&Tree>,
+    clock: Option<Clock>,
+) -> (Option<Tree>, Option<u32>) {
+    let start = clock.map(|clock| clock());
+    let tree = parser.parse(text, old_tree);
+    let parse_ms = clock
+        .zip(start)
+        .map(|(clock, start)| clock().saturating_sub(start) as u32);
+    (tree, parse_ms)
+}
+
+/// Call `f` with every ERROR and MISSING node under `root`, in document
+/// order.
+fn for_each_error_node(root: Node, mut f: impl FnMut(Node)) {
+    if !root.has_error() {
+        return;
+    }
+    // Only subtrees with errors can contain ERROR or MISSING nodes
+    let mut cursor = root.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            f(node);
+        }
+        if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return;
+            }
+        }
+    }
+}
+
+/// The default [`Clock`] on native targets.
+#[cfg(not(target_family = "wasm"))]
+fn instant_clock() -> u64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_millis() as u64
+}
+
 /// Batch convert UTF-8 byte offsets to UTF-16 code unit indices in a single pass.
 ///
 /// This is O(n + m) where n is string length and m is number of offsets,
 /// much better than O(n * m) for individual conversions.
 ///
-/// The offsets slice must be sorted in ascending order.
+/// The offsets slice must be sorted in ascending order, which is checked in
+/// debug builds. An offset inside a multi-byte character maps to the index
+/// of that character's start, and offsets past the end of `text` map to its
+/// length.
 fn batch_utf8_to_utf16(text: &str, offsets: &[usize]) -> Vec<u32> {
+    debug_assert!(
+        offsets.is_sorted(),
+        "offsets must be sorted in ascending order"
+    );
     let mut results = Vec::with_capacity(offsets.len());
     if offsets.is_empty() {
         return results;
@@ -76,8 +173,9 @@ fn batch_utf8_to_utf16(text: &str, offsets: &[usize]) -> Vec<u32> {
     let mut byte_index = 0usize;
 
     for c in text.chars() {
-        // Emit results for all offsets at current byte position
-        while offset_idx < offsets.len() && byte_index >= offsets[offset_idx] {
+        let next_byte_index = byte_index + c.len_utf8();
+        // Emit results for all offsets within the current character
+        while offset_idx < offsets.len() && offsets[offset_idx] < next_byte_index {
             results.push(utf16_index);
             offset_idx += 1;
         }
@@ -86,20 +184,177 @@ fn batch_utf8_to_utf16(text: &str, offsets: &[usize]) -> Vec<u32> {
             break;
         }
 
-        byte_index += c.len_utf8();
+        byte_index = next_byte_index;
         // Code points >= 0x10000 use surrogate pairs (2 UTF-16 code units)
         utf16_index += if c as u32 >= 0x10000 { 2 } else { 1 };
     }
 
-    // Handle any remaining offsets at or past the end
-    while offset_idx < offsets.len() {
-        results.push(utf16_index);
-        offset_idx += 1;
+    // Offsets at or past the end clamp to it
+    results.resize(offsets.len(), utf16_index);
+
+    results
+}
+
+/// Batch convert UTF-8 byte offsets to code point indices in a single pass.
+///
+/// Like [`batch_utf8_to_utf16`], but every character counts as one, however
+/// many UTF-16 code units it would take.
+///
+/// The offsets slice must be sorted in ascending order.
+fn batch_utf8_to_utf32(text: &str, offsets: &[usize]) -> Vec<u32> {
+    debug_assert!(
+        offsets.is_sorted(),
+        "offsets must be sorted in ascending order"
+    );
+    let mut results = Vec::with_capacity(offsets.len());
+    if offsets.is_empty() {
+        return results;
+    }
+
+    let mut offset_idx = 0;
+    let mut codepoint_index = 0u32;
+    let mut byte_index = 0usize;
+
+    for c in text.chars() {
+        let next_byte_index = byte_index + c.len_utf8();
+        // Emit results for all offsets within the current character
+        while offset_idx < offsets.len() && offsets[offset_idx] < next_byte_index {
+            results.push(codepoint_index);
+            offset_idx += 1;
+        }
+
+        if offset_idx >= offsets.len() {
+            break;
+        }
+
+        byte_index = next_byte_index;
+        codepoint_index += 1;
+    }
+
+    // Offsets at or past the end clamp to it
+    results.resize(offsets.len(), codepoint_index);
+
+    results
+}
+
+/// Batch convert UTF-8 byte offsets to UTF-16 columns (code unit indices
+/// within their line) in a single pass.
+///
+/// Like [`batch_utf8_to_utf16`], but counting restarts after every `\n`.
+/// An offset just past a `\n` is column 0 of the next line.
+///
+/// The offsets slice must be sorted in ascending order.
+fn batch_utf8_to_utf16_columns(text: &str, offsets: &[usize]) -> Vec<u32> {
+    debug_assert!(
+        offsets.is_sorted(),
+        "offsets must be sorted in ascending order"
+    );
+    let mut results = Vec::with_capacity(offsets.len());
+    if offsets.is_empty() {
+        return results;
+    }
+
+    let mut offset_idx = 0;
+    let mut column = 0u32;
+    let mut byte_index = 0usize;
+
+    for c in text.chars() {
+        let next_byte_index = byte_index + c.len_utf8();
+        // Emit results for all offsets within the current character
+        while offset_idx < offsets.len() && offsets[offset_idx] < next_byte_index {
+            results.push(column);
+            offset_idx += 1;
+        }
+
+        if offset_idx >= offsets.len() {
+            break;
+        }
+
+        byte_index = next_byte_index;
+        column = match c {
+            '\n' => 0,
+            _ => column + c.len_utf16() as u32,
+        };
     }
 
+    // Offsets at or past the end clamp to it
+    results.resize(offsets.len(), column);
+
     results
 }
 
+/// Convert the offsets of `spans` and `injections` with `convert`, one of
+/// the batch conversions above, returning a lookup from byte offset to
+/// converted offset.
+fn batch_offset_lookup(
+    text: &str,
+    spans: &[RawSpan],
+    injections: &[RawInjection],
+    convert: fn(&str, &[usize]) -> Vec<u32>,
+) -> impl Fn(usize) -> u32 + use<> {
+    let mut all_offsets: Vec<usize> = Vec::with_capacity((spans.len() + injections.len()) * 2);
+    for span in spans {
+        all_offsets.push(span.start);
+        all_offsets.push(span.end);
+    }
+    for inj in injections {
+        all_offsets.push(inj.start);
+        all_offsets.push(inj.end);
+    }
+    all_offsets.sort_unstable();
+
+    let converted = convert(text, &all_offsets);
+
+    // Offsets are sorted, so look them up with a binary search
+    move |byte_offset| {
+        let idx = all_offsets
+            .binary_search(&byte_offset)
+            .unwrap_or_else(|x| x);
+        converted.get(idx).copied().unwrap_or(0)
+    }
+}
+
+/// Clip `spans` to `window`, dropping those outside it.
+fn clip_spans(spans: &mut Vec<RawSpan>, window: Range<usize>) {
+    spans.retain_mut(|span| {
+        span.start = span.start.max(window.start);
+        span.end = span.end.min(window.end);
+        span.start < span.end
+    });
+}
+
+/// Convert a range of UTF-16 code unit indices into `text` to a byte range,
+/// widened to character boundaries.
+fn utf16_range_to_utf8(text: &str, range: Range<u32>) -> Result<Range<usize>, ParseError> {
+    let len = text.encode_utf16().count();
+    if range.start > range.end || range.end as usize > len {
+        return Err(invalid_range(range, len));
+    }
+
+    let (mut start, mut end) = (text.len(), text.len());
+    let mut utf16_index = 0u32;
+    for (byte_index, c) in text.char_indices() {
+        let next = utf16_index + c.len_utf16() as u32;
+        // The character containing the start, and the first one at or after the end
+        if start == text.len() && range.start < next {
+            start = byte_index;
+        }
+        if range.end <= utf16_index {
+            end = byte_index;
+            break;
+        }
+        utf16_index = next;
+    }
+    Ok(start..end)
+}
+
+fn invalid_range(range: Range<u32>, len: usize) -> ParseError {
+    ParseError::new(format!(
+        "invalid range {}..{} for text of length {len}",
+        range.start, range.end
+    ))
+}
+
 /// Configuration for syntax highlighting.
 ///
 /// Contains the compiled queries for highlights, injections, and locals.
@@ -179,19 +434,97 @@ impl HighlightConfig {
         })
     }
 
+    /// Create a highlight configuration whose highlights query inherits from
+    /// other grammars' queries.
+    ///
+    /// This formalizes the pattern used by grammars like TSX, which prepend the
+    /// JavaScript highlights to their own. The queries are concatenated with
+    /// `base_queries` first and `own_queries` last, so that the grammar's own
+    /// patterns get higher pattern indices and win during deduplication.
+    ///
+    /// The resulting configuration has no injections or locals. For grammars
+    /// that need those too, build the highlights query with [`inherited_query`]
+    /// and pass it to [`HighlightConfig::new`].
+    pub fn new_inherited(
+        language: LanguageFn,
+        base_queries: &[&str],
+        own_queries: &[&str],
+    ) -> Result<Self, QueryError> {
+        let highlights_query = inherited_query(base_queries, own_queries);
+        Self::new(language, &highlights_query, "", "")
+    }
+
     /// Get the capture names from the query.
     pub fn capture_names(&self) -> &[&str] {
         self.query.capture_names()
     }
+
+    /// The `#set!` properties of a pattern, as `(key, value)` pairs.
+    ///
+    /// Lets tooling inspect how a grammar configures its injections (the
+    /// `injection.language` they set, `injection.include-children`, ...)
+    /// without running a parse. Returns an empty list for pattern indices
+    /// out of range.
+    pub fn property_settings(&self, pattern_index: usize) -> Vec<(String, Option<String>)> {
+        if pattern_index >= self.query.pattern_count() {
+            return Vec::new();
+        }
+        self.query
+            .property_settings(pattern_index)
+            .iter()
+            .map(|prop| {
+                (
+                    prop.key.to_string(),
+                    prop.value.as_ref().map(|v| v.to_string()),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns `true` if the configuration has any injection patterns.
+    ///
+    /// Hosts can use this to skip resolving injected languages for grammars
+    /// that never report any.
+    pub fn has_injections(&self) -> bool {
+        // Injection patterns come first, before the locals
+        self.injection_content_capture_index.is_some() && self.locals_pattern_index > 0
+    }
+}
+
+/// Concatenate inherited queries in precedence order.
+///
+/// Base queries come first and own queries last, so that later (own) patterns
+/// have higher pattern indices and override inherited ones. A newline is
+/// inserted between queries that don't end with one.
+pub fn inherited_query(base_queries: &[&str], own_queries: &[&str]) -> String {
+    let mut query = String::new();
+    for part in base_queries.iter().chain(own_queries) {
+        if part.is_empty() {
+            continue;
+        }
+        query.push_str(part);
+        if !part.ends_with('\n') {
+            query.push('\n');
+        }
+    }
+    query
 }
 
 /// A parsing session that maintains parser state.
+///
+/// Sessions don't own a [`QueryCursor`]: cursors are only needed while a
+/// query runs, so they are borrowed from the runtime's [`CursorPool`].
 struct Session {
     parser: Parser,
     tree: Option<Tree>,
     text: String,
-    cursor: QueryCursor,
     cancelled: AtomicBool,
+    /// Milliseconds a query may run before it's stopped, or 0 for no limit.
+    timeout_ms: u32,
+    /// What changed since the last full parse.
+    changes: Changes,
+    /// How long the last parse took, if the runtime has a clock.
+    parse_ms: Option<u32>,
 }
 
 impl Session {
@@ -204,25 +537,171 @@ impl Session {
             parser,
             tree: None,
             text: String::new(),
-            cursor: QueryCursor::new(),
             cancelled: AtomicBool::new(false),
+            timeout_ms: 0,
+            changes: Changes::default(),
+            parse_ms: None,
+        }
+    }
+}
+
+/// Edits to a session's text since its last full parse, for
+/// [`PluginRuntime::parse_incremental`].
+#[derive(Clone, Default)]
+struct Changes {
+    /// Applied edits, in order.
+    edits: Vec<InputEdit>,
+    /// Byte ranges of the current text whose highlighting may differ from
+    /// the last parse.
+    dirty: Vec<Range<usize>>,
+}
+
+impl Changes {
+    fn push(&mut self, edit: InputEdit, changed_ranges: impl Iterator<Item = Range<usize>>) {
+        for range in &mut self.dirty {
+            *range = shift_range(range.clone(), &edit);
+        }
+        self.dirty.push(edit.start_byte..edit.new_end_byte);
+        self.dirty.extend(changed_ranges);
+        self.edits.push(edit);
+    }
+}
+
+/// Map a byte range of the text before `edit` onto the text after it.
+///
+/// A range overlapping the replaced bytes is stretched over the new ones.
+fn shift_range(range: Range<usize>, edit: &InputEdit) -> Range<usize> {
+    let shift = |pos: usize| pos - edit.old_end_byte + edit.new_end_byte;
+    let start = if range.start < edit.start_byte {
+        range.start
+    } else if range.start >= edit.old_end_byte {
+        shift(range.start)
+    } else {
+        edit.start_byte
+    };
+    let end = if range.end <= edit.start_byte {
+        range.end
+    } else if range.end >= edit.old_end_byte {
+        shift(range.end)
+    } else {
+        edit.new_end_byte
+    };
+    start..end.max(start)
+}
+
+/// Widen `range` to cover the children of `root` it touches.
+///
+/// Highlight patterns match within a top-level node (an item, a statement),
+/// so re-querying whole top-level nodes catches every match an edit inside
+/// them can affect.
+fn widen_to_top_level(root: Node, range: Range<usize>) -> Range<usize> {
+    let mut cursor = root.walk();
+    let (start, end) = (range.start, range.end);
+    root.children(&mut cursor)
+        .filter(|child| child.start_byte() <= end && start <= child.end_byte())
+        .fold(range, |range, child| {
+            range.start.min(child.start_byte())..range.end.max(child.end_byte())
+        })
+}
+
+/// Pool of query cursors shared by all sessions of a runtime.
+///
+/// Cursors hold non-trivial internal buffers, so keeping one per idle
+/// session wastes memory. Every setting a cursor supports is configured
+/// explicitly on acquisition, so no state leaks from one query to the next.
+struct CursorPool {
+    cursors: Vec<QueryCursor>,
+}
+
+impl CursorPool {
+    fn new() -> Self {
+        Self {
+            cursors: Vec::new(),
+        }
+    }
+
+    /// Take a cursor from the pool (or create one), configured for a query.
+    fn acquire(&mut self, byte_range: Range<usize>, match_limit: u32) -> QueryCursor {
+        let mut cursor = self.cursors.pop().unwrap_or_default();
+        cursor
+            .set_byte_range(byte_range)
+            .set_containing_byte_range(0..u32::MAX as usize)
+            .set_max_start_depth(None);
+        cursor.set_match_limit(match_limit);
+        cursor
+    }
+
+    /// Return a cursor to the pool for reuse.
+    fn release(&mut self, cursor: QueryCursor) {
+        self.cursors.push(cursor);
+    }
+
+    /// Drop all pooled cursors.
+    fn clear(&mut self) {
+        self.cursors.clear();
+    }
+}
+
+struct RawParse {
+    spans: Vec<RawSpan>,
+    injections: Vec<RawInjection>,
+    match_limit_exceeded: bool,
+    timed_out: bool,
+}
+
+impl RawParse {
+    fn empty() -> Self {
+        Self {
+            spans: Vec::new(),
+            injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
         }
     }
 }
 
-// Internal structs to hold raw byte offsets during parsing
-struct RawSpan {
-    start: usize,
-    end: usize,
-    capture: String,
-    pattern_index: usize,
+/// Result of [`PluginRuntime::parse_incremental`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncrementalSpans {
+    /// Spans for the whole current text, sorted by position.
+    pub spans: Vec<Utf8Span>,
+    /// The byte ranges that were re-queried; spans outside them were carried
+    /// over from the previous parse.
+    pub reparsed: Vec<Range<u32>>,
+    /// Whether the match limit was hit while re-querying.
+    pub match_limit_exceeded: bool,
+    /// Whether re-querying ran past the session's timeout. The spans are
+    /// then incomplete, and the next call re-queries the same regions.
+    pub timed_out: bool,
+}
+
+/// How [`PluginRuntime::parse_with_points`] counts columns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnEncoding {
+    /// UTF-8 bytes from the start of the line, as tree-sitter reports them.
+    #[default]
+    Byte,
+    /// UTF-16 code units from the start of the line, as LSP positions and
+    /// JavaScript editors expect.
+    Utf16,
+}
+
+/// Options for [`PluginRuntime::parse_with_options`] and
+/// [`PluginRuntime::parse_utf16_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Report the kind of syntax node each span and injection covers (e.g.
+    /// `"string_literal"`), for consumers such as semantic token providers
+    /// that want it alongside the capture. Off by default, since every span
+    /// then carries an extra string.
+    pub node_kinds: bool,
 }
 
-struct RawInjection {
-    start: usize,
-    end: usize,
-    language: String,
-    include_children: bool,
+impl ParseOptions {
+    /// `kind`, if node kinds are reported.
+    fn node_kind(&self, kind: &str) -> Option<String> {
+        self.node_kinds.then(|| String::from(kind))
+    }
 }
 
 /// Runtime for a grammar plugin.
@@ -233,6 +712,9 @@ pub struct PluginRuntime {
     config: HighlightConfig,
     sessions: BTreeMap<u32, Session>,
     next_session_id: AtomicU32,
+    cursor_pool: CursorPool,
+    match_limit: u32,
+    clock: Option<Clock>,
 }
 
 impl PluginRuntime {
@@ -242,7 +724,37 @@ impl PluginRuntime {
             config,
             sessions: BTreeMap::new(),
             next_session_id: AtomicU32::new(1),
+            cursor_pool: CursorPool::new(),
+            match_limit: u32::MAX,
+            #[cfg(not(target_family = "wasm"))]
+            clock: Some(instant_clock),
+            #[cfg(target_family = "wasm")]
+            clock: None,
+        }
+    }
+
+    /// Set the maximum number of in-progress query matches.
+    ///
+    /// Pathological input can make tree-sitter track a huge number of
+    /// partial matches. When the limit is hit, some matches are dropped and
+    /// the parse result reports `match_limit_exceeded`. Defaults to
+    /// `u32::MAX` (unlimited).
+    pub fn set_match_limit(&mut self, limit: u32) {
+        if limit != self.match_limit {
+            // A cursor keeps the capture lists it already allocated, so a
+            // reused cursor would ignore a lower limit. Start fresh instead.
+            self.cursor_pool.clear();
         }
+        self.match_limit = limit;
+    }
+
+    /// Set the clock used for session timeouts.
+    ///
+    /// Native builds use [`std::time::Instant`] by default. On WASM there is
+    /// no default, and [`set_timeout`](Self::set_timeout) has no effect until
+    /// a clock is set.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
     }
 
     /// Create a new parsing session.
@@ -260,13 +772,78 @@ impl PluginRuntime {
         self.sessions.remove(&session_id);
     }
 
+    /// Return a session to the state it had when created, keeping its id.
+    ///
+    /// Drops the text, parse tree and pending edits and clears any
+    /// cancellation, but keeps the session's parser and timeout. Cheaper than
+    /// [`free_session`](Self::free_session) followed by
+    /// [`create_session`](Self::create_session) when a buffer is closed and
+    /// reopened. Unknown ids are ignored.
+    pub fn reset_session(&mut self, session_id: u32) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            session.parser.reset();
+            session.tree = None;
+            session.text.clear();
+            session.changes = Changes::default();
+            session.parse_ms = None;
+            session.cancelled.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Fork a session: a new session with a copy of its text, parse tree,
+    /// pending edits and timeout.
+    ///
+    /// Edits to either session don't affect the other, so the fork can be
+    /// used to preview changes without touching the original. Returns `None`
+    /// if `source_id` is unknown.
+    pub fn clone_session(&mut self, source_id: u32) -> Option<u32> {
+        let source = self.sessions.get(&source_id)?;
+        let mut session = Session::new(&self.config.language);
+        session.tree = source.tree.clone();
+        session.text = source.text.clone();
+        session.timeout_ms = source.timeout_ms;
+        session.changes = source.changes.clone();
+        session.parse_ms = source.parse_ms;
+
+        let id = self.next_session_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions.insert(id, session);
+        Some(id)
+    }
+
+    /// Ids of the live sessions, in ascending order.
+    ///
+    /// Lets a host check for sessions it created but never freed.
+    pub fn session_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.sessions.keys().copied()
+    }
+
+    /// Number of live sessions.
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
     /// Set the full text content for a session.
     ///
     /// This replaces any previous content and resets the parse tree.
     pub fn set_text(&mut self, session_id: u32, text: &str) {
+        let clock = self.clock;
         if let Some(session) = self.sessions.get_mut(&session_id) {
+            // Nothing carries over to the new text
+            session.changes.push(
+                InputEdit {
+                    start_byte: 0,
+                    old_end_byte: session.text.len(),
+                    new_end_byte: text.len(),
+                    start_position: Point::default(),
+                    old_end_position: Point::default(),
+                    new_end_position: Point::default(),
+                },
+                core::iter::empty(),
+            );
             session.text = String::from(text);
-            session.tree = session.parser.parse(text, None);
+            let (tree, parse_ms) = timed_parse(&mut session.parser, text, None, clock);
+            session.tree = tree;
+            session.parse_ms = parse_ms;
             session.cancelled.store(false, Ordering::Relaxed);
         }
     }
@@ -275,13 +852,44 @@ impl PluginRuntime {
     ///
     /// The session must have had `set_text` called previously.
     pub fn apply_edit(&mut self, session_id: u32, new_text: &str, edit: &Edit) {
+        self.apply_edits(session_id, new_text, core::slice::from_ref(edit));
+    }
+
+    /// Apply several incremental edits to the session's text, re-parsing it
+    /// once rather than once per edit.
+    ///
+    /// For edits made together, such as a multi-cursor replace: they must
+    /// not overlap, and their positions are all in the text before any of
+    /// them. They're applied from the last to the first by `start_byte`, so
+    /// none shifts the ones still to apply. `new_text` is the text after all
+    /// of them.
+    ///
+    /// The session must have had `set_text` called previously.
+    pub fn apply_edit_batch(&mut self, session_id: u32, new_text: &str, edits: &[Edit]) {
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| core::cmp::Reverse(edit.start_byte));
+        self.apply_edits(session_id, new_text, &edits);
+    }
+
+    /// Apply a sequence of incremental edits to the session's text,
+    /// re-parsing it once rather than once per edit.
+    ///
+    /// For a burst of edits made one after another: they're applied in the
+    /// given order, each with positions in the text as left by the edits
+    /// before it, as tree-sitter expects. `new_text` is the text after all
+    /// of them. For simultaneous edits with positions in the original text,
+    /// use [`apply_edit_batch`](Self::apply_edit_batch).
+    ///
+    /// The session must have had `set_text` called previously.
+    pub fn apply_edits(&mut self, session_id: u32, new_text: &str, edits: &[Edit]) {
+        let clock = self.clock;
         if let Some(session) = self.sessions.get_mut(&session_id) {
             // Update the text
             session.text = String::from(new_text);
 
-            // Apply the edit to the existing tree if we have one
-            if let Some(tree) = &mut session.tree {
-                let input_edit = InputEdit {
+            let input_edits: Vec<InputEdit> = edits
+                .iter()
+                .map(|edit| InputEdit {
                     start_byte: edit.start_byte as usize,
                     old_end_byte: edit.old_end_byte as usize,
                     new_end_byte: edit.new_end_byte as usize,
@@ -294,16 +902,120 @@ impl PluginRuntime {
                         edit.new_end_row as usize,
                         edit.new_end_col as usize,
                     ),
-                };
-                tree.edit(&input_edit);
+                })
+                .collect();
+
+            // Apply the edits to the existing tree if we have one
+            if let Some(tree) = &mut session.tree {
+                for input_edit in &input_edits {
+                    tree.edit(input_edit);
+                }
             }
 
             // Re-parse with the old tree for incremental parsing
-            session.tree = session.parser.parse(&session.text, session.tree.as_ref());
+            let (new_tree, parse_ms) = timed_parse(
+                &mut session.parser,
+                &session.text,
+                session.tree.as_ref(),
+                clock,
+            );
+            let changed_ranges: Vec<Range<usize>> = match (&session.tree, &new_tree) {
+                (Some(old_tree), Some(new_tree)) => old_tree
+                    .changed_ranges(new_tree)
+                    .map(|range| range.start_byte..range.end_byte)
+                    .collect(),
+                _ => alloc::vec![0..session.text.len()],
+            };
+            for input_edit in input_edits {
+                session.changes.push(input_edit, core::iter::empty());
+            }
+            // Changed ranges are in the new text, past every edit
+            session.changes.dirty.extend(changed_ranges);
+            session.tree = new_tree;
+            session.parse_ms = parse_ms;
             session.cancelled.store(false, Ordering::Relaxed);
         }
     }
 
+    /// Statistics about the session's parse tree, for spotting grammars
+    /// that fail to parse the text cleanly.
+    ///
+    /// Returns `None` if the session is unknown or has no text set.
+    pub fn parse_stats(&self, session_id: u32) -> Option<ParseStats> {
+        let session = self.sessions.get(&session_id)?;
+        let tree = session.tree.as_ref()?;
+        let root = tree.root_node();
+
+        let mut stats = ParseStats {
+            node_count: root.descendant_count() as u32,
+            has_error: root.has_error(),
+            error_count: 0,
+            missing_count: 0,
+            parse_ms: session.parse_ms,
+        };
+        for_each_error_node(root, |node| {
+            if node.is_error() {
+                stats.error_count += 1;
+            } else {
+                stats.missing_count += 1;
+            }
+        });
+        Some(stats)
+    }
+
+    /// Where the session's text fails to parse: one diagnostic per ERROR or
+    /// MISSING node in the tree, in document order.
+    ///
+    /// For editors drawing squiggles with the grammar used for highlighting.
+    /// An ERROR node covers text the parser had to skip; a MISSING node is
+    /// a token the parser assumed, so its range is empty. Returns nothing
+    /// if the session is unknown or has no text set.
+    pub fn parse_diagnostics(&self, session_id: u32) -> Vec<Diagnostic> {
+        let Some(tree) = self
+            .sessions
+            .get(&session_id)
+            .and_then(|session| session.tree.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        let mut diagnostics = Vec::new();
+        for_each_error_node(tree.root_node(), |node| {
+            diagnostics.push(Diagnostic {
+                start: node.start_byte() as u32,
+                end: node.end_byte() as u32,
+                kind: if node.is_error() {
+                    DiagnosticKind::Error
+                } else {
+                    DiagnosticKind::Missing
+                },
+                node_kind: String::from(node.kind()),
+            });
+        });
+        diagnostics
+    }
+
+    /// Stop queries on this session that run longer than `millis`
+    /// milliseconds, or never stop them if `millis` is 0 (the default).
+    ///
+    /// Unlike [`cancel`](Self::cancel), this needs no second caller, so it
+    /// works in single-threaded hosts. A query that times out returns the
+    /// spans found so far with `timed_out` set. The deadline is checked
+    /// between batches of matches, so a parse may overrun it slightly.
+    pub fn set_timeout(&mut self, session_id: u32, millis: u32) {
+        if let Some(session) = self.sessions.get_mut(&session_id) {
+            session.timeout_ms = millis;
+        }
+    }
+
+    /// When a query on `session_id` starting now must stop, on the runtime's
+    /// clock.
+    fn deadline(&self, session_id: u32) -> Option<u64> {
+        let session = self.sessions.get(&session_id)?;
+        let clock = self.clock?;
+        (session.timeout_ms != 0).then(|| clock() + u64::from(session.timeout_ms))
+    }
+
     /// Request cancellation of an in-progress parse.
     pub fn cancel(&mut self, session_id: u32) {
         if let Some(session) = self.sessions.get(&session_id) {
@@ -312,10 +1024,15 @@ impl PluginRuntime {
     }
 
     /// Internal: execute query and collect raw spans/injections with byte offsets.
+    ///
+    /// Only matches intersecting `byte_range` are reported. Past `deadline`,
+    /// the query stops and the matches so far are returned.
     fn parse_raw(
         &mut self,
         session_id: u32,
-    ) -> Result<(String, Vec<RawSpan>, Vec<RawInjection>), ParseError> {
+        byte_range: Range<usize>,
+        deadline: Option<u64>,
+    ) -> Result<RawParse, ParseError> {
         let session = self
             .sessions
             .get_mut(&session_id)
@@ -323,7 +1040,7 @@ impl PluginRuntime {
 
         // Check for cancellation
         if session.cancelled.load(Ordering::Relaxed) {
-            return Ok((String::new(), Vec::new(), Vec::new()));
+            return Ok(RawParse::empty());
         }
 
         let tree = session
@@ -334,23 +1051,35 @@ impl PluginRuntime {
         let mut raw_spans: Vec<RawSpan> = Vec::new();
         let mut raw_injections: Vec<RawInjection> = Vec::new();
 
-        let text = session.text.clone();
-        let source = text.as_bytes();
+        let source = session.text.as_bytes();
         let root = tree.root_node();
 
+        // A full parse is the baseline for the next incremental one
+        let full = byte_range == (0..u32::MAX as usize);
+
         // Execute the query using streaming iterator
-        let mut matches = session.cursor.matches(&self.config.query, root, source);
+        let mut cursor = self.cursor_pool.acquire(byte_range, self.match_limit);
+        let mut matches = cursor.matches(&self.config.query, root, source);
+        let mut cancelled = false;
+        let mut timed_out = false;
 
         let mut check_count = 0;
         const CANCELLATION_CHECK_INTERVAL: usize = 100;
 
         while let Some(m) = matches.next() {
-            // Periodically check for cancellation
+            // Periodically check for cancellation and the deadline
             check_count += 1;
             if check_count >= CANCELLATION_CHECK_INTERVAL {
                 check_count = 0;
                 if session.cancelled.load(Ordering::Relaxed) {
-                    return Ok((String::new(), Vec::new(), Vec::new()));
+                    cancelled = true;
+                    break;
+                }
+                if let (Some(deadline), Some(clock)) = (deadline, self.clock)
+                    && clock() >= deadline
+                {
+                    timed_out = true;
+                    break;
                 }
             }
 
@@ -391,6 +1120,7 @@ impl PluginRuntime {
                         end: node.end_byte(),
                         language: String::from(lang),
                         include_children,
+                        node_kind: node.kind(),
                     });
                 }
 
@@ -425,13 +1155,34 @@ impl PluginRuntime {
                 raw_spans.push(RawSpan {
                     start: node.start_byte(),
                     end: node.end_byte(),
+                    start_point: node.start_position(),
+                    end_point: node.end_position(),
                     capture: String::from(capture_name),
                     pattern_index: m.pattern_index,
+                    node_kind: node.kind(),
                 });
             }
         }
 
-        Ok((text, raw_spans, raw_injections))
+        drop(matches);
+        let match_limit_exceeded = cursor.did_exceed_match_limit();
+        self.cursor_pool.release(cursor);
+
+        if cancelled {
+            return Ok(RawParse::empty());
+        }
+
+        // An incomplete parse is no baseline
+        if full && !timed_out {
+            session.changes = Changes::default();
+        }
+
+        Ok(RawParse {
+            spans: raw_spans,
+            injections: raw_injections,
+            match_limit_exceeded,
+            timed_out,
+        })
     }
 
     /// Parse the current text and return spans and injections with UTF-8 byte offsets.
@@ -441,72 +1192,395 @@ impl PluginRuntime {
     ///
     /// If cancelled, returns an empty result.
     pub fn parse(&mut self, session_id: u32) -> Result<Utf8ParseResult, ParseError> {
-        let (_text, raw_spans, raw_injections) = self.parse_raw(session_id)?;
-
-        // Convert to UTF-8 spans (just cast the byte offsets)
-        let mut spans: Vec<Utf8Span> = raw_spans
-            .into_iter()
-            .map(|s| Utf8Span {
-                start: s.start as u32,
-                end: s.end as u32,
-                capture: s.capture,
-                pattern_index: s.pattern_index as u32,
-            })
-            .collect();
-
-        // Sort spans by start position for consistent output
-        spans.sort_by_key(|s| (s.start, s.end));
-
-        // Convert injections
-        let injections: Vec<Utf8Injection> = raw_injections
-            .into_iter()
-            .map(|i| Utf8Injection {
-                start: i.start as u32,
-                end: i.end as u32,
-                language: i.language,
-                include_children: i.include_children,
-            })
-            .collect();
+        self.parse_with_options(session_id, &ParseOptions::default())
+    }
 
-        Ok(Utf8ParseResult { spans, injections })
+    /// Like [`parse`](Self::parse), with [`ParseOptions`].
+    pub fn parse_with_options(
+        &mut self,
+        session_id: u32,
+        options: &ParseOptions,
+    ) -> Result<Utf8ParseResult, ParseError> {
+        let deadline = self.deadline(session_id);
+        let raw = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        Ok(Self::to_utf8_result(raw, options))
     }
 
-    /// Parse the current text and return spans and injections with UTF-16 code unit indices.
+    /// Like [`parse`](Self::parse), but stops the query once it has run for
+    /// `max_duration`, instead of after the session's timeout.
     ///
-    /// Use this when working with JavaScript, as `String.prototype.slice()` and
-    /// DOM APIs use UTF-16 code unit indices.
+    /// For a one-off budget, such as a first highlight that has to be quick,
+    /// without changing the session's [`set_timeout`](Self::set_timeout).
+    /// As there, a query that runs out of time returns the spans found so
+    /// far with `timed_out` set, and without a clock there is no limit.
     ///
-    /// If cancelled, returns an empty result.
-    pub fn parse_utf16(&mut self, session_id: u32) -> Result<Utf16ParseResult, ParseError> {
-        let (text, raw_spans, raw_injections) = self.parse_raw(session_id)?;
+    /// The runtime's [`Clock`] only counts whole milliseconds, so
+    /// `max_duration` is rounded up to the next millisecond: a budget shorter
+    /// than that still gets one tick instead of expiring at once.
+    pub fn parse_with_timeout(
+        &mut self,
+        session_id: u32,
+        max_duration: Duration,
+    ) -> Result<Utf8ParseResult, ParseError> {
+        let millis = u64::try_from(max_duration.as_micros().div_ceil(1000)).unwrap_or(u64::MAX);
+        let deadline = self.clock.map(|clock| clock().saturating_add(millis));
+        let raw = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        Ok(Self::to_utf8_result(raw, &ParseOptions::default()))
+    }
 
-        if raw_spans.is_empty() && raw_injections.is_empty() {
-            return Ok(Utf16ParseResult::empty());
+    /// Highlight spans as the query produced them: byte offsets and points
+    /// into the session text, unsorted and not deduplicated, with pattern
+    /// index and node kind.
+    ///
+    /// For callers that convert offsets their own way, without the cost of
+    /// building a [`Utf8ParseResult`] first. Injections aren't included.
+    /// Cancelled parses return no spans, and timed-out ones the spans found
+    /// so far.
+    pub fn parse_raw_spans(&mut self, session_id: u32) -> Result<Vec<RawSpan>, ParseError> {
+        let deadline = self.deadline(session_id);
+        let raw = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        Ok(raw.spans)
+    }
+
+    /// Like [`parse`](Self::parse), but only runs the query over the given
+    /// UTF-8 byte range of the document.
+    ///
+    /// Useful for highlighting just the visible part of a large document:
+    /// the whole text is still parsed, so the highlighting is correct, but
+    /// querying a small window is far cheaper than querying everything.
+    /// Spans are clipped to the range and keep their document offsets.
+    /// Injections intersecting the range are reported whole, since an
+    /// injected language has to be parsed as a unit.
+    ///
+    /// Range boundaries inside a multi-byte character are moved outward to
+    /// the character's edges. A range that is inverted or extends past the
+    /// end of the text is an error.
+    pub fn parse_range(
+        &mut self,
+        session_id: u32,
+        byte_range: Range<u32>,
+    ) -> Result<Utf8ParseResult, ParseError> {
+        let window = self.byte_window(session_id, byte_range)?;
+        let deadline = self.deadline(session_id);
+        let mut raw = self.parse_raw(session_id, window.clone(), deadline)?;
+        clip_spans(&mut raw.spans, window);
+        Ok(Self::to_utf8_result(raw, &ParseOptions::default()))
+    }
+
+    /// Like [`parse_range`](Self::parse_range), but the range and the
+    /// results are in UTF-16 code units, for JavaScript editors.
+    ///
+    /// A boundary in the middle of a surrogate pair is moved outward to the
+    /// edge of the pair.
+    pub fn parse_range_utf16(
+        &mut self,
+        session_id: u32,
+        range: Range<u32>,
+    ) -> Result<Utf16ParseResult, ParseError> {
+        let text = &self
+            .sessions
+            .get(&session_id)
+            .ok_or_else(|| ParseError::new("invalid session id"))?
+            .text;
+        let byte_range = utf16_range_to_utf8(text, range)?;
+        let deadline = self.deadline(session_id);
+        let mut raw = self.parse_raw(session_id, byte_range.clone(), deadline)?;
+        clip_spans(&mut raw.spans, byte_range);
+        Ok(Self::to_utf16_result(
+            &self.sessions[&session_id].text,
+            raw,
+            &ParseOptions::default(),
+        ))
+    }
+
+    /// Check `byte_range` against the session's text, widening it to
+    /// character boundaries.
+    fn byte_window(
+        &self,
+        session_id: u32,
+        byte_range: Range<u32>,
+    ) -> Result<Range<usize>, ParseError> {
+        let text = &self
+            .sessions
+            .get(&session_id)
+            .ok_or_else(|| ParseError::new("invalid session id"))?
+            .text;
+        let (mut start, mut end) = (byte_range.start as usize, byte_range.end as usize);
+        if start > end || end > text.len() {
+            return Err(invalid_range(byte_range, text.len()));
+        }
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        Ok(start..end)
+    }
+
+    /// Re-highlight after [`apply_edit`](Self::apply_edit), re-querying only
+    /// the regions the edits touched.
+    ///
+    /// `prev_spans` must be the spans from the last [`parse`](Self::parse) or
+    /// `parse_incremental` of this session (or empty, after
+    /// [`set_text`](Self::set_text)). The edits applied since are replayed onto
+    /// them: spans before an edit are kept, spans after it are shifted by its
+    /// byte delta, and spans touching it are dropped. The top-level nodes
+    /// containing the edits and tree-sitter's changed ranges are then queried
+    /// again, and the new spans merged in.
+    ///
+    /// The result matches a full [`parse`](Self::parse) unless a pattern
+    /// relates captures across top-level nodes, e.g. a comment and the item
+    /// after it.
+    ///
+    /// If cancelled, returns an empty result; the edits are kept for the next
+    /// call.
+    pub fn parse_incremental(
+        &mut self,
+        session_id: u32,
+        prev_spans: &[Utf8Span],
+    ) -> Result<IncrementalSpans, ParseError> {
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| ParseError::new("invalid session id"))?;
+        let root = session
+            .tree
+            .as_ref()
+            .ok_or_else(|| ParseError::new("no text set for session"))?
+            .root_node();
+
+        let mut dirty: Vec<Range<usize>> = session
+            .changes
+            .dirty
+            .iter()
+            .map(|range| widen_to_top_level(root, range.clone()))
+            .collect();
+        dirty.sort_by_key(|range| range.start);
+        let mut reparsed: Vec<Range<usize>> = Vec::with_capacity(dirty.len());
+        for range in dirty {
+            match reparsed.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => reparsed.push(range),
+            }
         }
 
-        // Collect all byte offsets and batch convert to UTF-16
-        let mut all_offsets: Vec<usize> =
-            Vec::with_capacity((raw_spans.len() + raw_injections.len()) * 2);
-        for span in &raw_spans {
-            all_offsets.push(span.start);
-            all_offsets.push(span.end);
+        let changes = core::mem::take(&mut session.changes);
+        let mut spans: Vec<Utf8Span> = prev_spans
+            .iter()
+            .filter_map(|span| {
+                let mut range = span.start as usize..span.end as usize;
+                for edit in &changes.edits {
+                    if range.start <= edit.old_end_byte && edit.start_byte <= range.end {
+                        return None;
+                    }
+                    range = shift_range(range, edit);
+                }
+                let i = reparsed.partition_point(|r| r.end <= range.start);
+                if reparsed
+                    .get(i)
+                    .is_some_and(|r| r.start < range.end.max(range.start + 1))
+                {
+                    return None;
+                }
+                Some(Utf8Span {
+                    start: range.start as u32,
+                    end: range.end as u32,
+                    ..span.clone()
+                })
+            })
+            .collect();
+
+        // One deadline for all the ranges
+        let deadline = self.deadline(session_id);
+        let mut match_limit_exceeded = false;
+        let mut timed_out = false;
+        for range in &reparsed {
+            let raw = self.parse_raw(session_id, range.clone(), deadline)?;
+            if self.sessions[&session_id].cancelled.load(Ordering::Relaxed) {
+                self.sessions.get_mut(&session_id).unwrap().changes = changes;
+                return Ok(IncrementalSpans {
+                    spans: Vec::new(),
+                    reparsed: Vec::new(),
+                    match_limit_exceeded: false,
+                    timed_out: false,
+                });
+            }
+            match_limit_exceeded |= raw.match_limit_exceeded;
+            timed_out |= raw.timed_out;
+            spans.extend(Self::to_utf8_result(raw, &ParseOptions::default()).spans);
+            if timed_out {
+                break;
+            }
         }
-        for inj in &raw_injections {
-            all_offsets.push(inj.start);
-            all_offsets.push(inj.end);
+        if timed_out {
+            // Re-query the same regions next time
+            self.sessions.get_mut(&session_id).unwrap().changes = changes;
+        }
+
+        // Matches crossing a reparsed range report captures outside it too
+        spans.sort_by(|a, b| {
+            (a.start, a.end, a.pattern_index, &a.capture).cmp(&(
+                b.start,
+                b.end,
+                b.pattern_index,
+                &b.capture,
+            ))
+        });
+        spans.dedup();
+
+        Ok(IncrementalSpans {
+            spans,
+            reparsed: reparsed
+                .into_iter()
+                .map(|range| range.start as u32..range.end as u32)
+                .collect(),
+            match_limit_exceeded,
+            timed_out,
+        })
+    }
+
+    fn to_utf8_result(raw: RawParse, options: &ParseOptions) -> Utf8ParseResult {
+        // Convert to UTF-8 spans (just cast the byte offsets)
+        let mut spans: Vec<Utf8Span> = raw
+            .spans
+            .into_iter()
+            .map(|s| Utf8Span {
+                start: s.start as u32,
+                end: s.end as u32,
+                capture: s.capture,
+                pattern_index: s.pattern_index as u32,
+                node_kind: options.node_kind(s.node_kind),
+            })
+            .collect();
+
+        // Sort spans by start position for consistent output
+        spans.sort_by_key(|s| (s.start, s.end));
+
+        Utf8ParseResult {
+            spans,
+            injections: Self::to_utf8_injections(raw.injections, options),
+            match_limit_exceeded: raw.match_limit_exceeded,
+            timed_out: raw.timed_out,
         }
-        all_offsets.sort_unstable();
+    }
 
-        let utf16_offsets = batch_utf8_to_utf16(&text, &all_offsets);
+    fn to_utf8_injections(
+        injections: Vec<RawInjection>,
+        options: &ParseOptions,
+    ) -> Vec<Utf8Injection> {
+        injections
+            .into_iter()
+            .map(|i| Utf8Injection {
+                start: i.start as u32,
+                end: i.end as u32,
+                language: i.language,
+                include_children: i.include_children,
+                node_kind: options.node_kind(i.node_kind),
+            })
+            .collect()
+    }
 
-        // Build a lookup from byte offset to UTF-16 offset
-        // (using binary search since offsets are sorted)
-        let lookup = |byte_offset: usize| -> u32 {
-            let idx = all_offsets
-                .binary_search(&byte_offset)
-                .unwrap_or_else(|x| x);
-            utf16_offsets.get(idx).copied().unwrap_or(0)
+    /// Like [`parse`](Self::parse), but spans also carry their row and
+    /// column positions, with columns counted in `columns`.
+    ///
+    /// Byte columns come straight from tree-sitter. UTF-16 columns suit a
+    /// host that slices the text by byte but reports positions to a UTF-16
+    /// client, like a language server.
+    ///
+    /// If cancelled, returns an empty result.
+    pub fn parse_with_points(
+        &mut self,
+        session_id: u32,
+        columns: ColumnEncoding,
+    ) -> Result<Utf8PointParseResult, ParseError> {
+        let deadline = self.deadline(session_id);
+        let raw = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+
+        let utf16_column = match columns {
+            ColumnEncoding::Byte => None,
+            ColumnEncoding::Utf16 => Some(batch_offset_lookup(
+                &self.sessions[&session_id].text,
+                &raw.spans,
+                &[],
+                batch_utf8_to_utf16_columns,
+            )),
         };
+        let column = |offset: usize, point: Point| match &utf16_column {
+            Some(lookup) => lookup(offset),
+            None => point.column as u32,
+        };
+
+        let mut spans: Vec<Utf8PointSpan> = raw
+            .spans
+            .into_iter()
+            .map(|s| Utf8PointSpan {
+                start: s.start as u32,
+                end: s.end as u32,
+                start_row: s.start_point.row as u32,
+                start_col: column(s.start, s.start_point),
+                end_row: s.end_point.row as u32,
+                end_col: column(s.end, s.end_point),
+                capture: s.capture,
+                pattern_index: s.pattern_index as u32,
+            })
+            .collect();
+
+        // Sort spans by start position for consistent output
+        spans.sort_by_key(|s| (s.start, s.end));
+
+        Ok(Utf8PointParseResult {
+            spans,
+            injections: Self::to_utf8_injections(raw.injections, &ParseOptions::default()),
+            match_limit_exceeded: raw.match_limit_exceeded,
+            timed_out: raw.timed_out,
+        })
+    }
+
+    /// Parse the current text and return spans and injections with UTF-16 code unit indices.
+    ///
+    /// Use this when working with JavaScript, as `String.prototype.slice()` and
+    /// DOM APIs use UTF-16 code unit indices.
+    ///
+    /// If cancelled, returns an empty result.
+    pub fn parse_utf16(&mut self, session_id: u32) -> Result<Utf16ParseResult, ParseError> {
+        self.parse_utf16_with_options(session_id, &ParseOptions::default())
+    }
+
+    /// Like [`parse_utf16`](Self::parse_utf16), with [`ParseOptions`].
+    pub fn parse_utf16_with_options(
+        &mut self,
+        session_id: u32,
+        options: &ParseOptions,
+    ) -> Result<Utf16ParseResult, ParseError> {
+        let deadline = self.deadline(session_id);
+        let raw = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        Ok(Self::to_utf16_result(
+            &self.sessions[&session_id].text,
+            raw,
+            options,
+        ))
+    }
+
+    /// Convert `raw`, from a parse of `text`, to UTF-16 indices.
+    fn to_utf16_result(text: &str, raw: RawParse, options: &ParseOptions) -> Utf16ParseResult {
+        let RawParse {
+            spans: raw_spans,
+            injections: raw_injections,
+            match_limit_exceeded,
+            timed_out,
+        } = raw;
+
+        if raw_spans.is_empty() && raw_injections.is_empty() {
+            return Utf16ParseResult {
+                match_limit_exceeded,
+                timed_out,
+                ..Utf16ParseResult::empty()
+            };
+        }
+
+        // Batch convert all byte offsets to UTF-16
+        let lookup = batch_offset_lookup(text, &raw_spans, &raw_injections, batch_utf8_to_utf16);
 
         // Convert spans to UTF-16
         let mut spans: Vec<Utf16Span> = raw_spans
@@ -516,6 +1590,7 @@ impl PluginRuntime {
                 end: lookup(s.end),
                 capture: s.capture,
                 pattern_index: s.pattern_index as u32,
+                node_kind: options.node_kind(s.node_kind),
             })
             .collect();
 
@@ -530,10 +1605,139 @@ impl PluginRuntime {
                 end: lookup(i.end),
                 language: i.language,
                 include_children: i.include_children,
+                node_kind: options.node_kind(i.node_kind),
+            })
+            .collect();
+
+        Utf16ParseResult {
+            spans,
+            injections,
+            match_limit_exceeded,
+            timed_out,
+        }
+    }
+
+    /// Like [`parse_utf16`](Self::parse_utf16), but spans also carry their
+    /// row and UTF-16 column positions, as JavaScript editors expect.
+    ///
+    /// If cancelled, returns an empty result.
+    pub fn parse_utf16_with_points(
+        &mut self,
+        session_id: u32,
+    ) -> Result<Utf16PointParseResult, ParseError> {
+        let deadline = self.deadline(session_id);
+        let RawParse {
+            spans: raw_spans,
+            injections: raw_injections,
+            match_limit_exceeded,
+            timed_out,
+        } = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        let text = &self.sessions[&session_id].text;
+
+        if raw_spans.is_empty() && raw_injections.is_empty() {
+            return Ok(Utf16PointParseResult {
+                match_limit_exceeded,
+                timed_out,
+                ..Utf16PointParseResult::empty()
+            });
+        }
+
+        let lookup = batch_offset_lookup(text, &raw_spans, &raw_injections, batch_utf8_to_utf16);
+        let column = batch_offset_lookup(text, &raw_spans, &[], batch_utf8_to_utf16_columns);
+
+        let mut spans: Vec<Utf16PointSpan> = raw_spans
+            .into_iter()
+            .map(|s| Utf16PointSpan {
+                start: lookup(s.start),
+                end: lookup(s.end),
+                start_row: s.start_point.row as u32,
+                start_col: column(s.start),
+                end_row: s.end_point.row as u32,
+                end_col: column(s.end),
+                capture: s.capture,
+                pattern_index: s.pattern_index as u32,
+            })
+            .collect();
+
+        // Sort spans by start position for consistent output
+        spans.sort_by_key(|s| (s.start, s.end));
+
+        let injections: Vec<Utf16Injection> = raw_injections
+            .into_iter()
+            .map(|i| Utf16Injection {
+                start: lookup(i.start),
+                end: lookup(i.end),
+                language: i.language,
+                include_children: i.include_children,
+                node_kind: None,
+            })
+            .collect();
+
+        Ok(Utf16PointParseResult {
+            spans,
+            injections,
+            match_limit_exceeded,
+            timed_out,
+        })
+    }
+
+    /// Parse the current text and return spans and injections with code
+    /// point indices.
+    ///
+    /// Use this when text is indexed by code point, as Python `str` and
+    /// `Vec<char>` are. Unlike [`parse_utf16`](Self::parse_utf16), characters
+    /// outside the Basic Multilingual Plane count as one index, not two.
+    ///
+    /// If cancelled, returns an empty result.
+    pub fn parse_utf32(&mut self, session_id: u32) -> Result<Utf32ParseResult, ParseError> {
+        let deadline = self.deadline(session_id);
+        let RawParse {
+            spans: raw_spans,
+            injections: raw_injections,
+            match_limit_exceeded,
+            timed_out,
+        } = self.parse_raw(session_id, 0..u32::MAX as usize, deadline)?;
+        let text = &self.sessions[&session_id].text;
+
+        if raw_spans.is_empty() && raw_injections.is_empty() {
+            return Ok(Utf32ParseResult {
+                match_limit_exceeded,
+                timed_out,
+                ..Utf32ParseResult::empty()
+            });
+        }
+
+        let lookup = batch_offset_lookup(text, &raw_spans, &raw_injections, batch_utf8_to_utf32);
+
+        let mut spans: Vec<Utf32Span> = raw_spans
+            .into_iter()
+            .map(|s| Utf32Span {
+                start: lookup(s.start),
+                end: lookup(s.end),
+                capture: s.capture,
+                pattern_index: s.pattern_index as u32,
+            })
+            .collect();
+
+        // Sort spans by start position for consistent output
+        spans.sort_by_key(|s| (s.start, s.end));
+
+        let injections: Vec<Utf32Injection> = raw_injections
+            .into_iter()
+            .map(|i| Utf32Injection {
+                start: lookup(i.start),
+                end: lookup(i.end),
+                language: i.language,
+                include_children: i.include_children,
             })
             .collect();
 
-        Ok(Utf16ParseResult { spans, injections })
+        Ok(Utf32ParseResult {
+            spans,
+            injections,
+            match_limit_exceeded,
+            timed_out,
+        })
     }
 
     /// Get the language provided by this plugin.
@@ -617,10 +1821,112 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn test_batch_utf8_to_utf16_mid_emoji() {
+        // Offsets inside 🦀 (bytes 1..5) map to its start, not to `b`
+        let text = "a🦀b";
+        let offsets = [1, 2, 3, 4, 5];
+        let result = batch_utf8_to_utf16(text, &offsets);
+        assert_eq!(result, vec![1, 1, 1, 1, 3]);
+        assert_eq!(batch_utf8_to_utf32(text, &offsets), vec![1, 1, 1, 1, 2]);
+        assert_eq!(
+            batch_utf8_to_utf16_columns(text, &offsets),
+            vec![1, 1, 1, 1, 3]
+        );
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf16_past_end() {
+        // Offsets past the end clamp to the length in code units
+        let text = "a🦀";
+        let offsets = [1, 5, 6, 100];
+        let result = batch_utf8_to_utf16(text, &offsets);
+        assert_eq!(result, vec![1, 3, 3, 3]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "offsets must be sorted")]
+    fn test_batch_utf8_to_utf16_unsorted() {
+        batch_utf8_to_utf16("hello", &[3, 1]);
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf32() {
+        // é is 2 bytes, 中 is 3, 🦀 is 4 (and 2 UTF-16 code units): all
+        // count as one code point
+        let text = "aé中🦀b";
+        let offsets = [0, 1, 3, 6, 10, 11];
+        let result = batch_utf8_to_utf32(text, &offsets);
+        assert_eq!(result, vec![0, 1, 2, 3, 4, 5]);
+        // Where UTF-16 indices diverge after the emoji
+        assert_eq!(batch_utf8_to_utf16(text, &offsets), vec![0, 1, 2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf32_matches_chars() {
+        // Indices match Python `str` / `Vec<char>` indexing
+        let text = "hello🌍world";
+        let chars: Vec<char> = text.chars().collect();
+        let offsets = [0, 5, 9, 14];
+        let result = batch_utf8_to_utf32(text, &offsets);
+        assert_eq!(result, vec![0, 5, 6, 11]);
+        assert_eq!(chars[5..6], ['🌍']);
+        assert_eq!(chars[6..11].iter().collect::<String>(), "world");
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf32_supplementary_planes() {
+        // U+FFFF is the last character UTF-16 encodes in one unit; U+10000
+        // and U+10FFFF need a surrogate pair, but are still one code point
+        let text = "\u{FFFF}\u{10000}\u{10FFFF}x";
+        let offsets = [0, 3, 7, 11, 12];
+        assert_eq!(batch_utf8_to_utf32(text, &offsets), vec![0, 1, 2, 3, 4]);
+        assert_eq!(batch_utf8_to_utf16(text, &offsets), vec![0, 1, 3, 5, 6]);
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf16_columns() {
+        // Columns restart after each newline; 🦀 takes two code units
+        let text = "a🦀b\n中x\n\ny";
+        let offsets = [0, 1, 5, 6, 7, 10, 11, 12, 13, 14];
+        let result = batch_utf8_to_utf16_columns(text, &offsets);
+        assert_eq!(result, vec![0, 1, 3, 4, 0, 1, 2, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_utf16_range_to_utf8() {
+        // 🦀 is bytes 1..5 and UTF-16 units 1..3
+        let text = "a🦀b";
+        assert_eq!(utf16_range_to_utf8(text, 0..1).unwrap(), 0..1);
+        assert_eq!(utf16_range_to_utf8(text, 3..4).unwrap(), 5..6);
+        // Boundaries inside the surrogate pair move outward
+        assert_eq!(utf16_range_to_utf8(text, 2..3).unwrap(), 1..5);
+        assert_eq!(utf16_range_to_utf8(text, 0..2).unwrap(), 0..5);
+        assert_eq!(utf16_range_to_utf8(text, 4..4).unwrap(), 6..6);
+        assert!(utf16_range_to_utf8(text, 0..5).is_err());
+        assert!(utf16_range_to_utf8(text, Range { start: 3, end: 1 }).is_err());
+    }
+
+    #[test]
+    fn test_batch_utf8_to_utf32_empty() {
+        let offsets: [usize; 0] = [];
+        assert!(batch_utf8_to_utf32("hello", &offsets).is_empty());
+    }
+
+    #[test]
+    fn test_inherited_query_order() {
+        // Base queries first, own queries last, newline-separated
+        let query = inherited_query(&["(a) @x", "(b) @y\n"], &["", "(c) @z"]);
+        assert_eq!(query, "(a) @x\n(b) @y\n(c) @z\n");
+    }
+
     // Integration tests that require a grammar - only available after grammar generation
     #[cfg(feature = "integration-tests")]
     mod integration {
         use super::super::*;
+        use core::cell::Cell;
+        use std::alloc::{GlobalAlloc, Layout, System};
 
         #[test]
         fn test_parse_rust_code() {
@@ -652,6 +1958,114 @@ mod tests {
             runtime.free_session(session);
         }
 
+        #[test]
+        fn test_parse_utf32() {
+            let config = HighlightConfig::new(
+                arborium_rust::language(),
+                arborium_rust::HIGHLIGHTS_QUERY,
+                arborium_rust::INJECTIONS_QUERY,
+                arborium_rust::LOCALS_QUERY,
+            )
+            .expect("failed to create config");
+
+            let mut runtime = PluginRuntime::new(config);
+            let session = runtime.create_session();
+
+            let text = "// 🦀\nfn main() {}";
+            runtime.set_text(session, text);
+            let utf32 = runtime.parse_utf32(session).expect("parse failed");
+            let utf16 = runtime.parse_utf16(session).expect("parse failed");
+
+            // The emoji counts once in code points but twice in UTF-16
+            let keyword = utf32.spans.iter().find(|s| s.capture == "keyword").unwrap();
+            let utf16_keyword = utf16.spans.iter().find(|s| s.capture == "keyword").unwrap();
+            assert_eq!((keyword.start, keyword.end), (5, 7));
+            assert_eq!((utf16_keyword.start, utf16_keyword.end), (6, 8));
+
+            // Code point indices slice a `Vec<char>` directly
+            let chars: Vec<char> = text.chars().collect();
+            let keyword_text: String = chars[keyword.start as usize..keyword.end as usize]
+                .iter()
+                .collect();
+            assert_eq!(keyword_text, "fn");
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_parse_with_points() {
+            let text = "// 🦀 蟹\nfn main() {\n    let s = \"中文🦀\"; let x = 1;\n}\n";
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            runtime.set_text(session, text);
+
+            let utf8 = runtime
+                .parse_with_points(session, ColumnEncoding::Byte)
+                .expect("parse failed");
+            let utf8_utf16_columns = runtime
+                .parse_with_points(session, ColumnEncoding::Utf16)
+                .expect("parse failed");
+            let utf16 = runtime
+                .parse_utf16_with_points(session)
+                .expect("parse failed");
+            assert_eq!(utf8.spans.len(), utf16.spans.len());
+
+            // UTF-16 columns on byte offsets match those on UTF-16 offsets
+            for ((a, b), c) in utf8
+                .spans
+                .iter()
+                .zip(&utf8_utf16_columns.spans)
+                .zip(&utf16.spans)
+            {
+                assert_eq!((a.start, a.end), (b.start, b.end));
+                assert_eq!((a.start_row, a.end_row), (b.start_row, b.end_row));
+                assert_eq!(
+                    (b.start_col, b.end_col, &b.capture),
+                    (c.start_col, c.end_col, &c.capture)
+                );
+            }
+
+            // Rows and columns agree with the offsets in both encodings
+            let lines: Vec<&str> = text.split('\n').collect();
+            let utf16_len = |s: &str| s.encode_utf16().count() as u32;
+            for span in &utf8.spans {
+                let line_start: usize = lines[..span.start_row as usize]
+                    .iter()
+                    .map(|line| line.len() + 1)
+                    .sum();
+                assert_eq!(span.start as usize, line_start + span.start_col as usize);
+            }
+            for span in &utf16.spans {
+                let line_start: u32 = lines[..span.start_row as usize]
+                    .iter()
+                    .map(|line| utf16_len(line) + 1)
+                    .sum();
+                assert_eq!(span.start, line_start + span.start_col);
+            }
+
+            // The comment ends after an emoji and a CJK character
+            let comment = utf8.spans.iter().find(|s| s.capture == "comment").unwrap();
+            assert_eq!((comment.end_row, comment.end_col), (0, 11));
+            let comment = utf16.spans.iter().find(|s| s.capture == "comment").unwrap();
+            assert_eq!((comment.end_row, comment.end_col), (0, 7));
+
+            // The second `let` on line 2 follows a string of CJK and emoji
+            let keyword = utf8
+                .spans
+                .iter()
+                .find(|s| s.capture == "keyword" && s.start_row == 2 && s.start_col > 4)
+                .unwrap();
+            assert_eq!((keyword.start_col, keyword.end_col), (26, 29));
+            let keyword = utf16
+                .spans
+                .iter()
+                .find(|s| s.capture == "keyword" && s.start_row == 2 && s.start_col > 4)
+                .unwrap();
+            assert_eq!((keyword.start_col, keyword.end_col), (20, 23));
+
+            runtime.free_session(session);
+        }
+
         #[test]
         fn test_incremental_edit() {
             let config = HighlightConfig::new(
@@ -692,6 +2106,828 @@ mod tests {
             runtime.free_session(session);
         }
 
+        #[test]
+        fn test_apply_edit_batch() {
+            let mut runtime = rust_runtime();
+            let batched = runtime.create_session();
+            let sequential = runtime.create_session();
+            let initial = "fn a() {}\nfn b() {}";
+            runtime.set_text(batched, initial);
+            runtime.set_text(sequential, initial);
+
+            // Rename `a` to `alpha` and `b` to `beta`, positions in the
+            // initial text
+            let rename_a = Edit {
+                start_byte: 3,
+                old_end_byte: 4,
+                new_end_byte: 8,
+                start_row: 0,
+                start_col: 3,
+                old_end_row: 0,
+                old_end_col: 4,
+                new_end_row: 0,
+                new_end_col: 8,
+            };
+            let rename_b = Edit {
+                start_byte: 13,
+                old_end_byte: 14,
+                new_end_byte: 17,
+                start_row: 1,
+                start_col: 3,
+                old_end_row: 1,
+                old_end_col: 4,
+                new_end_row: 1,
+                new_end_col: 7,
+            };
+            let new_text = "fn alpha() {}\nfn beta() {}";
+            runtime.apply_edit_batch(batched, new_text, &[rename_a.clone(), rename_b.clone()]);
+
+            // One at a time, the later edit first so the earlier one's
+            // positions stay valid
+            runtime.apply_edit(sequential, "fn a() {}\nfn beta() {}", &rename_b);
+            runtime.apply_edit(sequential, new_text, &rename_a);
+
+            let sexp = |runtime: &PluginRuntime, session| {
+                runtime.sessions[&session]
+                    .tree
+                    .as_ref()
+                    .unwrap()
+                    .root_node()
+                    .to_sexp()
+            };
+            assert_eq!(sexp(&runtime, batched), sexp(&runtime, sequential));
+            let spans = runtime.parse(batched).expect("parse failed").spans;
+            assert_eq!(
+                spans,
+                runtime.parse(sequential).expect("parse failed").spans
+            );
+            assert!(spans.iter().any(|s| s.capture.contains("function")));
+        }
+
+        #[test]
+        fn test_apply_edits() {
+            let mut runtime = rust_runtime();
+            let batched = runtime.create_session();
+            let sequential = runtime.create_session();
+            let initial = "fn main() {}";
+            runtime.set_text(batched, initial);
+            runtime.set_text(sequential, initial);
+
+            // Type "x: u8" into the parameter list, then " x;" into the
+            // body, which the first insertion moved along
+            let params = Edit {
+                start_byte: 8,
+                old_end_byte: 8,
+                new_end_byte: 13,
+                start_row: 0,
+                start_col: 8,
+                old_end_row: 0,
+                old_end_col: 8,
+                new_end_row: 0,
+                new_end_col: 13,
+            };
+            let body = Edit {
+                start_byte: 16,
+                old_end_byte: 16,
+                new_end_byte: 19,
+                start_row: 0,
+                start_col: 16,
+                old_end_row: 0,
+                old_end_col: 16,
+                new_end_row: 0,
+                new_end_col: 19,
+            };
+            let new_text = "fn main(x: u8) { x;}";
+            runtime.apply_edits(batched, new_text, &[params.clone(), body.clone()]);
+
+            runtime.apply_edit(sequential, "fn main(x: u8) {}", &params);
+            runtime.apply_edit(sequential, new_text, &body);
+
+            let sexp = |runtime: &PluginRuntime, session| {
+                runtime.sessions[&session]
+                    .tree
+                    .as_ref()
+                    .unwrap()
+                    .root_node()
+                    .to_sexp()
+            };
+            assert_eq!(sexp(&runtime, batched), sexp(&runtime, sequential));
+            let spans = runtime.parse(batched).expect("parse failed").spans;
+            assert_eq!(
+                spans,
+                runtime.parse(sequential).expect("parse failed").spans
+            );
+            assert!(spans.iter().any(|s| s.start == 11 && s.end == 13));
+        }
+
+        #[test]
+        fn test_has_injections() {
+            let config = HighlightConfig::new_inherited(
+                arborium_rust::language(),
+                &[],
+                &["(function_item name: (identifier) @function)"],
+            )
+            .expect("failed to create config");
+            assert!(!config.has_injections());
+        }
+
+        #[test]
+        fn test_property_settings() {
+            let config = HighlightConfig::new(
+                arborium_rust::language(),
+                arborium_rust::HIGHLIGHTS_QUERY,
+                arborium_rust::INJECTIONS_QUERY,
+                arborium_rust::LOCALS_QUERY,
+            )
+            .expect("failed to create config");
+
+            // Rust injects itself into macro token trees, children included
+            let macro_pattern = (0..config.query.pattern_count())
+                .map(|i| config.property_settings(i))
+                .find(|settings| {
+                    settings.contains(&("injection.language".into(), Some("rust".into())))
+                })
+                .expect("expected a pattern injecting rust");
+            assert!(macro_pattern.contains(&("injection.include-children".into(), None)));
+
+            assert!(config.property_settings(usize::MAX).is_empty());
+        }
+
+        #[test]
+        fn test_inherited_own_pattern_overrides_base() {
+            let config = HighlightConfig::new_inherited(
+                arborium_rust::language(),
+                &["(function_item name: (identifier) @variable)"],
+                &["(function_item name: (identifier) @function)"],
+            )
+            .expect("failed to create config");
+
+            let mut runtime = PluginRuntime::new(config);
+            let session = runtime.create_session();
+
+            runtime.set_text(session, "fn main() {}");
+            let result = runtime.parse(session).expect("parse failed");
+
+            let inherited = result
+                .spans
+                .iter()
+                .find(|s| s.capture == "variable")
+                .expect("expected inherited @variable span");
+            let own = result
+                .spans
+                .iter()
+                .find(|s| s.capture == "function")
+                .expect("expected own @function span");
+
+            // Same node, but the own pattern comes later and must win
+            assert_eq!((inherited.start, inherited.end), (own.start, own.end));
+            assert!(own.pattern_index > inherited.pattern_index);
+
+            runtime.free_session(session);
+        }
+
+        fn rust_runtime() -> PluginRuntime {
+            let config = HighlightConfig::new(
+                arborium_rust::language(),
+                arborium_rust::HIGHLIGHTS_QUERY,
+                arborium_rust::INJECTIONS_QUERY,
+                arborium_rust::LOCALS_QUERY,
+            )
+            .expect("failed to create config");
+            PluginRuntime::new(config)
+        }
+
+        /// Allocator recording the largest allocation made while a thread
+        /// has `TRACK_ALLOCATIONS` set.
+        struct LargestAllocation;
+
+        std::thread_local! {
+            static TRACK_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+            static LARGEST_ALLOCATION: Cell<usize> = const { Cell::new(0) };
+        }
+
+        fn record_allocation(size: usize) {
+            if TRACK_ALLOCATIONS.try_with(Cell::get).unwrap_or(false) {
+                let _ = LARGEST_ALLOCATION.try_with(|largest| largest.set(largest.get().max(size)));
+            }
+        }
+
+        unsafe impl GlobalAlloc for LargestAllocation {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                record_allocation(layout.size());
+                unsafe { System.alloc(layout) }
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                unsafe { System.dealloc(ptr, layout) }
+            }
+
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                record_allocation(new_size);
+                unsafe { System.realloc(ptr, layout, new_size) }
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: LargestAllocation = LargestAllocation;
+
+        #[test]
+        fn test_parse_does_not_copy_text() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            // One big comment: a handful of spans, next to 4 MiB of text
+            let source = format!("/*{}*/\n", "x".repeat(4 << 20));
+            runtime.set_text(session, &source);
+
+            TRACK_ALLOCATIONS.with(|track| track.set(true));
+            let result = runtime.parse(session).expect("parse failed");
+            TRACK_ALLOCATIONS.with(|track| track.set(false));
+
+            assert!(result.spans.iter().any(|s| s.capture == "comment"));
+            let largest = LARGEST_ALLOCATION.with(Cell::get);
+            assert!(
+                largest < source.len(),
+                "parse made a {largest}-byte allocation for {} bytes of text",
+                source.len()
+            );
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_idle_sessions_share_pooled_cursor() {
+            let mut runtime = rust_runtime();
+            let sessions: Vec<u32> = (0..16).map(|_| runtime.create_session()).collect();
+
+            // Idle sessions don't hold any cursor
+            assert!(runtime.cursor_pool.cursors.is_empty());
+
+            for &session in &sessions {
+                runtime.set_text(session, "fn main() {}");
+                runtime.parse(session).expect("parse failed");
+            }
+
+            // Sequential parses all reuse the same cursor
+            assert_eq!(runtime.cursor_pool.cursors.len(), 1);
+
+            for session in sessions {
+                runtime.free_session(session);
+            }
+        }
+
+        #[test]
+        fn test_session_ids() {
+            let mut runtime = rust_runtime();
+            let a = runtime.create_session();
+            let b = runtime.create_session();
+            let c = runtime.create_session();
+
+            runtime.free_session(b);
+            assert!(runtime.session_ids().eq([a, c]));
+            assert_eq!(runtime.session_count(), 2);
+        }
+
+        #[test]
+        fn test_parse_raw_spans() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            let source = "fn main() {\n    let s = \"hi\";\n}";
+            runtime.set_text(session, source);
+
+            let raw = runtime.parse_raw_spans(session).expect("parse failed");
+            let string = raw.iter().find(|s| s.capture == "string").unwrap();
+            assert_eq!(&source[string.start..string.end], "\"hi\"");
+            assert_eq!(string.start_point, Point::new(1, 12));
+            assert_eq!(string.end_point, Point::new(1, 16));
+            assert_eq!(string.node_kind, "string_literal");
+
+            // The same spans parse reports, before conversion
+            let spans = runtime.parse(session).expect("parse failed").spans;
+            for span in &spans {
+                assert!(raw.iter().any(|r| r.start == span.start as usize
+                    && r.end == span.end as usize
+                    && r.capture == span.capture));
+            }
+
+            assert!(runtime.parse_raw_spans(session + 1).is_err());
+        }
+
+        #[test]
+        fn test_parse_with_node_kinds() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            runtime.set_text(session, "fn main() { let s = \"hi\"; }");
+
+            // Off by default
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(result.spans.iter().all(|s| s.node_kind.is_none()));
+
+            let options = ParseOptions { node_kinds: true };
+            let result = runtime
+                .parse_with_options(session, &options)
+                .expect("parse failed");
+            let string = result.spans.iter().find(|s| s.capture == "string").unwrap();
+            assert_eq!(string.node_kind.as_deref(), Some("string_literal"));
+            assert!(result.spans.iter().all(|s| s.node_kind.is_some()));
+
+            let result = runtime
+                .parse_utf16_with_options(session, &options)
+                .expect("parse failed");
+            let string = result.spans.iter().find(|s| s.capture == "string").unwrap();
+            assert_eq!(string.node_kind.as_deref(), Some("string_literal"));
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_reset_session() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            runtime.set_text(session, "fn main() {}");
+            runtime.cancel(session);
+
+            runtime.reset_session(session);
+            assert!(runtime.session_ids().eq([session]));
+            assert!(runtime.parse(session).is_err(), "text should be cleared");
+
+            // The session is usable again, and no longer cancelled
+            runtime.set_text(session, "fn main() {}");
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(result.spans.iter().any(|s| s.capture == "keyword"));
+
+            // Unknown ids are ignored
+            runtime.reset_session(session + 1);
+        }
+
+        #[test]
+        fn test_clone_session() {
+            let mut runtime = rust_runtime();
+            let original = runtime.create_session();
+            runtime.set_text(original, "fn main() {}");
+            let before = runtime.parse(original).expect("parse failed");
+
+            let fork = runtime.clone_session(original).expect("session exists");
+            assert_ne!(fork, original);
+            assert_eq!(runtime.parse(fork).expect("parse failed"), before);
+
+            // Editing the fork leaves the original alone
+            let edit = Edit {
+                start_byte: 11,
+                old_end_byte: 11,
+                new_end_byte: 23,
+                start_row: 0,
+                start_col: 11,
+                old_end_row: 0,
+                old_end_col: 11,
+                new_end_row: 0,
+                new_end_col: 23,
+            };
+            runtime.apply_edit(fork, "fn main() { let x = 1; }", &edit);
+            let forked = runtime.parse(fork).expect("parse failed");
+            assert!(forked.spans.iter().any(|s| s.start == 12 && s.end == 15));
+            assert_eq!(runtime.parse(original).expect("parse failed"), before);
+
+            assert_eq!(runtime.clone_session(fork + 1), None);
+        }
+
+        #[test]
+        fn test_parse_stats() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            assert_eq!(runtime.parse_stats(session), None, "no text set yet");
+
+            runtime.set_text(session, "fn main() {}");
+            let stats = runtime.parse_stats(session).expect("session has text");
+            assert!(stats.node_count > 1);
+            assert!(!stats.has_error);
+            assert_eq!((stats.error_count, stats.missing_count), (0, 0));
+            assert!(stats.parse_ms.is_some(), "native builds have a clock");
+
+            runtime.set_text(session, "fn main( {\n    let x = ;\n");
+            let stats = runtime.parse_stats(session).expect("session has text");
+            assert!(stats.has_error);
+            assert!(stats.error_count + stats.missing_count > 0);
+
+            assert_eq!(runtime.parse_stats(session + 1), None);
+        }
+
+        #[test]
+        fn test_parse_diagnostics() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            assert!(runtime.parse_diagnostics(session).is_empty());
+
+            runtime.set_text(session, "fn main() {}");
+            assert!(runtime.parse_diagnostics(session).is_empty());
+
+            // Whether the parser recovers by skipping text or by assuming
+            // the closing tokens is up to the grammar; either way the region
+            // around the unbalanced paren is flagged
+            runtime.set_text(session, "fn main( {");
+            let diagnostics = runtime.parse_diagnostics(session);
+            assert!(
+                diagnostics.iter().any(|d| d.start <= 10 && d.end >= 7),
+                "{diagnostics:?}"
+            );
+            for diagnostic in &diagnostics {
+                if diagnostic.kind == DiagnosticKind::Missing {
+                    assert_eq!(diagnostic.start, diagnostic.end);
+                }
+            }
+
+            // Stray tokens can only be skipped
+            runtime.set_text(session, "fn main() {} )");
+            let diagnostics = runtime.parse_diagnostics(session);
+            assert!(
+                diagnostics
+                    .iter()
+                    .any(|d| d.kind == DiagnosticKind::Error && d.start <= 13 && d.end >= 14),
+                "{diagnostics:?}"
+            );
+
+            assert!(runtime.parse_diagnostics(session + 1).is_empty());
+        }
+
+        #[test]
+        fn test_range_parse_does_not_leak_into_full_parse() {
+            let mut runtime = rust_runtime();
+            let source = "fn a() { let x = 1; }\nfn b() { let y = 2; }\n";
+
+            let first = runtime.create_session();
+            let second = runtime.create_session();
+            runtime.set_text(first, source);
+            runtime.set_text(second, source);
+
+            let full = runtime.parse(second).expect("parse failed");
+
+            // Range-limited parse of the first line only
+            let line_end = source.find('\n').unwrap() as u32;
+            let ranged = runtime
+                .parse_range(first, 0..line_end)
+                .expect("parse failed");
+            assert!(!ranged.spans.is_empty());
+            assert!(ranged.spans.len() < full.spans.len());
+            assert!(ranged.spans.iter().all(|s| s.start < line_end));
+
+            // The pooled cursor must not keep the byte range from the last call
+            let after = runtime.parse(second).expect("parse failed");
+            assert_eq!(after, full);
+            let first_full = runtime.parse(first).expect("parse failed");
+            assert_eq!(first_full, full);
+
+            runtime.free_session(first);
+            runtime.free_session(second);
+        }
+
+        #[test]
+        fn test_parse_range_clips_to_window() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            let source = "fn a() { let s = \"中文\"; }\nfn b() { let t = 2; }\n";
+            runtime.set_text(session, source);
+            let full = runtime.parse(session).expect("parse failed");
+
+            // Starts inside 中 (bytes 18..21) and ends inside 文 (21..24)
+            let ranged = runtime.parse_range(session, 19..22).expect("parse failed");
+            let string = ranged.spans.iter().find(|s| s.capture == "string").unwrap();
+            assert_eq!((string.start, string.end), (18, 24));
+            assert!(ranged.spans.iter().all(|s| s.start >= 18 && s.end <= 24));
+
+            // Clipped spans keep document offsets, and match the full parse
+            let window = 9..40;
+            let ranged = runtime
+                .parse_range(session, window.clone())
+                .expect("parse failed");
+            let mut expected: Vec<Utf8Span> = full
+                .spans
+                .iter()
+                .filter(|s| s.start < window.end && s.end > window.start)
+                .map(|s| Utf8Span {
+                    start: s.start.max(window.start),
+                    end: s.end.min(window.end),
+                    ..s.clone()
+                })
+                .collect();
+            expected.sort_by_key(|s| (s.start, s.end));
+            assert_eq!(ranged.spans, expected);
+
+            // The UTF-16 twin reports the same spans in UTF-16 units
+            let utf16 = runtime.parse_utf16(session).expect("parse failed");
+            let utf16_ranged = runtime.parse_range_utf16(session, 0..12).unwrap();
+            assert_eq!(
+                utf16_ranged.spans,
+                utf16
+                    .spans
+                    .iter()
+                    .filter(|s| s.start < 12)
+                    .map(|s| Utf16Span {
+                        end: s.end.min(12),
+                        ..s.clone()
+                    })
+                    .collect::<Vec<_>>()
+            );
+
+            // Inverted and out-of-bounds ranges are errors
+            assert!(
+                runtime
+                    .parse_range(session, Range { start: 10, end: 5 })
+                    .is_err()
+            );
+            let len = source.len() as u32;
+            assert!(runtime.parse_range(session, 0..len + 1).is_err());
+            assert!(runtime.parse_range(session, 0..len).is_ok());
+            assert!(runtime.parse_range_utf16(session, 0..len).is_err());
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_parse_range_confined_to_window() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            let source: String = (0..10_000)
+                .map(|i| format!("fn f{i}() {{ let s = \"{i}\"; m!({i}); }}\n"))
+                .collect();
+            runtime.set_text(session, &source);
+
+            // Lines 5000 to 5009
+            let line_start = |line: usize| {
+                source
+                    .split_inclusive('\n')
+                    .take(line)
+                    .map(str::len)
+                    .sum::<usize>()
+            };
+            let window = line_start(5000) as u32..line_start(5010) as u32;
+            let result = runtime
+                .parse_range(session, window.clone())
+                .expect("parse failed");
+
+            assert!(result.spans.iter().any(|s| s.capture == "keyword"));
+            for span in &result.spans {
+                assert!(
+                    window.start <= span.start && span.end <= window.end,
+                    "{span:?}"
+                );
+            }
+            for injection in &result.injections {
+                assert!(injection.start < window.end && window.start < injection.end);
+            }
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_parse_range_is_cheaper_than_full_query() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            let item = "fn item(x: u32) -> u32 {\n    let y = x * 2; // double\n    y + 1\n}\n\n";
+            let source = item.repeat(1024 * 1024 / item.len());
+            runtime.set_text(session, &source);
+
+            let clock = std::time::Instant::now();
+            let full = runtime.parse(session).expect("parse failed");
+            let full_time = clock.elapsed();
+
+            let middle = (source.len() / 2) as u32;
+            let clock = std::time::Instant::now();
+            let ranged = runtime
+                .parse_range(session, middle..middle + 2048)
+                .expect("parse failed");
+            let ranged_time = clock.elapsed();
+
+            assert!(!ranged.spans.is_empty());
+            assert!(ranged.spans.len() * 100 < full.spans.len());
+            assert!(
+                ranged_time * 10 < full_time,
+                "window took {ranged_time:?}, full query {full_time:?}"
+            );
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_match_limit_exceeded_is_reported() {
+            // Captures the outer call before the match completes deeper down,
+            // so nested calls keep several partial matches in flight at once
+            let config = HighlightConfig::new(
+                arborium_rust::language(),
+                "(call_expression (arguments (call_expression (arguments (call_expression))))) @function",
+                "",
+                "",
+            )
+            .expect("failed to create config");
+            let mut runtime = PluginRuntime::new(config);
+            let session = runtime.create_session();
+            let source = format!("fn main() {{ {}x{} }}", "f(".repeat(64), ")".repeat(64));
+            runtime.set_text(session, &source);
+
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(!result.match_limit_exceeded);
+
+            runtime.set_match_limit(1);
+            let limited = runtime.parse(session).expect("parse failed");
+            assert!(limited.match_limit_exceeded);
+
+            runtime.set_match_limit(u32::MAX);
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(!result.match_limit_exceeded);
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_timeout_stops_slow_queries() {
+            // Wildcards match nearly every node at every depth
+            let config =
+                HighlightConfig::new(arborium_rust::language(), "(_ (_ (_) @c) @b) @a", "", "")
+                    .expect("failed to create config");
+            let mut runtime = PluginRuntime::new(config);
+            let session = runtime.create_session();
+            let source = format!(
+                "fn main() {{\n{}}}\n",
+                "    let x = f(g(h(1, 2), [3, 4]), (5, 6));\n".repeat(2_000)
+            );
+            runtime.set_text(session, &source);
+
+            let full = runtime.parse(session).expect("parse failed");
+            assert!(!full.timed_out);
+
+            runtime.set_timeout(session, 1);
+            let limited = runtime.parse(session).expect("parse failed");
+            assert!(limited.timed_out);
+            assert!(limited.spans.len() < full.spans.len());
+            assert!(runtime.parse_utf16(session).unwrap().timed_out);
+
+            runtime.set_timeout(session, 0);
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(!result.timed_out);
+            assert_eq!(result.spans.len(), full.spans.len());
+
+            runtime.free_session(session);
+        }
+
+        #[test]
+        fn test_parse_with_timeout() {
+            // Wildcards match nearly every node at every depth
+            let config =
+                HighlightConfig::new(arborium_rust::language(), "(_ (_ (_) @c) @b) @a", "", "")
+                    .expect("failed to create config");
+            let mut runtime = PluginRuntime::new(config);
+            let session = runtime.create_session();
+            let source = format!(
+                "fn main() {{\n{}}}\n",
+                "    let x = f(g(h(1, 2), [3, 4]), (5, 6));\n".repeat(2_000)
+            );
+            runtime.set_text(session, &source);
+
+            let limited = runtime
+                .parse_with_timeout(session, Duration::from_millis(1))
+                .expect("parse failed");
+            assert!(limited.timed_out);
+
+            // The budget overrides the session's timeout, and only for this
+            // parse
+            runtime.set_timeout(session, 1);
+            let full = runtime
+                .parse_with_timeout(session, Duration::from_secs(3_600))
+                .expect("parse failed");
+            assert!(!full.timed_out);
+            assert!(limited.spans.len() < full.spans.len());
+            assert!(runtime.parse(session).unwrap().timed_out);
+
+            assert!(
+                runtime
+                    .parse_with_timeout(session + 1, Duration::from_secs(1))
+                    .is_err()
+            );
+
+            // Less than a millisecond rounds up to one, rather than a
+            // deadline that has already passed
+            runtime.set_clock(|| 0);
+            let rounded = runtime
+                .parse_with_timeout(session, Duration::from_micros(500))
+                .expect("parse failed");
+            assert!(!rounded.timed_out);
+        }
+
+        #[test]
+        fn test_timeout_uses_runtime_clock() {
+            // Every reading is a millisecond after the last
+            static NOW: AtomicU32 = AtomicU32::new(0);
+            fn ticking_clock() -> u64 {
+                u64::from(NOW.fetch_add(1, Ordering::Relaxed))
+            }
+
+            let mut runtime = rust_runtime();
+            runtime.set_clock(ticking_clock);
+            let session = runtime.create_session();
+            runtime.set_text(session, &"fn f() { let x = 1; }\n".repeat(100));
+            runtime.set_timeout(session, 1);
+
+            let result = runtime.parse(session).expect("parse failed");
+            assert!(result.timed_out);
+            assert!(
+                !result.spans.is_empty(),
+                "matches before the first check are kept"
+            );
+        }
+
+        /// Replace `range` of `text`, returning the edit that describes it.
+        fn replace(text: &mut String, range: Range<usize>, replacement: &str) -> Edit {
+            let point = |text: &str, offset: usize| {
+                let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+                (
+                    text[..offset].matches('\n').count() as u32,
+                    (offset - line_start) as u32,
+                )
+            };
+            let (start_row, start_col) = point(text, range.start);
+            let (old_end_row, old_end_col) = point(text, range.end);
+            let new_end = range.start + replacement.len();
+            text.replace_range(range.clone(), replacement);
+            let (new_end_row, new_end_col) = point(text, new_end);
+            Edit {
+                start_byte: range.start as u32,
+                old_end_byte: range.end as u32,
+                new_end_byte: new_end as u32,
+                start_row,
+                start_col,
+                old_end_row,
+                old_end_col,
+                new_end_row,
+                new_end_col,
+            }
+        }
+
+        fn canonical(mut spans: Vec<Utf8Span>) -> Vec<Utf8Span> {
+            spans.sort_by(|a, b| {
+                (a.start, a.end, a.pattern_index, &a.capture).cmp(&(
+                    b.start,
+                    b.end,
+                    b.pattern_index,
+                    &b.capture,
+                ))
+            });
+            spans.dedup();
+            spans
+        }
+
+        #[test]
+        fn test_parse_incremental_matches_full_parse() {
+            let mut runtime = rust_runtime();
+            let session = runtime.create_session();
+            let reference = runtime.create_session();
+
+            let mut text = String::from(
+                "fn a() { let x = 1; }\n\nfn b() -> u32 { 2 }\n\nstruct S { f: i32 }\n",
+            );
+            runtime.set_text(session, &text);
+            let mut spans = runtime
+                .parse_incremental(session, &[])
+                .expect("parse failed")
+                .spans;
+
+            // Each step is a batch of (search, replacement) edits
+            let steps: &[&[(&str, &str)]] = &[
+                &[("1", "\"one\"")],
+                &[("fn b", "pub fn b")],
+                &[("{ 2 }", "{ /* two */ 2 }"), ("struct S { f: i32 }\n", "")],
+                &[("let x", "let mut x"), ("u32", "String")],
+                // Unterminated, so everything after it becomes a comment
+                &[("fn a() {", "fn a() { /*")],
+                &[("/*", "")],
+            ];
+            for (i, step) in steps.iter().enumerate() {
+                for &(search, replacement) in *step {
+                    let start = text.find(search).expect("search text not found");
+                    let edit = replace(&mut text, start..start + search.len(), replacement);
+                    runtime.apply_edit(session, &text.clone(), &edit);
+                }
+
+                let incremental = runtime
+                    .parse_incremental(session, &spans)
+                    .expect("parse failed");
+                if i == 0 {
+                    // Only the first function is queried again
+                    let second_fn = text.find("fn b").unwrap() as u32;
+                    assert!(incremental.reparsed.iter().all(|r| r.end <= second_fn));
+                }
+
+                runtime.set_text(reference, &text);
+                let full = runtime.parse(reference).expect("parse failed").spans;
+                assert_eq!(
+                    canonical(incremental.spans.clone()),
+                    canonical(full),
+                    "step {i}: {text:?}"
+                );
+                spans = incremental.spans;
+            }
+
+            runtime.free_session(session);
+            runtime.free_session(reference);
+        }
+
         #[test]
         fn test_cancellation() {
             let config = HighlightConfig::new(
@@ -0,0 +1,45 @@
+//! Spans and injections as the query produces them, before any offset
+//! conversion.
+//!
+//! See [`PluginRuntime::parse_raw_spans`](crate::PluginRuntime::parse_raw_spans),
+//! for callers that convert offsets their own way, such as language servers
+//! building semantic tokens.
+
+use alloc::string::String;
+
+pub use arborium_tree_sitter::Point;
+
+/// A highlight capture, with UTF-8 byte offsets into the session text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawSpan {
+    /// UTF-8 byte offset where the span starts.
+    pub start: usize,
+    /// UTF-8 byte offset where the span ends (exclusive).
+    pub end: usize,
+    /// Row and byte column of `start`.
+    pub start_point: Point,
+    /// Row and byte column of `end`.
+    pub end_point: Point,
+    /// The capture name (e.g., "keyword", "function", "string").
+    pub capture: String,
+    /// Index of the query pattern that matched; later patterns take
+    /// precedence.
+    pub pattern_index: usize,
+    /// Kind of the captured syntax node (e.g. "string_literal").
+    pub node_kind: &'static str,
+}
+
+/// An injection point, with UTF-8 byte offsets into the session text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawInjection {
+    /// UTF-8 byte offset where the injected content starts.
+    pub start: usize,
+    /// UTF-8 byte offset where the injected content ends (exclusive).
+    pub end: usize,
+    /// The language to parse the content as.
+    pub language: String,
+    /// Whether the content node's children are part of the injection.
+    pub include_children: bool,
+    /// Kind of the content node.
+    pub node_kind: &'static str,
+}
@@ -2,7 +2,7 @@
 //!
 //! Transforms rustdoc HTML to add syntax highlighting for non-Rust code blocks.
 
-use arborium::{Error as ArboriumError, Highlighter};
+use arborium::{Error as ArboriumError, FenceLanguageMap, Highlighter};
 use lol_html::html_content::ContentType;
 use lol_html::{ElementContentHandlers, HtmlRewriter, Selector, Settings};
 use std::borrow::Cow;
@@ -34,6 +34,8 @@ struct TransformState {
     result: TransformResult,
     /// The highlighter (wrapped for sharing).
     highlighter: Option<Highlighter>,
+    /// Grammars for the code blocks' language tags.
+    languages: FenceLanguageMap,
 }
 
 /// Transform rustdoc HTML, adding syntax highlighting to non-Rust code blocks.
@@ -43,6 +45,16 @@ struct TransformState {
 pub fn transform_html(
     html: &str,
     highlighter: &mut Highlighter,
+) -> Result<(String, TransformResult), TransformError> {
+    transform_html_with_languages(html, highlighter, &FenceLanguageMap::default())
+}
+
+/// Like [`transform_html`], mapping the code blocks' language tags to
+/// grammars with `languages`.
+pub fn transform_html_with_languages(
+    html: &str,
+    highlighter: &mut Highlighter,
+    languages: &FenceLanguageMap,
 ) -> Result<(String, TransformResult), TransformError> {
     // Fork the highlighter - shares the grammar store but has its own parse context
     // This is needed because lol_html requires 'static closures
@@ -51,6 +63,7 @@ pub fn transform_html(
     // Shared state wrapped in Rc<RefCell<>> for the closure dance
     let state = Rc::new(RefCell::new(TransformState {
         highlighter: Some(forked),
+        languages: languages.clone(),
         ..Default::default()
     }));
 
@@ -123,17 +136,30 @@ pub fn transform_html(
                                             let decoded =
                                                 decode_html_entities(&state.collected_text);
 
-                                            // Highlight the code
-                                            let highlighter = state.highlighter.as_mut().unwrap();
-                                            match highlighter.highlight(&lang, &decoded) {
-                                                Ok(highlighted) => {
+                                            // Highlight the code, unless it's plain text.
+                                            // Unknown tags are tried as-is, and fail as
+                                            // unsupported.
+                                            let highlighted = if state.languages.is_plain(&lang) {
+                                                None
+                                            } else {
+                                                let grammar = state
+                                                    .languages
+                                                    .resolve(&lang)
+                                                    .unwrap_or(&lang)
+                                                    .to_string();
+                                                let highlighter =
+                                                    state.highlighter.as_mut().unwrap();
+                                                Some(highlighter.highlight(&grammar, &decoded))
+                                            };
+                                            match highlighted {
+                                                Some(Ok(highlighted)) => {
                                                     // Insert highlighted content before </code>
                                                     end.before(&highlighted, ContentType::Html);
                                                     state.result.blocks_highlighted += 1;
                                                 }
-                                                Err(ArboriumError::UnsupportedLanguage {
+                                                Some(Err(ArboriumError::UnsupportedLanguage {
                                                     ..
-                                                }) => {
+                                                })) => {
                                                     // Language not supported - keep original
                                                     if !state
                                                         .result
@@ -152,8 +178,8 @@ pub fn transform_html(
                                                     );
                                                     state.result.blocks_skipped += 1;
                                                 }
-                                                Err(_) => {
-                                                    // Other error - keep original
+                                                Some(Err(_)) | None => {
+                                                    // Plain text or other error - keep original
                                                     end.before(
                                                         &state.collected_text,
                                                         ContentType::Html,
@@ -333,6 +359,35 @@ name = "test"</code></pre>"#;
         assert!(output.contains("some code"));
     }
 
+    #[test]
+    fn test_transform_html_leaves_plain_text() {
+        let html = r#"<pre class="language-text"><code>some output</code></pre>"#;
+
+        let mut highlighter = Highlighter::new();
+        let (output, result) = transform_html(html, &mut highlighter).unwrap();
+
+        assert_eq!(result.blocks_highlighted, 0);
+        assert_eq!(result.blocks_skipped, 1);
+        // Plain text isn't an unsupported language
+        assert!(result.unsupported_languages.is_empty());
+        assert!(output.contains("some output"));
+    }
+
+    #[test]
+    fn test_transform_html_with_custom_languages() {
+        let html = r#"<pre class="language-cargo"><code>[package]
+name = "test"</code></pre>"#;
+
+        let mut languages = FenceLanguageMap::new();
+        languages.insert("cargo", "toml");
+        let mut highlighter = Highlighter::new();
+        let (output, result) =
+            transform_html_with_languages(html, &mut highlighter, &languages).unwrap();
+
+        assert_eq!(result.blocks_highlighted, 1);
+        assert!(output.contains("<a-"));
+    }
+
     #[test]
     fn test_transform_html_decodes_entities() {
         // TOML with HTML entities that need decoding
@@ -29,5 +29,5 @@ mod html;
 mod processor;
 
 pub use css::generate_rustdoc_theme_css;
-pub use html::transform_html;
+pub use html::{transform_html, transform_html_with_languages};
 pub use processor::{ProcessError, ProcessOptions, Processor, ProcessorStats};
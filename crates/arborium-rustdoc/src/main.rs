@@ -1,6 +1,7 @@
 //! arborium-rustdoc CLI - Post-process rustdoc output with syntax highlighting.
 
 use anyhow::{Result, bail};
+use arborium::FenceLanguageMap;
 use arborium_rustdoc::{ProcessOptions, Processor};
 use facet::Facet;
 use facet_args as args;
@@ -64,6 +65,7 @@ fn main() -> Result<()> {
         input_dir: args.input.clone(),
         output_dir: args.output.clone(),
         verbose: args.verbose,
+        languages: FenceLanguageMap::default(),
     };
 
     let mut processor = Processor::new(options);
@@ -1,8 +1,8 @@
 //! Main processor that transforms rustdoc output directories.
 
 use crate::css::generate_rustdoc_theme_css;
-use crate::html::{TransformError, TransformResult, transform_html};
-use arborium::{GrammarStore, Highlighter};
+use crate::html::{TransformError, TransformResult, transform_html_with_languages};
+use arborium::{FenceLanguageMap, GrammarStore, Highlighter};
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use std::fs;
@@ -21,6 +21,8 @@ pub struct ProcessOptions {
     pub output_dir: Option<PathBuf>,
     /// Whether to show verbose output.
     pub verbose: bool,
+    /// Grammars for the code blocks' language tags.
+    pub languages: FenceLanguageMap,
 }
 
 /// Statistics from processing.
@@ -156,6 +158,7 @@ impl Processor {
         let unsupported_languages = Mutex::new(Vec::<String>::new());
 
         let verbose = self.options.verbose;
+        let languages = &self.options.languages;
 
         // Process files in parallel using rayon
         // for_each_init creates one Highlighter per thread (not per file!)
@@ -166,7 +169,7 @@ impl Processor {
                     eprintln!("Processing: {}", path.display());
                 }
 
-                match Self::process_html_file_with_highlighter(path, highlighter) {
+                match Self::process_html_file_with_highlighter(path, highlighter, languages) {
                     Ok((result, input_size, output_size)) => {
                         files_processed.fetch_add(1, Ordering::Relaxed);
                         blocks_highlighted.fetch_add(result.blocks_highlighted, Ordering::Relaxed);
@@ -260,6 +263,7 @@ impl Processor {
     fn process_html_file_with_highlighter(
         path: &Path,
         highlighter: &mut Highlighter,
+        languages: &FenceLanguageMap,
     ) -> Result<(TransformResult, usize, usize), ProcessError> {
         let html = fs::read_to_string(path)?;
         let input_size = html.len();
@@ -270,7 +274,7 @@ impl Processor {
             return Ok((TransformResult::default(), input_size, input_size));
         }
 
-        let (transformed, result) = transform_html(&html, highlighter)?;
+        let (transformed, result) = transform_html_with_languages(&html, highlighter, languages)?;
         let output_size = transformed.len();
 
         // Only write if we actually changed something
@@ -22,8 +22,420 @@
 //!
 //! Adjacent spans that map to the same slot are coalesced into a single HTML element.
 
-/// The theme slots - the fixed set of color categories that themes define.
-/// This is the final destination for all capture names.
+use std::collections::HashMap;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// A theme slot: one of the fixed set of color categories that themes define.
+///
+/// Every capture name maps to at most one slot (see [`Slot::from_capture`]).
+/// Unlike the stringly-typed helpers in this module, a `Slot` always has a
+/// tag, a CSS name and a theme style, so no `Option` juggling is needed once
+/// a capture has been resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Slot {
+    Keyword,
+    Function,
+    String,
+    Comment,
+    Type,
+    Variable,
+    Constant,
+    Number,
+    Operator,
+    Punctuation,
+    Property,
+    Attribute,
+    Tag,
+    Macro,
+    Label,
+    Namespace,
+    Constructor,
+    /// Markup: headings, titles
+    Title,
+    /// Markup: bold text
+    Strong,
+    /// Markup: italic text
+    Emphasis,
+    /// Markup: links/URLs
+    Link,
+    /// Markup: raw/literal/code blocks
+    Literal,
+    /// Markup: strikethrough
+    Strikethrough,
+    /// Diff additions
+    DiffAdd,
+    /// Diff deletions
+    DiffDelete,
+    /// Embedded content
+    Embedded,
+    /// Errors
+    Error,
+}
+
+impl Slot {
+    /// All slots, in declaration order.
+    pub const ALL: &'static [Slot] = &[
+        Slot::Keyword,
+        Slot::Function,
+        Slot::String,
+        Slot::Comment,
+        Slot::Type,
+        Slot::Variable,
+        Slot::Constant,
+        Slot::Number,
+        Slot::Operator,
+        Slot::Punctuation,
+        Slot::Property,
+        Slot::Attribute,
+        Slot::Tag,
+        Slot::Macro,
+        Slot::Label,
+        Slot::Namespace,
+        Slot::Constructor,
+        Slot::Title,
+        Slot::Strong,
+        Slot::Emphasis,
+        Slot::Link,
+        Slot::Literal,
+        Slot::Strikethrough,
+        Slot::DiffAdd,
+        Slot::DiffDelete,
+        Slot::Embedded,
+        Slot::Error,
+    ];
+
+    /// Map any capture name to its slot.
+    ///
+    /// Returns `None` for captures that produce no styling (like `spell` or
+    /// `none`) and for unknown captures. Captures without a built-in slot
+    /// fall back to the aliases registered with [`register_capture_alias`].
+    ///
+    /// # Example
+    /// ```
+    /// use arborium_theme::Slot;
+    ///
+    /// assert_eq!(Slot::from_capture("keyword.function"), Some(Slot::Keyword));
+    /// assert_eq!(Slot::from_capture("@include"), Some(Slot::Keyword));
+    /// assert_eq!(Slot::from_capture("spell"), None);
+    /// ```
+    pub fn from_capture(capture: &str) -> Option<Slot> {
+        // First, strip any leading @ (some queries include it)
+        let capture = capture.strip_prefix('@').unwrap_or(capture);
+        Self::builtin_from_capture(capture).or_else(|| registered_alias(capture))
+    }
+
+    /// The built-in slot for `capture`, which has no leading `@`.
+    fn builtin_from_capture(capture: &str) -> Option<Slot> {
+        let slot = match capture {
+            // Keywords - base and all variants
+            "keyword" | "keyword.conditional" | "keyword.coroutine" | "keyword.debug"
+            | "keyword.exception" | "keyword.function" | "keyword.import" | "keyword.operator"
+            | "keyword.repeat" | "keyword.return" | "keyword.type" | "keyword.modifier"
+            | "keyword.directive" | "keyword.storage" | "keyword.control"
+            | "keyword.control.conditional" | "keyword.control.repeat" | "keyword.control.import"
+            | "keyword.control.return" | "keyword.control.exception"
+            // nvim-treesitter legacy names that are really keywords
+            | "include" | "conditional" | "repeat" | "exception" | "storageclass" | "preproc"
+            | "define" | "structure" => Slot::Keyword,
+
+            // Functions
+            "function" | "function.builtin" | "function.method" | "function.definition"
+            | "function.call" | "function.special" | "method" | "method.call" => Slot::Function,
+
+            // Strings
+            "string" | "string.special" | "string.special.symbol" | "string.special.path"
+            | "string.special.url" | "string.escape" | "string.regexp" | "string.regex"
+            | "character" | "character.special" | "escape" => Slot::String,
+
+            // Comments
+            "comment" | "comment.documentation" | "comment.line" | "comment.block"
+            | "comment.error" | "comment.warning" | "comment.note" | "comment.todo" => {
+                Slot::Comment
+            }
+
+            // Types
+            "type" | "type.builtin" | "type.qualifier" | "type.definition" | "type.enum"
+            | "type.enum.variant" | "type.parameter" => Slot::Type,
+
+            // Variables
+            "variable" | "variable.builtin" | "variable.parameter" | "variable.member"
+            | "variable.other" | "variable.other.member" | "parameter" | "field" => {
+                Slot::Variable
+            }
+
+            // Constants
+            "constant" | "constant.builtin" | "constant.builtin.boolean" | "boolean" => {
+                Slot::Constant
+            }
+
+            // Numbers
+            "number" | "constant.numeric" | "float" | "number.float" => Slot::Number,
+
+            // Operators
+            "operator" => Slot::Operator,
+
+            // Punctuation
+            "punctuation" | "punctuation.bracket" | "punctuation.delimiter" | "punctuation.special" => {
+                Slot::Punctuation
+            }
+
+            // Properties
+            "property" | "property.builtin" => Slot::Property,
+
+            // Attributes
+            "attribute" | "attribute.builtin" => Slot::Attribute,
+
+            // Tags (HTML/XML)
+            "tag" | "tag.delimiter" | "tag.error" | "tag.attribute" | "tag.builtin" => Slot::Tag,
+
+            // Macros
+            "macro" | "function.macro" | "preproc.macro" => Slot::Macro,
+
+            // Labels
+            "label" => Slot::Label,
+
+            // Namespaces/Modules
+            "namespace" | "module" => Slot::Namespace,
+
+            // Constructors
+            "constructor" | "constructor.builtin" => Slot::Constructor,
+
+            // Markup - titles/headings
+            "text.title" | "markup.heading" | "markup.heading.1" | "markup.heading.2"
+            | "markup.heading.3" | "markup.heading.4" | "markup.heading.5" | "markup.heading.6" => {
+                Slot::Title
+            }
+
+            // Markup - bold
+            "text.strong" | "markup.bold" => Slot::Strong,
+
+            // Markup - italic
+            "text.emphasis" | "markup.italic" => Slot::Emphasis,
+
+            // Markup - links
+            "text.uri" | "text.reference" | "markup.link" | "markup.link.url" | "markup.link.text"
+            | "markup.link.label" => Slot::Link,
+
+            // Markup - literal/raw/code
+            "text.literal" | "markup.raw" | "markup.raw.block" | "markup.raw.inline"
+            | "markup.inline" => Slot::Literal,
+
+            // Markup - strikethrough
+            "text.strikethrough" | "markup.strikethrough" => Slot::Strikethrough,
+
+            // Markup - lists (treat as punctuation)
+            "markup.list" | "markup.list.checked" | "markup.list.unchecked"
+            | "markup.list.numbered" | "markup.list.unnumbered" | "markup.quote" => {
+                Slot::Punctuation
+            }
+
+            // Diff
+            "diff.addition" | "diff.plus" | "diff.delta" => Slot::DiffAdd,
+            "diff.deletion" | "diff.minus" => Slot::DiffDelete,
+
+            // Embedded
+            "embedded" => Slot::Embedded,
+
+            // Error
+            "error" => Slot::Error,
+
+            // No styling
+            "none" | "nospell" | "spell" | "text" | "markup" => return None,
+
+            // Fallback: try to match by prefix
+            other => {
+                if other.starts_with("keyword") {
+                    Slot::Keyword
+                } else if other.starts_with("function") || other.starts_with("method") {
+                    Slot::Function
+                } else if other.starts_with("string") || other.starts_with("character") {
+                    Slot::String
+                } else if other.starts_with("comment") {
+                    Slot::Comment
+                } else if other.starts_with("type") {
+                    Slot::Type
+                } else if other.starts_with("variable") || other.starts_with("parameter") {
+                    Slot::Variable
+                } else if other.starts_with("constant") {
+                    Slot::Constant
+                } else if other.starts_with("punctuation") {
+                    Slot::Punctuation
+                } else if other.starts_with("tag") {
+                    Slot::Tag
+                } else if other.starts_with("markup.heading") || other.starts_with("text.title") {
+                    Slot::Title
+                } else if other.starts_with("markup") || other.starts_with("text") {
+                    // Generic markup/text - no styling
+                    return None
+                } else {
+                    // Unknown capture - no styling
+                    return None
+                }
+            }
+        };
+
+        Some(slot)
+    }
+
+    /// Get the HTML tag suffix for this slot (e.g. `"k"` for `<a-k>`).
+    pub fn tag(self) -> &'static str {
+        match self {
+            Slot::Keyword => "k",
+            Slot::Function => "f",
+            Slot::String => "s",
+            Slot::Comment => "c",
+            Slot::Type => "t",
+            Slot::Variable => "v",
+            Slot::Constant => "co",
+            Slot::Number => "n",
+            Slot::Operator => "o",
+            Slot::Punctuation => "p",
+            Slot::Property => "pr",
+            Slot::Attribute => "at",
+            Slot::Tag => "tg",
+            Slot::Macro => "m",
+            Slot::Label => "l",
+            Slot::Namespace => "ns",
+            Slot::Constructor => "cr",
+            Slot::Title => "tt",
+            Slot::Strong => "st",
+            Slot::Emphasis => "em",
+            Slot::Link => "tu",
+            Slot::Literal => "tl",
+            Slot::Strikethrough => "tx",
+            Slot::DiffAdd => "da",
+            Slot::DiffDelete => "dd",
+            Slot::Embedded => "eb",
+            Slot::Error => "er",
+        }
+    }
+
+    /// Get the full name for this slot (for CSS class names).
+    pub fn css_name(self) -> &'static str {
+        match self {
+            Slot::Keyword => "keyword",
+            Slot::Function => "function",
+            Slot::String => "string",
+            Slot::Comment => "comment",
+            Slot::Type => "type",
+            Slot::Variable => "variable",
+            Slot::Constant => "constant",
+            Slot::Number => "number",
+            Slot::Operator => "operator",
+            Slot::Punctuation => "punctuation",
+            Slot::Property => "property",
+            Slot::Attribute => "attribute",
+            Slot::Tag => "tag",
+            Slot::Macro => "macro",
+            Slot::Label => "label",
+            Slot::Namespace => "namespace",
+            Slot::Constructor => "constructor",
+            Slot::Title => "title",
+            Slot::Strong => "strong",
+            Slot::Emphasis => "emphasis",
+            Slot::Link => "link",
+            Slot::Literal => "literal",
+            Slot::Strikethrough => "strikethrough",
+            Slot::DiffAdd => "diff-add",
+            Slot::DiffDelete => "diff-delete",
+            Slot::Embedded => "embedded",
+            Slot::Error => "error",
+        }
+    }
+
+    /// Look up a slot by its HTML tag suffix (the inverse of [`Slot::tag`]).
+    pub fn from_tag(tag: &str) -> Option<Slot> {
+        Slot::ALL.iter().copied().find(|slot| slot.tag() == tag)
+    }
+
+    /// Get the canonical highlight index for this slot.
+    ///
+    /// This is the index of the slot's representative style in
+    /// [`Theme::styles`](crate::Theme::styles); prefer
+    /// [`Theme::style_for`](crate::Theme::style_for) to look styles up.
+    pub fn highlight_index(self) -> usize {
+        let index = match self {
+            Slot::Keyword => HIGHLIGHTS.iter().position(|h| h.name == "keyword"),
+            Slot::Function => HIGHLIGHTS.iter().position(|h| h.name == "function"),
+            Slot::String => HIGHLIGHTS.iter().position(|h| h.name == "string"),
+            Slot::Comment => HIGHLIGHTS.iter().position(|h| h.name == "comment"),
+            Slot::Type => HIGHLIGHTS.iter().position(|h| h.name == "type"),
+            Slot::Variable => HIGHLIGHTS.iter().position(|h| h.name == "variable"),
+            Slot::Constant => HIGHLIGHTS.iter().position(|h| h.name == "constant"),
+            Slot::Number => HIGHLIGHTS.iter().position(|h| h.name == "number"),
+            Slot::Operator => HIGHLIGHTS.iter().position(|h| h.name == "operator"),
+            Slot::Punctuation => HIGHLIGHTS.iter().position(|h| h.name == "punctuation"),
+            Slot::Property => HIGHLIGHTS.iter().position(|h| h.name == "property"),
+            Slot::Attribute => HIGHLIGHTS.iter().position(|h| h.name == "attribute"),
+            Slot::Tag => HIGHLIGHTS.iter().position(|h| h.name == "tag"),
+            Slot::Macro => HIGHLIGHTS.iter().position(|h| h.name == "macro"),
+            Slot::Label => HIGHLIGHTS.iter().position(|h| h.name == "label"),
+            Slot::Namespace => HIGHLIGHTS.iter().position(|h| h.name == "namespace"),
+            Slot::Constructor => HIGHLIGHTS.iter().position(|h| h.name == "constructor"),
+            Slot::Title => HIGHLIGHTS
+                .iter()
+                .position(|h| h.name == "text.title" || h.name == "markup.heading"),
+            Slot::Strong => HIGHLIGHTS
+                .iter()
+                .position(|h| h.name == "text.strong" || h.name == "markup.bold"),
+            Slot::Emphasis => HIGHLIGHTS
+                .iter()
+                .position(|h| h.name == "text.emphasis" || h.name == "markup.italic"),
+            Slot::Link => HIGHLIGHTS
+                .iter()
+                .position(|h| h.name == "text.uri" || h.name == "text.reference"),
+            Slot::Literal => HIGHLIGHTS.iter().position(|h| h.name == "text.literal"),
+            Slot::Strikethrough => HIGHLIGHTS
+                .iter()
+                .position(|h| h.name == "text.strikethrough"),
+            Slot::DiffAdd => HIGHLIGHTS.iter().position(|h| h.name == "diff.addition"),
+            Slot::DiffDelete => HIGHLIGHTS.iter().position(|h| h.name == "diff.deletion"),
+            Slot::Embedded => HIGHLIGHTS.iter().position(|h| h.name == "embedded"),
+            Slot::Error => HIGHLIGHTS.iter().position(|h| h.name == "error"),
+        };
+        index.expect("every slot has a highlight definition")
+    }
+}
+
+impl From<Slot> for ThemeSlot {
+    fn from(slot: Slot) -> Self {
+        match slot {
+            Slot::Keyword => ThemeSlot::Keyword,
+            Slot::Function => ThemeSlot::Function,
+            Slot::String => ThemeSlot::String,
+            Slot::Comment => ThemeSlot::Comment,
+            Slot::Type => ThemeSlot::Type,
+            Slot::Variable => ThemeSlot::Variable,
+            Slot::Constant => ThemeSlot::Constant,
+            Slot::Number => ThemeSlot::Number,
+            Slot::Operator => ThemeSlot::Operator,
+            Slot::Punctuation => ThemeSlot::Punctuation,
+            Slot::Property => ThemeSlot::Property,
+            Slot::Attribute => ThemeSlot::Attribute,
+            Slot::Tag => ThemeSlot::Tag,
+            Slot::Macro => ThemeSlot::Macro,
+            Slot::Label => ThemeSlot::Label,
+            Slot::Namespace => ThemeSlot::Namespace,
+            Slot::Constructor => ThemeSlot::Constructor,
+            Slot::Title => ThemeSlot::Title,
+            Slot::Strong => ThemeSlot::Strong,
+            Slot::Emphasis => ThemeSlot::Emphasis,
+            Slot::Link => ThemeSlot::Link,
+            Slot::Literal => ThemeSlot::Literal,
+            Slot::Strikethrough => ThemeSlot::Strikethrough,
+            Slot::DiffAdd => ThemeSlot::DiffAdd,
+            Slot::DiffDelete => ThemeSlot::DiffDelete,
+            Slot::Embedded => ThemeSlot::Embedded,
+            Slot::Error => ThemeSlot::Error,
+        }
+    }
+}
+
+/// The theme slots, including a `None` slot for unstyled captures.
+///
+/// Prefer [`Slot`], which uses `Option<Slot>` instead of a `None` variant.
+/// This type is kept for compatibility.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ThemeSlot {
     Keyword,
@@ -68,85 +480,50 @@ pub enum ThemeSlot {
 }
 
 impl ThemeSlot {
-    /// Get the HTML tag suffix for this slot.
-    /// Returns None for slots that produce no styling.
-    pub fn tag(self) -> Option<&'static str> {
+    /// Get the typed [`Slot`], or `None` for [`ThemeSlot::None`].
+    pub fn slot(self) -> Option<Slot> {
         match self {
-            ThemeSlot::Keyword => Some("k"),
-            ThemeSlot::Function => Some("f"),
-            ThemeSlot::String => Some("s"),
-            ThemeSlot::Comment => Some("c"),
-            ThemeSlot::Type => Some("t"),
-            ThemeSlot::Variable => Some("v"),
-            ThemeSlot::Constant => Some("co"),
-            ThemeSlot::Number => Some("n"),
-            ThemeSlot::Operator => Some("o"),
-            ThemeSlot::Punctuation => Some("p"),
-            ThemeSlot::Property => Some("pr"),
-            ThemeSlot::Attribute => Some("at"),
-            ThemeSlot::Tag => Some("tg"),
-            ThemeSlot::Macro => Some("m"),
-            ThemeSlot::Label => Some("l"),
-            ThemeSlot::Namespace => Some("ns"),
-            ThemeSlot::Constructor => Some("cr"),
-            // Markup: headings, titles
-            ThemeSlot::Title => Some("tt"),
-            // Markup: bold text
-            ThemeSlot::Strong => Some("st"),
-            // Markup: italic text
-            ThemeSlot::Emphasis => Some("em"),
-            // Markup: links/URLs
-            ThemeSlot::Link => Some("tu"),
-            // Markup: raw/literal/code blocks
-            ThemeSlot::Literal => Some("tl"),
-            // Markup: strikethrough
-            ThemeSlot::Strikethrough => Some("tx"),
-            // Diff additions
-            ThemeSlot::DiffAdd => Some("da"),
-            // Diff deletions
-            ThemeSlot::DiffDelete => Some("dd"),
-            // Embedded content
-            ThemeSlot::Embedded => Some("eb"),
-            // Errors
-            ThemeSlot::Error => Some("er"),
-            // No styling (invisible captures like spell, nospell)
+            ThemeSlot::Keyword => Some(Slot::Keyword),
+            ThemeSlot::Function => Some(Slot::Function),
+            ThemeSlot::String => Some(Slot::String),
+            ThemeSlot::Comment => Some(Slot::Comment),
+            ThemeSlot::Type => Some(Slot::Type),
+            ThemeSlot::Variable => Some(Slot::Variable),
+            ThemeSlot::Constant => Some(Slot::Constant),
+            ThemeSlot::Number => Some(Slot::Number),
+            ThemeSlot::Operator => Some(Slot::Operator),
+            ThemeSlot::Punctuation => Some(Slot::Punctuation),
+            ThemeSlot::Property => Some(Slot::Property),
+            ThemeSlot::Attribute => Some(Slot::Attribute),
+            ThemeSlot::Tag => Some(Slot::Tag),
+            ThemeSlot::Macro => Some(Slot::Macro),
+            ThemeSlot::Label => Some(Slot::Label),
+            ThemeSlot::Namespace => Some(Slot::Namespace),
+            ThemeSlot::Constructor => Some(Slot::Constructor),
+            ThemeSlot::Title => Some(Slot::Title),
+            ThemeSlot::Strong => Some(Slot::Strong),
+            ThemeSlot::Emphasis => Some(Slot::Emphasis),
+            ThemeSlot::Link => Some(Slot::Link),
+            ThemeSlot::Literal => Some(Slot::Literal),
+            ThemeSlot::Strikethrough => Some(Slot::Strikethrough),
+            ThemeSlot::DiffAdd => Some(Slot::DiffAdd),
+            ThemeSlot::DiffDelete => Some(Slot::DiffDelete),
+            ThemeSlot::Embedded => Some(Slot::Embedded),
+            ThemeSlot::Error => Some(Slot::Error),
             ThemeSlot::None => None,
         }
     }
 
+    /// Get the HTML tag suffix for this slot.
+    /// Returns None for slots that produce no styling.
+    pub fn tag(self) -> Option<&'static str> {
+        self.slot().map(Slot::tag)
+    }
+
     /// Get the full name for this slot (for CSS class names).
     /// Returns None for slots that produce no styling.
     pub fn name(self) -> Option<&'static str> {
-        match self {
-            ThemeSlot::Keyword => Some("keyword"),
-            ThemeSlot::Function => Some("function"),
-            ThemeSlot::String => Some("string"),
-            ThemeSlot::Comment => Some("comment"),
-            ThemeSlot::Type => Some("type"),
-            ThemeSlot::Variable => Some("variable"),
-            ThemeSlot::Constant => Some("constant"),
-            ThemeSlot::Number => Some("number"),
-            ThemeSlot::Operator => Some("operator"),
-            ThemeSlot::Punctuation => Some("punctuation"),
-            ThemeSlot::Property => Some("property"),
-            ThemeSlot::Attribute => Some("attribute"),
-            ThemeSlot::Tag => Some("tag"),
-            ThemeSlot::Macro => Some("macro"),
-            ThemeSlot::Label => Some("label"),
-            ThemeSlot::Namespace => Some("namespace"),
-            ThemeSlot::Constructor => Some("constructor"),
-            ThemeSlot::Title => Some("title"),
-            ThemeSlot::Strong => Some("strong"),
-            ThemeSlot::Emphasis => Some("emphasis"),
-            ThemeSlot::Link => Some("link"),
-            ThemeSlot::Literal => Some("literal"),
-            ThemeSlot::Strikethrough => Some("strikethrough"),
-            ThemeSlot::DiffAdd => Some("diff-add"),
-            ThemeSlot::DiffDelete => Some("diff-delete"),
-            ThemeSlot::Embedded => Some("embedded"),
-            ThemeSlot::Error => Some("error"),
-            ThemeSlot::None => None,
-        }
+        self.slot().map(Slot::css_name)
     }
 }
 
@@ -155,46 +532,7 @@ impl ThemeSlot {
 /// This is useful for ANSI rendering, where we want to
 /// look up a single representative style for each slot.
 pub fn slot_to_highlight_index(slot: ThemeSlot) -> Option<usize> {
-    match slot {
-        ThemeSlot::Keyword => HIGHLIGHTS.iter().position(|h| h.name == "keyword"),
-        ThemeSlot::Function => HIGHLIGHTS.iter().position(|h| h.name == "function"),
-        ThemeSlot::String => HIGHLIGHTS.iter().position(|h| h.name == "string"),
-        ThemeSlot::Comment => HIGHLIGHTS.iter().position(|h| h.name == "comment"),
-        ThemeSlot::Type => HIGHLIGHTS.iter().position(|h| h.name == "type"),
-        ThemeSlot::Variable => HIGHLIGHTS.iter().position(|h| h.name == "variable"),
-        ThemeSlot::Constant => HIGHLIGHTS.iter().position(|h| h.name == "constant"),
-        ThemeSlot::Number => HIGHLIGHTS.iter().position(|h| h.name == "number"),
-        ThemeSlot::Operator => HIGHLIGHTS.iter().position(|h| h.name == "operator"),
-        ThemeSlot::Punctuation => HIGHLIGHTS.iter().position(|h| h.name == "punctuation"),
-        ThemeSlot::Property => HIGHLIGHTS.iter().position(|h| h.name == "property"),
-        ThemeSlot::Attribute => HIGHLIGHTS.iter().position(|h| h.name == "attribute"),
-        ThemeSlot::Tag => HIGHLIGHTS.iter().position(|h| h.name == "tag"),
-        ThemeSlot::Macro => HIGHLIGHTS.iter().position(|h| h.name == "macro"),
-        ThemeSlot::Label => HIGHLIGHTS.iter().position(|h| h.name == "label"),
-        ThemeSlot::Namespace => HIGHLIGHTS.iter().position(|h| h.name == "namespace"),
-        ThemeSlot::Constructor => HIGHLIGHTS.iter().position(|h| h.name == "constructor"),
-        ThemeSlot::Title => HIGHLIGHTS
-            .iter()
-            .position(|h| h.name == "text.title" || h.name == "markup.heading"),
-        ThemeSlot::Strong => HIGHLIGHTS
-            .iter()
-            .position(|h| h.name == "text.strong" || h.name == "markup.bold"),
-        ThemeSlot::Emphasis => HIGHLIGHTS
-            .iter()
-            .position(|h| h.name == "text.emphasis" || h.name == "markup.italic"),
-        ThemeSlot::Link => HIGHLIGHTS
-            .iter()
-            .position(|h| h.name == "text.uri" || h.name == "text.reference"),
-        ThemeSlot::Literal => HIGHLIGHTS.iter().position(|h| h.name == "text.literal"),
-        ThemeSlot::Strikethrough => HIGHLIGHTS
-            .iter()
-            .position(|h| h.name == "text.strikethrough"),
-        ThemeSlot::DiffAdd => HIGHLIGHTS.iter().position(|h| h.name == "diff.addition"),
-        ThemeSlot::DiffDelete => HIGHLIGHTS.iter().position(|h| h.name == "diff.deletion"),
-        ThemeSlot::Embedded => HIGHLIGHTS.iter().position(|h| h.name == "embedded"),
-        ThemeSlot::Error => HIGHLIGHTS.iter().position(|h| h.name == "error"),
-        ThemeSlot::None => None,
-    }
+    slot.slot().map(Slot::highlight_index)
 }
 
 /// Map any capture name to its theme slot.
@@ -205,158 +543,81 @@ pub fn slot_to_highlight_index(slot: ThemeSlot) -> Option<usize> {
 /// - Helix names
 /// - Sub-categories (keyword.function, keyword.import, etc.)
 ///
-/// All are mapped to a fixed set of ~20 theme slots.
+/// All are mapped to a fixed set of ~20 theme slots. Prefer
+/// [`Slot::from_capture`], which returns `None` instead of
+/// [`ThemeSlot::None`] for unstyled captures.
 pub fn capture_to_slot(capture: &str) -> ThemeSlot {
-    // First, strip any leading @ (some queries include it)
-    let capture = capture.strip_prefix('@').unwrap_or(capture);
-
-    match capture {
-        // Keywords - base and all variants
-        "keyword" | "keyword.conditional" | "keyword.coroutine" | "keyword.debug"
-        | "keyword.exception" | "keyword.function" | "keyword.import" | "keyword.operator"
-        | "keyword.repeat" | "keyword.return" | "keyword.type" | "keyword.modifier"
-        | "keyword.directive" | "keyword.storage" | "keyword.control"
-        | "keyword.control.conditional" | "keyword.control.repeat" | "keyword.control.import"
-        | "keyword.control.return" | "keyword.control.exception"
-        // nvim-treesitter legacy names that are really keywords
-        | "include" | "conditional" | "repeat" | "exception" | "storageclass" | "preproc"
-        | "define" | "structure" => ThemeSlot::Keyword,
-
-        // Functions
-        "function" | "function.builtin" | "function.method" | "function.definition"
-        | "function.call" | "function.special" | "method" | "method.call" => ThemeSlot::Function,
-
-        // Strings
-        "string" | "string.special" | "string.special.symbol" | "string.special.path"
-        | "string.special.url" | "string.escape" | "string.regexp" | "string.regex"
-        | "character" | "character.special" | "escape" => ThemeSlot::String,
-
-        // Comments
-        "comment" | "comment.documentation" | "comment.line" | "comment.block"
-        | "comment.error" | "comment.warning" | "comment.note" | "comment.todo" => {
-            ThemeSlot::Comment
-        }
-
-        // Types
-        "type" | "type.builtin" | "type.qualifier" | "type.definition" | "type.enum"
-        | "type.enum.variant" | "type.parameter" => ThemeSlot::Type,
-
-        // Variables
-        "variable" | "variable.builtin" | "variable.parameter" | "variable.member"
-        | "variable.other" | "variable.other.member" | "parameter" | "field" => {
-            ThemeSlot::Variable
-        }
-
-        // Constants
-        "constant" | "constant.builtin" | "constant.builtin.boolean" | "boolean" => {
-            ThemeSlot::Constant
-        }
-
-        // Numbers
-        "number" | "constant.numeric" | "float" | "number.float" => ThemeSlot::Number,
-
-        // Operators
-        "operator" => ThemeSlot::Operator,
-
-        // Punctuation
-        "punctuation" | "punctuation.bracket" | "punctuation.delimiter" | "punctuation.special" => {
-            ThemeSlot::Punctuation
-        }
-
-        // Properties
-        "property" | "property.builtin" => ThemeSlot::Property,
-
-        // Attributes
-        "attribute" | "attribute.builtin" => ThemeSlot::Attribute,
-
-        // Tags (HTML/XML)
-        "tag" | "tag.delimiter" | "tag.error" | "tag.attribute" | "tag.builtin" => ThemeSlot::Tag,
-
-        // Macros
-        "macro" | "function.macro" | "preproc.macro" => ThemeSlot::Macro,
-
-        // Labels
-        "label" => ThemeSlot::Label,
-
-        // Namespaces/Modules
-        "namespace" | "module" => ThemeSlot::Namespace,
-
-        // Constructors
-        "constructor" | "constructor.builtin" => ThemeSlot::Constructor,
-
-        // Markup - titles/headings
-        "text.title" | "markup.heading" | "markup.heading.1" | "markup.heading.2"
-        | "markup.heading.3" | "markup.heading.4" | "markup.heading.5" | "markup.heading.6" => {
-            ThemeSlot::Title
-        }
-
-        // Markup - bold
-        "text.strong" | "markup.bold" => ThemeSlot::Strong,
-
-        // Markup - italic
-        "text.emphasis" | "markup.italic" => ThemeSlot::Emphasis,
-
-        // Markup - links
-        "text.uri" | "text.reference" | "markup.link" | "markup.link.url" | "markup.link.text"
-        | "markup.link.label" => ThemeSlot::Link,
+    Slot::from_capture(capture).map_or(ThemeSlot::None, ThemeSlot::from)
+}
 
-        // Markup - literal/raw/code
-        "text.literal" | "markup.raw" | "markup.raw.block" | "markup.raw.inline"
-        | "markup.inline" => ThemeSlot::Literal,
+/// Capture aliases registered with [`register_capture_alias`].
+static CAPTURE_ALIASES: OnceLock<RwLock<HashMap<String, Slot>>> = OnceLock::new();
 
-        // Markup - strikethrough
-        "text.strikethrough" | "markup.strikethrough" => ThemeSlot::Strikethrough,
+/// Map `capture` to `slot` everywhere, for grammars with capture names of
+/// their own.
+///
+/// `slot` is a slot name (as in [`Slot::css_name`]) or any capture name
+/// with a built-in slot, like `"keyword.function"`. Returns the slot the
+/// capture maps to from now on, or `None` if `slot` names none.
+///
+/// Registration is append-only: built-in mappings can't be overridden, and
+/// the first alias registered for a capture is kept, so that the slot a
+/// capture maps to never changes once it has one. In either case the slot
+/// already in effect is returned.
+///
+/// # Thread safety
+///
+/// Aliases live in a process-wide map behind a lock, so this can be called
+/// from any thread, but highlighting that's already under way may or may
+/// not see a new alias. Register aliases once at startup, before
+/// highlighting anything.
+///
+/// ```
+/// use arborium_theme::{Slot, register_capture_alias};
+///
+/// assert_eq!(register_capture_alias("@house.verb", "keyword"), Some(Slot::Keyword));
+/// assert_eq!(Slot::from_capture("house.verb"), Some(Slot::Keyword));
+/// ```
+pub fn register_capture_alias(capture: &str, slot: &str) -> Option<Slot> {
+    let slot = Slot::ALL
+        .iter()
+        .copied()
+        .find(|s| s.css_name() == slot)
+        .or_else(|| Slot::builtin_from_capture(slot.strip_prefix('@').unwrap_or(slot)))?;
+    let capture = capture.strip_prefix('@').unwrap_or(capture);
+    if let Some(builtin) = Slot::builtin_from_capture(capture) {
+        return Some(builtin);
+    }
+    let mut aliases = CAPTURE_ALIASES
+        .get_or_init(Default::default)
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    Some(*aliases.entry(capture.to_string()).or_insert(slot))
+}
 
-        // Markup - lists (treat as punctuation)
-        "markup.list" | "markup.list.checked" | "markup.list.unchecked"
-        | "markup.list.numbered" | "markup.list.unnumbered" | "markup.quote" => {
-            ThemeSlot::Punctuation
-        }
+/// The slot registered for `capture`, which has no leading `@`.
+fn registered_alias(capture: &str) -> Option<Slot> {
+    CAPTURE_ALIASES
+        .get()?
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(capture)
+        .copied()
+}
 
-        // Diff
-        "diff.addition" | "diff.plus" | "diff.delta" => ThemeSlot::DiffAdd,
-        "diff.deletion" | "diff.minus" => ThemeSlot::DiffDelete,
-
-        // Embedded
-        "embedded" => ThemeSlot::Embedded,
-
-        // Error
-        "error" => ThemeSlot::Error,
-
-        // No styling
-        "none" | "nospell" | "spell" | "text" | "markup" => ThemeSlot::None,
-
-        // Fallback: try to match by prefix
-        other => {
-            if other.starts_with("keyword") {
-                ThemeSlot::Keyword
-            } else if other.starts_with("function") || other.starts_with("method") {
-                ThemeSlot::Function
-            } else if other.starts_with("string") || other.starts_with("character") {
-                ThemeSlot::String
-            } else if other.starts_with("comment") {
-                ThemeSlot::Comment
-            } else if other.starts_with("type") {
-                ThemeSlot::Type
-            } else if other.starts_with("variable") || other.starts_with("parameter") {
-                ThemeSlot::Variable
-            } else if other.starts_with("constant") {
-                ThemeSlot::Constant
-            } else if other.starts_with("punctuation") {
-                ThemeSlot::Punctuation
-            } else if other.starts_with("tag") {
-                ThemeSlot::Tag
-            } else if other.starts_with("markup.heading") || other.starts_with("text.title") {
-                ThemeSlot::Title
-            } else if other.starts_with("markup") || other.starts_with("text") {
-                // Generic markup/text - no styling
-                ThemeSlot::None
-            } else {
-                // Unknown capture - no styling
-                ThemeSlot::None
-            }
-        }
-    }
+/// Returns `true` if two capture names map to the same theme slot, i.e. they
+/// are always rendered with the same style.
+///
+/// Two unstyled captures (both [`ThemeSlot::None`]) count as the same slot.
+///
+/// ```
+/// use arborium_theme::same_slot;
+///
+/// assert!(same_slot("keyword.function", "keyword"));
+/// assert!(!same_slot("keyword", "string"));
+/// ```
+pub fn same_slot(a: &str, b: &str) -> bool {
+    capture_to_slot(a) == capture_to_slot(b)
 }
 
 /// A highlight category definition.
@@ -819,8 +1080,68 @@ pub const HIGHLIGHTS: &[HighlightDef] = &[
         parent_tag: "",
         aliases: &[],
     }, // Same as constant.builtin
+    HighlightDef {
+        name: "keyword.control",
+        tag: "kx",
+        parent_tag: "k",
+        aliases: &[],
+    },
 ];
 
+/// Subcategories that themes can style apart from their slot, e.g. to color
+/// control flow keywords differently from `fn` or `let`.
+///
+/// Renderers only keep them apart when asked to, using
+/// [`extended_highlight_index`]; otherwise they render as their slot.
+pub const CAPTURE_NAMES_EXTENDED: &[&str] = &[
+    "keyword.control",
+    "keyword.import",
+    "function.builtin",
+    "variable.parameter",
+];
+
+/// Highlight index of `capture` with the [`CAPTURE_NAMES_EXTENDED`]
+/// subcategories kept apart, or `None` for unstyled captures.
+///
+/// Captures under an extended subcategory (`keyword.control.return`) get its
+/// index, as do the nvim-treesitter names for the same thing (`conditional`,
+/// `keyword.repeat`, `include`, ...). Other captures get the index of their
+/// slot, like [`Slot::highlight_index`]. Use
+/// [`Theme::resolve_index`](crate::Theme::resolve_index) to fall back to the
+/// slot's style when a theme doesn't style a subcategory.
+///
+/// ```
+/// use arborium_theme::{HIGHLIGHTS, Slot, extended_highlight_index};
+///
+/// let index = extended_highlight_index("keyword.control.return").unwrap();
+/// assert_eq!(HIGHLIGHTS[index].name, "keyword.control");
+/// assert_eq!(
+///     extended_highlight_index("keyword.function"),
+///     Some(Slot::Keyword.highlight_index())
+/// );
+/// ```
+pub fn extended_highlight_index(capture: &str) -> Option<usize> {
+    let slot = Slot::from_capture(capture)?;
+    let capture = capture.strip_prefix('@').unwrap_or(capture);
+    let extended = match capture {
+        "keyword.conditional" | "keyword.repeat" | "keyword.return" | "conditional" | "repeat" => {
+            Some("keyword.control")
+        }
+        "include" => Some("keyword.import"),
+        "parameter" => Some("variable.parameter"),
+        _ => CAPTURE_NAMES_EXTENDED.iter().copied().find(|name| {
+            capture
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        }),
+    };
+    Some(
+        extended
+            .and_then(|name| HIGHLIGHTS.iter().position(|h| h.name == name))
+            .unwrap_or_else(|| slot.highlight_index()),
+    )
+}
+
 /// Get the highlight names array for tree-sitter configuration.
 pub const fn names() -> [&'static str; HIGHLIGHTS.len()] {
     let mut names = [""; HIGHLIGHTS.len()];
@@ -911,7 +1232,7 @@ pub fn css_inheritance_rules() -> String {
 /// assert_eq!(tag_for_capture("nospell"), None);
 /// ```
 pub fn tag_for_capture(capture: &str) -> Option<&'static str> {
-    capture_to_slot(capture).tag()
+    Slot::from_capture(capture).map(Slot::tag)
 }
 
 /// Map a short tag to its full name.
@@ -928,36 +1249,7 @@ pub fn tag_for_capture(capture: &str) -> Option<&'static str> {
 /// assert_eq!(tag_to_name("s"), Some("string"));
 /// ```
 pub fn tag_to_name(tag: &str) -> Option<&'static str> {
-    match tag {
-        "k" => Some("keyword"),
-        "f" => Some("function"),
-        "s" => Some("string"),
-        "c" => Some("comment"),
-        "t" => Some("type"),
-        "v" => Some("variable"),
-        "co" => Some("constant"),
-        "n" => Some("number"),
-        "o" => Some("operator"),
-        "p" => Some("punctuation"),
-        "pr" => Some("property"),
-        "at" => Some("attribute"),
-        "tg" => Some("tag"),
-        "m" => Some("macro"),
-        "l" => Some("label"),
-        "ns" => Some("namespace"),
-        "cr" => Some("constructor"),
-        "tt" => Some("title"),
-        "st" => Some("strong"),
-        "em" => Some("emphasis"),
-        "tu" => Some("link"),
-        "tl" => Some("literal"),
-        "tx" => Some("strikethrough"),
-        "da" => Some("diff-add"),
-        "dd" => Some("diff-delete"),
-        "eb" => Some("embedded"),
-        "er" => Some("error"),
-        _ => None,
-    }
+    Slot::from_tag(tag).map(Slot::css_name)
 }
 
 /// The complete list of capture names that arborium recognizes.
@@ -1141,6 +1433,34 @@ pub const CAPTURE_NAMES: &[&str] = &[
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_same_slot() {
+        assert!(same_slot("keyword.function", "keyword"));
+        assert!(same_slot("include", "keyword.import"));
+        assert!(!same_slot("keyword", "string"));
+        assert!(same_slot("spell", "nospell"));
+    }
+
+    #[test]
+    fn test_capture_alias_is_append_only() {
+        assert_eq!(
+            register_capture_alias("test.first.wins", "string"),
+            Some(Slot::String)
+        );
+        assert_eq!(
+            register_capture_alias("test.first.wins", "keyword"),
+            Some(Slot::String)
+        );
+        assert_eq!(Slot::from_capture("test.first.wins"), Some(Slot::String));
+
+        // Built-in mappings stay in effect
+        assert_eq!(
+            register_capture_alias("keyword", "string"),
+            Some(Slot::Keyword)
+        );
+        assert_eq!(Slot::from_capture("keyword"), Some(Slot::Keyword));
+    }
+
     #[test]
     fn test_names_count() {
         assert_eq!(names().len(), COUNT);
@@ -1244,4 +1564,218 @@ mod tests {
             let _ = slot.tag();
         }
     }
+
+    /// Tag of every capture name, and of names only matched by prefix, as
+    /// mapped before [`Slot`] existed.
+    const BASELINE_TAGS: &[(&str, Option<&str>)] = &[
+        ("keyword", Some("k")),
+        ("keyword.conditional", Some("k")),
+        ("keyword.coroutine", Some("k")),
+        ("keyword.debug", Some("k")),
+        ("keyword.exception", Some("k")),
+        ("keyword.function", Some("k")),
+        ("keyword.import", Some("k")),
+        ("keyword.operator", Some("k")),
+        ("keyword.repeat", Some("k")),
+        ("keyword.return", Some("k")),
+        ("keyword.type", Some("k")),
+        ("keyword.modifier", Some("k")),
+        ("keyword.directive", Some("k")),
+        ("keyword.storage", Some("k")),
+        ("keyword.control", Some("k")),
+        ("keyword.control.conditional", Some("k")),
+        ("keyword.control.repeat", Some("k")),
+        ("keyword.control.import", Some("k")),
+        ("keyword.control.return", Some("k")),
+        ("keyword.control.exception", Some("k")),
+        ("include", Some("k")),
+        ("conditional", Some("k")),
+        ("repeat", Some("k")),
+        ("exception", Some("k")),
+        ("storageclass", Some("k")),
+        ("preproc", Some("k")),
+        ("define", Some("k")),
+        ("structure", Some("k")),
+        ("function", Some("f")),
+        ("function.builtin", Some("f")),
+        ("function.method", Some("f")),
+        ("function.definition", Some("f")),
+        ("function.call", Some("f")),
+        ("function.macro", Some("m")),
+        ("function.special", Some("f")),
+        ("method", Some("f")),
+        ("method.call", Some("f")),
+        ("string", Some("s")),
+        ("string.special", Some("s")),
+        ("string.special.symbol", Some("s")),
+        ("string.special.path", Some("s")),
+        ("string.special.url", Some("s")),
+        ("string.escape", Some("s")),
+        ("string.regexp", Some("s")),
+        ("string.regex", Some("s")),
+        ("character", Some("s")),
+        ("character.special", Some("s")),
+        ("escape", Some("s")),
+        ("comment", Some("c")),
+        ("comment.documentation", Some("c")),
+        ("comment.line", Some("c")),
+        ("comment.block", Some("c")),
+        ("comment.error", Some("c")),
+        ("comment.warning", Some("c")),
+        ("comment.note", Some("c")),
+        ("comment.todo", Some("c")),
+        ("type", Some("t")),
+        ("type.builtin", Some("t")),
+        ("type.qualifier", Some("t")),
+        ("type.definition", Some("t")),
+        ("type.enum", Some("t")),
+        ("type.enum.variant", Some("t")),
+        ("type.parameter", Some("t")),
+        ("variable", Some("v")),
+        ("variable.builtin", Some("v")),
+        ("variable.parameter", Some("v")),
+        ("variable.member", Some("v")),
+        ("variable.other", Some("v")),
+        ("variable.other.member", Some("v")),
+        ("parameter", Some("v")),
+        ("field", Some("v")),
+        ("constant", Some("co")),
+        ("constant.builtin", Some("co")),
+        ("constant.builtin.boolean", Some("co")),
+        ("constant.numeric", Some("n")),
+        ("boolean", Some("co")),
+        ("number", Some("n")),
+        ("float", Some("n")),
+        ("number.float", Some("n")),
+        ("operator", Some("o")),
+        ("punctuation", Some("p")),
+        ("punctuation.bracket", Some("p")),
+        ("punctuation.delimiter", Some("p")),
+        ("punctuation.special", Some("p")),
+        ("property", Some("pr")),
+        ("property.builtin", Some("pr")),
+        ("attribute", Some("at")),
+        ("attribute.builtin", Some("at")),
+        ("tag", Some("tg")),
+        ("tag.delimiter", Some("tg")),
+        ("tag.error", Some("tg")),
+        ("tag.attribute", Some("tg")),
+        ("tag.builtin", Some("tg")),
+        ("macro", Some("m")),
+        ("label", Some("l")),
+        ("namespace", Some("ns")),
+        ("module", Some("ns")),
+        ("constructor", Some("cr")),
+        ("constructor.builtin", Some("cr")),
+        ("text.title", Some("tt")),
+        ("markup.heading", Some("tt")),
+        ("markup.heading.1", Some("tt")),
+        ("markup.heading.2", Some("tt")),
+        ("markup.heading.3", Some("tt")),
+        ("markup.heading.4", Some("tt")),
+        ("markup.heading.5", Some("tt")),
+        ("markup.heading.6", Some("tt")),
+        ("text.strong", Some("st")),
+        ("markup.bold", Some("st")),
+        ("text.emphasis", Some("em")),
+        ("markup.italic", Some("em")),
+        ("text.uri", Some("tu")),
+        ("text.reference", Some("tu")),
+        ("markup.link", Some("tu")),
+        ("markup.link.url", Some("tu")),
+        ("markup.link.text", Some("tu")),
+        ("markup.link.label", Some("tu")),
+        ("text.literal", Some("tl")),
+        ("markup.raw", Some("tl")),
+        ("markup.raw.block", Some("tl")),
+        ("markup.raw.inline", Some("tl")),
+        ("markup.inline", Some("tl")),
+        ("text.strikethrough", Some("tx")),
+        ("markup.strikethrough", Some("tx")),
+        ("markup.list", Some("p")),
+        ("markup.list.checked", Some("p")),
+        ("markup.list.unchecked", Some("p")),
+        ("markup.list.numbered", Some("p")),
+        ("markup.list.unnumbered", Some("p")),
+        ("markup.quote", Some("p")),
+        ("text", None),
+        ("markup", None),
+        ("diff.addition", Some("da")),
+        ("diff.plus", Some("da")),
+        ("diff.delta", Some("da")),
+        ("diff.deletion", Some("dd")),
+        ("diff.minus", Some("dd")),
+        ("embedded", Some("eb")),
+        ("error", Some("er")),
+        ("none", None),
+        ("nospell", None),
+        ("spell", None),
+        ("@keyword", Some("k")),
+        ("@function.method", Some("f")),
+        ("keyword.custom", Some("k")),
+        ("keyword.control.flow", Some("k")),
+        ("function.custom", Some("f")),
+        ("string.special.custom", Some("s")),
+        ("markup.heading.7", Some("tt")),
+        ("markup.custom", None),
+        ("comment.custom", Some("c")),
+        ("type.custom", Some("t")),
+        ("variable.custom", Some("v")),
+        ("punctuation.custom", Some("p")),
+        ("unknown", None),
+        ("", None),
+    ];
+
+    #[test]
+    fn test_slot_matches_baseline_tags() {
+        for &name in CAPTURE_NAMES {
+            assert!(
+                BASELINE_TAGS.iter().any(|&(baseline, _)| baseline == name),
+                "{name} is missing from the baseline table"
+            );
+        }
+        for &(name, tag) in BASELINE_TAGS {
+            assert_eq!(Slot::from_capture(name).map(Slot::tag), tag, "{name}");
+            assert_eq!(tag_for_capture(name), tag, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_slot_matches_theme_slot_for_all_captures() {
+        let extra = [
+            "@keyword",
+            "keyword.custom",
+            "markup.heading.7",
+            "unknown",
+            "",
+        ];
+        for name in CAPTURE_NAMES.iter().copied().chain(extra) {
+            let slot = Slot::from_capture(name);
+            let theme_slot = capture_to_slot(name);
+            assert_eq!(
+                slot.map(ThemeSlot::from).unwrap_or(ThemeSlot::None),
+                theme_slot
+            );
+            assert_eq!(slot.map(Slot::tag), theme_slot.tag(), "{name}");
+            assert_eq!(slot.map(Slot::css_name), theme_slot.name(), "{name}");
+            assert_eq!(
+                slot.map(Slot::highlight_index),
+                slot_to_highlight_index(theme_slot),
+                "{name}"
+            );
+            assert_eq!(slot.map(Slot::tag), tag_for_capture(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_slot_tag_round_trip() {
+        for &slot in Slot::ALL {
+            assert_eq!(Slot::from_tag(slot.tag()), Some(slot));
+            assert_eq!(tag_to_name(slot.tag()), Some(slot.css_name()));
+            assert_eq!(ThemeSlot::from(slot).slot(), Some(slot));
+            // The representative style uses the slot's own tag
+            assert_eq!(tag(slot.highlight_index()), Some(slot.tag()));
+        }
+        assert_eq!(tag_to_name("nope"), None);
+    }
 }
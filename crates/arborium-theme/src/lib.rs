@@ -11,14 +11,15 @@
 //!
 //! The crate provides a unified system for mapping the many capture names from
 //! various sources (nvim-treesitter, helix, etc.) to a small set of theme slots.
-//! See [`highlights::capture_to_slot`] and [`highlights::tag_for_capture`] for details.
+//! See [`Slot::from_capture`] and [`highlights::tag_for_capture`] for details.
 
 pub mod highlights;
 pub mod theme;
 
 pub use highlights::{
-    CAPTURE_NAMES, COUNT, HIGHLIGHTS, HighlightDef, ThemeSlot, capture_to_slot,
+    CAPTURE_NAMES, CAPTURE_NAMES_EXTENDED, COUNT, HIGHLIGHTS, HighlightDef, Slot, ThemeSlot,
+    capture_to_slot, extended_highlight_index, register_capture_alias, same_slot,
     slot_to_highlight_index, tag_for_capture, tag_to_name,
 };
 
-pub use theme::{Color, Modifiers, Style, Theme, ThemeError, builtin};
+pub use theme::{Ansi16Color, Color, ColorDepth, Modifiers, Style, Theme, ThemeError, builtin};
@@ -74,6 +74,110 @@ impl Color {
             b: (self.b as f32 * (1.0 - factor)).round() as u8,
         }
     }
+
+    /// Blend toward `other` by a factor (0.0 keeps this color, 1.0 gives `other`).
+    pub fn lerp(&self, other: Color, factor: f32) -> Self {
+        let factor = factor.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * factor).round() as u8;
+        Self {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+        }
+    }
+}
+
+/// How many colors ANSI output may use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ColorDepth {
+    /// 24-bit RGB colors (`\x1b[38;2;R;G;Bm`).
+    #[default]
+    TrueColor,
+    /// The 16 standard colors (`\x1b[3Nm` / `\x1b[9Nm`), whose actual
+    /// values are up to the terminal's palette. See [`Ansi16Color`].
+    Ansi16,
+}
+
+/// One of the 16 standard terminal colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ansi16Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+impl Ansi16Color {
+    /// The color that best matches `color` by name.
+    ///
+    /// Terminals pick their own values for these colors, so this matches on
+    /// hue and lightness rather than RGB distance to some reference palette:
+    /// a pastel purple becomes magenta rather than the gray it is closest to.
+    /// Colors with little saturation map to black, white, and their bright
+    /// variants by lightness.
+    pub fn nearest(color: Color) -> Self {
+        use Ansi16Color::*;
+
+        let (r, g, b) = (color.r as f32, color.g as f32, color.b as f32);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0 / 255.0;
+
+        if chroma < 40.0 {
+            return match lightness {
+                l if l < 0.25 => Black,
+                l if l < 0.5 => BrightBlack,
+                l if l < 0.85 => White,
+                _ => BrightWhite,
+            };
+        }
+
+        let hue = if max == r {
+            60.0 * ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / chroma + 2.0)
+        } else {
+            60.0 * ((r - g) / chroma + 4.0)
+        };
+        // Hue sectors centered on red (0°), yellow (60°), green (120°), ...
+        let (normal, bright) = match ((hue + 30.0) / 60.0) as u32 % 6 {
+            0 => (Red, BrightRed),
+            1 => (Yellow, BrightYellow),
+            2 => (Green, BrightGreen),
+            3 => (Cyan, BrightCyan),
+            4 => (Blue, BrightBlue),
+            _ => (Magenta, BrightMagenta),
+        };
+        if lightness > 0.6 { bright } else { normal }
+    }
+
+    /// SGR parameter for this color as a foreground (30–37, 90–97).
+    pub fn fg_code(self) -> u8 {
+        let index = self as u8;
+        if index < 8 {
+            30 + index
+        } else {
+            90 + index - 8
+        }
+    }
+
+    /// SGR parameter for this color as a background (40–47, 100–107).
+    pub fn bg_code(self) -> u8 {
+        self.fg_code() + 10
+    }
 }
 
 /// Text style modifiers.
@@ -156,6 +260,20 @@ pub struct Theme {
     pub background: Option<Color>,
     /// Foreground (default text) color.
     pub foreground: Option<Color>,
+    /// Color of the ANSI border, if the theme sets one (`ui.border`).
+    ///
+    /// See [`ansi_border_style`](Self::ansi_border_style) for the fallback.
+    pub border_color: Option<Color>,
+    /// Foreground of the line number gutter, if the theme sets one
+    /// (`ui.linenr`).
+    ///
+    /// See [`gutter_fg`](Self::gutter_fg) for the fallback.
+    pub gutter_fg: Option<Color>,
+    /// Background of the line number gutter, if the theme sets one
+    /// (`ui.linenr`).
+    ///
+    /// See [`gutter_bg`](Self::gutter_bg) for the fallback.
+    pub gutter_bg: Option<Color>,
     /// Styles for each highlight category, indexed by HIGHLIGHT_NAMES.
     pub styles: [Style; crate::highlights::COUNT],
 }
@@ -168,6 +286,9 @@ impl Default for Theme {
             source_url: None,
             background: None,
             foreground: None,
+            border_color: None,
+            gutter_fg: None,
+            gutter_bg: None,
             styles: std::array::from_fn(|_| Style::new()),
         }
     }
@@ -187,6 +308,23 @@ impl Theme {
         self.styles.get(index)
     }
 
+    /// Get the style for a slot.
+    pub fn style_for(&self, slot: crate::highlights::Slot) -> &Style {
+        &self.styles[slot.highlight_index()]
+    }
+
+    /// Index of the style to use for highlight `index`: `index` itself if
+    /// this theme styles it, otherwise its parent category's (e.g. `keyword`
+    /// for `keyword.control`).
+    pub fn resolve_index(&self, index: usize) -> usize {
+        use crate::highlights::{HIGHLIGHTS, Slot};
+
+        if self.styles.get(index).is_none_or(|style| !style.is_empty()) {
+            return index;
+        }
+        Slot::from_tag(HIGHLIGHTS[index].parent_tag).map_or(index, Slot::highlight_index)
+    }
+
     /// Set the style for a highlight index.
     pub fn set_style(&mut self, index: usize, style: Style) {
         if index < self.styles.len() {
@@ -264,6 +402,16 @@ impl Theme {
             theme.foreground = resolve_color(fg_str);
         }
 
+        // Border and gutter colors, as a plain color or an fg/bg table
+        if let Some(border) = table.get("ui.border") {
+            theme.border_color = parse_style_value(border, &resolve_color)?.fg;
+        }
+        if let Some(linenr) = table.get("ui.linenr") {
+            let style = parse_style_value(linenr, &resolve_color)?;
+            theme.gutter_fg = style.fg;
+            theme.gutter_bg = style.bg;
+        }
+
         // Build mapping from Helix names to our indices using highlights module
         use crate::highlights::HIGHLIGHTS;
 
@@ -439,41 +587,15 @@ impl Theme {
 
     /// Generate ANSI escape sequence for a style.
     pub fn ansi_style(&self, index: usize) -> String {
+        self.ansi_style_at(index, ColorDepth::TrueColor)
+    }
+
+    /// Like [`ansi_style`](Self::ansi_style), with colors at the given depth.
+    pub fn ansi_style_at(&self, index: usize, depth: ColorDepth) -> String {
         let Some(style) = self.styles.get(index) else {
             return String::new();
         };
-
-        if style.is_empty() {
-            return String::new();
-        }
-
-        let mut codes = Vec::new();
-
-        if style.modifiers.bold {
-            codes.push("1".to_string());
-        }
-        if style.modifiers.italic {
-            codes.push("3".to_string());
-        }
-        if style.modifiers.underline {
-            codes.push("4".to_string());
-        }
-        if style.modifiers.strikethrough {
-            codes.push("9".to_string());
-        }
-
-        if let Some(fg) = &style.fg {
-            codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
-        }
-        if let Some(bg) = &style.bg {
-            codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
-        }
-
-        if codes.is_empty() {
-            String::new()
-        } else {
-            format!("\x1b[{}m", codes.join(";"))
-        }
+        ansi_sequence(style, style.fg, style.bg, depth)
     }
 
     /// Generate ANSI escape sequence for a style, inheriting base foreground/background if not set.
@@ -484,48 +606,24 @@ impl Theme {
     /// disappearing when switching between styled and unstyled text, and ensures colors
     /// are complete.
     pub fn ansi_style_with_base_bg(&self, index: usize) -> String {
+        self.ansi_style_with_base_bg_at(index, ColorDepth::TrueColor)
+    }
+
+    /// Like [`ansi_style_with_base_bg`](Self::ansi_style_with_base_bg), with
+    /// colors at the given depth.
+    pub fn ansi_style_with_base_bg_at(&self, index: usize, depth: ColorDepth) -> String {
         let Some(style) = self.styles.get(index) else {
             return String::new();
         };
-
         if style.is_empty() {
             return String::new();
         }
-
-        let mut codes = Vec::new();
-
-        if style.modifiers.bold {
-            codes.push("1".to_string());
-        }
-        if style.modifiers.italic {
-            codes.push("3".to_string());
-        }
-        if style.modifiers.underline {
-            codes.push("4".to_string());
-        }
-        if style.modifiers.strikethrough {
-            codes.push("9".to_string());
-        }
-
-        // Use style's foreground if defined, otherwise fall back to theme foreground
-        if let Some(fg) = &style.fg {
-            codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
-        } else if let Some(fg) = &self.foreground {
-            codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
-        }
-
-        // Use style's background if defined, otherwise fall back to theme background
-        if let Some(bg) = &style.bg {
-            codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
-        } else if let Some(bg) = &self.background {
-            codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
-        }
-
-        if codes.is_empty() {
-            String::new()
-        } else {
-            format!("\x1b[{}m", codes.join(";"))
-        }
+        ansi_sequence(
+            style,
+            style.fg.or(self.foreground),
+            style.bg.or(self.background),
+            depth,
+        )
     }
 
     /// Generate ANSI escape sequence for the theme's base foreground/background.
@@ -533,27 +631,31 @@ impl Theme {
     /// This uses `background` and `foreground` and does not include any
     /// per-highlight styling or text modifiers.
     pub fn ansi_base_style(&self) -> String {
-        let mut codes = Vec::new();
-
-        if let Some(fg) = &self.foreground {
-            codes.push(format!("38;2;{};{};{}", fg.r, fg.g, fg.b));
-        }
-        if let Some(bg) = &self.background {
-            codes.push(format!("48;2;{};{};{}", bg.r, bg.g, bg.b));
-        }
+        self.ansi_base_style_at(ColorDepth::TrueColor)
+    }
 
-        if codes.is_empty() {
-            String::new()
-        } else {
-            format!("\x1b[{}m", codes.join(";"))
-        }
+    /// Like [`ansi_base_style`](Self::ansi_base_style), with colors at the
+    /// given depth.
+    pub fn ansi_base_style_at(&self, depth: ColorDepth) -> String {
+        ansi_sequence(&Style::new(), self.foreground, self.background, depth)
     }
 
     /// Generate ANSI escape sequence for border characters (half-blocks).
     ///
-    /// Returns fg color only (no bg), slightly darker/lighter than theme background.
+    /// Returns fg color only (no bg): the theme's
+    /// [`border_color`](Self::border_color), or if it has none, a color
+    /// slightly lighter/darker than the theme background.
     /// The transparent half of the half-block char shows the terminal background.
     pub fn ansi_border_style(&self) -> String {
+        self.ansi_border_style_at(ColorDepth::TrueColor)
+    }
+
+    /// Like [`ansi_border_style`](Self::ansi_border_style), with colors at
+    /// the given depth.
+    pub fn ansi_border_style_at(&self, depth: ColorDepth) -> String {
+        if let Some(border) = self.border_color {
+            return ansi_sequence(&Style::new(), Some(border), None, depth);
+        }
         let Some(bg) = &self.background else {
             return String::new();
         };
@@ -573,13 +675,107 @@ impl Theme {
             )
         };
 
-        format!("\x1b[38;2;{};{};{}m", border.r, border.g, border.b)
+        ansi_sequence(&Style::new(), Some(border), None, depth)
+    }
+
+    /// Foreground color for line numbers in the gutter.
+    ///
+    /// Defaults to the theme foreground, faded halfway towards the
+    /// background.
+    pub fn gutter_fg(&self) -> Option<Color> {
+        self.gutter_fg.or(match (self.foreground, self.background) {
+            (Some(fg), Some(bg)) => Some(fg.lerp(bg, 0.5)),
+            (fg, _) => fg,
+        })
+    }
+
+    /// Background color for the line number gutter.
+    ///
+    /// Defaults to the theme background.
+    pub fn gutter_bg(&self) -> Option<Color> {
+        self.gutter_bg.or(self.background)
+    }
+
+    /// Generate ANSI escape sequence for the line number gutter, using
+    /// [`gutter_fg`](Self::gutter_fg) and [`gutter_bg`](Self::gutter_bg).
+    pub fn ansi_gutter_style(&self) -> String {
+        self.ansi_gutter_style_at(ColorDepth::TrueColor)
+    }
+
+    /// Like [`ansi_gutter_style`](Self::ansi_gutter_style), with colors at
+    /// the given depth.
+    pub fn ansi_gutter_style_at(&self, depth: ColorDepth) -> String {
+        ansi_sequence(&Style::new(), self.gutter_fg(), self.gutter_bg(), depth)
+    }
+
+    /// The nearest of the 16 standard terminal colors for each slot's
+    /// foreground, indexed like [`styles`](Self::styles).
+    ///
+    /// Slots without a foreground get the color for the theme's default
+    /// foreground, or white (black for light themes) if it has none.
+    pub fn to_ansi16_map(&self) -> [Ansi16Color; crate::highlights::COUNT] {
+        let default = self.foreground.map_or(
+            if self.is_dark {
+                Ansi16Color::White
+            } else {
+                Ansi16Color::Black
+            },
+            Ansi16Color::nearest,
+        );
+        std::array::from_fn(|i| self.styles[i].fg.map_or(default, Ansi16Color::nearest))
     }
 
     /// ANSI reset sequence.
     pub const ANSI_RESET: &'static str = "\x1b[0m";
 }
 
+/// SGR escape sequence for `style`'s modifiers with the given colors, or an
+/// empty string if there is nothing to set.
+fn ansi_sequence(style: &Style, fg: Option<Color>, bg: Option<Color>, depth: ColorDepth) -> String {
+    let mut codes = Vec::new();
+
+    if style.modifiers.bold {
+        codes.push("1".to_string());
+    }
+    if style.modifiers.italic {
+        codes.push("3".to_string());
+    }
+    if style.modifiers.underline {
+        codes.push("4".to_string());
+    }
+    if style.modifiers.strikethrough {
+        codes.push("9".to_string());
+    }
+
+    let color = |color: Color, background: bool| match depth {
+        ColorDepth::TrueColor => {
+            let layer = if background { 48 } else { 38 };
+            format!("{layer};2;{};{};{}", color.r, color.g, color.b)
+        }
+        ColorDepth::Ansi16 => {
+            let color = Ansi16Color::nearest(color);
+            let code = if background {
+                color.bg_code()
+            } else {
+                color.fg_code()
+            };
+            code.to_string()
+        }
+    };
+    if let Some(fg) = fg {
+        codes.push(color(fg, false));
+    }
+    if let Some(bg) = bg {
+        codes.push(color(bg, true));
+    }
+
+    if codes.is_empty() {
+        String::new()
+    } else {
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
 /// Parse a style value from TOML (either string or table).
 #[cfg(feature = "toml")]
 fn parse_style_value(
@@ -647,6 +843,16 @@ impl std::error::Error for ThemeError {}
 /// No runtime TOML parsing is required.
 pub mod builtin {
     include!("builtin_generated.rs");
+
+    /// Look up a built-in theme by name (e.g., `"dracula"`, `"catppuccin-mocha"`).
+    ///
+    /// Names are the ones listed by [`names`]. All built-in themes are built
+    /// on the first lookup and kept for the rest of the program.
+    pub fn by_name(name: &str) -> Option<&'static Theme> {
+        static THEMES: std::sync::OnceLock<Vec<Theme>> = std::sync::OnceLock::new();
+        let index = names().iter().position(|n| *n == name)?;
+        THEMES.get_or_init(all).get(index)
+    }
 }
 
 #[cfg(test)]
@@ -660,6 +866,82 @@ mod tests {
         assert_eq!(Color::from_hex("#invalid"), None);
     }
 
+    #[test]
+    fn test_builtin_by_name() {
+        assert!(builtin::by_name("dracula").is_some());
+        assert!(builtin::by_name("nope").is_none());
+        for name in builtin::names() {
+            assert!(builtin::by_name(name).is_some(), "{name} does not resolve");
+        }
+        assert_eq!(builtin::names().len(), builtin::all().len());
+    }
+
+    #[test]
+    fn test_color_lerp() {
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(255, 255, 255);
+        assert_eq!(black.lerp(white, 0.0), black);
+        assert_eq!(black.lerp(white, 1.0), white);
+        assert_eq!(black.lerp(white, 0.5), Color::new(128, 128, 128));
+        assert_eq!(white.lerp(black, 2.0), black);
+    }
+
+    #[test]
+    fn test_border_and_gutter_colors() {
+        let theme = Theme::from_toml(
+            r##"
+            "ui.background" = { bg = "#101010" }
+            "ui.foreground" = "#f0f0f0"
+            "ui.border" = "#ff0000"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(theme.border_color, Some(Color::new(255, 0, 0)));
+        assert_eq!(theme.ansi_border_style(), "\x1b[38;2;255;0;0m");
+        // Gutter colors fall back to the base colors
+        assert_eq!(theme.gutter_fg(), Some(Color::new(128, 128, 128)));
+        assert_eq!(theme.gutter_bg(), Some(Color::new(16, 16, 16)));
+
+        let theme = Theme::from_toml(
+            r##"
+            "ui.background" = { bg = "#101010" }
+            "ui.linenr" = { fg = "#00ff00", bg = "#000000" }
+            "##,
+        )
+        .unwrap();
+        // Border falls back to a color derived from the background
+        assert_eq!(theme.ansi_border_style(), "\x1b[38;2;46;46;46m");
+        assert_eq!(theme.ansi_gutter_style(), "\x1b[38;2;0;255;0;48;2;0;0;0m");
+    }
+
+    #[test]
+    fn test_ansi16_map() {
+        use crate::highlights::Slot;
+
+        // One Dark's keywords are purple (#c678dd), strings green (#98c379)
+        let map = builtin::one_dark().to_ansi16_map();
+        assert!(matches!(
+            map[Slot::Keyword.highlight_index()],
+            Ansi16Color::Magenta | Ansi16Color::BrightMagenta
+        ));
+        assert!(matches!(
+            map[Slot::String.highlight_index()],
+            Ansi16Color::Green | Ansi16Color::BrightGreen
+        ));
+
+        assert_eq!(
+            Ansi16Color::nearest(Color::new(0, 0, 0)),
+            Ansi16Color::Black
+        );
+        assert_eq!(
+            Ansi16Color::nearest(Color::new(205, 0, 0)),
+            Ansi16Color::Red
+        );
+        assert_eq!(Ansi16Color::Red.fg_code(), 31);
+        assert_eq!(Ansi16Color::BrightBlue.fg_code(), 94);
+        assert_eq!(Ansi16Color::BrightBlue.bg_code(), 104);
+    }
+
     #[test]
     fn test_color_to_hex() {
         assert_eq!(Color::new(255, 0, 0).to_hex(), "#ff0000");
@@ -9,9 +9,15 @@
 //! Tree-sitter natively produces UTF-8 byte offsets. However, JavaScript
 //! strings use UTF-16 encoding, so offsets need conversion for JS interop.
 //!
-//! This crate provides two sets of types:
+//! This crate provides three sets of types:
 //! - `Utf8*` types use UTF-8 byte offsets (for Rust code, string slicing)
 //! - `Utf16*` types use UTF-16 code unit indices (for JavaScript `slice()`, editors)
+//! - `Utf32*` types use code point indices (for Python `str`, Rust `char` indexing)
+//!
+//! `Utf8PointSpan` and `Utf16PointSpan` also carry zero-based row/column
+//! positions, for editors that address text by line. `Utf16PointSpan`
+//! columns are UTF-16 code units; `Utf8PointSpan` columns are bytes or
+//! UTF-16 code units, as requested.
 //!
 //! # Wire Version
 //!
@@ -52,6 +58,10 @@ pub struct Utf8Span {
     /// Pattern index from the query (higher = later in highlights.scm = higher priority).
     #[serde(default)]
     pub pattern_index: u32,
+    /// The kind of syntax node the span covers (e.g. "string_literal"), if
+    /// the host asked for node kinds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_kind: Option<String>,
 }
 
 /// An injection point with UTF-8 byte offsets.
@@ -67,6 +77,10 @@ pub struct Utf8Injection {
     pub language: String,
     /// Whether to include the node children in the injection.
     pub include_children: bool,
+    /// The kind of syntax node holding the injected text, if the host asked
+    /// for node kinds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_kind: Option<String>,
 }
 
 /// Result of parsing text, with UTF-8 byte offsets.
@@ -79,6 +93,14 @@ pub struct Utf8ParseResult {
     pub spans: Vec<Utf8Span>,
     /// Injection points for other languages.
     pub injections: Vec<Utf8Injection>,
+    /// Whether the query hit the runtime's match limit, meaning some
+    /// matches were dropped and the result may be incomplete.
+    #[serde(default)]
+    pub match_limit_exceeded: bool,
+    /// Whether the query ran past the session's timeout and was stopped
+    /// early, meaning the result only covers the matches found until then.
+    #[serde(default)]
+    pub timed_out: bool,
 }
 
 impl Utf8ParseResult {
@@ -87,6 +109,66 @@ impl Utf8ParseResult {
         Self {
             spans: Vec::new(),
             injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
+        }
+    }
+}
+
+/// A span of highlighted text with UTF-8 byte offsets and row/column
+/// positions.
+///
+/// Use this for editor integrations working with Rust strings, such as
+/// language servers reporting semantic tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf8PointSpan {
+    /// UTF-8 byte offset where the span starts.
+    pub start: u32,
+    /// UTF-8 byte offset where the span ends (exclusive).
+    pub end: u32,
+    /// Zero-based line where the span starts.
+    pub start_row: u32,
+    /// Column within `start_row` where the span starts: in bytes, or in
+    /// UTF-16 code units if the host asked for those (as LSP does).
+    pub start_col: u32,
+    /// Zero-based line where the span ends.
+    pub end_row: u32,
+    /// Column within `end_row` where the span ends (exclusive), in the same
+    /// unit as `start_col`.
+    pub end_col: u32,
+    /// The capture name (e.g., "keyword", "function", "string").
+    pub capture: String,
+    /// Pattern index from the query (higher = later in highlights.scm = higher priority).
+    #[serde(default)]
+    pub pattern_index: u32,
+}
+
+/// Result of parsing text, with UTF-8 byte offsets and row/column
+/// positions for spans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf8PointParseResult {
+    /// Highlighted spans from this parse.
+    pub spans: Vec<Utf8PointSpan>,
+    /// Injection points for other languages.
+    pub injections: Vec<Utf8Injection>,
+    /// Whether the query hit the runtime's match limit, meaning some
+    /// matches were dropped and the result may be incomplete.
+    #[serde(default)]
+    pub match_limit_exceeded: bool,
+    /// Whether the query ran past the session's timeout and was stopped
+    /// early, meaning the result only covers the matches found until then.
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl Utf8PointParseResult {
+    /// Create an empty parse result.
+    pub fn empty() -> Self {
+        Self {
+            spans: Vec::new(),
+            injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
         }
     }
 }
@@ -110,6 +192,10 @@ pub struct Utf16Span {
     /// Pattern index from the query (higher = later in highlights.scm = higher priority).
     #[serde(default)]
     pub pattern_index: u32,
+    /// The kind of syntax node the span covers (e.g. "string_literal"), if
+    /// the host asked for node kinds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_kind: Option<String>,
 }
 
 /// An injection point with UTF-16 code unit indices.
@@ -125,6 +211,10 @@ pub struct Utf16Injection {
     pub language: String,
     /// Whether to include the node children in the injection.
     pub include_children: bool,
+    /// The kind of syntax node holding the injected text, if the host asked
+    /// for node kinds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_kind: Option<String>,
 }
 
 /// Result of parsing text, with UTF-16 code unit indices.
@@ -137,6 +227,14 @@ pub struct Utf16ParseResult {
     pub spans: Vec<Utf16Span>,
     /// Injection points for other languages.
     pub injections: Vec<Utf16Injection>,
+    /// Whether the query hit the runtime's match limit, meaning some
+    /// matches were dropped and the result may be incomplete.
+    #[serde(default)]
+    pub match_limit_exceeded: bool,
+    /// Whether the query ran past the session's timeout and was stopped
+    /// early, meaning the result only covers the matches found until then.
+    #[serde(default)]
+    pub timed_out: bool,
 }
 
 impl Utf16ParseResult {
@@ -145,6 +243,133 @@ impl Utf16ParseResult {
         Self {
             spans: Vec::new(),
             injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
+        }
+    }
+}
+
+/// A span of highlighted text with UTF-16 code unit indices and row/column
+/// positions.
+///
+/// Use this for JavaScript editors, such as CodeMirror decorations, whose
+/// positions count UTF-16 code units.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf16PointSpan {
+    /// UTF-16 code unit index where the span starts.
+    pub start: u32,
+    /// UTF-16 code unit index where the span ends (exclusive).
+    pub end: u32,
+    /// Zero-based line where the span starts.
+    pub start_row: u32,
+    /// UTF-16 code unit column within `start_row` where the span starts.
+    pub start_col: u32,
+    /// Zero-based line where the span ends.
+    pub end_row: u32,
+    /// UTF-16 code unit column within `end_row` where the span ends (exclusive).
+    pub end_col: u32,
+    /// The capture name (e.g., "keyword", "function", "string").
+    pub capture: String,
+    /// Pattern index from the query (higher = later in highlights.scm = higher priority).
+    #[serde(default)]
+    pub pattern_index: u32,
+}
+
+/// Result of parsing text, with UTF-16 code unit indices and row/column
+/// positions for spans.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf16PointParseResult {
+    /// Highlighted spans from this parse.
+    pub spans: Vec<Utf16PointSpan>,
+    /// Injection points for other languages.
+    pub injections: Vec<Utf16Injection>,
+    /// Whether the query hit the runtime's match limit, meaning some
+    /// matches were dropped and the result may be incomplete.
+    #[serde(default)]
+    pub match_limit_exceeded: bool,
+    /// Whether the query ran past the session's timeout and was stopped
+    /// early, meaning the result only covers the matches found until then.
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl Utf16PointParseResult {
+    /// Create an empty parse result.
+    pub fn empty() -> Self {
+        Self {
+            spans: Vec::new(),
+            injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
+        }
+    }
+}
+
+// ============================================================================
+// UTF-32 types (code point indices)
+// ============================================================================
+
+/// A span of highlighted text with code point indices.
+///
+/// Use this when text is indexed by code point, like Python `str` or a
+/// `Vec<char>`. Unlike UTF-16 indices, a character outside the Basic
+/// Multilingual Plane (an emoji, say) counts as one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf32Span {
+    /// Code point index where the span starts.
+    pub start: u32,
+    /// Code point index where the span ends (exclusive).
+    pub end: u32,
+    /// The capture name (e.g., "keyword", "function", "string").
+    pub capture: String,
+    /// Pattern index from the query (higher = later in highlights.scm = higher priority).
+    #[serde(default)]
+    pub pattern_index: u32,
+}
+
+/// An injection point with code point indices.
+///
+/// Use this when text is indexed by code point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf32Injection {
+    /// Code point index where the injection starts.
+    pub start: u32,
+    /// Code point index where the injection ends (exclusive).
+    pub end: u32,
+    /// The language ID to inject (e.g., "javascript", "css").
+    pub language: String,
+    /// Whether to include the node children in the injection.
+    pub include_children: bool,
+}
+
+/// Result of parsing text, with code point indices.
+///
+/// This format is suitable for editors and languages that index strings by
+/// code point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Utf32ParseResult {
+    /// Highlighted spans from this parse.
+    pub spans: Vec<Utf32Span>,
+    /// Injection points for other languages.
+    pub injections: Vec<Utf32Injection>,
+    /// Whether the query hit the runtime's match limit, meaning some
+    /// matches were dropped and the result may be incomplete.
+    #[serde(default)]
+    pub match_limit_exceeded: bool,
+    /// Whether the query ran past the session's timeout and was stopped
+    /// early, meaning the result only covers the matches found until then.
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl Utf32ParseResult {
+    /// Create an empty parse result.
+    pub fn empty() -> Self {
+        Self {
+            spans: Vec::new(),
+            injections: Vec::new(),
+            match_limit_exceeded: false,
+            timed_out: false,
         }
     }
 }
@@ -214,6 +439,50 @@ impl ParseError {
     }
 }
 
+/// Statistics about a parse tree.
+///
+/// An error-free tree is a sign the grammar understood the text; ERROR and
+/// MISSING nodes mark where it didn't, and highlighting there may be off.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseStats {
+    /// Number of nodes in the tree, including the root.
+    pub node_count: u32,
+    /// Whether the tree contains any ERROR or MISSING nodes.
+    pub has_error: bool,
+    /// Number of ERROR nodes, where the parser skipped text it couldn't fit.
+    pub error_count: u32,
+    /// Number of MISSING nodes, tokens the parser assumed to recover.
+    pub missing_count: u32,
+    /// How long the last parse took, in milliseconds, if it was timed.
+    #[serde(default)]
+    pub parse_ms: Option<u32>,
+}
+
+/// What kind of problem a [`Diagnostic`] reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiagnosticKind {
+    /// Text the parser couldn't fit into the grammar, and skipped.
+    Error,
+    /// A token the parser assumed was there, to recover.
+    Missing,
+}
+
+/// A place where the text fails to parse, from an ERROR or MISSING node in
+/// the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// UTF-8 byte offset where the problem starts.
+    pub start: u32,
+    /// UTF-8 byte offset where the problem ends (exclusive). Equal to
+    /// `start` for missing tokens.
+    pub end: u32,
+    /// Whether text was skipped or a token is missing.
+    pub kind: DiagnosticKind,
+    /// Kind of the node: `ERROR`, or for missing tokens the token that was
+    /// expected (e.g. `)`).
+    pub node_kind: String,
+}
+
 /// Check if a wire version is compatible with the current version.
 ///
 /// Currently requires exact match. In the future, we might allow
@@ -38,16 +38,24 @@
 
 // Core tree-sitter types
 pub use arborium_highlight::tree_sitter::{
-    CompiledGrammar, GrammarConfig, GrammarError, ParseContext,
+    CompiledGrammar, GrammarConfig, GrammarError, ParseContext, ParseTimings,
 };
 
 // Data types
-pub use arborium_highlight::{Injection, ParseResult, Span};
+pub use arborium_highlight::{Injection, InjectionRegion, ParseResult, Span};
 
 // Low-level rendering utilities
 pub use arborium_highlight::{
-    html_escape, spans_to_ansi, spans_to_ansi_with_options, spans_to_html, write_spans_as_html,
+    RenderedHtml, estimate_output_size, html_escape, normalize_html_output, spans_to_ansi,
+    spans_to_ansi_dimmed, spans_to_ansi_multi_theme, spans_to_ansi_with_colors,
+    spans_to_ansi_with_options, spans_to_html, spans_to_html_exact, spans_to_html_figure,
+    spans_to_html_lines, spans_to_html_with_data_capture, spans_to_html_with_limit,
+    spans_to_html_with_scopes, spans_to_rtf, used_slots, write_html_escaped_into,
+    write_spans_as_html,
 };
 
+// tree-sitter-highlight compatible event stream
+pub use arborium_highlight::{Highlight, HighlightEvent, to_highlight_events};
+
 // ANSI rendering options
-pub use arborium_highlight::AnsiOptions;
+pub use arborium_highlight::{AnsiOptions, ControlCharMode};
@@ -0,0 +1,91 @@
+//! LRU cache of rendered output for [`Highlighter::with_cache`](crate::Highlighter::with_cache).
+
+use std::collections::{BTreeMap, HashMap};
+
+use arborium_highlight::HtmlFormat;
+
+/// Everything the rendered output depends on besides the configuration,
+/// which is fixed for the lifetime of a cache.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    language: String,
+    source: blake3::Hash,
+    format: HtmlFormat,
+}
+
+impl CacheKey {
+    pub fn new(language: &str, source: &str, format: &HtmlFormat) -> Self {
+        Self {
+            language: language.to_string(),
+            source: blake3::hash(source.as_bytes()),
+            format: format.clone(),
+        }
+    }
+}
+
+/// Rendered strings, evicting the least recently used one when full.
+pub(crate) struct OutputCache {
+    capacity: usize,
+    /// Incremented on every access; an entry's tick records its last use.
+    tick: u64,
+    entries: HashMap<CacheKey, (u64, String)>,
+    /// Entries by last use, oldest first.
+    recency: BTreeMap<u64, CacheKey>,
+}
+
+impl OutputCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<String> {
+        self.tick += 1;
+        let (tick, output) = self.entries.get_mut(key)?;
+        self.recency.remove(tick);
+        self.recency.insert(self.tick, key.clone());
+        *tick = self.tick;
+        Some(output.clone())
+    }
+
+    pub fn insert(&mut self, key: CacheKey, output: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((old_tick, _)) = self.entries.remove(&key) {
+            self.recency.remove(&old_tick);
+        } else if self.entries.len() >= self.capacity {
+            let (_, oldest) = self.recency.pop_first().expect("a full cache has entries");
+            self.entries.remove(&oldest);
+        }
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (self.tick, output));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let format = HtmlFormat::default();
+        let key = |source: &str| CacheKey::new("rust", source, &format);
+
+        let mut cache = OutputCache::new(2);
+        cache.insert(key("a"), "A".into());
+        cache.insert(key("b"), "B".into());
+        // Touch "a" so that "b" is the oldest
+        assert_eq!(cache.get(&key("a")).as_deref(), Some("A"));
+        cache.insert(key("c"), "C".into());
+
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")).as_deref(), Some("A"));
+        assert_eq!(cache.get(&key("c")).as_deref(), Some("C"));
+    }
+}
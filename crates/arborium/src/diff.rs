@@ -0,0 +1,183 @@
+//! Unified diff parsing for [`Highlighter::highlight_diff`](crate::Highlighter::highlight_diff).
+//!
+//! A diff is split into files, and each file's hunks are turned back into
+//! the old and new contents they show, so that the code can be highlighted
+//! in its own language. The resulting spans are then mapped back onto the
+//! lines of the diff.
+
+use arborium_highlight::Span;
+use arborium_theme::Slot;
+
+/// One file's worth of hunks.
+#[derive(Default)]
+pub(crate) struct DiffFile {
+    /// Path from the `+++` header, or the `---` header for deleted files,
+    /// without the `a/` / `b/` prefix.
+    pub path: Option<String>,
+    /// Context and removed lines, as they appeared before the change.
+    pub old: Side,
+    /// Context and added lines, as they appear after the change.
+    pub new: Side,
+}
+
+/// The reconstructed contents of one side of a diff.
+#[derive(Default)]
+pub(crate) struct Side {
+    pub text: String,
+    /// Lines whose spans are mapped back onto the diff, in order.
+    lines: Vec<MappedLine>,
+}
+
+/// A line of a [`Side`] and where it came from in the diff.
+struct MappedLine {
+    /// Byte offset of the line in [`Side::text`].
+    side_start: usize,
+    /// Byte offset of the line's content (after the marker) in the diff.
+    diff_start: usize,
+    /// Length of the line, without its newline.
+    len: usize,
+}
+
+impl Side {
+    /// Append a hunk line (without its marker). Spans on `mapped` lines are
+    /// reported by [`map_spans`](Self::map_spans); the others only give the
+    /// grammar context.
+    fn push(&mut self, line: &str, diff_start: usize, mapped: bool) {
+        if mapped {
+            self.lines.push(MappedLine {
+                side_start: self.text.len(),
+                diff_start,
+                len: line.trim_end_matches('\n').len(),
+            });
+        }
+        self.text.push_str(line);
+        if !line.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+
+    /// Move spans highlighted on [`text`](Self::text) onto the diff,
+    /// splitting them at line boundaries and dropping unmapped lines.
+    pub fn map_spans(&self, spans: Vec<Span>, out: &mut Vec<Span>) {
+        for span in spans {
+            let (start, end) = (span.start as usize, span.end as usize);
+            let first = self
+                .lines
+                .partition_point(|line| line.side_start + line.len <= start);
+            for line in self.lines[first..]
+                .iter()
+                .take_while(|line| line.side_start < end)
+            {
+                let clipped_start = start.max(line.side_start) - line.side_start;
+                let clipped_end = end.min(line.side_start + line.len) - line.side_start;
+                if clipped_start >= clipped_end {
+                    continue;
+                }
+                out.push(Span {
+                    start: (line.diff_start + clipped_start) as u32,
+                    end: (line.diff_start + clipped_end) as u32,
+                    capture: span.capture.clone(),
+                    pattern_index: span.pattern_index,
+                });
+            }
+        }
+    }
+}
+
+/// Split a unified diff into files, and collect the line numbers of added
+/// and removed lines with their slot ([`Slot::DiffAdd`] / [`Slot::DiffDelete`]).
+pub(crate) fn parse(source: &str) -> (Vec<DiffFile>, Vec<(usize, Slot)>) {
+    let mut files: Vec<DiffFile> = Vec::new();
+    let mut changes = Vec::new();
+    // Lines left in the current hunk, per side
+    let (mut old_left, mut new_left) = (0usize, 0usize);
+
+    let mut offset = 0;
+    for (line_number, line) in source.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+
+        if old_left > 0 || new_left > 0 {
+            let file = files.last_mut().expect("hunks belong to a file");
+            match line.as_bytes()[0] {
+                b'+' => {
+                    new_left = new_left.saturating_sub(1);
+                    file.new.push(&line[1..], start + 1, true);
+                    changes.push((line_number, Slot::DiffAdd));
+                    continue;
+                }
+                b'-' => {
+                    old_left = old_left.saturating_sub(1);
+                    file.old.push(&line[1..], start + 1, true);
+                    changes.push((line_number, Slot::DiffDelete));
+                    continue;
+                }
+                // Some tools strip the space marker from empty context lines
+                b' ' | b'\n' => {
+                    let content = line.get(1..).unwrap_or("");
+                    old_left = old_left.saturating_sub(1);
+                    new_left = new_left.saturating_sub(1);
+                    file.new.push(content, start + 1, true);
+                    file.old.push(content, start + 1, false);
+                    continue;
+                }
+                // "\ No newline at end of file"
+                b'\\' => continue,
+                _ => (old_left, new_left) = (0, 0),
+            }
+        }
+
+        if line.starts_with("diff ") {
+            files.push(DiffFile::default());
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            // Plain `diff -u` output has no "diff" line between files
+            if files
+                .last()
+                .is_none_or(|f| f.path.is_some() || !f.new.text.is_empty())
+            {
+                files.push(DiffFile::default());
+            }
+            files.last_mut().unwrap().path = header_path(path);
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            if files.is_empty() {
+                files.push(DiffFile::default());
+            }
+            let file = files.last_mut().unwrap();
+            file.path = header_path(path).or(file.path.take());
+        } else if let Some((old, new)) = hunk_counts(line) {
+            if files.is_empty() {
+                files.push(DiffFile::default());
+            }
+            (old_left, new_left) = (old, new);
+        }
+    }
+
+    (files, changes)
+}
+
+/// The path in a `---` / `+++` header, or `None` for `/dev/null`.
+fn header_path(header: &str) -> Option<String> {
+    let path = header.trim_end().split('\t').next()?;
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// Old and new line counts from a `@@ -l,s +l,s @@` hunk header.
+fn hunk_counts(line: &str) -> Option<(usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split(' ');
+    let old = ranges.next()?.strip_prefix('-')?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    // A range without a count covers one line
+    let count = |range: &str| {
+        range
+            .split_once(',')
+            .map_or(Some(1), |(_, n)| n.parse().ok())
+    };
+    Some((count(old)?, count(new)?))
+}
@@ -30,6 +30,22 @@ pub enum Error {
         message: String,
     },
 
+    /// The grammar panicked while parsing the source code.
+    ///
+    /// This usually points at a bug in a grammar's external scanner. It is
+    /// only reported when built with `panic = "unwind"` (the default); with
+    /// `panic = "abort"` the process aborts instead.
+    GrammarPanic {
+        /// The language whose grammar panicked.
+        language: String,
+    },
+
+    /// The rendered output would exceed [`Config::max_output_bytes`](crate::Config::max_output_bytes).
+    OutputTooLarge {
+        /// The configured limit, in bytes.
+        limit: usize,
+    },
+
     /// An error occurred while compiling a tree-sitter query.
     ///
     /// This indicates a problem with the grammar's highlight or injection queries.
@@ -55,6 +71,12 @@ impl fmt::Display for Error {
             Error::ParseError { language, message } => {
                 write!(f, "parse error for {}: {}", language, message)
             }
+            Error::GrammarPanic { language } => {
+                write!(f, "grammar for {} panicked during parse", language)
+            }
+            Error::OutputTooLarge { limit } => {
+                write!(f, "highlighted output exceeds {} bytes", limit)
+            }
             Error::QueryError { language, message } => {
                 write!(f, "query error for {}: {}", language, message)
             }
@@ -89,6 +111,12 @@ impl From<arborium_highlight::HighlightError> for Error {
                 language: String::new(), // We don't have the language here
                 message,
             },
+            arborium_highlight::HighlightError::GrammarPanic { language } => {
+                Error::GrammarPanic { language }
+            }
+            arborium_highlight::HighlightError::OutputTooLarge { limit } => {
+                Error::OutputTooLarge { limit }
+            }
         }
     }
 }
@@ -0,0 +1,97 @@
+//! Language tags of fenced code blocks.
+
+use std::collections::HashMap;
+
+use crate::store::GrammarStore;
+
+/// Tags that mean the block isn't code to highlight.
+const PLAIN_TAGS: &[&str] = &["text", "txt", "plain", "plaintext", "none", "output"];
+
+/// Common tags that aren't a grammar's name or alias, and the grammar to
+/// highlight them with.
+const DEFAULT_TAGS: &[(&str, &str)] = &[
+    ("console", "bash"),
+    ("node", "javascript"),
+    ("nodejs", "javascript"),
+    ("shell-session", "bash"),
+    ("shellsession", "bash"),
+];
+
+/// Maps the language tags of fenced code blocks, such as Markdown info
+/// strings or rustdoc's `language-*` classes, to grammars.
+///
+/// Tags are messier than language names: `js`, `node` and `jsx` all mean
+/// JavaScript, and `text` means no highlighting at all. The default map
+/// knows every grammar's name and aliases and a few common tags besides;
+/// [`insert`](Self::insert) and [`insert_plain`](Self::insert_plain) add
+/// more. Tags are matched case-insensitively.
+///
+/// ```rust
+/// use arborium::FenceLanguageMap;
+///
+/// let mut languages = FenceLanguageMap::new();
+/// assert_eq!(languages.resolve("sh"), Some("bash"));
+/// assert_eq!(languages.resolve("text"), None);
+///
+/// languages.insert("cargo", "toml");
+/// assert_eq!(languages.resolve("Cargo"), Some("toml"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FenceLanguageMap {
+    /// Grammar by lowercase tag, or `None` for plain text.
+    entries: HashMap<String, Option<String>>,
+}
+
+impl FenceLanguageMap {
+    /// The default map.
+    pub fn new() -> Self {
+        let plain = PLAIN_TAGS.iter().map(|&tag| (tag.to_string(), None));
+        let defaults = DEFAULT_TAGS
+            .iter()
+            .map(|&(tag, language)| (tag.to_string(), Some(language.to_string())));
+        Self {
+            entries: plain.chain(defaults).collect(),
+        }
+    }
+
+    /// Highlight blocks tagged `tag` as `language`.
+    pub fn insert(&mut self, tag: impl Into<String>, language: impl Into<String>) {
+        let tag = tag.into().to_lowercase();
+        self.entries.insert(tag, Some(language.into()));
+    }
+
+    /// Leave blocks tagged `tag` unhighlighted.
+    pub fn insert_plain(&mut self, tag: impl Into<String>) {
+        self.entries.insert(tag.into().to_lowercase(), None);
+    }
+
+    /// The grammar to highlight blocks tagged `tag` with, or `None` if they
+    /// should be left unstyled: the tag is plain text, or isn't known.
+    ///
+    /// The grammar may not be compiled into this build; see
+    /// [`language_support`](crate::language_support).
+    pub fn resolve(&self, tag: &str) -> Option<&str> {
+        let tag = tag.trim().to_lowercase();
+        if let Some(language) = self.entries.get(&tag) {
+            return language.as_deref();
+        }
+        let language = GrammarStore::normalize_language(&tag);
+        GrammarStore::ALL_LANGUAGES
+            .iter()
+            .copied()
+            .find(|&known| known == language)
+    }
+
+    /// Whether blocks tagged `tag` are plain text, as opposed to code in a
+    /// language that isn't known.
+    pub fn is_plain(&self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        matches!(self.entries.get(&tag), Some(None))
+    }
+}
+
+impl Default for FenceLanguageMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -11,6 +11,12 @@
 //! has its own parse context (cheap to create). Use [`Highlighter::fork`] to create
 //! a new highlighter that shares the grammar store.
 //!
+//! A highlighter's parse context (parser and query cursor) is reused across
+//! calls, and handed back to the store when the highlighter is dropped. Forking
+//! a short-lived highlighter per request therefore doesn't allocate a new
+//! cursor each time. A single highlighter is not meant to be shared between
+//! threads (its methods take `&mut self`); fork one per thread instead.
+//!
 //! # Example
 //!
 //! ```rust,ignore
@@ -27,17 +33,51 @@
 //! }).collect();
 //! ```
 
-use std::io::Write;
+use std::io::{BufRead, Write};
+use std::ops::Range;
 use std::sync::Arc;
-
-use arborium_highlight::tree_sitter::{CompiledGrammar, ParseContext};
-use arborium_highlight::{AnsiOptions, Span, spans_to_ansi_with_options, spans_to_html};
+#[cfg(feature = "cache")]
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use arborium_highlight::tree_sitter::{CompiledGrammar, ParseContext, ParseTimings};
+use arborium_highlight::{
+    AnsiOptions, DocumentOptions, InjectionFilter, InjectionRegion, LineNumberConfig, RenderedHtml,
+    Scope, Span, html_tags_for_slot, normalize_html_output, spans_to_ansi_with_options,
+    spans_to_html_exact, spans_to_html_with_data_capture, spans_to_html_with_limit,
+    spans_to_html_with_line_numbers, spans_to_html_with_scopes,
+};
 use arborium_theme::Theme;
 
 use crate::Config;
+#[cfg(feature = "cache")]
+use crate::cache::{CacheKey, OutputCache};
+use crate::diff;
 use crate::error::Error;
 use crate::store::GrammarStore;
 
+#[cfg(test)]
+thread_local! {
+    /// Number of documents (and injections) parsed on this thread.
+    static PARSES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Time spent in each phase of highlighting, from
+/// [`Highlighter::highlight_timed`] or [`AnsiHighlighter::highlight_timed`].
+///
+/// Load, parse and query times include those of injected languages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Looking up grammars, including compiling them on first use.
+    pub load: Duration,
+    /// Building syntax trees.
+    pub parse: Duration,
+    /// Running highlight and injection queries.
+    pub query: Duration,
+    /// Rendering spans to the output format.
+    pub render: Duration,
+}
+
 /// High-level syntax highlighter for HTML output.
 ///
 /// This is the primary entry point for syntax highlighting. It produces HTML
@@ -63,6 +103,11 @@ pub struct Highlighter {
     store: Arc<GrammarStore>,
     ctx: Option<ParseContext>,
     config: Config,
+    /// Rendered output, shared with forks (see [`Highlighter::with_cache`]).
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<Mutex<OutputCache>>>,
+    /// Timings being collected for [`Highlighter::highlight_timed`].
+    timings: Option<Timings>,
 }
 
 impl Default for Highlighter {
@@ -80,6 +125,9 @@ impl Clone for Highlighter {
             store: self.store.clone(),
             ctx: None, // New context will be created on first use
             config: self.config.clone(),
+            #[cfg(feature = "cache")]
+            cache: self.cache.clone(),
+            timings: None,
         }
     }
 }
@@ -93,6 +141,9 @@ impl Highlighter {
             store: Arc::new(GrammarStore::new()),
             ctx: None,
             config: Config::default(),
+            #[cfg(feature = "cache")]
+            cache: None,
+            timings: None,
         }
     }
 
@@ -102,6 +153,9 @@ impl Highlighter {
             store: Arc::new(GrammarStore::new()),
             ctx: None,
             config,
+            #[cfg(feature = "cache")]
+            cache: None,
+            timings: None,
         }
     }
 
@@ -113,6 +167,9 @@ impl Highlighter {
             store,
             ctx: None,
             config: Config::default(),
+            #[cfg(feature = "cache")]
+            cache: None,
+            timings: None,
         }
     }
 
@@ -122,6 +179,9 @@ impl Highlighter {
             store,
             ctx: None,
             config,
+            #[cfg(feature = "cache")]
+            cache: None,
+            timings: None,
         }
     }
 
@@ -134,9 +194,33 @@ impl Highlighter {
             store: self.store.clone(),
             ctx: None,
             config: self.config.clone(),
+            #[cfg(feature = "cache")]
+            cache: self.cache.clone(),
+            timings: None,
         }
     }
 
+    /// Cache up to `capacity` rendered outputs, keyed by language, a hash of
+    /// the source and the HTML format.
+    ///
+    /// Repeated [`highlight`](Self::highlight) calls for the same source then
+    /// skip parsing and rendering entirely. The cache is shared with
+    /// highlighters forked (or cloned) from this one, so one cache serves all
+    /// the threads of a service. Errors are not cached.
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Arc::new(Mutex::new(OutputCache::new(capacity))));
+        self
+    }
+
+    /// Compile the grammars for `languages` now instead of on first use.
+    ///
+    /// See [`GrammarStore::warm`]; use [`GrammarStore::warm_all`] on
+    /// [`store`](Self::store) to warm every enabled language.
+    pub fn warm(&self, languages: &[&str]) -> Result<(), Error> {
+        self.store.warm(languages)
+    }
+
     /// Get the grammar store.
     ///
     /// Use this to create additional highlighters that share compiled grammars.
@@ -149,8 +233,273 @@ impl Highlighter {
     /// This automatically handles language injections (e.g., CSS/JS in HTML,
     /// SQL in Python strings, etc.).
     pub fn highlight(&mut self, language: &str, source: &str) -> Result<String, Error> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.cache.clone() {
+            let key = CacheKey::new(language, source, &self.config.html_format);
+            if let Some(html) = cache.lock().unwrap().get(&key) {
+                return Ok(html);
+            }
+            let spans = self.highlight_spans(language, source)?;
+            let html = self.render_html(source, spans, false)?;
+            cache.lock().unwrap().insert(key, html.clone());
+            return Ok(html);
+        }
+
+        let spans = self.highlight_spans(language, source)?;
+        self.render_html(source, spans, false)
+    }
+
+    /// Highlight source code once and render it as both HTML and ANSI text
+    /// colored with `theme`, returning `(html, ansi)`.
+    ///
+    /// Cheaper than [`highlight`](Self::highlight) plus
+    /// [`AnsiHighlighter::highlight`] for tools that emit both, as the source
+    /// is only parsed once. The ANSI output uses default [`AnsiOptions`].
+    pub fn highlight_both(
+        &mut self,
+        language: &str,
+        source: &str,
+        theme: &Theme,
+    ) -> Result<(String, String), Error> {
         let spans = self.highlight_spans(language, source)?;
-        Ok(spans_to_html(source, spans, &self.config.html_format))
+        let ansi =
+            spans_to_ansi_with_options(source, spans.clone(), theme, &AnsiOptions::default());
+        let html = self.render_html(source, spans, false)?;
+        Ok((html, ansi))
+    }
+
+    /// Like [`highlight`](Self::highlight), but also report how long each
+    /// phase took.
+    ///
+    /// For finding out why a particular input highlights slowly. The output
+    /// cache is bypassed, so the timings always reflect real work.
+    pub fn highlight_timed(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<(String, Timings), Error> {
+        let (spans, mut timings) = self.highlight_spans_timed(language, source)?;
+        let started = Instant::now();
+        let html = self.render_html(source, spans, false)?;
+        timings.render = started.elapsed();
+        Ok((html, timings))
+    }
+
+    /// Highlight source code and return a complete `<pre><code>` block.
+    ///
+    /// With [`DocumentOptions::accessible`] set, the block is labelled for
+    /// screen readers with the language name. With
+    /// [`DocumentOptions::emit_data_capture`] set, every token gets its own
+    /// element recording its capture, bypassing the output cache. With
+    /// [`DocumentOptions::embed_raw_source`] set, the source is embedded for
+    /// copy buttons. With [`DocumentOptions::inline_compact`] set, the result
+    /// is a single-line `<code>` fragment instead of a block.
+    pub fn highlight_document(
+        &mut self,
+        language: &str,
+        source: &str,
+        options: &DocumentOptions,
+    ) -> Result<String, Error> {
+        let source = if options.inline_compact {
+            source.trim()
+        } else {
+            source
+        };
+        let body = if options.emit_data_capture {
+            let spans = self.highlight_spans(language, source)?;
+            self.render_html(source, spans, true)?
+        } else {
+            self.highlight(language, source)?
+        };
+        Ok(options.wrap_with_source(&body, language, source))
+    }
+
+    /// Highlight source code, coloring variables according to scopes the
+    /// caller computed (from a language server, for instance).
+    ///
+    /// Each variable defined in `scopes` gets a color of its own, shared by
+    /// all its references, in place of the theme's variable color. See
+    /// [`spans_to_html_with_scopes`](arborium_highlight::spans_to_html_with_scopes).
+    pub fn highlight_with_scopes(
+        &mut self,
+        language: &str,
+        source: &str,
+        scopes: &[Scope],
+    ) -> Result<String, Error> {
+        let spans = self.highlight_spans(language, source)?;
+        let rendered = spans_to_html_with_scopes(
+            source,
+            spans,
+            scopes,
+            &self.config.html_format,
+            self.config.max_output_bytes,
+            self.config.control_char_handling,
+        );
+        self.finish_html(rendered)
+    }
+
+    /// Highlight source code, then overlay spans for every match of the
+    /// given patterns, e.g. to make every mention of one API stand out in
+    /// documentation.
+    ///
+    /// Each match gets a span with the paired capture (such as
+    /// `"text.strong"`), which goes through the same deduplication as the
+    /// grammar's spans: overlays rank above every grammar pattern, so a match
+    /// covering exactly a grammar capture replaces it, and one inside a
+    /// larger capture is nested in it. Matches are found in the whole
+    /// source, whatever the syntax.
+    #[cfg(feature = "overlay")]
+    pub fn highlight_with_overlay(
+        &mut self,
+        language: &str,
+        source: &str,
+        overlays: &[(regex::Regex, &str)],
+    ) -> Result<String, Error> {
+        let mut spans = self.highlight_spans(language, source)?;
+        for (pattern, capture) in overlays {
+            spans.extend(pattern.find_iter(source).map(|m| Span {
+                start: m.start() as u32,
+                end: m.end() as u32,
+                capture: capture.to_string(),
+                pattern_index: u32::MAX,
+            }));
+        }
+        self.render_html(source, spans, false)
+    }
+
+    /// Highlight a unified diff, with the changed code in its own language.
+    ///
+    /// The old and new contents of each file are pieced back together from
+    /// the hunks and highlighted as `base_language`, or, if that is `None`,
+    /// as the language detected from the file's `+++` header (files whose
+    /// language can't be detected or isn't available are left plain). Added
+    /// and removed lines, markers included, are then wrapped in the
+    /// [`Slot::DiffAdd`](crate::theme::Slot::DiffAdd) /
+    /// [`Slot::DiffDelete`](crate::theme::Slot::DiffDelete) elements so
+    /// themes can give them a background.
+    pub fn highlight_diff(
+        &mut self,
+        source: &str,
+        base_language: Option<&str>,
+    ) -> Result<String, Error> {
+        let (files, changes) = diff::parse(source);
+        let mut spans = Vec::new();
+        for file in &files {
+            let language = match base_language {
+                Some(language) => language,
+                None => match file.path.as_deref().and_then(crate::detect_language) {
+                    Some(language) => language,
+                    None => continue,
+                },
+            };
+            for side in [&file.old, &file.new] {
+                if side.text.is_empty() {
+                    continue;
+                }
+                match self.highlight_spans(language, &side.text) {
+                    Ok(side_spans) => side.map_spans(side_spans, &mut spans),
+                    Err(Error::UnsupportedLanguage { .. }) if base_language.is_none() => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        // Spans were split at line boundaries, so every line of the diff is
+        // still a line of the HTML and can be wrapped on its own
+        let body = self.render_html(source, spans, false)?;
+        let mut html = String::with_capacity(body.len() + changes.len() * 16);
+        let mut changes = changes.into_iter().peekable();
+        for (line_number, line) in body.split_inclusive('\n').enumerate() {
+            match changes.next_if(|&(n, _)| n == line_number) {
+                Some((_, slot)) => {
+                    let (open, close) = html_tags_for_slot(slot, &self.config.html_format);
+                    let content = line.trim_end_matches('\n');
+                    html.push_str(&open);
+                    html.push_str(content);
+                    html.push_str(&close);
+                    html.push_str(&line[content.len()..]);
+                }
+                None => html.push_str(line),
+            }
+        }
+        if let Some(limit) = self
+            .config
+            .max_output_bytes
+            .filter(|&limit| html.len() > limit)
+        {
+            return Err(Error::OutputTooLarge { limit });
+        }
+        Ok(html)
+    }
+
+    /// Render spans to HTML, enforcing the configured output limit.
+    ///
+    /// With `data_capture`, elements carry their capture and aren't coalesced.
+    fn render_html(
+        &self,
+        source: &str,
+        spans: Vec<Span>,
+        data_capture: bool,
+    ) -> Result<String, Error> {
+        self.render_html_at_line(source, spans, data_capture, 0)
+    }
+
+    /// [`render_html`](Self::render_html) for a part of a document starting
+    /// `line_offset` lines in, so numbered lines carry on from the parts
+    /// before it.
+    fn render_html_at_line(
+        &self,
+        source: &str,
+        spans: Vec<Span>,
+        data_capture: bool,
+        line_offset: usize,
+    ) -> Result<String, Error> {
+        let (format, limit, control_chars) = (
+            &self.config.html_format,
+            self.config.max_output_bytes,
+            self.config.control_char_handling,
+        );
+        let rendered = if self.config.exact_spans {
+            spans_to_html_exact(source, spans, format, limit, control_chars, data_capture)
+        } else if data_capture {
+            spans_to_html_with_data_capture(source, spans, format, limit, control_chars)
+        } else if let Some(line_numbers) = &self.config.line_numbers {
+            let line_numbers = LineNumberConfig {
+                start: line_numbers.start + line_offset,
+                ..line_numbers.clone()
+            };
+            spans_to_html_with_line_numbers(
+                source,
+                spans,
+                format,
+                &line_numbers,
+                limit,
+                control_chars,
+            )
+        } else {
+            spans_to_html_with_limit(source, spans, format, limit, control_chars)
+        };
+        self.finish_html(rendered)
+    }
+
+    /// Fail if `rendered` was truncated, and apply the configured output
+    /// normalization otherwise.
+    fn finish_html(&self, rendered: RenderedHtml) -> Result<String, Error> {
+        if rendered.truncated {
+            return Err(Error::OutputTooLarge {
+                limit: self.config.max_output_bytes.unwrap_or_default(),
+            });
+        }
+        let (strip_bom, normalize_line_endings) =
+            (self.config.strip_bom, self.config.normalize_line_endings);
+        if strip_bom || normalize_line_endings {
+            return Ok(normalize_html_output(
+                &rendered.html,
+                strip_bom,
+                normalize_line_endings,
+            ));
+        }
+        Ok(rendered.html)
     }
 
     /// Highlight source code and write HTML directly to a writer.
@@ -168,15 +517,217 @@ impl Highlighter {
         Ok(())
     }
 
+    /// Highlight everything read from `reader`, writing HTML to `writer` as
+    /// it goes.
+    ///
+    /// For inputs too large to hold in memory, such as multi-gigabyte logs.
+    /// The input is highlighted in chunks of whole lines, ending at blank
+    /// lines where possible and each parsed on its own, so memory use stays
+    /// bounded by the chunk size. This only works well for line-oriented
+    /// grammars (`diff`, `ini`, log formats): a construct spanning a chunk
+    /// boundary, like a multi-line string or comment, may be mis-highlighted.
+    ///
+    /// The input must be UTF-8; anything else fails with [`Error::Io`].
+    ///
+    /// With [`Config::line_numbers`], numbering carries on across chunks,
+    /// but blank lines between two chunks are left unwrapped.
+    pub fn highlight_stream<R: BufRead, W: Write>(
+        &mut self,
+        language: &str,
+        reader: R,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        let mut line_offset = 0;
+        highlight_chunks(reader, writer, |chunk| {
+            let spans = self.highlight_spans(language, chunk)?;
+            let html = self.render_html_at_line(chunk, spans, false, line_offset)?;
+            line_offset += chunk.matches('\n').count();
+            Ok(html)
+        })
+    }
+
     /// Highlight and return raw spans (for custom rendering).
     pub fn highlight_spans(&mut self, language: &str, source: &str) -> Result<Vec<Span>, Error> {
-        // Get the primary grammar
-        let grammar = self
-            .store
-            .get(language)
-            .ok_or_else(|| Error::UnsupportedLanguage {
-                language: language.to_string(),
-            })?;
+        let mut all_spans = Vec::new();
+        self.collect_spans(
+            language,
+            source,
+            &mut all_spans,
+            &mut Vec::new(),
+            &mut self.injection_filter(),
+        )?;
+        Ok(all_spans)
+    }
+
+    /// [`highlight_spans`](Self::highlight_spans), collecting the load,
+    /// parse and query timings.
+    fn highlight_spans_timed(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<(Vec<Span>, Timings), Error> {
+        self.timings = Some(Timings::default());
+        let spans = self.highlight_spans(language, source);
+        let timings = self.timings.take().unwrap_or_default();
+        Ok((spans?, timings))
+    }
+
+    /// Like [`highlight_spans`](Self::highlight_spans), but also returns the
+    /// regions highlighted as injected languages, with their nesting depth.
+    ///
+    /// Regions are only reported for injections that were actually
+    /// highlighted (the language is available, allowed, and within
+    /// [`Config::max_injection_depth`]).
+    pub fn highlight_with_injection_markers(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<(Vec<Span>, Vec<InjectionRegion>), Error> {
+        let mut all_spans = Vec::new();
+        let mut regions = Vec::new();
+        self.collect_spans(
+            language,
+            source,
+            &mut all_spans,
+            &mut regions,
+            &mut self.injection_filter(),
+        )?;
+        Ok((all_spans, regions))
+    }
+
+    /// Highlight a document where a fixed byte range is in another language.
+    ///
+    /// `inner_range` is highlighted as `inner_language` and the rest of the
+    /// document as `outer_language`, regardless of what the outer grammar's
+    /// injection query says. This is meant for front-matter, e.g. a YAML
+    /// block at the top of a Markdown file:
+    ///
+    /// ```rust,ignore
+    /// let source = "---\ntitle: Hello\n---\n# Heading\n";
+    /// let html = hl.highlight_with_base_injection("markdown", "yaml", 4..17, source)?;
+    /// ```
+    ///
+    /// The outer grammar never sees the inner range (it is blanked out before
+    /// parsing), so the inner content can't confuse it. Injections found by
+    /// either grammar are still processed as usual.
+    pub fn highlight_with_base_injection(
+        &mut self,
+        outer_language: &str,
+        inner_language: &str,
+        inner_range: Range<usize>,
+        source: &str,
+    ) -> Result<String, Error> {
+        let spans = self.highlight_spans_with_base_injection(
+            outer_language,
+            inner_language,
+            inner_range,
+            source,
+        )?;
+        self.render_html(source, spans, false)
+    }
+
+    /// Like [`highlight_with_base_injection`](Self::highlight_with_base_injection),
+    /// but returns raw spans (for custom rendering).
+    pub fn highlight_spans_with_base_injection(
+        &mut self,
+        outer_language: &str,
+        inner_language: &str,
+        inner_range: Range<usize>,
+        source: &str,
+    ) -> Result<Vec<Span>, Error> {
+        if inner_range.start > inner_range.end
+            || !source.is_char_boundary(inner_range.start)
+            || !source.is_char_boundary(inner_range.end)
+        {
+            return Err(Error::ParseError {
+                language: inner_language.to_string(),
+                message: format!(
+                    "invalid inner range {}..{} for a source of {} bytes",
+                    inner_range.start,
+                    inner_range.end,
+                    source.len()
+                ),
+            });
+        }
+
+        // Blank out the inner range (keeping newlines so rows still line up)
+        // so the outer grammar parses the rest of the document on its own.
+        let mut outer_source = String::with_capacity(source.len());
+        outer_source.push_str(&source[..inner_range.start]);
+        outer_source.extend(
+            source[inner_range.clone()]
+                .bytes()
+                .map(|b| if b == b'\n' { '\n' } else { ' ' }),
+        );
+        outer_source.push_str(&source[inner_range.end..]);
+
+        // Both parts share one filter, so `max_injected_languages` holds
+        // for the whole document
+        let mut filter = self.injection_filter();
+        let mut all_spans = Vec::new();
+        self.collect_spans(
+            outer_language,
+            &outer_source,
+            &mut all_spans,
+            &mut Vec::new(),
+            &mut filter,
+        )?;
+
+        // The base injection doesn't count against the injection depth
+        let inner_start = inner_range.start as u32;
+        let mut inner_spans = Vec::new();
+        self.collect_spans(
+            inner_language,
+            &source[inner_range],
+            &mut inner_spans,
+            &mut Vec::new(),
+            &mut filter,
+        )?;
+        all_spans.extend(inner_spans.into_iter().map(|mut span| {
+            span.start += inner_start;
+            span.end += inner_start;
+            span
+        }));
+
+        Ok(all_spans)
+    }
+
+    /// The [`InjectionFilter`] for one document, applying the configured
+    /// injection settings.
+    fn injection_filter(&self) -> InjectionFilter {
+        InjectionFilter::with_options(
+            self.config.injection_allowlist.clone(),
+            self.config.max_injected_languages,
+            self.config.trim_injection_blank_lines,
+        )
+    }
+
+    /// Parse `source` as `language`, pushing its spans (and those of any
+    /// injections) onto `all_spans`, and the injected regions onto `regions`.
+    ///
+    /// `filter` decides which injections are highlighted; `source` is added
+    /// to it as a document of its own.
+    fn collect_spans(
+        &mut self,
+        language: &str,
+        source: &str,
+        all_spans: &mut Vec<Span>,
+        regions: &mut Vec<InjectionRegion>,
+        filter: &mut InjectionFilter,
+    ) -> Result<(), Error> {
+        #[cfg(test)]
+        PARSES.with(|parses| parses.set(parses.get() + 1));
+
+        // Get the primary grammar, or the fallback one
+        let load_started = self.timings.is_some().then(Instant::now);
+        let (grammar, grammar_language) =
+            match (self.store.try_get(language), &self.config.fallback_language) {
+                (Err(Error::UnsupportedLanguage { .. }), Some(fallback)) => {
+                    (self.store.try_get(fallback)?, fallback.clone())
+                }
+                (grammar, _) => (grammar?, language.to_string()),
+            };
+        self.add_load_time(load_started);
 
         // Ensure we have a parse context
         self.ensure_context(&grammar)?;
@@ -190,27 +741,38 @@ impl Highlighter {
             })?;
 
         // Parse the primary language
-        let result = grammar.parse(ctx, source);
+        let result = self
+            .parse_catching_panics(&grammar, source)
+            .ok_or_else(|| Error::GrammarPanic {
+                language: language.to_string(),
+            })?;
 
         // Collect all spans (including from injections)
-        let mut all_spans = result.spans;
+        all_spans.extend(result.spans);
 
         // Process injections recursively
-        if self.config.max_injection_depth > 0 {
+        if self.config.max_injection_depth > 0 && grammar.has_injections() {
+            let document = filter.document(&grammar_language, source.len());
             self.process_injections(
                 source,
+                document,
                 result.injections,
-                0,
                 self.config.max_injection_depth,
-                &mut all_spans,
+                all_spans,
+                regions,
+                filter,
             )?;
         }
 
-        Ok(all_spans)
+        Ok(())
     }
 
-    /// Ensure we have a parse context, creating one if needed.
+    /// Ensure we have a parse context, reusing one from the store's pool or
+    /// creating one if needed.
     fn ensure_context(&mut self, grammar: &CompiledGrammar) -> Result<(), Error> {
+        if self.ctx.is_none() {
+            self.ctx = self.store.take_context();
+        }
         if self.ctx.is_none() {
             self.ctx = Some(
                 ParseContext::for_grammar(grammar).map_err(|e| Error::ParseError {
@@ -219,48 +781,149 @@ impl Highlighter {
                 })?,
             );
         }
+        // Pooled contexts may come from highlighters with another config
+        if let Some(ctx) = self.ctx.as_mut() {
+            ctx.set_suppress_error_regions(self.config.suppress_error_regions);
+        }
         Ok(())
     }
 
+    /// Add the time since `started` to the load time, if collecting timings.
+    fn add_load_time(&mut self, started: Option<Instant>) {
+        if let (Some(timings), Some(started)) = (self.timings.as_mut(), started) {
+            timings.load += started.elapsed();
+        }
+    }
+
+    /// Parse with the current context, returning `None` if the grammar panics.
+    ///
+    /// The parser may be left in an inconsistent state by the panic, so the
+    /// context is dropped and recreated on next use.
+    fn parse_catching_panics(
+        &mut self,
+        grammar: &CompiledGrammar,
+        source: &str,
+    ) -> Option<arborium_highlight::ParseResult> {
+        let ctx = self.ctx.as_mut()?;
+        let timings = self.timings.as_mut();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match timings {
+            Some(timings) => {
+                let mut parse_timings = ParseTimings::default();
+                let result = grammar.parse_timed(ctx, source, &mut parse_timings);
+                timings.parse += parse_timings.parse;
+                timings.query += parse_timings.query;
+                result
+            }
+            None => grammar.parse(ctx, source),
+        }));
+        if result.is_err() {
+            self.ctx = None;
+        }
+        result.ok()
+    }
+
     /// Process injections recursively.
+    ///
+    /// Each injection gets its own parse of just the injected text, so
+    /// per-parse state stays scoped to its layer. That holds for a language
+    /// injected into itself too: the host's spans are collected before its
+    /// injections are parsed, and no tree is kept between parses, so reusing
+    /// the context for the same grammar can't disturb them.
+    ///
+    /// `source` is the whole document, and `region` the region of `filter`
+    /// the injections were found in.
+    #[allow(clippy::too_many_arguments)]
     fn process_injections(
         &mut self,
         source: &str,
+        region: usize,
         injections: Vec<arborium_highlight::Injection>,
-        base_offset: u32,
         remaining_depth: u32,
         all_spans: &mut Vec<Span>,
+        regions: &mut Vec<InjectionRegion>,
+        filter: &mut InjectionFilter,
     ) -> Result<(), Error> {
         if remaining_depth == 0 {
             return Ok(());
         }
 
         for injection in injections {
-            let start = injection.start as usize;
-            let end = injection.end as usize;
-
-            if start >= source.len() || end > source.len() || start >= end {
+            let Some(range) = filter.accept(source, region, &injection) else {
                 continue;
-            }
-
-            let injected_source = &source[start..end];
+            };
+            let injected_source = &source[range.clone()];
 
             // Try to get grammar for injected language
-            let Some(grammar) = self.store.get(&injection.language) else {
+            let load_started = self.timings.is_some().then(Instant::now);
+            let grammar = self.store.get(&injection.language);
+            self.add_load_time(load_started);
+            let Some(grammar) = grammar else {
                 continue;
             };
+            let injected = filter.enter(&injection.language, range.clone(), region);
 
-            // Set language for this grammar
+            // Set language for this grammar (the context may have been
+            // dropped by an earlier panicking injection)
+            if self.ensure_context(&grammar).is_err() {
+                continue;
+            }
             let ctx = self.ctx.as_mut().unwrap();
             if ctx.set_language(grammar.language()).is_err() {
                 continue;
             }
 
-            // Parse injected content
-            let result = grammar.parse(ctx, injected_source);
+            let unescaped = self
+                .config
+                .string_unescapers
+                .get(filter.language(region))
+                .and_then(|unescaper| unescaper.unescape(injected_source));
+            let unescaped_source = unescaped.as_ref().map_or(injected_source, |u| &u.text);
+            let dedented = if self.config.dedent_injections {
+                arborium_highlight::dedent(unescaped_source)
+            } else {
+                None
+            };
+            let parsed_source = dedented.as_ref().map_or(unescaped_source, |d| &d.text);
+
+            // Parse injected content; a panicking grammar only loses its own spans
+            let Some(mut result) = self.parse_catching_panics(&grammar, parsed_source) else {
+                continue;
+            };
+
+            // Back to offsets in the injected source
+            if dedented.is_some() || unescaped.is_some() {
+                let original_range = |range: Range<usize>| {
+                    let range = dedented
+                        .as_ref()
+                        .map_or(range.clone(), |d| d.original_range(range));
+                    unescaped
+                        .as_ref()
+                        .map_or(range.clone(), |u| u.original_range(range))
+                };
+                for span in &mut result.spans {
+                    let range = original_range(span.start as usize..span.end as usize);
+                    (span.start, span.end) = (range.start as u32, range.end as u32);
+                }
+                for nested in &mut result.injections {
+                    let range = original_range(nested.start as usize..nested.end as usize);
+                    (nested.start, nested.end) = (range.start as u32, range.end as u32);
+                }
+            }
+
+            // Keep spans within the injection, in case the grammar misbehaves
+            let len = range.len() as u32;
+            result.spans.retain_mut(|span| {
+                span.end = span.end.min(len);
+                span.start < span.end
+            });
 
             // Offset spans to document coordinates
-            let offset = base_offset + injection.start;
+            let offset = range.start as u32;
+            regions.push(InjectionRegion {
+                language: injection.language.clone(),
+                byte_range: range,
+                depth: self.config.max_injection_depth - remaining_depth + 1,
+            });
             for mut span in result.spans {
                 span.start += offset;
                 span.end += offset;
@@ -268,19 +931,33 @@ impl Highlighter {
             }
 
             // Recurse into nested injections
-            self.process_injections(
-                injected_source,
-                result.injections,
-                offset,
-                remaining_depth - 1,
-                all_spans,
-            )?;
+            if grammar.has_injections() {
+                self.process_injections(
+                    source,
+                    injected,
+                    result.injections,
+                    remaining_depth - 1,
+                    all_spans,
+                    regions,
+                    filter,
+                )?;
+            }
         }
 
         Ok(())
     }
 }
 
+impl Drop for Highlighter {
+    /// Hand the parse context back to the store so other highlighters
+    /// sharing it don't have to allocate a new parser and query cursor.
+    fn drop(&mut self) {
+        if let Some(ctx) = self.ctx.take() {
+            self.store.recycle_context(ctx);
+        }
+    }
+}
+
 /// High-level syntax highlighter for ANSI terminal output.
 ///
 /// This highlighter produces ANSI escape sequences for colored terminal output.
@@ -389,6 +1066,20 @@ impl AnsiHighlighter {
         ))
     }
 
+    /// Like [`highlight`](Self::highlight), but also report how long each
+    /// phase took.
+    pub fn highlight_timed(
+        &mut self,
+        language: &str,
+        source: &str,
+    ) -> Result<(String, Timings), Error> {
+        let (spans, mut timings) = self.inner.highlight_spans_timed(language, source)?;
+        let started = Instant::now();
+        let ansi = spans_to_ansi_with_options(source, spans, &self.theme, &self.options);
+        timings.render = started.elapsed();
+        Ok((ansi, timings))
+    }
+
     /// Highlight source code and write ANSI output directly to a writer.
     pub fn highlight_to_writer<W: Write>(
         &mut self,
@@ -400,10 +1091,519 @@ impl AnsiHighlighter {
         writer.write_all(ansi.as_bytes())?;
         Ok(())
     }
+
+    /// Highlight everything read from `reader`, writing ANSI output to
+    /// `writer` as it goes.
+    ///
+    /// See [`Highlighter::highlight_stream`] for how the input is chunked
+    /// and which grammars this suits. Each chunk is rendered on its own, so
+    /// options that frame the output, like borders and line numbers, apply
+    /// per chunk.
+    pub fn highlight_stream<R: BufRead, W: Write>(
+        &mut self,
+        language: &str,
+        reader: R,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        highlight_chunks(reader, writer, |chunk| self.highlight(language, chunk))
+    }
+}
+
+/// Size after which [`highlight_chunks`] ends a chunk at the next blank
+/// line, or at the next line once it's twice this size.
+const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Highlight `reader` a chunk of whole lines at a time with `highlight`,
+/// writing each chunk's output to `writer`.
+///
+/// Chunks preferably end at blank lines, which in line-oriented formats
+/// usually separate blocks (INI sections, log records).
+fn highlight_chunks<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    mut highlight: impl FnMut(&str) -> Result<String, Error>,
+) -> Result<(), Error> {
+    let mut chunk = String::with_capacity(STREAM_CHUNK_BYTES);
+    let mut line_start = 0;
+    // Newlines trimmed from the end of the previous chunk's output, which
+    // only belong in the output if more follows
+    let mut trimmed_newlines = 0;
+    loop {
+        let read = reader.read_line(&mut chunk)?;
+        let blank = chunk[line_start..].trim().is_empty();
+        line_start = chunk.len();
+        let full =
+            chunk.len() >= 2 * STREAM_CHUNK_BYTES || (chunk.len() >= STREAM_CHUNK_BYTES && blank);
+        if read != 0 && !full {
+            continue;
+        }
+        if !chunk.is_empty() {
+            let output = highlight(&chunk)?;
+            if !output.is_empty() {
+                writer.write_all("\n".repeat(trimmed_newlines).as_bytes())?;
+                writer.write_all(output.as_bytes())?;
+                trimmed_newlines = 0;
+            }
+            trimmed_newlines += chunk.len() - chunk.trim_end_matches('\n').len();
+            chunk.clear();
+            line_start = 0;
+        }
+        if read == 0 {
+            return Ok(());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_accessible_document() {
+        use crate::{DocumentOptions, Highlighter};
+
+        let mut hl = Highlighter::new();
+        let options = DocumentOptions {
+            accessible: true,
+            ..Default::default()
+        };
+        let html = hl
+            .highlight_document("rust", "fn main() {}", &options)
+            .unwrap();
+
+        assert!(
+            html.starts_with(r#"<pre role="region" aria-label="code, rust" tabindex="0"><code>"#)
+        );
+        assert!(html.ends_with("</code></pre>"));
+        assert!(html.contains("<a-k>fn</a-k>"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_diff() {
+        use crate::Highlighter;
+
+        let diff = "\
+diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    let x = 1;
++    let y = 2;
+ }
+";
+        let mut hl = Highlighter::new();
+        let html = hl.highlight_diff(diff, None).unwrap();
+
+        // Context lines are highlighted as Rust
+        assert!(html.contains("<a-k>fn</a-k>"), "{html}");
+        // Changed lines keep their markers, with the code highlighted inside
+        assert!(
+            html.contains("<a-dd>-    <a-k>let</a-k> x = <a-co>1</a-co><a-p>;</a-p></a-dd>\n"),
+            "{html}"
+        );
+        assert!(html.contains("<a-da>+    <a-k>let</a-k>"), "{html}");
+        // Headers are left alone
+        assert!(html.contains("+++ b/src/main.rs\n"), "{html}");
+
+        // Explicit language, and nothing to detect from
+        let hunk = "@@ -1 +1 @@\n-fn a() {}\n+fn b() {}\n";
+        let html = hl.highlight_diff(hunk, Some("rust")).unwrap();
+        assert!(html.contains("<a-da>+<a-k>fn</a-k>"), "{html}");
+        let html = hl.highlight_diff(hunk, None).unwrap();
+        assert!(!html.contains("<a-k>"), "{html}");
+        assert!(html.contains("<a-da>+fn b() {}</a-da>"), "{html}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "overlay", feature = "lang-rust"))]
+    fn test_highlight_with_overlay() {
+        use crate::Highlighter;
+
+        let source = "// Make a Highlighter\nlet hl = Highlighter::new();";
+        let overlays = [(
+            regex::Regex::new(r"\bHighlighter\b").unwrap(),
+            "text.strong",
+        )];
+        let mut hl = Highlighter::new();
+        let html = hl
+            .highlight_with_overlay("rust", source, &overlays)
+            .unwrap();
+
+        // Both mentions stand out, in the comment and in the code
+        assert_eq!(
+            html.matches("<a-st>Highlighter</a-st>").count(),
+            2,
+            "{html}"
+        );
+        // Everything else is highlighted as usual
+        assert!(html.contains("<a-k>let</a-k>"), "{html}");
+        assert!(html.contains("<a-c>// Make a </a-c>"), "{html}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "cache", feature = "lang-rust"))]
+    fn test_output_cache() {
+        use super::PARSES;
+        use crate::{Highlighter, HtmlFormat};
+
+        let parses = || PARSES.with(|parses| parses.get());
+        let mut hl = Highlighter::new().with_cache(8);
+
+        let first = hl.highlight("rust", "fn main() {}").unwrap();
+        assert!(first.contains("<a-k>fn</a-k>"), "{first}");
+        let after_first = parses();
+        let second = hl.highlight("rust", "fn main() {}").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(parses(), after_first, "cached output was re-parsed");
+
+        // Forks share the cache
+        let mut forked = hl.fork();
+        forked.highlight("rust", "fn main() {}").unwrap();
+        assert_eq!(parses(), after_first);
+
+        // A different source is a miss
+        hl.highlight("rust", "fn other() {}").unwrap();
+        assert!(parses() > after_first);
+
+        // Output in another format doesn't collide with the cached one, even
+        // in the same cache
+        let mut classes = hl.fork();
+        classes.config.html_format = HtmlFormat::ClassNames;
+        let html = classes.highlight("rust", "fn main() {}").unwrap();
+        assert!(html.contains("<span class=\"keyword\">fn</span>"), "{html}");
+        assert_eq!(classes.highlight("rust", "fn main() {}").unwrap(), html);
+        assert_eq!(hl.highlight("rust", "fn main() {}").unwrap(), first);
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "lang-html",
+        feature = "lang-css",
+        feature = "lang-javascript"
+    ))]
+    fn test_max_injected_languages() {
+        use crate::{Config, Highlighter};
+
+        let source = "<script>let a = 1;</script><style>p { color: red; }</style><script>let b = 2;</script>";
+        let injected = |max_injected_languages| {
+            let mut hl = Highlighter::with_config(Config {
+                max_injected_languages,
+                ..Default::default()
+            });
+            let (_, regions) = hl.highlight_with_injection_markers("html", source).unwrap();
+            regions
+                .iter()
+                .map(|r| (r.language.clone(), &source[r.byte_range.clone()]))
+                .collect::<Vec<_>>()
+        };
+
+        let all = [
+            ("javascript".to_string(), "let a = 1;"),
+            ("css".to_string(), "p { color: red; }"),
+            ("javascript".to_string(), "let b = 2;"),
+        ];
+        assert_eq!(injected(None), all);
+        assert_eq!(injected(Some(2)), all);
+
+        // A language already injected doesn't count again
+        assert_eq!(injected(Some(1)), [all[0].clone(), all[2].clone()]);
+        assert!(injected(Some(0)).is_empty());
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-rust", feature = "lang-markdown"))]
+    fn test_dedent_injections() {
+        use crate::{Config, Highlighter};
+
+        // A fenced block indented by its list item
+        let source = "- item\n\n    ```rust\n    fn main() {\n        let x = 1;\n    }\n    ```\n";
+        let mut hl = Highlighter::with_config(Config {
+            dedent_injections: true,
+            ..Default::default()
+        });
+
+        let spans = hl.highlight_spans("markdown", source).unwrap();
+        let keywords: Vec<_> = spans
+            .iter()
+            .filter(|s| s.capture == "keyword")
+            .map(|s| (s.start as usize, &source[s.start as usize..s.end as usize]))
+            .collect();
+        assert_eq!(
+            keywords,
+            [
+                (source.find("fn").unwrap(), "fn"),
+                (source.find("let").unwrap(), "let")
+            ]
+        );
+
+        // The indentation is still there in the output
+        let html = hl.highlight("markdown", source).unwrap();
+        assert!(html.contains("      </a-tl><a-k>let</a-k>"), "{html}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "lang-rust", feature = "lang-markdown"))]
+    fn test_warm() {
+        use crate::Highlighter;
+        use std::sync::Arc;
+
+        let hl = Highlighter::new();
+        hl.warm(&["rust", "markdown"]).unwrap();
+        assert_eq!(hl.store().compiled_grammars(), 2);
+        let rust = hl.store().get("rust").unwrap();
+
+        // Highlighting uses the warmed grammar instead of compiling another
+        let mut hl = hl.fork();
+        hl.highlight("rust", "fn main() {}").unwrap();
+        assert_eq!(hl.store().compiled_grammars(), 2);
+        assert!(Arc::ptr_eq(&rust, &hl.store().get("rust").unwrap()));
+
+        let err = hl.warm(&["rust", "not-a-language"]).unwrap_err();
+        assert!(matches!(err, crate::Error::UnsupportedLanguage { .. }));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_both() {
+        use super::PARSES;
+        use arborium_theme::builtin;
+
+        use crate::{AnsiHighlighter, Highlighter};
+
+        let source = "fn main() {\n    println!(\"hi\");\n}\n";
+        let theme = builtin::dracula();
+
+        let mut hl = Highlighter::new();
+        let parses = || PARSES.with(|parses| parses.get());
+        let before = parses();
+        let (html, ansi) = hl.highlight_both("rust", source, &theme).unwrap();
+        assert_eq!(parses() - before, 1, "parsed once for both outputs");
+
+        assert_eq!(html, hl.highlight("rust", source).unwrap());
+        let mut ansi_hl = AnsiHighlighter::new(theme);
+        assert_eq!(ansi, ansi_hl.highlight("rust", source).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_highlight_timed() {
+        use arborium_theme::builtin;
+        use std::time::Duration;
+
+        use crate::{AnsiHighlighter, Highlighter};
+
+        let source = "fn main() {\n    let x = \"hello\";\n}\n".repeat(200);
+
+        let mut hl = Highlighter::new();
+        let (html, timings) = hl.highlight_timed("rust", &source).unwrap();
+        assert_eq!(html, hl.highlight("rust", &source).unwrap());
+        assert!(timings.load > Duration::ZERO, "{timings:?}");
+        assert!(timings.parse > Duration::ZERO, "{timings:?}");
+        assert!(timings.query > Duration::ZERO, "{timings:?}");
+        assert!(timings.render > Duration::ZERO, "{timings:?}");
+
+        // Timings aren't collected outside highlight_timed
+        assert!(hl.timings.is_none());
+
+        let mut ansi = AnsiHighlighter::new(builtin::dracula().clone());
+        let (_, timings) = ansi.highlight_timed("rust", &source).unwrap();
+        assert!(timings.render > Duration::ZERO, "{timings:?}");
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_injection_regions() {
+        use crate::Highlighter;
+
+        // Rust injects itself into macro token trees
+        let source = "a!(b!(c));";
+        let mut hl = Highlighter::new();
+        let (spans, regions) = hl.highlight_with_injection_markers("rust", source).unwrap();
+        assert!(!spans.is_empty());
+
+        let regions: Vec<_> = regions
+            .iter()
+            .map(|r| (r.language.as_str(), &source[r.byte_range.clone()], r.depth))
+            .collect();
+        assert_eq!(regions, [("rust", "(b!(c))", 1), ("rust", "(c)", 2)]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_self_injection() {
+        use crate::Highlighter;
+
+        // The macro's token tree is Rust injected into Rust
+        let source = "fn main() { assert!(v.len() == 2); }";
+        let mut hl = Highlighter::new();
+        let spans = hl.highlight_spans("rust", source).unwrap();
+        let has = |text: &str, capture: &str| {
+            let start = source.find(text).unwrap() as u32;
+            spans.iter().any(|s| {
+                s.start == start && s.end == start + text.len() as u32 && s.capture == capture
+            })
+        };
+
+        // From the host parse
+        assert!(has("fn", "keyword"), "{spans:?}");
+        assert!(has("main", "function"), "{spans:?}");
+        assert!(has("assert", "function.macro"), "{spans:?}");
+        // From the injected parse, at document offsets
+        assert!(has("len", "function.method"), "{spans:?}");
+
+        let html = hl.highlight("rust", source).unwrap();
+        assert!(html.starts_with("<a-k>fn</a-k> <a-f>main</a-f>"), "{html}");
+        assert!(html.contains("<a-f>len</a-f>"), "{html}");
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_self_injection_is_skipped() {
+        use arborium_highlight::tree_sitter::GrammarConfig;
+
+        use super::CompiledGrammar;
+        use crate::Highlighter;
+
+        // Rust, injecting `language` over the whole document
+        let injecting = |language: &str| {
+            let injections_query = format!(
+                "((source_file) @injection.content (#set! injection.language \"{language}\"))"
+            );
+            CompiledGrammar::new(GrammarConfig {
+                language: crate::lang_rust::language().into(),
+                highlights_query: crate::lang_rust::HIGHLIGHTS_QUERY,
+                injections_query: &injections_query,
+                locals_query: "",
+            })
+            .unwrap()
+        };
+        let injected = |hl: &mut Highlighter| {
+            let (_, regions) = hl
+                .highlight_with_injection_markers("rust", "fn main() {}")
+                .unwrap();
+            regions
+                .into_iter()
+                .map(|r| (r.language, r.depth))
+                .collect::<Vec<_>>()
+        };
+
+        let mut hl = Highlighter::new();
+        hl.store().insert("rust", injecting("rust"));
+        assert!(injected(&mut hl).is_empty());
+
+        // Repeating an ancestor further up is caught too
+        let mut hl = Highlighter::new();
+        hl.store().insert("rust", injecting("other"));
+        hl.store().insert("other", injecting("rust"));
+        assert_eq!(injected(&mut hl), [("other".to_string(), 1)]);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_line_numbers() {
+        use crate::{Config, Highlighter, LineNumberConfig};
+
+        let mut hl = Highlighter::with_config(Config {
+            line_numbers: Some(LineNumberConfig::default()),
+            ..Default::default()
+        });
+
+        // A block comment over three lines is closed and reopened around
+        // each line's wrapper
+        let html = hl
+            .highlight("rust", "let x = 1; /* one\ntwo\nthree */\n")
+            .unwrap();
+        let lines: Vec<_> = html.split('\n').collect();
+        assert_eq!(lines.len(), 3, "{html}");
+        assert!(
+            lines[0].starts_with("<span class=\"line\" data-line=\"1\"><a-k>let</a-k>"),
+            "{html}"
+        );
+        assert!(lines[0].ends_with("<a-c>/* one</a-c></span>"), "{html}");
+        assert_eq!(
+            lines[1],
+            "<span class=\"line\" data-line=\"2\"><a-c>two</a-c></span>"
+        );
+        assert_eq!(
+            lines[2],
+            "<span class=\"line\" data-line=\"3\"><a-c>three */</a-c></span>"
+        );
+
+        // Not applied with exact spans
+        let mut exact = Highlighter::with_config(Config {
+            line_numbers: Some(LineNumberConfig::default()),
+            exact_spans: true,
+            ..Default::default()
+        });
+        let html = exact.highlight("rust", "let x = 1;").unwrap();
+        assert!(!html.contains("data-line"), "{html}");
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_suppress_error_regions() {
+        use crate::{Config, Highlighter, advanced::Span};
+
+        let valid = "fn ok() -> u32 { 1 }\n";
+        let broken = "fn f(a: u32, b: &str -> { let a = \"x\"; let b = 1; let c = 2\n";
+        let source = format!("{valid}{broken}");
+        let spans = |suppress_error_regions| {
+            let mut hl = Highlighter::with_config(Config {
+                suppress_error_regions,
+                ..Default::default()
+            });
+            hl.highlight_spans("rust", &source).unwrap()
+        };
+        let in_broken = |spans: &[Span]| {
+            spans
+                .iter()
+                .filter(|s| s.start as usize >= valid.len())
+                .count()
+        };
+
+        let unsuppressed = spans(false);
+        assert!(in_broken(&unsuppressed) > 0);
+
+        let suppressed = spans(true);
+        assert_eq!(in_broken(&suppressed), 0, "{suppressed:?}");
+        // The valid function is still highlighted
+        assert!(suppressed.iter().any(|s| s.capture == "keyword"));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-ini")]
+    fn test_highlight_stream() {
+        use crate::Highlighter;
+
+        // Large enough to be split into several chunks
+        let source: String = (0..4000)
+            .map(|i| format!("[section_{i}]\nname = value {i}\n; note\n\n"))
+            .collect();
+        assert!(source.len() > 2 * super::STREAM_CHUNK_BYTES);
+
+        let mut hl = Highlighter::new();
+        let mut streamed = Vec::new();
+        hl.highlight_stream("ini", source.as_bytes(), &mut streamed)
+            .unwrap();
+        let streamed = String::from_utf8(streamed).unwrap();
+
+        // Chunks end between sections, so nothing is mis-highlighted
+        assert_eq!(streamed, hl.highlight("ini", &source).unwrap());
+
+        // Numbered lines carry on from one chunk to the next
+        let mut hl = Highlighter::with_config(crate::Config {
+            line_numbers: Some(crate::LineNumberConfig::default()),
+            ..Default::default()
+        });
+        let mut streamed = Vec::new();
+        hl.highlight_stream("ini", source.as_bytes(), &mut streamed)
+            .unwrap();
+        let streamed = String::from_utf8(streamed).unwrap();
+        assert_eq!(streamed.matches("data-line=\"1\"").count(), 1);
+        assert!(streamed.contains("data-line=\"15999\">"));
+    }
+
     #[test]
     #[cfg(feature = "lang-rust")]
     fn test_highlighter_fork() {
@@ -520,6 +1720,44 @@ fn main() {
         assert_ne!(output1, output2);
     }
 
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_parse_context_reuse() {
+        use std::sync::Arc;
+
+        use crate::{GrammarStore, Highlighter};
+        let store = Arc::new(GrammarStore::new());
+
+        let mut hl = Highlighter::with_store(store.clone());
+        let expected = hl.highlight("rust", "fn main() { let x = 1; }").unwrap();
+        drop(hl);
+        assert_eq!(store.idle_contexts(), 1);
+
+        // Per-request highlighters keep reusing the single pooled context
+        for i in 0..1000 {
+            let mut hl = Highlighter::with_store(store.clone());
+            let source = if i % 2 == 0 {
+                "fn main() { let x = 1; }"
+            } else {
+                "fn f() {}"
+            };
+            let html = hl.highlight("rust", source).unwrap();
+            if i % 2 == 0 {
+                assert_eq!(html, expected);
+            }
+            assert_eq!(store.idle_contexts(), 0);
+        }
+        assert_eq!(store.idle_contexts(), 1);
+
+        // Concurrent highlighters each get their own context
+        let mut hl1 = Highlighter::with_store(store.clone());
+        let mut hl2 = hl1.fork();
+        hl1.highlight("rust", "fn a() {}").unwrap();
+        hl2.highlight("rust", "fn b() {}").unwrap();
+        drop((hl1, hl2));
+        assert_eq!(store.idle_contexts(), 2);
+    }
+
     #[test]
     #[cfg(feature = "lang-rust")]
     fn test_shared_store() {
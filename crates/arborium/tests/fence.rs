@@ -0,0 +1,49 @@
+//! Tests for mapping fenced code block tags to grammars.
+
+use arborium::FenceLanguageMap;
+
+#[test]
+fn aliases_map_to_their_grammar() {
+    let languages = FenceLanguageMap::new();
+    assert_eq!(languages.resolve("sh"), Some("bash"));
+    assert_eq!(languages.resolve("shell"), Some("bash"));
+    assert_eq!(languages.resolve("js"), Some("javascript"));
+    assert_eq!(languages.resolve("jsx"), Some("javascript"));
+    assert_eq!(languages.resolve("rust"), Some("rust"));
+}
+
+#[test]
+fn common_tags_map_to_a_grammar() {
+    let languages = FenceLanguageMap::new();
+    assert_eq!(languages.resolve("node"), Some("javascript"));
+    assert_eq!(languages.resolve("console"), Some("bash"));
+    assert_eq!(languages.resolve("SH"), Some("bash"));
+}
+
+#[test]
+fn text_is_plain() {
+    let languages = FenceLanguageMap::new();
+    assert_eq!(languages.resolve("text"), None);
+    assert!(languages.is_plain("text"));
+    assert!(languages.is_plain("plaintext"));
+}
+
+#[test]
+fn unknown_tags_are_unstyled() {
+    let languages = FenceLanguageMap::new();
+    assert_eq!(languages.resolve("bartholomew"), None);
+    assert!(!languages.is_plain("bartholomew"));
+}
+
+#[test]
+fn custom_entries_take_precedence() {
+    let mut languages = FenceLanguageMap::new();
+    languages.insert("Cargo", "toml");
+    languages.insert("sh", "zsh");
+    languages.insert_plain("console");
+
+    assert_eq!(languages.resolve("cargo"), Some("toml"));
+    assert_eq!(languages.resolve("sh"), Some("zsh"));
+    assert_eq!(languages.resolve("console"), None);
+    assert!(languages.is_plain("console"));
+}
@@ -0,0 +1,81 @@
+//! Base injection tests.
+//!
+//! Tests that a fixed byte range can be highlighted as another language,
+//! as needed for YAML front-matter in Markdown documents.
+
+#![cfg(all(feature = "lang-markdown", feature = "lang-yaml"))]
+
+use arborium::Highlighter;
+use arborium_highlight::Span;
+use indoc::indoc;
+
+const SOURCE: &str = indoc! {"
+    ---
+    title: Hello
+    draft: true
+    ---
+    # Heading
+
+    Some *text* here.
+"};
+
+/// Byte range of the YAML between the `---` fences
+fn front_matter_range() -> std::ops::Range<usize> {
+    let start = "---\n".len();
+    let end = start + SOURCE[start..].find("---").unwrap();
+    start..end
+}
+
+fn spans_within<'a>(spans: &'a [Span], range: &std::ops::Range<usize>) -> Vec<&'a Span> {
+    spans
+        .iter()
+        .filter(|s| s.start as usize >= range.start && s.end as usize <= range.end)
+        .collect()
+}
+
+#[test]
+fn test_front_matter_is_highlighted_as_yaml() {
+    let mut highlighter = Highlighter::new();
+    let range = front_matter_range();
+
+    let spans = highlighter
+        .highlight_spans_with_base_injection("markdown", "yaml", range.clone(), SOURCE)
+        .unwrap();
+
+    // YAML keys get highlighted inside the front-matter
+    let inner = spans_within(&spans, &range);
+    assert!(
+        inner
+            .iter()
+            .any(|s| &SOURCE[s.start as usize..s.end as usize] == "title"),
+        "expected a span on the `title` key, got {inner:?}"
+    );
+
+    // Markdown spans exist for the body, and none leak into the front-matter
+    let heading = SOURCE.find("# Heading").unwrap();
+    assert!(spans.iter().any(|s| s.start as usize >= heading));
+    let yaml_only = highlighter
+        .highlight_spans("yaml", &SOURCE[range.clone()])
+        .unwrap();
+    assert_eq!(inner.len(), yaml_only.len());
+}
+
+#[test]
+fn test_front_matter_html() {
+    let mut highlighter = Highlighter::new();
+    let html = highlighter
+        .highlight_with_base_injection("markdown", "yaml", front_matter_range(), SOURCE)
+        .unwrap();
+
+    // Rendering must preserve the text of the whole document
+    assert!(html.contains("title"));
+    assert!(html.contains("Heading"));
+}
+
+#[test]
+fn test_invalid_inner_range() {
+    let mut highlighter = Highlighter::new();
+    let result =
+        highlighter.highlight_with_base_injection("markdown", "yaml", 0..SOURCE.len() + 1, SOURCE);
+    assert!(result.is_err());
+}
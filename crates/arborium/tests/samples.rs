@@ -0,0 +1,15 @@
+//! Tests for the `sample_for` function.
+
+#![cfg(feature = "samples")]
+
+#[test]
+#[cfg(feature = "lang-rust")]
+fn sample_for_rust() {
+    let sample = arborium::sample_for("rust").expect("rust sample should be available");
+    assert!(!sample.trim().is_empty(), "rust sample should not be empty");
+}
+
+#[test]
+fn sample_for_unsupported() {
+    assert!(arborium::sample_for("bartholomew").is_none());
+}
@@ -135,6 +135,8 @@ interface WasmBindgenPlugin {
   /** Parse and return UTF-16 code unit indices (for JavaScript) */
   parse_utf16: (session: number) => Utf16ParseResult;
   cancel: (session: number) => void;
+  /** Stop queries running longer than `millis` (0 for no limit); missing in older plugins */
+  set_timeout?: (session: number, millis: number) => void;
 }
 
 /** A loaded grammar plugin */
@@ -93,6 +93,8 @@ struct LibRsTemplate<'a> {
     /// Crate names to prepend highlights from, in order
     /// e.g. ["arborium_c"] for C++ inheriting from C
     highlights_prepend: Vec<String>,
+    /// Path of the default sample, relative to the crate root
+    sample_path: Option<String>,
 }
 
 #[derive(TemplateSimple)]
@@ -214,6 +216,8 @@ struct UmbrellaLibRsTemplate<'a> {
     permissive_grammars: &'a [LanguageEntry],
     /// List of GPL-licensed grammars
     gpl_grammars: &'a [LanguageEntry],
+    /// List of (crate_name, grammar_id) for grammars that ship a default sample
+    sampled_grammars: &'a [(String, String)],
 }
 
 #[derive(TemplateSimple)]
@@ -758,10 +762,38 @@ fn generate_lib_rs(
         locals_exists,
         tests_cursed,
         highlights_prepend,
+        sample_path: default_sample_path(def_path, config),
     };
     template.render_once().expect("LibRsTemplate render failed")
 }
 
+/// Find the default sample for a grammar crate, relative to the crate root.
+///
+/// This is the first sample declared in arborium.yaml, falling back to a
+/// `sample.*` file at the root of def/. Both get copied into the crate.
+fn default_sample_path(def_path: &Utf8Path, config: &crate::types::CrateConfig) -> Option<String> {
+    let declared = config
+        .grammars
+        .first()
+        .and_then(|g| g.samples.as_ref())
+        .and_then(|samples| samples.first())
+        .map(|sample| sample.path().to_string())
+        .filter(|path| def_path.join(path).is_file());
+    if declared.is_some() {
+        return declared;
+    }
+
+    let mut root_samples: Vec<String> = fs::read_dir(def_path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("sample."))
+        .collect();
+    root_samples.sort();
+    root_samples.into_iter().next()
+}
+
 /// Generate tests/corpus.rs content for a grammar crate.
 fn generate_corpus_tests(crate_name: &str, grammar_id: &str) -> String {
     let crate_name_snake = crate_name.replace('-', "_");
@@ -2050,6 +2082,15 @@ include = [
 [features]
 default = []
 
+# Embed each enabled language's default sample (see `sample_for`)
+samples = []
+
+# Cache rendered output (see `Highlighter::with_cache`)
+cache = ["dep:blake3"]
+
+# Regex overlays on top of highlighting (see `Highlighter::highlight_with_overlay`)
+overlay = ["dep:regex"]
+
 # All languages
 all-languages = [
 "#
@@ -2078,6 +2119,8 @@ all-languages = [
 arborium-tree-sitter = {{ version = "{version}", path = "../arborium-tree-sitter" }}
 arborium-theme = {{ version = "{version}", path = "../arborium-theme" }}
 arborium-highlight = {{ version = "{version}", path = "../arborium-highlight", features = ["tree-sitter"] }}
+blake3 = {{ version = "1", optional = true }}
+regex = {{ version = "1", optional = true }}
 
 # Optional grammar dependencies
 "#
@@ -2150,6 +2193,20 @@ dlmalloc = "0.2"
         .map(|(name, grammar_id, _)| (name.clone(), grammar_id.clone()))
         .collect();
 
+    // Grammars whose crate exports a default SAMPLE (see generate_lib_rs)
+    let mut sampled_grammars: Vec<(String, String)> = prepared
+        .prepared_temps
+        .iter()
+        .filter(|pt| default_sample_path(&pt.crate_state.def_path, &pt.config).is_some())
+        .map(|pt| {
+            let name = pt.crate_state.name.clone();
+            let grammar_id = name.strip_prefix("arborium-").unwrap_or(&name).to_string();
+            (name, grammar_id)
+        })
+        .filter(|(_, grammar_id)| !grammar_id.ends_with("_inline"))
+        .collect();
+    sampled_grammars.sort();
+
     // Collect aliases and extensions from all grammars in the registry
     let mut aliases: Vec<(String, String)> = Vec::new();
     let mut extensions: Vec<(String, String)> = Vec::new();
@@ -2242,6 +2299,7 @@ dlmalloc = "0.2"
         extensions: &extensions,
         permissive_grammars: &permissive_grammars,
         gpl_grammars: &gpl_grammars,
+        sampled_grammars: &sampled_grammars,
     }
     .render_once()
     .expect("UmbrellaLibRsTemplate render failed");
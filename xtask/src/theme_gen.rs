@@ -73,6 +73,9 @@ pub struct Theme {
     pub source_url: Option<String>,
     pub background: Option<Color>,
     pub foreground: Option<Color>,
+    pub border_color: Option<Color>,
+    pub gutter_fg: Option<Color>,
+    pub gutter_bg: Option<Color>,
     /// Styles keyed by highlight name (e.g., "keyword", "punctuation.special")
     pub styles: HashMap<String, Style>,
 }
@@ -344,6 +347,13 @@ pub fn parse_theme_toml(toml_str: &str) -> Result<Theme, String> {
         style
     };
 
+    // Border and gutter colors
+    let border_color = table.get("ui.border").and_then(|v| parse_style_value(v).fg);
+    let linenr = table
+        .get("ui.linenr")
+        .map(&parse_style_value)
+        .unwrap_or_default();
+
     // Collect all styles
     let mut styles = HashMap::new();
 
@@ -374,6 +384,9 @@ pub fn parse_theme_toml(toml_str: &str) -> Result<Theme, String> {
         source_url,
         background: background.map(|(r, g, b)| Color(r, g, b)),
         foreground: foreground.map(|(r, g, b)| Color(r, g, b)),
+        border_color,
+        gutter_fg: linenr.fg,
+        gutter_bg: linenr.bg,
         styles,
     })
 }
@@ -422,12 +435,17 @@ fn gen_style(style: &Style) -> String {
 
 /// Theme definition for code generation.
 struct ThemeDef {
+    /// Theme file stem, used as the lookup name (e.g., "catppuccin-mocha").
+    file_name: String,
     fn_name: String,
     name: String,
     is_dark: bool,
     source_url: Option<String>,
     background: Option<Color>,
     foreground: Option<Color>,
+    border_color: Option<Color>,
+    gutter_fg: Option<Color>,
+    gutter_bg: Option<Color>,
     styles: HashMap<String, Style>,
 }
 
@@ -507,12 +525,16 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
                 .map_err(|e| format!("Failed to parse {:?}: {e}", path))?;
 
             themes.push(ThemeDef {
+                file_name: file_stem.to_string(),
                 fn_name,
                 name: theme.name,
                 is_dark: theme.is_dark,
                 source_url: theme.source_url,
                 background: theme.background,
                 foreground: theme.foreground,
+                border_color: theme.border_color,
+                gutter_fg: theme.gutter_fg,
+                gutter_bg: theme.gutter_bg,
                 styles: theme.styles,
             });
         }
@@ -569,6 +591,24 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
             gen_color_option(&def.foreground)
         )
         .unwrap();
+        writeln!(
+            code,
+            "        border_color: {},",
+            gen_color_option(&def.border_color)
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        gutter_fg: {},",
+            gen_color_option(&def.gutter_fg)
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        gutter_bg: {},",
+            gen_color_option(&def.gutter_bg)
+        )
+        .unwrap();
 
         writeln!(code, "        styles: [").unwrap();
         for (i, highlight_def) in highlights.defs.iter().enumerate() {
@@ -595,6 +635,21 @@ pub fn generate_theme_code(crates_dir: &Utf8Path) -> Result<(), String> {
     }
     writeln!(code, "    ]").unwrap();
     writeln!(code, "}}").unwrap();
+    writeln!(code).unwrap();
+
+    // Generate names() function, in the same order as all()
+    writeln!(
+        code,
+        "/// Names of all built-in themes, in the same order as [`all`]."
+    )
+    .unwrap();
+    writeln!(code, "pub fn names() -> &'static [&'static str] {{").unwrap();
+    writeln!(code, "    &[").unwrap();
+    for def in &themes {
+        writeln!(code, "        {:?},", def.file_name).unwrap();
+    }
+    writeln!(code, "    ]").unwrap();
+    writeln!(code, "}}").unwrap();
 
     // Write the file
     fs::write(&output_path, &code).map_err(|e| format!("Failed to write output: {e}"))?;
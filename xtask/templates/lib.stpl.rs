@@ -54,6 +54,11 @@ pub const LOCALS_QUERY: &str = include_str!("../queries/locals.scm");
 /// The locals query for <%= grammar_id %> (empty - no locals available).
 pub const LOCALS_QUERY: &str = "";
 <% } %>
+<% if let Some(sample_path) = &sample_path { %>
+
+/// A representative <%= grammar_id %> source sample, for demos and tests.
+pub const SAMPLE: &str = include_str!("../<%= sample_path %>");
+<% } %>
 <% if !tests_cursed { %>
 
 #[cfg(test)]
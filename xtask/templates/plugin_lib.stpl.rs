@@ -1,10 +1,24 @@
 //! <%= grammar_id %> grammar plugin for arborium.
 
 use wasm_bindgen::prelude::*;
-use arborium_plugin_runtime::{HighlightConfig, PluginRuntime};
-use arborium_wire::{Utf8ParseResult, Utf16ParseResult};
+use arborium_plugin_runtime::{ColumnEncoding, HighlightConfig, PluginRuntime};
+use arborium_wire::{
+    Utf8ParseResult, Utf8PointParseResult, Utf16ParseResult, Utf16PointParseResult,
+    Utf32ParseResult,
+};
 use std::cell::RefCell;
 
+#[wasm_bindgen]
+extern "C" {
+    /// The host's monotonic clock, for session timeouts.
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+fn clock() -> u64 {
+    performance_now() as u64
+}
+
 thread_local! {
     static RUNTIME: RefCell<Option<PluginRuntime>> = const { RefCell::new(None) };
 }
@@ -21,7 +35,9 @@ fn with_runtime<T>(f: impl FnOnce(&mut PluginRuntime) -> T) -> T {
                 <%= grammar_crate_name_snake %>::LOCALS_QUERY,
             )
             .expect("failed to create highlight config");
-            *runtime = Some(PluginRuntime::new(config));
+            let mut new_runtime = PluginRuntime::new(config);
+            new_runtime.set_clock(clock);
+            *runtime = Some(new_runtime);
         }
         f(runtime.as_mut().expect("runtime not initialized"))
     })
@@ -88,8 +104,90 @@ pub fn parse_utf16(session: u32) -> Result<JsValue, JsValue> {
     }
 }
 
+/// Like `parse`, but only queries the byte range `start..end`, for
+/// highlighting the visible part of a large document.
+#[wasm_bindgen]
+pub fn parse_range(session: u32, start: u32, end: u32) -> Result<JsValue, JsValue> {
+    let result: Result<Utf8ParseResult, _> =
+        with_runtime(|runtime| runtime.parse_range(session, start..end));
+
+    match result {
+        Ok(r) => serde_wasm_bindgen::to_value(&r)
+            .map_err(|e| JsValue::from_str(&format!("serialization error: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("parse error: {}", e.message))),
+    }
+}
+
+/// Like `parse_utf16`, but only queries the UTF-16 range `start..end`, for
+/// highlighting the visible part of a large document.
+#[wasm_bindgen]
+pub fn parse_range_utf16(session: u32, start: u32, end: u32) -> Result<JsValue, JsValue> {
+    let result: Result<Utf16ParseResult, _> =
+        with_runtime(|runtime| runtime.parse_range_utf16(session, start..end));
+
+    match result {
+        Ok(r) => serde_wasm_bindgen::to_value(&r)
+            .map_err(|e| JsValue::from_str(&format!("serialization error: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("parse error: {}", e.message))),
+    }
+}
+
+/// Parses the text in a session and returns spans with code point indices.
+///
+/// Use this for hosts that index strings by code point, like Python or Lua.
+#[wasm_bindgen]
+pub fn parse_utf32(session: u32) -> Result<JsValue, JsValue> {
+    let result: Result<Utf32ParseResult, _> = with_runtime(|runtime| runtime.parse_utf32(session));
+
+    match result {
+        Ok(r) => serde_wasm_bindgen::to_value(&r)
+            .map_err(|e| JsValue::from_str(&format!("serialization error: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("parse error: {}", e.message))),
+    }
+}
+
+/// Like `parse`, but spans also carry row and column positions. Columns are
+/// bytes, or UTF-16 code units if `utf16_columns` is set.
+#[wasm_bindgen]
+pub fn parse_with_points(session: u32, utf16_columns: bool) -> Result<JsValue, JsValue> {
+    let columns = if utf16_columns {
+        ColumnEncoding::Utf16
+    } else {
+        ColumnEncoding::Byte
+    };
+    let result: Result<Utf8PointParseResult, _> =
+        with_runtime(|runtime| runtime.parse_with_points(session, columns));
+
+    match result {
+        Ok(r) => serde_wasm_bindgen::to_value(&r)
+            .map_err(|e| JsValue::from_str(&format!("serialization error: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("parse error: {}", e.message))),
+    }
+}
+
+/// Like `parse_utf16`, but spans also carry row and UTF-16 column positions,
+/// as editors like CodeMirror expect.
+#[wasm_bindgen]
+pub fn parse_utf16_with_points(session: u32) -> Result<JsValue, JsValue> {
+    let result: Result<Utf16PointParseResult, _> =
+        with_runtime(|runtime| runtime.parse_utf16_with_points(session));
+
+    match result {
+        Ok(r) => serde_wasm_bindgen::to_value(&r)
+            .map_err(|e| JsValue::from_str(&format!("serialization error: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("parse error: {}", e.message))),
+    }
+}
+
 /// Cancels an ongoing parse operation.
 #[wasm_bindgen]
 pub fn cancel(session: u32) {
     with_runtime(|runtime| runtime.cancel(session));
 }
+
+/// Stops queries on a session that run longer than `millis` milliseconds
+/// (0 for no limit). Timed-out results have `timed_out` set.
+#[wasm_bindgen]
+pub fn set_timeout(session: u32, millis: u32) {
+    with_runtime(|runtime| runtime.set_timeout(session, millis));
+}
@@ -85,7 +85,11 @@
 //! [`advanced`] module.
 
 // Internal modules
+#[cfg(feature = "cache")]
+mod cache;
+mod diff;
 mod error;
+mod fence;
 mod highlighter;
 pub(crate) mod store;
 
@@ -96,20 +100,37 @@ pub mod advanced;
 ///
 /// Re-exports types from `arborium-theme` for configuring syntax colors.
 pub mod theme {
-    pub use arborium_theme::theme::{builtin, Color, Modifiers, Style, Theme};
+    pub use arborium_theme::theme::{
+        builtin, Ansi16Color, Color, ColorDepth, Modifiers, Style, Theme,
+    };
+    pub use arborium_theme::{
+        CAPTURE_NAMES_EXTENDED, Slot, extended_highlight_index, register_capture_alias,
+    };
+
+    pub use arborium_highlight::preview_spans;
 }
 
 // Primary API exports
 pub use error::Error;
-pub use highlighter::{AnsiHighlighter, Highlighter};
+pub use fence::FenceLanguageMap;
+pub use highlighter::{AnsiHighlighter, Highlighter, Timings};
 pub use store::GrammarStore;
 
 // Configuration types (re-exported from arborium-highlight)
-pub use arborium_highlight::HtmlFormat;
+pub use arborium_highlight::{ControlCharMode, DocumentOptions, HtmlFormat, LineNumberConfig};
+
+// Text utilities (re-exported from arborium-highlight)
+pub use arborium_highlight::line_ranges;
+
+// Caller-supplied scopes for `Highlighter::highlight_with_scopes`
+pub use arborium_highlight::Scope;
+
+// Unescaping of code injected into string literals, see `Config::string_unescapers`
+pub use arborium_highlight::{CStringUnescaper, StringUnescaper};
 
 /// Configuration for highlighting.
 ///
-/// Controls injection depth and HTML output format.
+/// Controls injection depth, allowed injection languages, and HTML output format.
 #[derive(Debug, Clone)]
 pub struct Config {
     /// Maximum depth for processing language injections.
@@ -123,6 +144,111 @@ pub struct Config {
     ///
     /// See [`HtmlFormat`] for options.
     pub html_format: HtmlFormat,
+
+    /// Languages that injections may be highlighted as.
+    ///
+    /// - `None`: Default, any injected language with a compiled-in grammar
+    /// - `Some(set)`: Only languages in the set; an empty set disables injections
+    ///
+    /// Use this when highlighting untrusted input to control which grammars
+    /// a document can pull in.
+    pub injection_allowlist: Option<std::collections::HashSet<String>>,
+
+    /// Maximum number of distinct languages injections may be highlighted
+    /// as in one document.
+    ///
+    /// Injections of further languages are left plain. `None` (the default)
+    /// means no limit; unlike `injection_allowlist`, this bounds how many
+    /// grammars untrusted input can pull in without fixing which ones.
+    pub max_injected_languages: Option<usize>,
+
+    /// Trim leading and trailing blank lines from injected regions (such as
+    /// fenced code blocks) before highlighting them.
+    ///
+    /// Off by default so output matches the source exactly.
+    pub trim_injection_blank_lines: bool,
+
+    /// Remove the indentation shared by all lines of an injected region
+    /// (such as a fenced code block in a Markdown list) before highlighting
+    /// it, so the grammar sees un-indented code.
+    ///
+    /// Spans are mapped back onto the original text, so the output keeps
+    /// its indentation. Off by default.
+    pub dedent_injections: bool,
+
+    /// Maximum size of the rendered HTML, in bytes.
+    ///
+    /// Highlighting fails with [`Error::OutputTooLarge`] rather than produce
+    /// more. `None` (the default) means no limit; set one when highlighting
+    /// untrusted input in a public service.
+    pub max_output_bytes: Option<usize>,
+
+    /// How control characters in the source are rendered in HTML.
+    ///
+    /// Defaults to [`ControlCharMode::Picture`], which shows them as
+    /// Unicode control pictures (`␀`, `␇`, ...). ANSI output uses
+    /// [`AnsiOptions::control_char_handling`](arborium_highlight::AnsiOptions::control_char_handling).
+    pub control_char_handling: ControlCharMode,
+
+    /// Leave large unparseable regions unhighlighted.
+    ///
+    /// Input that is still being typed often contains regions tree-sitter
+    /// can't make sense of. When enabled, spans entirely inside an ERROR node
+    /// larger than [`ERROR_REGION_MIN_BYTES`](arborium_highlight::tree_sitter::ERROR_REGION_MIN_BYTES)
+    /// are dropped, instead of coloring the region arbitrarily. Off by default.
+    ///
+    /// Only [`Highlighter`] and [`AnsiHighlighter`] apply this: it needs the
+    /// syntax tree, which [`HighlightConfig`](arborium_highlight::HighlightConfig)
+    /// users only get spans from, so converting to one drops it.
+    pub suppress_error_regions: bool,
+
+    /// Language to highlight documents as when their own language isn't
+    /// supported.
+    ///
+    /// `None` (the default) fails with [`Error::UnsupportedLanguage`]
+    /// instead. Set it to a generic grammar, or to one that's close enough,
+    /// so unknown fenced-code languages still get some highlighting.
+    /// Injections of unsupported languages are always left plain.
+    pub fallback_language: Option<String>,
+
+    /// Render one HTML element per span, exactly as the grammar captured
+    /// them: spans are neither deduplicated nor merged, and nested captures
+    /// become nested elements.
+    ///
+    /// Meant for tools that map elements back to captures one to one; the
+    /// output is larger. Off by default. See
+    /// [`spans_to_html_exact`](arborium_highlight::spans_to_html_exact).
+    pub exact_spans: bool,
+
+    /// Wrap each line of the HTML output in `<span class="line"
+    /// data-line="N">`, for CSS to render numbered code blocks.
+    ///
+    /// Elements spanning several lines are closed and reopened at line
+    /// breaks so the wrappers nest properly. Not applied with
+    /// `exact_spans` or [`DocumentOptions::emit_data_capture`]. `None` (the
+    /// default) leaves lines unwrapped. See [`LineNumberConfig`].
+    pub line_numbers: Option<LineNumberConfig>,
+
+    /// String unescapers for injections, keyed by the language injected
+    /// into, e.g. `"c"` mapped to [`CStringUnescaper`].
+    ///
+    /// Code embedded in a string literal is unescaped before the injected
+    /// grammar sees it, so SQL in a C string parses `\"name\"` as
+    /// `"name"`. Spans are mapped back onto the escaped source. Empty by
+    /// default.
+    pub string_unescapers: std::collections::HashMap<String, std::sync::Arc<dyn StringUnescaper>>,
+
+    /// Drop a leading UTF-8 byte order mark from the HTML output.
+    ///
+    /// Span offsets still count the BOM. Off by default.
+    pub strip_bom: bool,
+
+    /// Write `\r\n` and lone `\r` line endings as `\n` in the HTML output,
+    /// for embedding in pages that expect LF.
+    ///
+    /// Span offsets still refer to the original line endings. Off by
+    /// default.
+    pub normalize_line_endings: bool,
 }
 
 impl Default for Config {
@@ -130,15 +256,42 @@ impl Default for Config {
         Self {
             max_injection_depth: 3,
             html_format: HtmlFormat::default(),
+            injection_allowlist: None,
+            max_injected_languages: None,
+            trim_injection_blank_lines: false,
+            dedent_injections: false,
+            max_output_bytes: None,
+            control_char_handling: ControlCharMode::default(),
+            suppress_error_regions: false,
+            fallback_language: None,
+            exact_spans: false,
+            line_numbers: None,
+            string_unescapers: std::collections::HashMap::new(),
+            strip_bom: false,
+            normalize_line_endings: false,
         }
     }
 }
 
 impl From<Config> for arborium_highlight::HighlightConfig {
     fn from(config: Config) -> Self {
+        // `suppress_error_regions` has no counterpart: it needs the syntax
+        // tree, and grammars only hand the core highlighter spans
         arborium_highlight::HighlightConfig {
             max_injection_depth: config.max_injection_depth,
             html_format: config.html_format,
+            injection_allowlist: config.injection_allowlist,
+            max_injected_languages: config.max_injected_languages,
+            trim_injection_blank_lines: config.trim_injection_blank_lines,
+            dedent_injections: config.dedent_injections,
+            max_output_bytes: config.max_output_bytes,
+            control_char_handling: config.control_char_handling,
+            fallback_language: config.fallback_language,
+            exact_spans: config.exact_spans,
+            line_numbers: config.line_numbers,
+            string_unescapers: config.string_unescapers,
+            strip_bom: config.strip_bom,
+            normalize_line_endings: config.normalize_line_endings,
         }
     }
 }
@@ -233,3 +386,79 @@ pub fn get_language(name: &str) -> Option<tree_sitter::Language> {
         _ => None,
     }
 }
+
+/// Names of the languages compiled into this build, one for each enabled
+/// `lang-*` feature.
+pub fn supported_languages() -> &'static [&'static str] {
+    GrammarStore::AVAILABLE_LANGUAGES
+}
+
+/// Names of every language arborium has a grammar for, whether or not it's
+/// compiled into this build.
+pub fn all_languages() -> &'static [&'static str] {
+    GrammarStore::ALL_LANGUAGES
+}
+
+/// Whether a language can be highlighted by this build, see
+/// [`language_support`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageSupport {
+    /// The language is compiled in, under this canonical name.
+    Supported(&'static str),
+    /// The language is known, under this canonical name, but its
+    /// `lang-<name>` feature wasn't enabled at build time.
+    NotCompiled(&'static str),
+    /// No grammar for the language exists.
+    Unknown,
+}
+
+/// Check whether `language`, a language name or alias, can be highlighted
+/// by this build.
+///
+/// Unlike [`get_language`], this tells languages that arborium has no
+/// grammar for apart from ones that merely weren't compiled in.
+///
+/// # Example
+///
+/// ```rust
+/// use arborium::{LanguageSupport, language_support};
+///
+/// assert_eq!(language_support("no-such-language"), LanguageSupport::Unknown);
+/// ```
+pub fn language_support(language: &str) -> LanguageSupport {
+    let normalized = GrammarStore::normalize_language(language);
+    let find = |languages: &[&'static str]| languages.iter().copied().find(|l| *l == normalized);
+    let compiled = find(GrammarStore::AVAILABLE_LANGUAGES);
+    match (compiled, find(GrammarStore::ALL_LANGUAGES)) {
+        (Some(name), _) => LanguageSupport::Supported(name),
+        (None, Some(name)) => LanguageSupport::NotCompiled(name),
+        (None, None) => LanguageSupport::Unknown,
+    }
+}
+
+/// Returns a representative source sample for the given language.
+///
+/// Samples are embedded from each grammar's registered samples, so this
+/// works without filesystem access. Only languages enabled via feature
+/// flags (and shipping a sample) are available; requires the `samples`
+/// feature.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use arborium::sample_for;
+///
+/// // Returns Some if the "samples" and "lang-rust" features are enabled
+/// let code = sample_for("rust").unwrap();
+/// let html = arborium::Highlighter::new().highlight("rust", code)?;
+/// ```
+#[cfg(feature = "samples")]
+pub fn sample_for(language: &str) -> Option<&'static str> {
+    match language {
+<% for (crate_name, grammar_id) in sampled_grammars { %>
+        #[cfg(feature = "lang-<%= grammar_id %>")]
+        "<%= grammar_id %>" => Some(<%= crate_name.replace('-', "_") %>::SAMPLE),
+<% } %>
+        _ => None,
+    }
+}
@@ -1,7 +1,9 @@
 //! Thread-safe grammar store for caching compiled grammars.
 //!
 //! The `GrammarStore` holds compiled grammars that can be shared across threads.
-//! Each grammar is compiled once and cached for reuse.
+//! Each grammar is compiled once and cached for reuse. It also keeps a pool of
+//! idle parse contexts, so highlighters created per request reuse parsers and
+//! query cursors instead of allocating new ones.
 //!
 //! # Generated Code
 //!
@@ -10,10 +12,12 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 #[allow(unused_imports)]
-use arborium_highlight::tree_sitter::{CompiledGrammar, GrammarConfig};
+use arborium_highlight::tree_sitter::{CompiledGrammar, GrammarConfig, GrammarError, ParseContext};
+
+use crate::Error;
 
 /// Thread-safe cache of compiled grammars.
 ///
@@ -37,6 +41,8 @@ use arborium_highlight::tree_sitter::{CompiledGrammar, GrammarConfig};
 /// ```
 pub struct GrammarStore {
     grammars: RwLock<HashMap<String, Arc<CompiledGrammar>>>,
+    /// Parse contexts released by dropped highlighters, ready for reuse.
+    contexts: Mutex<Vec<ParseContext>>,
 }
 
 impl Default for GrammarStore {
@@ -50,41 +56,132 @@ impl GrammarStore {
     pub fn new() -> Self {
         Self {
             grammars: RwLock::new(HashMap::new()),
+            contexts: Mutex::new(Vec::new()),
         }
     }
 
+    /// Take an idle parse context from the pool, if there is one.
+    ///
+    /// The context's parser may still be set to another language; callers
+    /// must set the language before parsing.
+    pub(crate) fn take_context(&self) -> Option<ParseContext> {
+        self.contexts.lock().unwrap().pop()
+    }
+
+    /// Return a parse context to the pool for reuse by other highlighters.
+    pub(crate) fn recycle_context(&self, ctx: ParseContext) {
+        self.contexts.lock().unwrap().push(ctx);
+    }
+
+    /// Number of idle parse contexts in the pool.
+    #[cfg(test)]
+    pub(crate) fn idle_contexts(&self) -> usize {
+        self.contexts.lock().unwrap().len()
+    }
+
+    /// Cache `grammar` under `language`, in place of the compiled-in one.
+    #[cfg(test)]
+    pub(crate) fn insert(&self, language: &str, grammar: CompiledGrammar) {
+        self.grammars
+            .write()
+            .unwrap()
+            .insert(language.to_string(), Arc::new(grammar));
+    }
+
     /// Get a grammar by language name, compiling and caching it if needed.
     ///
     /// Returns `None` if the language is not supported.
     pub fn get(&self, language: &str) -> Option<Arc<CompiledGrammar>> {
+        self.try_get(language).ok()
+    }
+
+    /// Like [`get`](Self::get), but reports why the grammar isn't available:
+    /// [`Error::UnsupportedLanguage`] if it isn't compiled in, or
+    /// [`Error::QueryError`] if its queries don't compile.
+    pub fn try_get(&self, language: &str) -> Result<Arc<CompiledGrammar>, Error> {
         let normalized = Self::normalize_language(language);
 
         // Fast path: check if already cached
         {
             let grammars = self.grammars.read().unwrap();
             if let Some(grammar) = grammars.get(&*normalized) {
-                return Some(grammar.clone());
+                return Ok(grammar.clone());
             }
         }
 
         // Slow path: compile and cache
-        let grammar = Self::compile_grammar(&normalized)?;
+        let grammar = Self::compile_grammar(&normalized)
+            .ok_or_else(|| Error::UnsupportedLanguage {
+                language: language.to_string(),
+            })?
+            .map_err(|e| match e {
+                GrammarError::QueryError(message) => Error::QueryError {
+                    language: normalized.to_string(),
+                    message,
+                },
+                GrammarError::LanguageError => Error::ParseError {
+                    language: normalized.to_string(),
+                    message: e.to_string(),
+                },
+            })?;
         let grammar = Arc::new(grammar);
 
         {
             let mut grammars = self.grammars.write().unwrap();
             // Double-check in case another thread compiled it
             if let Some(existing) = grammars.get(&*normalized) {
-                return Some(existing.clone());
+                return Ok(existing.clone());
             }
             grammars.insert(normalized.into_owned(), grammar.clone());
         }
 
-        Some(grammar)
+        Ok(grammar)
+    }
+
+    /// Compile and cache the grammars for `languages` now, rather than on
+    /// first use.
+    ///
+    /// Servers can call this at startup to pay the compilation cost up
+    /// front. Stops at the first language that is unsupported or whose
+    /// queries don't compile.
+    pub fn warm(&self, languages: &[&str]) -> Result<(), Error> {
+        for language in languages {
+            self.try_get(language)?;
+        }
+        Ok(())
+    }
+
+    /// Compile and cache every grammar enabled in this build.
+    ///
+    /// Besides moving compilation to startup, this checks that the queries
+    /// of every enabled grammar compile.
+    pub fn warm_all(&self) -> Result<(), Error> {
+        self.warm(Self::AVAILABLE_LANGUAGES)
+    }
+
+    /// Number of compiled grammars in the store.
+    #[cfg(test)]
+    pub(crate) fn compiled_grammars(&self) -> usize {
+        self.grammars.read().unwrap().len()
     }
 
+    /// Names of the languages compiled into this build.
+    pub(crate) const AVAILABLE_LANGUAGES: &[&str] = &[
+<% for (feature, module, grammar_id) in languages { %>
+        #[cfg(feature = "<%= feature %>")]
+        "<%= grammar_id %>",
+<% } %>
+    ];
+
+    /// Names of every language in the registry, compiled in or not.
+    pub(crate) const ALL_LANGUAGES: &[&str] = &[
+<% for (feature, module, grammar_id) in languages { %>
+        "<%= grammar_id %>",
+<% } %>
+    ];
+
     /// Normalize a language name to its canonical form.
-    fn normalize_language(language: &str) -> Cow<'_, str> {
+    pub(crate) fn normalize_language(language: &str) -> Cow<'_, str> {
         match language {
             // Aliases (generated from arborium.kdl)
 <% for (alias, canonical) in aliases { %>
@@ -96,8 +193,10 @@ impl GrammarStore {
     }
 
     /// Compile a grammar for a language.
+    ///
+    /// Returns `None` if the language is not compiled in.
     #[allow(unused_variables)]
-    fn compile_grammar(language: &str) -> Option<CompiledGrammar> {
+    fn compile_grammar(language: &str) -> Option<Result<CompiledGrammar, GrammarError>> {
         macro_rules! try_lang {
             ($feature:literal, $module:ident, $primary:literal) => {
                 #[cfg(feature = $feature)]
@@ -108,7 +207,7 @@ impl GrammarStore {
                         injections_query: crate::$module::INJECTIONS_QUERY,
                         locals_query: crate::$module::LOCALS_QUERY,
                     };
-                    return CompiledGrammar::new(config).ok();
+                    return Some(CompiledGrammar::new(config));
                 }
             };
         }